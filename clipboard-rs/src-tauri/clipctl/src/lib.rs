@@ -0,0 +1,106 @@
+// Client side of the clipboard-rs scripting socket: where it lives, how its
+// directory is kept private, and a call helper for `clipctl`
+
+#![cfg(unix)]
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// A JSON-RPC error object.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError { code, message: message.into() }
+    }
+}
+
+/// Socket the app listens on: `$CLIPBOARD_RS_SOCKET` if set, otherwise
+/// `clipboard-rs.sock` in `socket_dir()`.
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("CLIPBOARD_RS_SOCKET") {
+        Some(path) => PathBuf::from(path),
+        None => socket_dir().join("clipboard-rs.sock"),
+    }
+}
+
+/// `$XDG_RUNTIME_DIR`, or without one a `clipboard-rs-<uid>` directory in
+/// the temp directory. Either must be private to the user (see
+/// `check_private`); nobody else can then put a socket there for us to
+/// talk to.
+pub fn socket_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::temp_dir().join(format!("clipboard-rs-{}", current_uid())),
+    }
+}
+
+/// Create `socket_dir()` with mode `0700` if it doesn't exist yet, then
+/// check it's private.
+pub fn create_socket_dir() -> std::io::Result<PathBuf> {
+    let dir = socket_dir();
+    match fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+    check_private(&dir)?;
+    Ok(dir)
+}
+
+/// Fail unless `dir` is a real directory (not a symlink) owned by the
+/// current user that no one else can read or write.
+pub fn check_private(dir: &Path) -> std::io::Result<()> {
+    let metadata = fs::symlink_metadata(dir)?;
+    let private = metadata.is_dir() && metadata.uid() == current_uid() && metadata.permissions().mode() & 0o077 == 0;
+    if !private {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("{} is not a directory private to this user", dir.display()),
+        ));
+    }
+    Ok(())
+}
+
+fn current_uid() -> u32 {
+    // SAFETY: getuid has no preconditions and can't fail
+    unsafe { libc::getuid() }
+}
+
+/// Send one request to a running app and return its result.
+pub fn call(method: &str, params: Value) -> Result<Value, String> {
+    let path = socket_path();
+    // A missing directory just means the app isn't running, which connecting
+    // reports below
+    if std::env::var_os("CLIPBOARD_RS_SOCKET").is_none() {
+        match check_private(&socket_dir()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(format!("Won't connect to {}: {}", path.display(), e));
+            }
+            _ => {}
+        }
+    }
+    let mut stream = UnixStream::connect(&path)
+        .map_err(|e| format!("Can't connect to {} (is clipboard-rs running?): {}", path.display(), e))?;
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    writeln!(stream, "{}", request).map_err(|e| e.to_string())?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line).map_err(|e| e.to_string())?;
+    let mut response: Value = serde_json::from_str(&line).map_err(|e| e.to_string())?;
+    if let Some(error) = response.get_mut("error").filter(|error| !error.is_null()) {
+        let error: RpcError = serde_json::from_value(error.take()).map_err(|e| e.to_string())?;
+        return Err(error.message);
+    }
+    Ok(response.get_mut("result").map(Value::take).unwrap_or(Value::Null))
+}
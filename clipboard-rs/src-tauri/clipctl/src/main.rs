@@ -0,0 +1,158 @@
+// Command-line client for the clipboard-rs scripting socket. Prints results
+// as JSON so they can be piped into jq.
+
+#[cfg(unix)]
+use serde_json::{json, Value};
+
+const USAGE: &str = "usage: clipctl <command> [args]
+
+commands:
+  list [--limit N] [--offset N]   newest items first
+  search <query> [--mode MODE] [--type TYPE] [--tag TAG] [--favorites]
+         [--since DATE] [--until DATE] [--limit N] [--offset N]
+                                  ranked search over item text; MODE is
+                                  substring (default), fuzzy or regex; TYPE is
+                                  url, email, path, color, json, uuid, phone or code;
+                                  DATE is YYYY-MM-DD or a unix time
+  get <id>                        one item
+  copy <id>                       put an item back on the clipboard
+  favorite <id>                   toggle an item's favorite flag
+  tag <id> <tag>                  add a tag
+  untag <id> <tag>                remove a tag
+  delete <id>                     delete an item
+  pause [SECONDS]                 stop recording clips, for SECONDS if given
+  resume                          start recording clips again";
+
+#[cfg(unix)]
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = parse_args(&args).and_then(|(method, params)| clipctl::call(method, params));
+    match result {
+        Ok(Value::Null) => {}
+        Ok(value) => println!("{}", serde_json::to_string_pretty(&value).unwrap_or_default()),
+        Err(e) => {
+            eprintln!("clipctl: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("clipctl: the scripting socket is only available on Unix");
+    std::process::exit(1);
+}
+
+// Turn the command line into a method name and its params
+#[cfg(unix)]
+fn parse_args(args: &[String]) -> Result<(&'static str, Value), String> {
+    let Some((command, rest)) = args.split_first() else {
+        return Err(USAGE.to_string());
+    };
+    let options = Options::parse(rest)?;
+    let arg = |index: usize| options.positional.get(index).cloned().ok_or_else(|| USAGE.to_string());
+
+    let request = match command.as_str() {
+        "list" => ("list", json!({ "limit": options.limit, "offset": options.offset })),
+        "search" => (
+            "search",
+            json!({
+                "query": options.positional.first().cloned().unwrap_or_default(),
+                "mode": options.mode,
+                "limit": options.limit,
+                "offset": options.offset,
+                "content_type": options.content_type,
+                "tag": options.tag,
+                "favorite": options.favorites.then_some(true),
+                "since": options.since.as_deref().map(|date| parse_time(date, false)).transpose()?,
+                "until": options.until.as_deref().map(|date| parse_time(date, true)).transpose()?,
+            }),
+        ),
+        "get" => ("get", json!({ "id": arg(0)? })),
+        "copy" => ("copy", json!({ "id": arg(0)? })),
+        "favorite" => ("favorite", json!({ "id": arg(0)? })),
+        "tag" => ("tag", json!({ "id": arg(0)?, "tag": arg(1)? })),
+        "untag" => ("untag", json!({ "id": arg(0)?, "tag": arg(1)? })),
+        "delete" => ("delete", json!({ "id": arg(0)? })),
+        "pause" => {
+            let seconds = match options.positional.first() {
+                Some(seconds) => Some(seconds.parse::<u64>().map_err(|_| "SECONDS must be a number".to_string())?),
+                None => None,
+            };
+            ("pause", json!({ "seconds": seconds }))
+        }
+        "resume" => ("resume", json!({})),
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            std::process::exit(0);
+        }
+        other => return Err(format!("unknown command '{}'\n\n{}", other, USAGE)),
+    };
+    Ok(request)
+}
+
+#[cfg(unix)]
+#[derive(Default)]
+struct Options {
+    positional: Vec<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    content_type: Option<String>,
+    mode: Option<String>,
+    tag: Option<String>,
+    favorites: bool,
+    since: Option<String>,
+    until: Option<String>,
+}
+
+#[cfg(unix)]
+impl Options {
+    // Pull the `--` options out of the arguments
+    fn parse(args: &[String]) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let text_slot = match arg.as_str() {
+                "--type" => Some(&mut options.content_type),
+                "--mode" => Some(&mut options.mode),
+                "--tag" => Some(&mut options.tag),
+                "--since" => Some(&mut options.since),
+                "--until" => Some(&mut options.until),
+                _ => None,
+            };
+            if let Some(slot) = text_slot {
+                *slot = Some(args.next().ok_or_else(|| format!("{} needs a value", arg))?.clone());
+                continue;
+            }
+            if arg == "--favorites" {
+                options.favorites = true;
+                continue;
+            }
+            let slot = match arg.as_str() {
+                "--limit" => &mut options.limit,
+                "--offset" => &mut options.offset,
+                _ => {
+                    options.positional.push(arg.clone());
+                    continue;
+                }
+            };
+            let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+            *slot = Some(value.parse().map_err(|_| format!("{} must be a number", arg))?);
+        }
+        Ok(options)
+    }
+}
+
+// A unix time, or a local date: its first second, or its last when `end_of_day`
+#[cfg(unix)]
+fn parse_time(value: &str, end_of_day: bool) -> Result<u64, String> {
+    if let Ok(seconds) = value.parse() {
+        return Ok(seconds);
+    }
+    let date = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("'{}' is not a date (YYYY-MM-DD) or unix time", value))?;
+    let time = if end_of_day { date.and_hms_opt(23, 59, 59) } else { date.and_hms_opt(0, 0, 0) };
+    time.and_then(|time| time.and_local_timezone(chrono::Local).earliest())
+        .map(|time| time.timestamp().max(0) as u64)
+        .ok_or_else(|| format!("'{}' is not a valid local date", value))
+}
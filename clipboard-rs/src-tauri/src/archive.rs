@@ -0,0 +1,475 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use chrono::{Datelike, Timelike};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::blobs::{self, BlobStore, PendingBlob};
+use crate::collections::Collection;
+use crate::db::HistoryDb;
+use crate::history::History;
+use crate::item::{decode_history, now_secs, ClipboardItem, ClipboardPayload};
+use crate::search::SearchFilters;
+use crate::vault::Vault;
+
+/// Written to `manifest.json` so an importer can tell what it's reading.
+const FORMAT: &str = "clipboard-rs-archive";
+const VERSION: u32 = 1;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const HISTORY_ENTRY: &str = "history.json";
+const COLLECTIONS_ENTRY: &str = "collections.json";
+const BLOB_DIR: &str = "blobs/";
+
+// Zip record signatures
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+
+// Compression methods
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+// General purpose flag marking file names as UTF-8
+const UTF8_NAMES: u16 = 1 << 11;
+
+// Deflate can't shrink data by more than about 1032:1, so an entry claiming
+// more than that was forged
+const MAX_DEFLATE_RATIO: usize = 1032;
+// Most we reserve up front for an inflated entry; beyond this the buffer
+// grows as data actually comes out of the decoder
+const MAX_PREALLOCATION: usize = 64 * 1024 * 1024;
+
+/// Describes an exported archive.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: u64,
+    pub items: usize,
+    pub blobs: usize,
+    /// Archives from before collections existed have none.
+    #[serde(default)]
+    pub collections: usize,
+}
+
+/// Build a portable archive of the whole history: `manifest.json`,
+/// `history.json` with every item, `collections.json`, and the images under
+/// `blobs/`. With `collection`, only that collection and its items go in.
+/// Image items point at their file inside the archive instead of a path on
+/// this machine, and sealed blobs are stored decrypted so the archive opens
+/// anywhere. Images whose file is missing are skipped.
+pub fn export(db: &HistoryDb, vault: &Vault, collection: Option<Uuid>) -> Result<Vec<u8>, String> {
+    let mut collections = db.collections(false).map_err(|e| e.to_string())?;
+    let source = match collection {
+        Some(id) => {
+            collections.retain(|collection| collection.id == id);
+            if collections.is_empty() {
+                return Err("Collection not found".to_string());
+            }
+            let filters = SearchFilters { collection: Some(id), ..Default::default() };
+            db.search_candidates(None, &filters)
+        }
+        None => db.list(None, 0),
+    };
+
+    let mut items = Vec::new();
+    let mut blob_entries: HashMap<String, String> = HashMap::new();
+    let mut zip = ZipWriter::new();
+
+    for mut item in source.map_err(|e| e.to_string())? {
+        if let ClipboardPayload::Image { blob_path, .. } = &mut item.payload {
+            let name = match blob_entries.get(blob_path.as_str()) {
+                Some(name) => name.clone(),
+                None => {
+                    // Images whose file has gone missing are left out
+                    let Ok(bytes) = vault.read_blob(blob_path) else {
+                        continue;
+                    };
+                    // Name blobs by their plain content so the same image in
+                    // a sealed and a plain history ends up under the same name
+                    let name = format!("{}{}", BLOB_DIR, blobs::address(&bytes, None, &blobs::plain_extension(blob_path)));
+                    zip.add(&name, &bytes, false)?;
+                    blob_entries.insert(blob_path.clone(), name.clone());
+                    name
+                }
+            };
+            *blob_path = name;
+        }
+        items.push(item);
+    }
+
+    let manifest = Manifest {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        exported_at: now_secs(),
+        items: items.len(),
+        blobs: blob_entries.len(),
+        collections: collections.len(),
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    let history_json = serde_json::to_vec_pretty(&items).map_err(|e| e.to_string())?;
+    let collections_json = serde_json::to_vec_pretty(&collections).map_err(|e| e.to_string())?;
+    zip.add(MANIFEST_ENTRY, &manifest_json, true)?;
+    zip.add(HISTORY_ENTRY, &history_json, true)?;
+    zip.add(COLLECTIONS_ENTRY, &collections_json, true)?;
+    zip.finish()
+}
+
+/// What `read` found in an archive.
+pub struct Contents<'a> {
+    pub items: Vec<ClipboardItem>,
+    pub collections: Vec<Collection>,
+    /// The images, which must be kept until the items have been inserted.
+    pub blobs: Vec<PendingBlob<'a>>,
+}
+
+/// Read an archive written by `export`. Images are copied into the blob
+/// store (sealed if encryption is on) and their items repointed. Image items
+/// whose file is missing from the archive are dropped.
+pub fn read<'a>(data: &[u8], vault: &Vault, blob_store: &'a BlobStore) -> Result<Contents<'a>, String> {
+    let mut entries = read_zip(data)?;
+
+    let manifest = entries.remove(MANIFEST_ENTRY).ok_or("Not a clipboard history archive: manifest.json is missing")?;
+    let manifest: Manifest = serde_json::from_slice(&manifest).map_err(|e| format!("Invalid archive manifest: {}", e))?;
+    if manifest.format != FORMAT {
+        return Err(format!("Not a clipboard history archive: unknown format \"{}\"", manifest.format));
+    }
+    if manifest.version > VERSION {
+        return Err(format!("Archive version {} is newer than this app supports", manifest.version));
+    }
+
+    let history = entries.remove(HISTORY_ENTRY).ok_or("Archive is missing history.json")?;
+    let value: serde_json::Value = serde_json::from_slice(&history).map_err(|e| e.to_string())?;
+    let (items, _) = decode_history(value);
+    let collections: Vec<Collection> = match entries.remove(COLLECTIONS_ENTRY) {
+        Some(json) => serde_json::from_slice(&json).map_err(|e| format!("Invalid collections.json: {}", e))?,
+        None => Vec::new(),
+    };
+
+    let mut stored: HashMap<String, PendingBlob<'a>> = HashMap::new();
+    let mut imported = Vec::with_capacity(items.len());
+    for mut item in items {
+        if let ClipboardPayload::Image { blob_path, .. } = &mut item.payload {
+            if !stored.contains_key(blob_path.as_str()) {
+                let Some(bytes) = entries.get(blob_path.as_str()) else {
+                    continue;
+                };
+                let blob = vault.write_blob(blob_store, bytes, &blobs::plain_extension(blob_path))?;
+                stored.insert(blob_path.clone(), blob);
+            }
+            *blob_path = stored[blob_path.as_str()].path();
+        }
+        imported.push(item);
+    }
+    Ok(Contents { items: imported, collections, blobs: stored.into_values().collect() })
+}
+
+// Function to export the history, images included, as a zip archive
+#[tauri::command]
+pub fn export_history_archive(
+    db: tauri::State<'_, HistoryDb>,
+    vault: tauri::State<'_, Vault>,
+) -> Result<tauri::ipc::Response, String> {
+    export(&db, &vault, None).map(tauri::ipc::Response::new)
+}
+
+// Function to import a zip archive made by `export_history_archive` or
+// `export_collection_archive`. The archive's bytes are sent as the raw
+// request body. Items already in the history are skipped, collections are
+// merged into ones of the same name, and the items (not the collections) can
+// be removed again by undo like a JSON import.
+#[tauri::command]
+pub fn import_history_archive(
+    history: tauri::State<'_, History>,
+    vault: tauri::State<'_, Vault>,
+    blob_store: tauri::State<'_, BlobStore>,
+    request: tauri::ipc::Request<'_>,
+) -> Result<usize, String> {
+    let tauri::ipc::InvokeBody::Raw(data) = request.body() else {
+        return Err("Expected the archive as raw bytes".to_string());
+    };
+    let Contents { items, collections, blobs: _blobs } = read(data, &vault, &blob_store)?;
+    history.import_with_collections(items, collections)
+}
+
+// Writes a zip file in memory. Archives are limited to what fits without
+// the Zip64 extensions, which is far more than a clipboard history needs.
+struct ZipWriter {
+    data: Vec<u8>,
+    central_directory: Vec<u8>,
+    entries: u16,
+    time: u16,
+    date: u16,
+}
+
+impl ZipWriter {
+    fn new() -> Self {
+        let (time, date) = dos_timestamp(chrono::Local::now().naive_local());
+        ZipWriter { data: Vec::new(), central_directory: Vec::new(), entries: 0, time, date }
+    }
+
+    // Add a file. Images are already compressed, so they're stored as is.
+    fn add(&mut self, name: &str, contents: &[u8], compress: bool) -> Result<(), String> {
+        let (method, stored) = if compress {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(contents).map_err(|e| e.to_string())?;
+            (DEFLATED, encoder.finish().map_err(|e| e.to_string())?)
+        } else {
+            (STORED, contents.to_vec())
+        };
+
+        let too_large = || "History is too large for a zip archive".to_string();
+        let crc = crc32fast::hash(contents);
+        let size = u32::try_from(contents.len()).map_err(|_| too_large())?;
+        let compressed_size = u32::try_from(stored.len()).map_err(|_| too_large())?;
+        let offset = u32::try_from(self.data.len()).map_err(|_| too_large())?;
+        let name_length = u16::try_from(name.len()).map_err(|_| "File name too long".to_string())?;
+        self.entries = self.entries.checked_add(1).ok_or_else(too_large)?;
+
+        // Fields shared by the local header and the central directory entry,
+        // from "version needed" through the extra field length
+        let mut fields = Vec::with_capacity(26);
+        put_u16(&mut fields, 20);
+        put_u16(&mut fields, UTF8_NAMES);
+        put_u16(&mut fields, method);
+        put_u16(&mut fields, self.time);
+        put_u16(&mut fields, self.date);
+        put_u32(&mut fields, crc);
+        put_u32(&mut fields, compressed_size);
+        put_u32(&mut fields, size);
+        put_u16(&mut fields, name_length);
+        put_u16(&mut fields, 0);
+
+        put_u32(&mut self.data, LOCAL_HEADER);
+        self.data.extend_from_slice(&fields);
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.extend_from_slice(&stored);
+
+        let cd = &mut self.central_directory;
+        put_u32(cd, CENTRAL_HEADER);
+        put_u16(cd, 20); // version made by
+        cd.extend_from_slice(&fields);
+        put_u16(cd, 0); // comment length
+        put_u16(cd, 0); // disk number
+        put_u16(cd, 0); // internal attributes
+        put_u32(cd, 0); // external attributes
+        put_u32(cd, offset);
+        cd.extend_from_slice(name.as_bytes());
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, String> {
+        let too_large = || "History is too large for a zip archive".to_string();
+        let offset = u32::try_from(self.data.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(self.central_directory.len()).map_err(|_| too_large())?;
+        self.data.append(&mut self.central_directory);
+
+        put_u32(&mut self.data, END_OF_CENTRAL_DIRECTORY);
+        put_u16(&mut self.data, 0); // this disk
+        put_u16(&mut self.data, 0); // disk with the central directory
+        put_u16(&mut self.data, self.entries);
+        put_u16(&mut self.data, self.entries);
+        put_u32(&mut self.data, size);
+        put_u32(&mut self.data, offset);
+        put_u16(&mut self.data, 0); // comment length
+        Ok(self.data)
+    }
+}
+
+// Read every file in a zip archive, keyed by name. Only stored and deflated
+// entries are supported, which covers what `ZipWriter` and common zip tools
+// produce.
+fn read_zip(data: &[u8]) -> Result<HashMap<String, Vec<u8>>, String> {
+    let invalid = || "Not a valid zip archive".to_string();
+
+    // The end record is at least 22 bytes and may be followed by a comment
+    // of up to 64 KiB
+    let search_start = data.len().saturating_sub(22 + u16::MAX as usize);
+    let end = (search_start..data.len().saturating_sub(21))
+        .rev()
+        .find(|&i| read_u32(data, i) == Some(END_OF_CENTRAL_DIRECTORY))
+        .ok_or_else(invalid)?;
+    let count = read_u16(data, end + 10).ok_or_else(invalid)?;
+    let mut pos = read_u32(data, end + 16).ok_or_else(invalid)? as usize;
+
+    let mut files = HashMap::new();
+    for _ in 0..count {
+        if read_u32(data, pos) != Some(CENTRAL_HEADER) {
+            return Err(invalid());
+        }
+        let field = |offset: usize| read_u16(data, pos + offset).ok_or_else(invalid);
+        let flags = field(8)?;
+        let method = field(10)?;
+        let crc = read_u32(data, pos + 16).ok_or_else(invalid)?;
+        let compressed_size = read_u32(data, pos + 20).ok_or_else(invalid)? as usize;
+        let size = read_u32(data, pos + 24).ok_or_else(invalid)? as usize;
+        let name_length = field(28)? as usize;
+        let extra_length = field(30)? as usize;
+        let comment_length = field(32)? as usize;
+        let local_offset = read_u32(data, pos + 42).ok_or_else(invalid)? as usize;
+        let name = data.get(pos + 46..pos + 46 + name_length).ok_or_else(invalid)?;
+        let name = String::from_utf8_lossy(name).to_string();
+        pos += 46 + name_length + extra_length + comment_length;
+
+        if flags & 1 != 0 {
+            return Err(format!("{} is encrypted", name));
+        }
+        if name.ends_with('/') {
+            continue;
+        }
+
+        // The local header repeats the name and may have its own extra field
+        if read_u32(data, local_offset) != Some(LOCAL_HEADER) {
+            return Err(invalid());
+        }
+        let start = local_offset
+            + 30
+            + read_u16(data, local_offset + 26).ok_or_else(invalid)? as usize
+            + read_u16(data, local_offset + 28).ok_or_else(invalid)? as usize;
+        let stored = data.get(start..start + compressed_size).ok_or_else(invalid)?;
+
+        let contents = match method {
+            STORED => stored.to_vec(),
+            DEFLATED => {
+                if size > stored.len().saturating_mul(MAX_DEFLATE_RATIO) {
+                    return Err(format!("{} is corrupted", name));
+                }
+                // Never inflate past the declared size, and only trust it
+                // for the initial allocation as far as the stored data
+                // could plausibly expand
+                let capacity = size.min(stored.len().saturating_mul(4)).min(MAX_PREALLOCATION);
+                let mut contents = Vec::with_capacity(capacity);
+                DeflateDecoder::new(stored)
+                    .take(size as u64 + 1)
+                    .read_to_end(&mut contents)
+                    .map_err(|e| format!("Failed to decompress {}: {}", name, e))?;
+                contents
+            }
+            _ => return Err(format!("{} uses an unsupported compression method", name)),
+        };
+        if contents.len() != size || crc32fast::hash(&contents) != crc {
+            return Err(format!("{} is corrupted", name));
+        }
+        files.insert(name, contents);
+    }
+    Ok(files)
+}
+
+// MS-DOS time and date fields. Zip can't represent years before 1980.
+fn dos_timestamp(now: chrono::NaiveDateTime) -> (u16, u16) {
+    let time = ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16;
+    let date = (((now.year().max(1980) - 1980) as u32) << 9 | (now.month() << 5) | now.day()) as u16;
+    (time, date)
+}
+
+fn put_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose, Engine as _};
+
+    use super::*;
+
+    // Offset of the first central directory entry in `data`
+    fn central_entry(data: &[u8]) -> usize {
+        (0..data.len()).find(|&i| read_u32(data, i) == Some(CENTRAL_HEADER)).unwrap()
+    }
+
+    fn sample() -> Vec<u8> {
+        let mut zip = ZipWriter::new();
+        zip.add("history.json", "[{\"text\":\"hello\"}]".repeat(50).as_bytes(), true).unwrap();
+        zip.add("blobs/ä.png", &[0x89, b'P', b'N', b'G', 0, 1, 2, 3], false).unwrap();
+        zip.add("empty.txt", b"", true).unwrap();
+        zip.finish().unwrap()
+    }
+
+    #[test]
+    fn zip_round_trip() {
+        let files = read_zip(&sample()).unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!(files["history.json"], "[{\"text\":\"hello\"}]".repeat(50).as_bytes());
+        assert_eq!(files["blobs/ä.png"], [0x89, b'P', b'N', b'G', 0, 1, 2, 3]);
+        assert!(files["empty.txt"].is_empty());
+    }
+
+    #[test]
+    fn reads_zips_from_other_tools() {
+        // Written by Python's zipfile: a directory entry, a deflated file and
+        // an archive comment
+        let data = general_purpose::STANDARD
+            .decode(
+                "UEsDBBQAAAAAAGAoUl0AAAAAAAAAAAAAAAAGAAAAbm90ZXMvUEsDBBQAAAAIAGAoUl0AiFkLCwAAABgAAAAPAAAAbm90ZXMvaGVsbG8udH\
+                 h0y0jNyclXyEAnuQBQSwECFAMUAAAAAABgKFJdAAAAAAAAAAAAAAAABgAAAAAAAAAAABAA/UEAAAAAbm90ZXMvUEsBAhQDFAAAAAgAYChSXQ\
+                 CIWQsLAAAAGAAAAA8AAAAAAAAAAAAAAIABJAAAAG5vdGVzL2hlbGxvLnR4dFBLBQYAAAAAAgACAHEAAABcAAAADgBtYWRlIGJ5IHB5dGhvbg==",
+            )
+            .unwrap();
+        let files = read_zip(&data).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files["notes/hello.txt"], b"hello hello hello hello\n");
+    }
+
+    #[test]
+    fn rejects_damaged_archives() {
+        let data = sample();
+        assert!(read_zip(&data[..data.len() - 10]).is_err());
+        assert!(read_zip(&data[..data.len() / 2]).is_err());
+        assert!(read_zip(b"").is_err());
+        assert!(read_zip(b"PK\x05\x06").is_err());
+
+        // A flipped bit in the stored image fails the checksum
+        let mut corrupted = data.clone();
+        let png = corrupted.windows(4).position(|window| window == [0x89, b'P', b'N', b'G']).unwrap();
+        corrupted[png + 5] ^= 1;
+        assert_eq!(read_zip(&corrupted).unwrap_err(), "blobs/ä.png is corrupted");
+
+        let mut encrypted = data;
+        let entry = central_entry(&encrypted);
+        encrypted[entry + 8] |= 1;
+        assert_eq!(read_zip(&encrypted).unwrap_err(), "history.json is encrypted");
+    }
+
+    #[test]
+    fn rejects_forged_sizes() {
+        let data = sample();
+        let entry = central_entry(&data);
+
+        // A size no deflate stream could expand to is refused before any
+        // allocation
+        let mut forged = data.clone();
+        forged[entry + 24..entry + 28].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(read_zip(&forged).unwrap_err(), "history.json is corrupted");
+
+        // A plausible size that's wrong is caught once inflated
+        let mut forged = data;
+        let size = read_u32(&forged, entry + 24).unwrap();
+        forged[entry + 24..entry + 28].copy_from_slice(&(size * 4).to_le_bytes());
+        assert_eq!(read_zip(&forged).unwrap_err(), "history.json is corrupted");
+    }
+
+    #[test]
+    fn dos_timestamps() {
+        let time = chrono::NaiveDate::from_ymd_opt(2024, 3, 15).unwrap().and_hms_opt(13, 45, 30).unwrap();
+        assert_eq!(dos_timestamp(time), ((13 << 11) | (45 << 5) | 15, (44 << 9) | (3 << 5) | 15));
+        let early = chrono::NaiveDate::from_ymd_opt(1970, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        assert_eq!(dos_timestamp(early).1, (1 << 5) | 1);
+    }
+}
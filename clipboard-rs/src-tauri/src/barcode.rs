@@ -0,0 +1,476 @@
+use crate::scan::{BitMatrix, CodeFormat, DecodedCode};
+
+// About this many rows are scanned, whatever the image height
+const ROWS: usize = 128;
+
+// A barcode must be read on scanned rows adding up to at least this
+// fraction of its width. Real bars are tall; chance matches in QR codes,
+// text and photos don't repeat that often.
+const MIN_HEIGHT_RATIO: usize = 10;
+
+/// Decode the EAN-13, UPC-A, EAN-8 and Code 128 barcodes along the rows of
+/// a binarized image, in either direction.
+pub fn decode_all(bits: &BitMatrix) -> Vec<DecodedCode> {
+    let step = (bits.height / ROWS).max(1);
+    // Each code with the number of rows it was read on and its widest read
+    let mut seen: Vec<(DecodedCode, usize, usize)> = Vec::new();
+
+    for y in (step / 2..bits.height).step_by(step) {
+        let runs = row_runs(bits, y);
+        let mut codes = decode_row(&runs);
+        codes.extend(decode_row(&reversed(&runs)));
+        for (code, width) in codes {
+            match seen.iter_mut().find(|(other, ..)| *other == code) {
+                Some((_, reads, widest)) => {
+                    *reads += 1;
+                    *widest = (*widest).max(width);
+                }
+                None => seen.push((code, 1, width)),
+            }
+        }
+    }
+
+    seen.into_iter()
+        .filter(|&(_, reads, width)| reads * step * MIN_HEIGHT_RATIO >= width)
+        .map(|(code, ..)| code)
+        .collect()
+}
+
+// Lengths of the alternating light and dark runs along a row, starting with
+// a light one, which is empty if the row starts dark
+fn row_runs(bits: &BitMatrix, y: usize) -> Vec<usize> {
+    let mut runs = vec![0];
+    for x in 0..bits.width {
+        let dark = runs.len() % 2 == 0;
+        if bits.get(x, y) == dark {
+            let last = runs.len() - 1;
+            runs[last] += 1;
+        } else {
+            runs.push(1);
+        }
+    }
+    runs
+}
+
+// The runs of the row read right to left, again starting with a light one
+fn reversed(runs: &[usize]) -> Vec<usize> {
+    let mut reversed: Vec<usize> = runs.iter().rev().copied().collect();
+    if runs.len().is_multiple_of(2) {
+        reversed.insert(0, 0);
+    }
+    reversed
+}
+
+// Every barcode along a row of runs, trying each dark run as a start, with
+// its width in pixels
+fn decode_row(runs: &[usize]) -> Vec<(DecodedCode, usize)> {
+    let mut codes = Vec::new();
+    let mut start = 1;
+    while start + 2 < runs.len() {
+        match decode_ean(runs, start).or_else(|| decode_code128(runs, start)) {
+            Some((code, end)) => {
+                codes.push((code, runs[start..=end].iter().sum()));
+                start = end + 2;
+            }
+            None => start += 2,
+        }
+    }
+    codes
+}
+
+// How far `runs` are from the module widths in `pattern`, as the average
+// deviation per module. `None` if any single run is off by more than
+// `max_run_deviation` modules.
+fn pattern_distance(runs: &[usize], pattern: &[u8], max_run_deviation: f64) -> Option<f64> {
+    let total: usize = runs.iter().sum();
+    let modules: usize = pattern.iter().map(|&m| m as usize).sum();
+    if total < modules {
+        return None;
+    }
+    let unit = total as f64 / modules as f64;
+    let mut deviation = 0.0;
+    for (&run, &width) in runs.iter().zip(pattern) {
+        let off = (run as f64 - width as f64 * unit).abs();
+        if off > max_run_deviation * unit {
+            return None;
+        }
+        deviation += off;
+    }
+    Some(deviation / total as f64)
+}
+
+// The pattern in `patterns` nearest to `runs`, if any is near enough
+fn best_match(runs: &[usize], patterns: &[[u8; 4]], max_distance: f64) -> Option<usize> {
+    patterns
+        .iter()
+        .enumerate()
+        .filter_map(|(i, pattern)| Some((pattern_distance(runs, pattern, 0.7)?, i)))
+        .filter(|&(distance, _)| distance < max_distance)
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, i)| i)
+}
+
+// Whether a light run is wide enough to be the quiet zone of a barcode with
+// modules `unit` wide. The image edge counts as one.
+fn is_quiet(runs: &[usize], index: usize, modules: f64, unit: f64) -> bool {
+    index == 0 || index + 1 >= runs.len() || runs[index] as f64 >= modules * unit
+}
+
+// EAN/UPC digits as light-dark-light-dark module widths; the right half
+// uses the same widths dark-light-dark-light
+const DIGITS: [[u8; 4]; 10] = [
+    [3, 2, 1, 1],
+    [2, 2, 2, 1],
+    [2, 1, 2, 2],
+    [1, 4, 1, 1],
+    [1, 1, 3, 2],
+    [1, 2, 3, 1],
+    [1, 1, 1, 4],
+    [1, 3, 1, 2],
+    [1, 2, 1, 3],
+    [3, 1, 1, 2],
+];
+
+// Which left-half digits of an EAN-13 are mirrored, by the implied first
+// digit, as bits from the first digit down
+const FIRST_DIGIT_PARITY: [u8; 10] = [0x00, 0x0b, 0x0d, 0x0e, 0x13, 0x19, 0x1c, 0x15, 0x16, 0x1a];
+
+// Largest average deviation accepted for a digit or guard
+const MAX_EAN_DISTANCE: f64 = 0.48;
+
+// An EAN-13, UPC-A or EAN-8 barcode whose start guard is the dark run at
+// `start`. Returns it and the index of its end guard's last run.
+fn decode_ean(runs: &[usize], start: usize) -> Option<(DecodedCode, usize)> {
+    let guard = runs.get(start..start + 3)?;
+    if pattern_distance(guard, &[1, 1, 1], 0.7)? >= MAX_EAN_DISTANCE {
+        return None;
+    }
+    let unit = guard.iter().sum::<usize>() as f64 / 3.0;
+    if !is_quiet(runs, start - 1, 3.0, unit) {
+        return None;
+    }
+    decode_ean_digits(runs, start, 6).or_else(|| decode_ean_digits(runs, start, 4))
+}
+
+fn decode_ean_digits(runs: &[usize], start: usize, half: usize) -> Option<(DecodedCode, usize)> {
+    let mirrored: Vec<[u8; 4]> = DIGITS.iter().map(|d| [d[3], d[2], d[1], d[0]]).collect();
+    let mut digits = Vec::with_capacity(2 * half + 1);
+    let mut parity = 0u8;
+    let mut i = start + 3;
+
+    for _ in 0..half {
+        let digit = runs.get(i..i + 4)?;
+        let plain = best_match(digit, &DIGITS, MAX_EAN_DISTANCE);
+        // EAN-13 mirrors some left digits to encode the first digit
+        let mirror = if half == 6 { best_match(digit, &mirrored, MAX_EAN_DISTANCE) } else { None };
+        let (value, is_mirrored) = match (plain, mirror) {
+            (Some(p), Some(m)) => {
+                let distance = |pattern: &[u8; 4]| pattern_distance(digit, pattern, 0.7).unwrap_or(f64::MAX);
+                if distance(&DIGITS[p]) <= distance(&mirrored[m]) { (p, false) } else { (m, true) }
+            }
+            (Some(p), None) => (p, false),
+            (None, Some(m)) => (m, true),
+            (None, None) => return None,
+        };
+        digits.push(value as u8);
+        parity = (parity << 1) | is_mirrored as u8;
+        i += 4;
+    }
+
+    if pattern_distance(runs.get(i..i + 5)?, &[1, 1, 1, 1, 1], 0.7)? >= MAX_EAN_DISTANCE {
+        return None;
+    }
+    i += 5;
+    for _ in 0..half {
+        digits.push(best_match(runs.get(i..i + 4)?, &DIGITS, MAX_EAN_DISTANCE)? as u8);
+        i += 4;
+    }
+    if pattern_distance(runs.get(i..i + 3)?, &[1, 1, 1], 0.7)? >= MAX_EAN_DISTANCE {
+        return None;
+    }
+    let end = i + 2;
+
+    // The symbol is 95 (EAN-13) or 67 (EAN-8) modules wide
+    let modules = if half == 6 { 95.0 } else { 67.0 };
+    let unit = runs[start..=end].iter().sum::<usize>() as f64 / modules;
+    if !is_quiet(runs, end + 1, 3.0, unit) {
+        return None;
+    }
+
+    let format = if half == 6 {
+        let first = FIRST_DIGIT_PARITY.iter().position(|&p| p == parity)?;
+        digits.insert(0, first as u8);
+        if first == 0 { CodeFormat::UpcA } else { CodeFormat::Ean13 }
+    } else {
+        CodeFormat::Ean8
+    };
+    if !has_valid_check_digit(&digits) {
+        return None;
+    }
+
+    // UPC-A is EAN-13 with a leading zero, which it doesn't print
+    let shown = if format == CodeFormat::UpcA { &digits[1..] } else { &digits[..] };
+    let text = shown.iter().map(|d| char::from(b'0' + d)).collect();
+    Some((DecodedCode { format, text }, end))
+}
+
+// The last digit is 10 minus the sum of the others weighted 3, 1, 3, ...
+// from the right, modulo 10
+fn has_valid_check_digit(digits: &[u8]) -> bool {
+    let Some((&check, body)) = digits.split_last() else {
+        return false;
+    };
+    let sum: u32 = body.iter().rev().enumerate().map(|(i, &d)| d as u32 * if i % 2 == 0 { 3 } else { 1 }).sum();
+    (10 - sum % 10) % 10 == check as u32
+}
+
+// Code 128 symbols as dark-light-dark-light-dark-light module widths, by
+// value. 103 to 105 start code sets A, B and C; 106 is the stop code,
+// which has a final two-module bar.
+const CODE128: [[u8; 6]; 107] = [
+    [2, 1, 2, 2, 2, 2], [2, 2, 2, 1, 2, 2], [2, 2, 2, 2, 2, 1], [1, 2, 1, 2, 2, 3], [1, 2, 1, 3, 2, 2],
+    [1, 3, 1, 2, 2, 2], [1, 2, 2, 2, 1, 3], [1, 2, 2, 3, 1, 2], [1, 3, 2, 2, 1, 2], [2, 2, 1, 2, 1, 3],
+    [2, 2, 1, 3, 1, 2], [2, 3, 1, 2, 1, 2], [1, 1, 2, 2, 3, 2], [1, 2, 2, 1, 3, 2], [1, 2, 2, 2, 3, 1],
+    [1, 1, 3, 2, 2, 2], [1, 2, 3, 1, 2, 2], [1, 2, 3, 2, 2, 1], [2, 2, 3, 2, 1, 1], [2, 2, 1, 1, 3, 2],
+    [2, 2, 1, 2, 3, 1], [2, 1, 3, 2, 1, 2], [2, 2, 3, 1, 1, 2], [3, 1, 2, 1, 3, 1], [3, 1, 1, 2, 2, 2],
+    [3, 2, 1, 1, 2, 2], [3, 2, 1, 2, 2, 1], [3, 1, 2, 2, 1, 2], [3, 2, 2, 1, 1, 2], [3, 2, 2, 2, 1, 1],
+    [2, 1, 2, 1, 2, 3], [2, 1, 2, 3, 2, 1], [2, 3, 2, 1, 2, 1], [1, 1, 1, 3, 2, 3], [1, 3, 1, 1, 2, 3],
+    [1, 3, 1, 3, 2, 1], [1, 1, 2, 3, 1, 3], [1, 3, 2, 1, 1, 3], [1, 3, 2, 3, 1, 1], [2, 1, 1, 3, 1, 3],
+    [2, 3, 1, 1, 1, 3], [2, 3, 1, 3, 1, 1], [1, 1, 2, 1, 3, 3], [1, 1, 2, 3, 3, 1], [1, 3, 2, 1, 3, 1],
+    [1, 1, 3, 1, 2, 3], [1, 1, 3, 3, 2, 1], [1, 3, 3, 1, 2, 1], [3, 1, 3, 1, 2, 1], [2, 1, 1, 3, 3, 1],
+    [2, 3, 1, 1, 3, 1], [2, 1, 3, 1, 1, 3], [2, 1, 3, 3, 1, 1], [2, 1, 3, 1, 3, 1], [3, 1, 1, 1, 2, 3],
+    [3, 1, 1, 3, 2, 1], [3, 3, 1, 1, 2, 1], [3, 1, 2, 1, 1, 3], [3, 1, 2, 3, 1, 1], [3, 3, 2, 1, 1, 1],
+    [3, 1, 4, 1, 1, 1], [2, 2, 1, 4, 1, 1], [4, 3, 1, 1, 1, 1], [1, 1, 1, 2, 2, 4], [1, 1, 1, 4, 2, 2],
+    [1, 2, 1, 1, 2, 4], [1, 2, 1, 4, 2, 1], [1, 4, 1, 1, 2, 2], [1, 4, 1, 2, 2, 1], [1, 1, 2, 2, 1, 4],
+    [1, 1, 2, 4, 1, 2], [1, 2, 2, 1, 1, 4], [1, 2, 2, 4, 1, 1], [1, 4, 2, 1, 1, 2], [1, 4, 2, 2, 1, 1],
+    [2, 4, 1, 2, 1, 1], [2, 2, 1, 1, 1, 4], [4, 1, 3, 1, 1, 1], [2, 4, 1, 1, 1, 2], [1, 3, 4, 1, 1, 1],
+    [1, 1, 1, 2, 4, 2], [1, 2, 1, 1, 4, 2], [1, 2, 1, 2, 4, 1], [1, 1, 4, 2, 1, 2], [1, 2, 4, 1, 1, 2],
+    [1, 2, 4, 2, 1, 1], [4, 1, 1, 2, 1, 2], [4, 2, 1, 1, 1, 2], [4, 2, 1, 2, 1, 1], [2, 1, 2, 1, 4, 1],
+    [2, 1, 4, 1, 2, 1], [4, 1, 2, 1, 2, 1], [1, 1, 1, 1, 4, 3], [1, 1, 1, 3, 4, 1], [1, 3, 1, 1, 4, 1],
+    [1, 1, 4, 1, 1, 3], [1, 1, 4, 3, 1, 1], [4, 1, 1, 1, 1, 3], [4, 1, 1, 3, 1, 1], [1, 1, 3, 1, 4, 1],
+    [1, 1, 4, 1, 3, 1], [3, 1, 1, 1, 4, 1], [4, 1, 1, 1, 3, 1], [2, 1, 1, 4, 1, 2], [2, 1, 1, 2, 1, 4],
+    [2, 1, 1, 2, 3, 2], [2, 3, 3, 1, 1, 1],
+];
+
+const CODE128_STOP: usize = 106;
+
+// Largest average deviation accepted for a Code 128 symbol
+const MAX_CODE128_DISTANCE: f64 = 0.25;
+
+#[derive(Clone, Copy, PartialEq)]
+enum CodeSet {
+    A,
+    B,
+    C,
+}
+
+fn code128_symbol(runs: &[usize]) -> Option<usize> {
+    CODE128
+        .iter()
+        .enumerate()
+        .filter_map(|(i, pattern)| Some((pattern_distance(runs, pattern, 0.7)?, i)))
+        .filter(|&(distance, _)| distance < MAX_CODE128_DISTANCE)
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, i)| i)
+}
+
+// A Code 128 barcode whose start code begins with the dark run at `start`.
+// Returns it and the index of its final bar.
+fn decode_code128(runs: &[usize], start: usize) -> Option<(DecodedCode, usize)> {
+    let first = code128_symbol(runs.get(start..start + 6)?)?;
+    if !(103..=105).contains(&first) {
+        return None;
+    }
+    let unit = runs[start..start + 6].iter().sum::<usize>() as f64 / 11.0;
+    if !is_quiet(runs, start - 1, 5.0, unit) {
+        return None;
+    }
+
+    let mut values = vec![first];
+    let mut i = start + 6;
+    loop {
+        let value = code128_symbol(runs.get(i..i + 6)?)?;
+        i += 6;
+        if value == CODE128_STOP {
+            break;
+        }
+        if (103..=105).contains(&value) {
+            return None;
+        }
+        values.push(value);
+    }
+    // The stop code ends with a two-module bar, then the quiet zone
+    if (*runs.get(i)? as f64 - 2.0 * unit).abs() > unit || !is_quiet(runs, i + 1, 5.0, unit) {
+        return None;
+    }
+
+    // Start code, at least one symbol, check symbol
+    let (&check, body) = values.split_last()?;
+    if body.len() < 2 {
+        return None;
+    }
+    let sum = body.iter().enumerate().map(|(position, &value)| position.max(1) * value).sum::<usize>();
+    if sum % 103 != check {
+        return None;
+    }
+
+    let text = code128_text(body)?;
+    Some((DecodedCode { format: CodeFormat::Code128, text }, i))
+}
+
+// Translate Code 128 values, starting with the start code, into text. FNC1
+// after the first position becomes the GS1 group separator.
+fn code128_text(values: &[usize]) -> Option<String> {
+    let mut set = match values[0] {
+        103 => CodeSet::A,
+        104 => CodeSet::B,
+        _ => CodeSet::C,
+    };
+    let mut text = String::new();
+    let mut shifted = false;
+
+    for (position, &value) in values.iter().enumerate().skip(1) {
+        // A shift switches between sets A and B for one character
+        let current = match (shifted, set) {
+            (true, CodeSet::A) => CodeSet::B,
+            (true, CodeSet::B) => CodeSet::A,
+            _ => set,
+        };
+        shifted = false;
+        match (current, value) {
+            (CodeSet::C, 0..=99) => text.push_str(&format!("{:02}", value)),
+            (CodeSet::A, 0..=63) | (CodeSet::B, 0..=95) => text.push(char::from(value as u8 + 32)),
+            (CodeSet::A, 64..=95) => text.push(char::from(value as u8 - 64)),
+            (_, 102) => {
+                if position > 1 {
+                    text.push('\u{1d}');
+                }
+            }
+            (CodeSet::A | CodeSet::B, 98) => shifted = true,
+            (CodeSet::A | CodeSet::B, 99) => set = CodeSet::C,
+            (CodeSet::A | CodeSet::C, 100) => set = CodeSet::B,
+            (CodeSet::B | CodeSet::C, 101) => set = CodeSet::A,
+            // FNC2, FNC3 and FNC4 carry no text here
+            (CodeSet::A | CodeSet::B, 96 | 97) | (CodeSet::B, 100) | (CodeSet::A, 101) => {}
+            _ => return None,
+        }
+    }
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{imageops, GrayImage, Luma};
+
+    use super::*;
+    use crate::scan::scan;
+
+    // Module widths of an EAN-13, EAN-8 or (with a leading zero) UPC-A
+    // barcode, starting with a bar
+    fn ean(digits: &str) -> Vec<u8> {
+        let digits: Vec<usize> = digits.bytes().map(|b| (b - b'0') as usize).collect();
+        let (parity, encoded) = match digits.len() {
+            13 => (FIRST_DIGIT_PARITY[digits[0]], &digits[1..]),
+            _ => (0, &digits[..]),
+        };
+        let half = encoded.len() / 2;
+        let mut widths = vec![1, 1, 1];
+        for (i, &digit) in encoded[..half].iter().enumerate() {
+            let mut pattern = DIGITS[digit];
+            if parity >> (5 - i) & 1 == 1 {
+                pattern.reverse();
+            }
+            widths.extend(pattern);
+        }
+        widths.extend([1, 1, 1, 1, 1]);
+        for &digit in &encoded[half..] {
+            widths.extend(DIGITS[digit]);
+        }
+        widths.extend([1, 1, 1]);
+        widths
+    }
+
+    // Module widths of a Code 128 barcode from its values, starting with
+    // the start code; the check symbol and stop code are added
+    fn code128(values: &[usize]) -> Vec<u8> {
+        let check = values.iter().enumerate().map(|(position, &value)| position.max(1) * value).sum::<usize>() % 103;
+        let mut widths: Vec<u8> = values.iter().chain([check, CODE128_STOP].iter()).flat_map(|&value| CODE128[value]).collect();
+        widths.push(2);
+        widths
+    }
+
+    // Draw bars two pixels per module with a twelve-module quiet zone
+    fn draw(widths: &[u8]) -> GrayImage {
+        let mut dark = vec![false; 12];
+        for (i, &width) in widths.iter().enumerate() {
+            dark.extend(std::iter::repeat_n(i % 2 == 0, width as usize));
+        }
+        dark.extend([false; 12]);
+        GrayImage::from_fn(dark.len() as u32 * 2, 70, |x, _| Luma([if dark[x as usize / 2] { 0 } else { 255 }]))
+    }
+
+    fn code_set_b(text: &str) -> Vec<usize> {
+        std::iter::once(104).chain(text.bytes().map(|b| (b - 32) as usize)).collect()
+    }
+
+    // The codes found in the image and in it turned and mirrored every way
+    fn assert_reads(img: &GrayImage, expected: DecodedCode) {
+        for (name, turned) in [
+            ("upright", img.clone()),
+            ("rotated 90", imageops::rotate90(img)),
+            ("rotated 180", imageops::rotate180(img)),
+            ("rotated 270", imageops::rotate270(img)),
+            ("mirrored", imageops::flip_horizontal(img)),
+            ("mirrored and rotated 90", imageops::rotate90(&imageops::flip_horizontal(img))),
+        ] {
+            assert_eq!(scan(&turned), std::slice::from_ref(&expected), "{} {}", expected.text, name);
+        }
+    }
+
+    #[test]
+    fn ean_and_upc() {
+        for (digits, format, text) in [
+            ("4006381333931", CodeFormat::Ean13, "4006381333931"),
+            ("9780201379624", CodeFormat::Ean13, "9780201379624"),
+            ("96385074", CodeFormat::Ean8, "96385074"),
+            ("0036000291452", CodeFormat::UpcA, "036000291452"),
+        ] {
+            assert_reads(&draw(&ean(digits)), DecodedCode { format, text: text.to_string() });
+        }
+    }
+
+    #[test]
+    fn ean_with_wrong_check_digit() {
+        assert!(scan(&draw(&ean("4006381333932"))).is_empty());
+        assert!(has_valid_check_digit(&[4, 0, 0, 6, 3, 8, 1, 3, 3, 3, 9, 3, 1]));
+        assert!(!has_valid_check_digit(&[9, 6, 3, 8, 5, 0, 7, 5]));
+        assert!(!has_valid_check_digit(&[]));
+    }
+
+    #[test]
+    fn code128_sets() {
+        let cases = [
+            (code_set_b("Hello-128!"), "Hello-128!"),
+            (vec![105, 12, 34, 56, 78, 90, 12], "123456789012"),
+            (vec![103, 40, 37, 44, 44, 47, 73], "HELLO\t"),
+            // B, then C for the digits, then back to B
+            (vec![104, 33, 34, 99, 12, 34, 100, 88], "AB1234x"),
+            // A shifted lowercase letter in set A
+            (vec![103, 33, 98, 66, 33], "AbA"),
+            // GS1-128: FNC1 first, then as a group separator
+            (vec![105, 102, 1, 23, 45, 67, 102, 10, 12], "01234567\u{1d}1012"),
+        ];
+        for (values, text) in cases {
+            assert_reads(&draw(&code128(&values)), DecodedCode { format: CodeFormat::Code128, text: text.to_string() });
+        }
+    }
+
+    #[test]
+    fn code128_with_wrong_check_symbol() {
+        let mut widths = code128(&code_set_b("Hello"));
+        // Swap the check symbol for another value's pattern
+        let check = widths.len() - 13;
+        widths[check..check + 6].copy_from_slice(&CODE128[0]);
+        assert!(scan(&draw(&widths)).is_empty());
+    }
+}
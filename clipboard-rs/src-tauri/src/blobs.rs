@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use sha2::{Digest, Sha256};
+
+use crate::db::HistoryDb;
+use crate::thumbnails;
+use crate::vault::Vault;
+
+/// Image blobs stored in the app data directory under their content hash, so
+/// copying the same image twice keeps one file. The history database counts
+/// how many items point at each blob; `collect_garbage` deletes the ones no
+/// item uses any more.
+///
+/// Thumbnails of each blob are cached in a sibling directory under the
+/// blob's name (see `thumbnail_path`) and deleted along with it.
+pub struct BlobStore {
+    dir: PathBuf,
+    thumbnail_dir: PathBuf,
+    // Blobs written but not yet referenced by an item. The collector leaves
+    // these alone so it can't delete a file out from under an insert.
+    pending: Mutex<HashSet<PathBuf>>,
+}
+
+/// A freshly stored blob. Garbage collection skips it until this is dropped,
+/// which should happen after the item pointing at it has been inserted.
+pub struct PendingBlob<'a> {
+    store: &'a BlobStore,
+    path: PathBuf,
+}
+
+impl PendingBlob<'_> {
+    pub fn path(&self) -> String {
+        self.path.to_string_lossy().to_string()
+    }
+}
+
+impl Drop for PendingBlob<'_> {
+    fn drop(&mut self) {
+        self.store.pending().remove(&self.path);
+    }
+}
+
+impl BlobStore {
+    pub fn open(data_dir: &Path) -> Result<Self, String> {
+        let dir = data_dir.join("blobs");
+        let thumbnail_dir = data_dir.join("thumbnails");
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        fs::create_dir_all(&thumbnail_dir).map_err(|e| e.to_string())?;
+        Ok(BlobStore { dir, thumbnail_dir, pending: Mutex::new(HashSet::new()) })
+    }
+
+    fn pending(&self) -> MutexGuard<'_, HashSet<PathBuf>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether `path` is a blob in this store, as opposed to a file written
+    /// by an older version or referenced by an imported item.
+    pub fn contains(&self, path: &str) -> bool {
+        Path::new(path).parent() == Some(self.dir.as_path())
+    }
+
+    /// Store a blob named `name` (see `address`) unless it already exists.
+    /// `contents` produces the file's bytes and only runs if it's missing.
+    pub fn put(&self, name: &str, contents: impl FnOnce() -> Result<Vec<u8>, String>) -> Result<PendingBlob<'_>, String> {
+        let path = self.dir.join(name);
+        let mut pending = self.pending();
+        if !path.exists() {
+            // Write to a temporary name first so a crash never leaves a
+            // truncated file under a valid content address
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, contents()?).map_err(|e| e.to_string())?;
+            fs::rename(&tmp_path, &path).map_err(|e| e.to_string())?;
+        }
+        pending.insert(path.clone());
+        Ok(PendingBlob { store: self, path })
+    }
+
+    /// Where the thumbnail of the blob at `blob_path` that fits within
+    /// `size` pixels is cached: `<content address>-<size>.thumb`, with an
+    /// `.enc` suffix if the blob is sealed. `None` for blobs outside the
+    /// store, whose names aren't content addresses.
+    pub fn thumbnail_path(&self, blob_path: &str, size: u32) -> Option<PathBuf> {
+        if !self.contains(blob_path) {
+            return None;
+        }
+        let name = Path::new(blob_path).file_name()?.to_string_lossy().to_string();
+        let (name, sealed) = match name.strip_suffix(&format!(".{}", crate::vault::BLOB_EXTENSION)) {
+            Some(plain) => (plain.to_string(), true),
+            None => (name, false),
+        };
+        let stem = name.split('.').next().unwrap_or_default();
+        let mut thumbnail = format!("{}-{}.{}", stem, size, thumbnails::EXTENSION);
+        if sealed {
+            thumbnail = format!("{}.{}", thumbnail, crate::vault::BLOB_EXTENSION);
+        }
+        Some(self.thumbnail_dir.join(thumbnail))
+    }
+
+    /// Cached thumbnails of the blob at `blob_path` that exist on disk.
+    pub fn thumbnails_of(&self, blob_path: &str) -> Vec<PathBuf> {
+        thumbnails::SIZES
+            .iter()
+            .filter_map(|&size| self.thumbnail_path(blob_path, size))
+            .filter(|path| path.exists())
+            .collect()
+    }
+
+    /// Cache a thumbnail, replacing any older copy.
+    pub fn put_thumbnail(&self, path: &Path, bytes: &[u8]) -> Result<(), String> {
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, bytes).map_err(|e| e.to_string())?;
+        fs::rename(&tmp_path, path).map_err(|e| e.to_string())
+    }
+
+    /// Delete blobs whose last item was removed, and their thumbnails.
+    /// Returns how many blobs were deleted.
+    pub fn collect_garbage(&self, db: &HistoryDb) -> Result<usize, String> {
+        let unreferenced = db.unreferenced_blobs().map_err(|e| e.to_string())?;
+        let pending = self.pending();
+        let mut removed = 0;
+        for path in unreferenced {
+            if self.contains(&path) && !pending.contains(Path::new(&path)) && fs::remove_file(&path).is_ok() {
+                for thumbnail in self.thumbnails_of(&path) {
+                    let _ = fs::remove_file(thumbnail);
+                }
+                removed += 1;
+            }
+            db.forget_blob(&path).map_err(|e| e.to_string())?;
+        }
+        Ok(removed)
+    }
+
+    /// Delete files in the store that no item references, such as blobs
+    /// left behind by a crash or by an encrypted history whose last changes
+    /// were never saved, along with thumbnails of blobs that are gone. Only
+    /// run this while the history is unlocked.
+    pub fn sweep(&self, db: &HistoryDb) -> Result<usize, String> {
+        let referenced: HashSet<PathBuf> = db
+            .blob_paths()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        let pending = self.pending();
+        let mut removed = 0;
+        for entry in fs::read_dir(&self.dir).map_err(|e| e.to_string())? {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if !referenced.contains(&path) && !pending.contains(&path) && fs::remove_file(&path).is_ok() {
+                removed += 1;
+            }
+        }
+
+        // Thumbnails are only kept for blobs that are
+        let kept: HashSet<PathBuf> = referenced
+            .iter()
+            .chain(pending.iter())
+            .flat_map(|blob| thumbnails::SIZES.map(|size| self.thumbnail_path(&blob.to_string_lossy(), size)))
+            .flatten()
+            .collect();
+        for entry in fs::read_dir(&self.thumbnail_dir).map_err(|e| e.to_string())? {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if !kept.contains(&path) {
+                let _ = fs::remove_file(&path);
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Move blobs that live outside the store, like the temp files older
+    /// versions wrote, into it and repoint their items. Blobs that have
+    /// already disappeared are left as they are.
+    pub fn adopt(&self, db: &HistoryDb, vault: &Vault) -> Result<usize, String> {
+        let mut adopted = 0;
+        for old_path in db.blob_paths().map_err(|e| e.to_string())? {
+            if self.contains(&old_path) || !Path::new(&old_path).is_file() {
+                continue;
+            }
+            let bytes = vault.read_blob(&old_path)?;
+            let blob = vault.write_blob(self, &bytes, &plain_extension(&old_path))?;
+            db.replace_blob_path(&old_path, &blob.path()).map_err(|e| e.to_string())?;
+
+            // Only remove files we wrote ourselves; imported items may point
+            // anywhere
+            if Path::new(&old_path).starts_with(std::env::temp_dir()) {
+                let _ = fs::remove_file(&old_path);
+            }
+            adopted += 1;
+        }
+        Ok(adopted)
+    }
+}
+
+/// File name for a blob: the hex SHA-256 of its plain bytes, prefixed with
+/// `key` when one is given so encrypted blob names don't reveal which
+/// images are stored, followed by the extension.
+pub fn address(bytes: &[u8], key: Option<&[u8]>, extension: &str) -> String {
+    let mut hasher = Sha256::new();
+    if let Some(key) = key {
+        hasher.update(key);
+    }
+    hasher.update(bytes);
+    format!("{:x}.{}", hasher.finalize(), sanitize_extension(extension))
+}
+
+/// Extension of the image inside a blob, ignoring the `.enc` suffix of
+/// sealed blobs.
+pub fn plain_extension(path: &str) -> String {
+    let path = Path::new(path);
+    let path = match path.extension() {
+        Some(ext) if ext == crate::vault::BLOB_EXTENSION => Path::new(path.file_stem().unwrap_or_default()),
+        _ => path,
+    };
+    path.extension().unwrap_or_default().to_string_lossy().to_string()
+}
+
+// Keep extensions to lowercase alphanumerics so a copied file's name can't
+// escape the store
+fn sanitize_extension(extension: &str) -> String {
+    let extension: String = extension
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    if extension.is_empty() { "bin".to_string() } else { extension }
+}
@@ -0,0 +1,568 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tauri_plugin_opener::OpenerExt;
+use uuid::Uuid;
+
+use crate::db::HistoryDb;
+use crate::item::ClipboardPayload;
+use crate::transform::Transform;
+
+// Longer clips are left unclassified rather than scanned on every capture
+const MAX_CLASSIFY_LEN: usize = 256 * 1024;
+
+static UUID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?i)\{?[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\}?$").unwrap()
+});
+static EMAIL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?i)(?:mailto:)?[a-z0-9._%+-]+@[a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,}$").unwrap()
+});
+static HEX_COLOR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^#([0-9a-fA-F]{3,4}|[0-9a-fA-F]{6}|[0-9a-fA-F]{8})$").unwrap());
+// `rgb(1, 2, 3)`, `rgba(1 2 3 / 50%)`, `hsl(120, 50%, 40%)`, ...
+static FUNCTION_COLOR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?i)(rgba?|hsla?)\(\s*([\d.]+%?)(?:deg)?\s*[,\s]\s*([\d.]+%?)\s*[,\s]\s*([\d.]+%?)\s*(?:[,/]\s*([\d.]+%?)\s*)?\)$",
+    )
+    .unwrap()
+});
+static PATH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^(?:(?:~|\.{1,2})?/[^/*\s]|[A-Za-z]:\\|\\\\[^\\\s]+\\)[^\n"<>|]*$"#).unwrap());
+static PHONE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\+?[\d\s()-]+$").unwrap());
+static ISO_DATE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap());
+
+// Telltale substrings per language. Code is recognized when one language has
+// at least two of its markers, and that language is the guess.
+const LANGUAGES: &[(&str, &[&str])] = &[
+    ("rust", &["fn ", "let mut ", "impl ", "pub fn ", "::", "-> ", "#[derive", "println!", "&mut ", "match "]),
+    ("python", &["def ", "import ", "self.", "elif ", "print(", "__init__", "None", "lambda ", "):\n"]),
+    ("typescript", &["interface ", ": string", ": number", "as const", "export type ", ": boolean", "readonly "]),
+    ("javascript", &["const ", "function ", "=> ", "console.log", "require(", "document.", "===", "export default"]),
+    ("go", &["func ", "package ", ":= ", "fmt.", "go func", "chan ", "defer "]),
+    ("java", &["public class ", "public static void", "System.out", "private ", "@Override", "import java."]),
+    ("cpp", &["#include", "std::", "int main(", "printf(", "nullptr", "cout <<", "->"]),
+    ("shell", &["#!/bin/", "echo ", "sudo ", "$(", "| grep", "export ", "\nfi", "apt ", "${"]),
+    ("sql", &["select ", " from ", " where ", "insert into", "update ", "create table", " join ", "group by"]),
+    ("html", &["<div", "</", "<html", "<!doctype", "class=\"", "<span", "href="]),
+    ("css", &["px;", "color:", "margin:", "padding:", "display:", "font-", "@media"]),
+];
+
+/// What a text clip looks like, detected when it is captured.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentType {
+    Url,
+    Email,
+    Path,
+    /// A hex, `rgb()` or `hsl()` color.
+    Color,
+    Json,
+    Uuid,
+    Phone,
+    /// Source code, with the language it most looks like.
+    Code { language: Option<String> },
+}
+
+impl ContentType {
+    /// Every type name, as stored and as accepted by search filters.
+    pub const NAMES: [&'static str; 8] = ["url", "email", "path", "color", "json", "uuid", "phone", "code"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContentType::Url => "url",
+            ContentType::Email => "email",
+            ContentType::Path => "path",
+            ContentType::Color => "color",
+            ContentType::Json => "json",
+            ContentType::Uuid => "uuid",
+            ContentType::Phone => "phone",
+            ContentType::Code { .. } => "code",
+        }
+    }
+
+    /// Rebuild a type from its stored name and code language.
+    pub fn from_name(name: &str, language: Option<String>) -> Option<Self> {
+        Some(match name {
+            "url" => ContentType::Url,
+            "email" => ContentType::Email,
+            "path" => ContentType::Path,
+            "color" => ContentType::Color,
+            "json" => ContentType::Json,
+            "uuid" => ContentType::Uuid,
+            "phone" => ContentType::Phone,
+            "code" => ContentType::Code { language },
+            _ => return None,
+        })
+    }
+
+    pub fn language(&self) -> Option<&str> {
+        match self {
+            ContentType::Code { language } => language.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Actions offered for items of this type.
+    pub fn actions(&self) -> &'static [ItemAction] {
+        match self {
+            ContentType::Url => &[ItemAction::OpenUrl],
+            ContentType::Email => &[ItemAction::ComposeEmail],
+            ContentType::Path => &[ItemAction::OpenPath, ItemAction::RevealPath],
+            ContentType::Color => &[ItemAction::CopyAsHex, ItemAction::CopyAsRgb, ItemAction::CopyAsHsl],
+            ContentType::Json => &[ItemAction::ValidateJson, ItemAction::FormatJson, ItemAction::MinifyJson],
+            ContentType::Uuid => &[ItemAction::CopyUppercase, ItemAction::CopyWithoutHyphens],
+            ContentType::Phone => &[ItemAction::Call],
+            ContentType::Code { .. } => &[ItemAction::CopyAsMarkdown],
+        }
+    }
+}
+
+/// Classify the text of a text or rich text payload. Images and file lists
+/// have no content type.
+pub fn classify_payload(payload: &ClipboardPayload) -> Option<ContentType> {
+    match payload {
+        ClipboardPayload::Text { text } | ClipboardPayload::RichText { text, .. } => classify(text),
+        _ => None,
+    }
+}
+
+/// Work out what `text` is, checking the narrowest types first.
+pub fn classify(text: &str) -> Option<ContentType> {
+    let text = text.trim();
+    if text.is_empty() || text.len() > MAX_CLASSIFY_LEN {
+        return None;
+    }
+    let single_line = !text.contains('\n');
+
+    if single_line && is_url(text) {
+        Some(ContentType::Url)
+    } else if single_line && EMAIL.is_match(text) {
+        Some(ContentType::Email)
+    } else if UUID.is_match(text) {
+        Some(ContentType::Uuid)
+    } else if parse_color(text).is_some() {
+        Some(ContentType::Color)
+    } else if text.starts_with(['{', '[']) && serde_json::from_str::<serde_json::Value>(text).is_ok() {
+        Some(ContentType::Json)
+    } else if single_line && PATH.is_match(text) {
+        Some(ContentType::Path)
+    } else if is_phone(text) {
+        Some(ContentType::Phone)
+    } else {
+        code_language(text).map(|language| ContentType::Code { language })
+    }
+}
+
+fn is_url(text: &str) -> bool {
+    if text.contains(char::is_whitespace) {
+        return false;
+    }
+    if let Some(rest) = text.strip_prefix("www.") {
+        return rest.contains('.');
+    }
+    url::Url::parse(text).is_ok_and(|url| {
+        matches!(url.scheme(), "http" | "https" | "ftp" | "ftps" | "ws" | "wss") && url.host().is_some()
+    })
+}
+
+// 7 to 15 digits with the usual separators. A leading `+` or a separator is
+// required so bare numbers like timestamps don't count.
+fn is_phone(text: &str) -> bool {
+    let digits = text.chars().filter(char::is_ascii_digit).count();
+    PHONE.is_match(text)
+        && (7..=15).contains(&digits)
+        && (text.starts_with('+') || text.contains([' ', '-', '(']))
+        && !ISO_DATE.is_match(text)
+}
+
+// `Some(language)` if the text looks like code, with the language guess if
+// one stands out
+fn code_language(text: &str) -> Option<Option<String>> {
+    let lower = text.to_lowercase();
+    let symbols = text.chars().filter(|c| "{}[]();=<>*".contains(*c)).count();
+    let indented = text.lines().skip(1).any(|line| line.starts_with("    ") || line.starts_with('\t'));
+    if symbols < 2 && !indented {
+        return None;
+    }
+
+    let (language, score) = LANGUAGES
+        .iter()
+        .map(|(language, markers)| {
+            // SQL keywords are case-insensitive; the rest are matched as written
+            let haystack = if *language == "sql" { &lower } else { text };
+            (*language, markers.iter().filter(|marker| haystack.contains(*marker)).count())
+        })
+        .fold(("", 0), |best, candidate| if candidate.1 > best.1 { candidate } else { best });
+    if score >= 2 {
+        return Some(Some(language.to_string()));
+    }
+
+    // Several lines mostly ending in `;`, `{` or `}` look like code even
+    // without known keywords
+    let lines: Vec<&str> = text.lines().map(str::trim_end).filter(|line| !line.is_empty()).collect();
+    let terminated = lines.iter().filter(|line| line.ends_with([';', '{', '}'])).count();
+    (lines.len() >= 3 && terminated * 2 >= lines.len()).then_some(None)
+}
+
+/// A color as 8-bit RGB channels and an alpha between 0 and 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: f64,
+}
+
+/// Parse `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`, `rgb()`, `rgba()`,
+/// `hsl()` or `hsla()`.
+pub fn parse_color(text: &str) -> Option<Color> {
+    let text = text.trim();
+    if let Some(caps) = HEX_COLOR.captures(text) {
+        let hex = &caps[1];
+        let digits: Vec<u8> = if hex.len() <= 4 {
+            hex.chars().map(|c| c.to_digit(16).map(|d| d as u8 * 17)).collect::<Option<_>>()?
+        } else {
+            (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect::<Option<_>>()?
+        };
+        return Some(Color {
+            r: digits[0],
+            g: digits[1],
+            b: digits[2],
+            a: digits.get(3).map_or(1.0, |&a| a as f64 / 255.0),
+        });
+    }
+
+    let caps = FUNCTION_COLOR.captures(text)?;
+    let alpha = match caps.get(5) {
+        Some(a) => component(a.as_str(), 1.0)?.clamp(0.0, 1.0),
+        None => 1.0,
+    };
+    if caps[1].to_lowercase().starts_with("rgb") {
+        let channel = |i: usize| component(&caps[i], 255.0).map(|v| v.round().clamp(0.0, 255.0) as u8);
+        Some(Color { r: channel(2)?, g: channel(3)?, b: channel(4)?, a: alpha })
+    } else {
+        let h: f64 = caps[2].parse().ok()?;
+        let s = component(&caps[3], 100.0)? / 100.0;
+        let l = component(&caps[4], 100.0)? / 100.0;
+        let (r, g, b) = hsl_to_rgb(h, s.clamp(0.0, 1.0), l.clamp(0.0, 1.0));
+        Some(Color { r, g, b, a: alpha })
+    }
+}
+
+// A number, or a percentage of `full`
+fn component(value: &str, full: f64) -> Option<f64> {
+    match value.strip_suffix('%') {
+        Some(percent) => percent.parse::<f64>().ok().map(|p| p / 100.0 * full),
+        None => value.parse().ok(),
+    }
+}
+
+fn hsl_to_rgb(h: f64, s: f64, l: f64) -> (u8, u8, u8) {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c / 2.0;
+    let to_byte = |v: f64| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    (to_byte(r), to_byte(g), to_byte(b))
+}
+
+impl Color {
+    pub fn to_hex(self) -> String {
+        let hex = format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b);
+        if self.a < 1.0 {
+            format!("{}{:02x}", hex, (self.a * 255.0).round() as u8)
+        } else {
+            hex
+        }
+    }
+
+    pub fn to_rgb(self) -> String {
+        if self.a < 1.0 {
+            format!("rgba({}, {}, {}, {})", self.r, self.g, self.b, round2(self.a))
+        } else {
+            format!("rgb({}, {}, {})", self.r, self.g, self.b)
+        }
+    }
+
+    pub fn to_hsl(self) -> String {
+        let (r, g, b) = (self.r as f64 / 255.0, self.g as f64 / 255.0, self.b as f64 / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let l = (max + min) / 2.0;
+        let d = max - min;
+        let (h, s) = if d == 0.0 {
+            (0.0, 0.0)
+        } else {
+            let s = d / (1.0 - (2.0 * l - 1.0).abs());
+            let h = if max == r {
+                60.0 * ((g - b) / d).rem_euclid(6.0)
+            } else if max == g {
+                60.0 * ((b - r) / d + 2.0)
+            } else {
+                60.0 * ((r - g) / d + 4.0)
+            };
+            (h, s)
+        };
+        let (h, s, l) = (h.round(), (s * 100.0).round(), (l * 100.0).round());
+        if self.a < 1.0 {
+            format!("hsla({}, {}%, {}%, {})", h, s, l, round2(self.a))
+        } else {
+            format!("hsl({}, {}%, {}%)", h, s, l)
+        }
+    }
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Something that can be done with an item of a given content type.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemAction {
+    OpenUrl,
+    ComposeEmail,
+    OpenPath,
+    RevealPath,
+    CopyAsHex,
+    CopyAsRgb,
+    CopyAsHsl,
+    ValidateJson,
+    FormatJson,
+    MinifyJson,
+    CopyUppercase,
+    CopyWithoutHyphens,
+    Call,
+    CopyAsMarkdown,
+}
+
+impl ItemAction {
+    pub fn label(self) -> &'static str {
+        match self {
+            ItemAction::OpenUrl => "Open in browser",
+            ItemAction::ComposeEmail => "Compose email",
+            ItemAction::OpenPath => "Open",
+            ItemAction::RevealPath => "Show in folder",
+            ItemAction::CopyAsHex => "Copy as hex",
+            ItemAction::CopyAsRgb => "Copy as RGB",
+            ItemAction::CopyAsHsl => "Copy as HSL",
+            ItemAction::ValidateJson => "Validate JSON",
+            ItemAction::FormatJson => "Copy formatted",
+            ItemAction::MinifyJson => "Copy minified",
+            ItemAction::CopyUppercase => "Copy in uppercase",
+            ItemAction::CopyWithoutHyphens => "Copy without hyphens",
+            ItemAction::Call => "Call",
+            ItemAction::CopyAsMarkdown => "Copy as Markdown code block",
+        }
+    }
+
+    /// Run the action on `text`. Returns the text to put on the clipboard,
+    /// if the action produces any.
+    fn run(self, app: &tauri::AppHandle, text: &str, content_type: &ContentType) -> Result<Option<String>, String> {
+        let text = text.trim();
+        let converted = |convert: fn(Color) -> String| {
+            parse_color(text).map(|color| Some(convert(color))).ok_or_else(|| "Not a color".to_string())
+        };
+        match self {
+            ItemAction::OpenUrl => {
+                let url = if text.starts_with("www.") { format!("https://{}", text) } else { text.to_string() };
+                app.opener().open_url(url, None::<&str>).map_err(|e| e.to_string())?;
+                Ok(None)
+            }
+            ItemAction::ComposeEmail => {
+                let address = text.strip_prefix("mailto:").unwrap_or(text);
+                app.opener().open_url(format!("mailto:{}", address), None::<&str>).map_err(|e| e.to_string())?;
+                Ok(None)
+            }
+            ItemAction::OpenPath => {
+                app.opener().open_path(expand_home(text), None::<&str>).map_err(|e| e.to_string())?;
+                Ok(None)
+            }
+            ItemAction::RevealPath => {
+                app.opener().reveal_item_in_dir(expand_home(text)).map_err(|e| e.to_string())?;
+                Ok(None)
+            }
+            ItemAction::CopyAsHex => converted(Color::to_hex),
+            ItemAction::CopyAsRgb => converted(Color::to_rgb),
+            ItemAction::CopyAsHsl => converted(Color::to_hsl),
+            ItemAction::ValidateJson => {
+                serde_json::from_str::<serde_json::Value>(text).map_err(|e| format!("Invalid JSON: {}", e))?;
+                Ok(None)
+            }
+            ItemAction::FormatJson => Transform::JsonPretty.apply(text).map(Some),
+            ItemAction::MinifyJson => Transform::JsonMinify.apply(text).map(Some),
+            ItemAction::CopyUppercase => Ok(Some(text.to_uppercase())),
+            ItemAction::CopyWithoutHyphens => Ok(Some(text.replace('-', ""))),
+            ItemAction::Call => {
+                let number: String = text.chars().filter(|c| c.is_ascii_digit() || *c == '+').collect();
+                app.opener().open_url(format!("tel:{}", number), None::<&str>).map_err(|e| e.to_string())?;
+                Ok(None)
+            }
+            ItemAction::CopyAsMarkdown => {
+                Ok(Some(format!("```{}\n{}\n```", content_type.language().unwrap_or(""), text)))
+            }
+        }
+    }
+}
+
+// Resolve a leading `~/` against the home directory
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home, rest),
+        _ => path.to_string(),
+    }
+}
+
+#[derive(Serialize)]
+pub struct ActionInfo {
+    action: ItemAction,
+    label: &'static str,
+}
+
+// Function to list the actions available for an item's content type
+#[tauri::command]
+pub fn get_item_actions(db: tauri::State<'_, HistoryDb>, id: Uuid) -> Result<Vec<ActionInfo>, String> {
+    let item = db.get(id).map_err(|e| e.to_string())?.ok_or("Item not found")?;
+    Ok(item
+        .content_type
+        .map(|content_type| content_type.actions())
+        .unwrap_or_default()
+        .iter()
+        .map(|&action| ActionInfo { action, label: action.label() })
+        .collect())
+}
+
+// Function to run an action on an item. Actions that produce text put it on
+// the clipboard and return it.
+#[tauri::command]
+pub fn run_item_action(
+    app: tauri::AppHandle,
+    db: tauri::State<'_, HistoryDb>,
+    id: Uuid,
+    action: ItemAction,
+) -> Result<Option<String>, String> {
+    let item = db.get(id).map_err(|e| e.to_string())?.ok_or("Item not found")?;
+    let content_type = item.content_type.ok_or("The item has no content type")?;
+    if !content_type.actions().contains(&action) {
+        return Err(format!("'{}' doesn't apply to this item", action.label()));
+    }
+    let text = action.run(&app, &item.payload.search_text(), &content_type)?;
+    if let Some(text) = &text {
+        crate::write_clipboard_text(app, text, None, None)?;
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color(r: u8, g: u8, b: u8, a: f64) -> Option<Color> {
+        Some(Color { r, g, b, a })
+    }
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(parse_color("#fff"), color(255, 255, 255, 1.0));
+        assert_eq!(parse_color("#1e90FF"), color(30, 144, 255, 1.0));
+        assert_eq!(parse_color(" #0f08 "), color(0, 255, 0, 136.0 / 255.0));
+        assert_eq!(parse_color("#ff000080"), color(255, 0, 0, 128.0 / 255.0));
+        for text in ["#ff", "#fffff", "#fffffff", "#ggg", "fff", "#fff;"] {
+            assert_eq!(parse_color(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn parses_function_colors() {
+        assert_eq!(parse_color("rgb(30, 144, 255)"), color(30, 144, 255, 1.0));
+        assert_eq!(parse_color("RGBA(30 144 255 / 50%)"), color(30, 144, 255, 0.5));
+        assert_eq!(parse_color("rgba(100%, 0%, 50%, 0.25)"), color(255, 0, 128, 0.25));
+        assert_eq!(parse_color("rgb(300, 0, 0)"), color(255, 0, 0, 1.0));
+        assert_eq!(parse_color("rgba(0, 0, 0, 2)"), color(0, 0, 0, 1.0));
+        assert_eq!(parse_color("hsl(120, 100%, 25%)"), color(0, 128, 0, 1.0));
+        assert_eq!(parse_color("hsla(210deg 50% 40% / 0.5)"), color(51, 102, 153, 0.5));
+        for text in ["rgb(1, 2)", "rgb(1, 2, 3, 4, 5)", "cmyk(1, 2, 3, 4)", "rgb(a, b, c)", "rgb(1.2.3, 0, 0)"] {
+            assert_eq!(parse_color(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn converts_hsl_to_rgb() {
+        assert_eq!(hsl_to_rgb(0.0, 1.0, 0.5), (255, 0, 0));
+        assert_eq!(hsl_to_rgb(120.0, 1.0, 0.5), (0, 255, 0));
+        assert_eq!(hsl_to_rgb(240.0, 1.0, 0.5), (0, 0, 255));
+        assert_eq!(hsl_to_rgb(60.0, 1.0, 0.5), (255, 255, 0));
+        assert_eq!(hsl_to_rgb(300.0, 1.0, 0.5), (255, 0, 255));
+        assert_eq!(hsl_to_rgb(0.0, 0.0, 0.5), (128, 128, 128));
+        assert_eq!(hsl_to_rgb(200.0, 0.3, 1.0), (255, 255, 255));
+        assert_eq!(hsl_to_rgb(200.0, 0.3, 0.0), (0, 0, 0));
+        // Hues wrap around the circle
+        assert_eq!(hsl_to_rgb(360.0, 1.0, 0.5), (255, 0, 0));
+        assert_eq!(hsl_to_rgb(-120.0, 1.0, 0.5), (0, 0, 255));
+    }
+
+    #[test]
+    fn colors_round_trip_through_every_notation() {
+        let original = parse_color("#3366cc").unwrap();
+        for text in [original.to_hex(), original.to_rgb(), original.to_hsl()] {
+            assert_eq!(parse_color(&text), Some(original), "{}", text);
+        }
+        let translucent = Color { a: 0.5, ..original };
+        assert_eq!(translucent.to_hex(), "#3366cc80");
+        assert_eq!(translucent.to_rgb(), "rgba(51, 102, 204, 0.5)");
+        assert_eq!(translucent.to_hsl(), "hsla(220, 60%, 50%, 0.5)");
+    }
+
+    #[test]
+    fn recognizes_phone_numbers() {
+        for text in ["+44 20 7946 0958", "(555) 123-4567", "555-1234", "+4915112345678", "030 1234567"] {
+            assert!(is_phone(text), "{}", text);
+        }
+        // A bare number like a timestamp, a date, too few or too many digits, letters
+        for text in ["1700000000", "2024-01-15", "123-45", "+1 234 567 890 123 456", "555-CALL-NOW", "1.5 + 2"] {
+            assert!(!is_phone(text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn guesses_code_languages() {
+        let guess = |text: &str| code_language(text).map(|language| language.unwrap_or_default());
+        assert_eq!(guess("fn main() {\n    let mut x = 1;\n    println!(\"{}\", x);\n}").as_deref(), Some("rust"));
+        assert_eq!(guess("def greet(name):\n    print(f\"hi {name}\")\n    return None").as_deref(), Some("python"));
+        assert_eq!(guess("SELECT id FROM users WHERE age > 21;").as_deref(), Some("sql"));
+        assert_eq!(guess("func main() {\n\tx := 1\n\tfmt.Println(x)\n}").as_deref(), Some("go"));
+        assert_eq!(guess(".card { color: red; padding: 4px; }").as_deref(), Some("css"));
+        // Code-shaped without any language standing out
+        assert_eq!(guess("a = b;\nc = d;\nwhile (x) {\n}").as_deref(), Some(""));
+        // Prose, and prose that mentions a keyword or two
+        assert_eq!(guess("Meet me at the station at noon."), None);
+        assert_eq!(guess("Let's match the import numbers."), None);
+    }
+
+    #[test]
+    fn classifies_text() {
+        let kind = |text: &str| classify(text).map(|content_type| content_type.name());
+        assert_eq!(kind("https://example.com/a?b=c"), Some("url"));
+        assert_eq!(kind("www.example.com"), Some("url"));
+        assert_eq!(kind("mailto:someone@example.org"), Some("email"));
+        assert_eq!(kind("{123e4567-e89b-12d3-a456-426614174000}"), Some("uuid"));
+        assert_eq!(kind("#abcdef"), Some("color"));
+        assert_eq!(kind(r#"{"a": [1, 2]}"#), Some("json"));
+        assert_eq!(kind("~/Documents/notes.txt"), Some("path"));
+        assert_eq!(kind(r"C:\Users\me\file.txt"), Some("path"));
+        assert_eq!(kind("+1 (555) 123-4567"), Some("phone"));
+        assert_eq!(kind("const x = require('y');\nconsole.log(x);"), Some("code"));
+        assert_eq!(kind("   "), None);
+        assert_eq!(kind("just some words"), None);
+        assert_eq!(kind("javascript:alert(1)"), None);
+    }
+}
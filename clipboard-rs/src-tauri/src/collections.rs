@@ -0,0 +1,226 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::archive;
+use crate::db::HistoryDb;
+use crate::history::History;
+use crate::item::{now_secs, ClipboardItem};
+use crate::search::SearchFilters;
+use crate::vault::Vault;
+
+/// A named set of history items in an order the user picks, like a board
+/// for release notes or SQL snippets. Collected items are never evicted by
+/// the retention policy. Deleting an item takes it out of its collections
+/// once it leaves the trash; restoring it puts it back in place.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Collection {
+    pub id: Uuid,
+    /// Unique, ignoring case.
+    pub name: String,
+    pub created_at: u64,
+    /// Members in order.
+    pub item_ids: Vec<Uuid>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionStatistics {
+    pub total_items: usize,
+    pub favorite_items: usize,
+    /// Members in the trash, which come back if restored.
+    pub trashed_items: usize,
+    pub text_items: usize,
+    pub image_items: usize,
+    pub file_list_items: usize,
+    pub rich_text_items: usize,
+    /// Stored size of the items, counting image blobs.
+    pub total_bytes: u64,
+    pub top_tags: Vec<(String, usize)>,
+    pub earliest_timestamp: u64,
+    pub latest_timestamp: u64,
+}
+
+// A collection name without surrounding whitespace. Fails if it's empty or
+// another collection already has it.
+fn check_name(db: &HistoryDb, name: &str, renaming: Option<Uuid>) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Collection name can't be empty".to_string());
+    }
+    let taken = db
+        .collections(false)
+        .map_err(|e| e.to_string())?
+        .iter()
+        .any(|collection| collection.name.to_lowercase() == name.to_lowercase() && Some(collection.id) != renaming);
+    if taken {
+        return Err(format!("A collection named \"{}\" already exists", name));
+    }
+    Ok(name.to_string())
+}
+
+// A collection as it is after a change, for the command's reply
+fn find(db: &HistoryDb, id: Uuid) -> Result<Collection, String> {
+    db.collections(false)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .find(|collection| collection.id == id)
+        .ok_or_else(|| "Collection not found".to_string())
+}
+
+/// Merge collections from an archive into the history once their items
+/// were inserted. Members are matched to the history's items by ID or
+/// content. A collection whose name is taken adds its missing members to
+/// the end of that one.
+pub fn merge(db: &HistoryDb, items: &[ClipboardItem], collections: &[Collection]) -> rusqlite::Result<()> {
+    let resolved = db.resolve_ids(items)?;
+    for imported in collections {
+        let item_ids: Vec<Uuid> = imported.item_ids.iter().filter_map(|id| resolved.get(id).copied()).collect();
+        let existing = db.collections(true)?;
+        match existing.iter().find(|collection| collection.name.to_lowercase() == imported.name.to_lowercase()) {
+            Some(collection) => {
+                db.add_to_collection(collection.id, &item_ids)?;
+            }
+            None => {
+                let id_taken = existing.iter().any(|collection| collection.id == imported.id);
+                db.create_collection(&Collection {
+                    id: if id_taken { Uuid::new_v4() } else { imported.id },
+                    name: imported.name.clone(),
+                    created_at: imported.created_at,
+                    item_ids,
+                })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Function to list the collections by name, with their items in order
+#[tauri::command]
+pub fn get_collections(db: tauri::State<'_, HistoryDb>) -> Result<Vec<Collection>, String> {
+    db.collections(false).map_err(|e| e.to_string())
+}
+
+// Function to create a collection, optionally starting with some items
+#[tauri::command]
+pub fn create_collection(
+    history: tauri::State<'_, History>,
+    name: String,
+    item_ids: Option<Vec<Uuid>>,
+) -> Result<Collection, String> {
+    history.edit_collections(move |db| {
+        let collection = Collection {
+            id: Uuid::new_v4(),
+            name: check_name(db, &name, None)?,
+            created_at: now_secs(),
+            item_ids: item_ids.unwrap_or_default(),
+        };
+        db.create_collection(&collection).map_err(|e| e.to_string())?;
+        find(db, collection.id)
+    })
+}
+
+// Function to turn a tag into a collection of the items carrying it, newest
+// first. The collection is named after the tag unless `name` is given; the
+// tag stays on the items.
+#[tauri::command]
+pub fn create_collection_from_tag(
+    history: tauri::State<'_, History>,
+    tag: String,
+    name: Option<String>,
+) -> Result<Collection, String> {
+    history.edit_collections(move |db| {
+        let filters = SearchFilters { tag: Some(tag.clone()), ..Default::default() };
+        let items = db.search_candidates(None, &filters).map_err(|e| e.to_string())?;
+        if items.is_empty() {
+            return Err(format!("No items are tagged \"{}\"", tag));
+        }
+        let collection = Collection {
+            id: Uuid::new_v4(),
+            name: check_name(db, name.as_deref().unwrap_or(&tag), None)?,
+            created_at: now_secs(),
+            item_ids: items.iter().map(|item| item.id).collect(),
+        };
+        db.create_collection(&collection).map_err(|e| e.to_string())?;
+        find(db, collection.id)
+    })
+}
+
+// Function to rename a collection
+#[tauri::command]
+pub fn rename_collection(history: tauri::State<'_, History>, id: Uuid, name: String) -> Result<(), String> {
+    history.edit_collections(move |db| {
+        let name = check_name(db, &name, Some(id))?;
+        if !db.rename_collection(id, &name).map_err(|e| e.to_string())? {
+            return Err("Collection not found".to_string());
+        }
+        Ok(())
+    })
+}
+
+// Function to delete a collection. Its items stay in the history, where the
+// retention policy applies to them again.
+#[tauri::command]
+pub fn delete_collection(history: tauri::State<'_, History>, id: Uuid) -> Result<(), String> {
+    history.edit_collections(move |db| {
+        if !db.delete_collection(id).map_err(|e| e.to_string())? {
+            return Err("Collection not found".to_string());
+        }
+        Ok(())
+    })
+}
+
+// Function to add items to the end of a collection. Returns how many were
+// added; items already in it are skipped.
+#[tauri::command]
+pub fn add_to_collection(history: tauri::State<'_, History>, id: Uuid, item_ids: Vec<Uuid>) -> Result<usize, String> {
+    history.edit_collections(move |db| {
+        db.add_to_collection(id, &item_ids)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "Collection not found".to_string())
+    })
+}
+
+// Function to take items out of a collection without deleting them
+#[tauri::command]
+pub fn remove_from_collection(
+    history: tauri::State<'_, History>,
+    id: Uuid,
+    item_ids: Vec<Uuid>,
+) -> Result<usize, String> {
+    history.edit_collections(move |db| {
+        db.remove_from_collection(id, &item_ids)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "Collection not found".to_string())
+    })
+}
+
+// Function to put a collection's items in a new order. Items left out of
+// `item_ids` keep their order after the listed ones.
+#[tauri::command]
+pub fn reorder_collection(history: tauri::State<'_, History>, id: Uuid, item_ids: Vec<Uuid>) -> Result<(), String> {
+    history.edit_collections(move |db| {
+        if !db.reorder_collection(id, &item_ids).map_err(|e| e.to_string())? {
+            return Err("Collection not found".to_string());
+        }
+        Ok(())
+    })
+}
+
+// Function to get counts for one collection's items
+#[tauri::command]
+pub fn get_collection_statistics(db: tauri::State<'_, HistoryDb>, id: Uuid) -> Result<CollectionStatistics, String> {
+    db.collection_statistics(id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Collection not found".to_string())
+}
+
+// Function to export one collection and its items, images included, as a
+// zip archive that `import_history_archive` reads
+#[tauri::command]
+pub fn export_collection_archive(
+    db: tauri::State<'_, HistoryDb>,
+    vault: tauri::State<'_, Vault>,
+    id: Uuid,
+) -> Result<tauri::ipc::Response, String> {
+    archive::export(&db, &vault, Some(id)).map(tauri::ipc::Response::new)
+}
@@ -0,0 +1,1734 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use rusqlite::{params, Connection, OptionalExtension, Params, Row};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::classify::{self, ContentType};
+use crate::collections::{Collection, CollectionStatistics};
+use crate::imagehash;
+use crate::item::{ClipboardItem, ClipboardPayload};
+use crate::retention::{Eviction, EvictionReason, RetentionPolicy};
+use crate::search::SearchFilters;
+use crate::source::SourceApp;
+use crate::trash::{BulkOperation, LastOperation, TrashedItem};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS items (
+        id           TEXT PRIMARY KEY,
+        kind         TEXT NOT NULL,
+        payload      TEXT NOT NULL,
+        search_text  TEXT NOT NULL,
+        content_hash TEXT NOT NULL,
+        timestamp    INTEGER NOT NULL,
+        is_favorite  INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX IF NOT EXISTS items_timestamp ON items(timestamp DESC);
+    CREATE INDEX IF NOT EXISTS items_kind ON items(kind);
+    CREATE INDEX IF NOT EXISTS items_content_hash ON items(content_hash);
+
+    CREATE TABLE IF NOT EXISTS item_tags (
+        item_id TEXT NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+        tag     TEXT NOT NULL,
+        PRIMARY KEY (item_id, tag)
+    );
+    CREATE INDEX IF NOT EXISTS item_tags_tag ON item_tags(tag);
+
+    -- Trigram tokens give the same substring semantics the old in-memory
+    -- filter had, but backed by an index.
+    CREATE VIRTUAL TABLE IF NOT EXISTS items_fts USING fts5(
+        search_text,
+        content = 'items',
+        content_rowid = 'rowid',
+        tokenize = 'trigram'
+    );
+    CREATE TRIGGER IF NOT EXISTS items_fts_insert AFTER INSERT ON items BEGIN
+        INSERT INTO items_fts(rowid, search_text) VALUES (new.rowid, new.search_text);
+    END;
+    CREATE TRIGGER IF NOT EXISTS items_fts_delete AFTER DELETE ON items BEGIN
+        INSERT INTO items_fts(items_fts, rowid, search_text) VALUES ('delete', old.rowid, old.search_text);
+    END;
+    CREATE TRIGGER IF NOT EXISTS items_fts_update AFTER UPDATE OF search_text ON items BEGIN
+        INSERT INTO items_fts(items_fts, rowid, search_text) VALUES ('delete', old.rowid, old.search_text);
+        INSERT INTO items_fts(rowid, search_text) VALUES (new.rowid, new.search_text);
+    END;
+";
+
+// Schema changes applied on top of `SCHEMA`, in order. `PRAGMA user_version`
+// records how many have run, so append new steps and never edit old ones.
+const MIGRATIONS: &[&str] = &[
+    // 1: perceptual hashes for near-duplicate image detection
+    "ALTER TABLE items ADD COLUMN perceptual_hash INTEGER;",
+    // 2: expiry times for sensitive clips
+    "ALTER TABLE items ADD COLUMN expires_at INTEGER;
+     CREATE INDEX IF NOT EXISTS items_expires_at ON items(expires_at) WHERE expires_at IS NOT NULL;",
+    // 3: HTML items became rich text items that can also carry RTF
+    "UPDATE items SET kind = 'rich_text', payload = json_set(payload, '$.kind', 'rich_text') WHERE kind = 'html';",
+    // 4: reference counts for image blobs, kept up to date by triggers so
+    // every way of removing items is covered
+    "CREATE TABLE blob_refs (
+         path TEXT PRIMARY KEY,
+         refs INTEGER NOT NULL
+     );
+     CREATE TRIGGER items_blob_ref_insert AFTER INSERT ON items
+     WHEN json_extract(new.payload, '$.blob_path') IS NOT NULL BEGIN
+         INSERT OR IGNORE INTO blob_refs (path, refs) VALUES (json_extract(new.payload, '$.blob_path'), 0);
+         UPDATE blob_refs SET refs = refs + 1 WHERE path = json_extract(new.payload, '$.blob_path');
+     END;
+     CREATE TRIGGER items_blob_ref_delete AFTER DELETE ON items
+     WHEN json_extract(old.payload, '$.blob_path') IS NOT NULL BEGIN
+         UPDATE blob_refs SET refs = refs - 1 WHERE path = json_extract(old.payload, '$.blob_path');
+     END;
+     CREATE TRIGGER items_blob_ref_update AFTER UPDATE OF payload ON items
+     WHEN json_extract(old.payload, '$.blob_path') IS NOT json_extract(new.payload, '$.blob_path') BEGIN
+         UPDATE blob_refs SET refs = refs - 1 WHERE path = json_extract(old.payload, '$.blob_path');
+         INSERT OR IGNORE INTO blob_refs (path, refs)
+         SELECT json_extract(new.payload, '$.blob_path'), 0 WHERE json_extract(new.payload, '$.blob_path') IS NOT NULL;
+         UPDATE blob_refs SET refs = refs + 1 WHERE path = json_extract(new.payload, '$.blob_path');
+     END;
+     INSERT INTO blob_refs (path, refs)
+     SELECT json_extract(payload, '$.blob_path'), COUNT(*) FROM items
+     WHERE json_extract(payload, '$.blob_path') IS NOT NULL
+     GROUP BY 1;",
+    // 5: stored size of each item for the retention policy's byte limit.
+    // Left NULL here and filled in by `fill_missing_sizes`, which can read
+    // image blob sizes from disk.
+    "ALTER TABLE items ADD COLUMN size_bytes INTEGER;",
+    // 6: the window each clip was copied from. Its class and title are also
+    // appended to `search_text` so searches match them.
+    "ALTER TABLE items ADD COLUMN source_class TEXT;
+     ALTER TABLE items ADD COLUMN source_title TEXT;
+     CREATE INDEX IF NOT EXISTS items_source_class ON items(source_class) WHERE source_class IS NOT NULL;",
+    // 7: trash for removed items, holding on to their image blobs, and the
+    // most recent bulk operation for undo
+    "CREATE TABLE trash (
+         id         TEXT PRIMARY KEY,
+         item       TEXT NOT NULL,
+         deleted_at INTEGER NOT NULL,
+         reason     TEXT NOT NULL
+     );
+     CREATE INDEX trash_deleted_at ON trash(deleted_at);
+     CREATE TRIGGER trash_blob_ref_insert AFTER INSERT ON trash
+     WHEN json_extract(new.item, '$.payload.blob_path') IS NOT NULL BEGIN
+         INSERT OR IGNORE INTO blob_refs (path, refs) VALUES (json_extract(new.item, '$.payload.blob_path'), 0);
+         UPDATE blob_refs SET refs = refs + 1 WHERE path = json_extract(new.item, '$.payload.blob_path');
+     END;
+     CREATE TRIGGER trash_blob_ref_delete AFTER DELETE ON trash
+     WHEN json_extract(old.item, '$.payload.blob_path') IS NOT NULL BEGIN
+         UPDATE blob_refs SET refs = refs - 1 WHERE path = json_extract(old.item, '$.payload.blob_path');
+     END;
+     CREATE TRIGGER trash_blob_ref_update AFTER UPDATE OF item ON trash
+     WHEN json_extract(old.item, '$.payload.blob_path') IS NOT json_extract(new.item, '$.payload.blob_path') BEGIN
+         UPDATE blob_refs SET refs = refs - 1 WHERE path = json_extract(old.item, '$.payload.blob_path');
+         INSERT OR IGNORE INTO blob_refs (path, refs)
+         SELECT json_extract(new.item, '$.payload.blob_path'), 0 WHERE json_extract(new.item, '$.payload.blob_path') IS NOT NULL;
+         UPDATE blob_refs SET refs = refs + 1 WHERE path = json_extract(new.item, '$.payload.blob_path');
+     END;
+     CREATE TABLE last_operation (
+         id       INTEGER PRIMARY KEY CHECK (id = 1),
+         kind     TEXT NOT NULL,
+         item_ids TEXT NOT NULL,
+         at       INTEGER NOT NULL
+     );",
+    // 8: detected content type of text items, and the language guessed for
+    // code. Existing items are classified by `classify_existing`.
+    "ALTER TABLE items ADD COLUMN content_type TEXT;
+     ALTER TABLE items ADD COLUMN code_language TEXT;
+     CREATE INDEX IF NOT EXISTS items_content_type ON items(content_type) WHERE content_type IS NOT NULL;",
+    // 9: text recognized in images, also appended to `search_text`. The
+    // index finds images still waiting for OCR.
+    "ALTER TABLE items ADD COLUMN ocr_text TEXT;
+     CREATE INDEX IF NOT EXISTS items_ocr_pending ON items(timestamp) WHERE kind = 'image' AND ocr_text IS NULL;",
+    // 10: named collections of items in a chosen order. Members aren't tied
+    // to `items` by a foreign key so they survive a trip through the trash;
+    // the triggers drop them once an item is gone from both.
+    "CREATE TABLE collections (
+         id         TEXT PRIMARY KEY,
+         name       TEXT NOT NULL UNIQUE COLLATE NOCASE,
+         created_at INTEGER NOT NULL
+     );
+     CREATE TABLE collection_items (
+         collection_id TEXT NOT NULL REFERENCES collections(id) ON DELETE CASCADE,
+         item_id       TEXT NOT NULL,
+         position      INTEGER NOT NULL,
+         PRIMARY KEY (collection_id, item_id)
+     );
+     CREATE INDEX collection_items_item ON collection_items(item_id);
+     CREATE TRIGGER collection_items_item_delete AFTER DELETE ON items
+     WHEN NOT EXISTS (SELECT 1 FROM trash WHERE id = old.id) BEGIN
+         DELETE FROM collection_items WHERE item_id = old.id;
+     END;
+     CREATE TRIGGER collection_items_trash_delete AFTER DELETE ON trash
+     WHEN NOT EXISTS (SELECT 1 FROM items WHERE id = old.id) BEGIN
+         DELETE FROM collection_items WHERE item_id = old.id;
+     END;",
+];
+
+// Schema version that added `content_type`
+const CONTENT_TYPE_VERSION: usize = 8;
+
+// Conditions for `scan_candidates` and `count_candidates`: ?1 is an FTS
+// query, ?2 to ?7 the search filters and ?8 a LIKE pattern
+const CANDIDATE_FILTERS: &str = "
+    (?1 IS NULL OR items.rowid IN (SELECT rowid FROM items_fts WHERE items_fts MATCH ?1))
+    AND (?2 IS NULL OR items.content_type = ?2)
+    AND (?3 IS NULL OR items.is_favorite = ?3)
+    AND (?4 IS NULL OR items.timestamp >= ?4)
+    AND (?5 IS NULL OR items.timestamp <= ?5)
+    AND (?6 IS NULL OR items.id IN (SELECT item_id FROM item_tags WHERE tag = ?6))
+    AND (?7 IS NULL OR items.id IN (SELECT item_id FROM collection_items WHERE collection_id = ?7))
+    AND (?8 IS NULL OR items.search_text LIKE ?8 ESCAPE '\\')";
+
+/// Text a search candidate must have, checked against the item's text,
+/// source app and recognized text together. It only rules out items that
+/// can't match; `search` decides what actually does.
+pub enum TextFilter<'a> {
+    Any,
+    /// Contains this, ignoring case.
+    Contains(&'a str),
+    /// Contains these lowercase characters in order, with anything between.
+    Subsequence(&'a [char]),
+}
+
+impl TextFilter<'_> {
+    // The FTS query and LIKE pattern for the filter. Three characters or
+    // more can use the trigram index; shorter text and subsequences fall back
+    // to LIKE, which only ignores case for ASCII, so other text is left to
+    // `search`.
+    fn to_sql(&self) -> (Option<String>, Option<String>) {
+        match self {
+            TextFilter::Contains(text) if text.chars().count() >= 3 => {
+                (Some(format!("\"{}\"", text.replace('"', "\"\""))), None)
+            }
+            TextFilter::Contains(text) if !text.is_empty() && text.is_ascii() => {
+                (None, Some(like_pattern(std::iter::once(text.to_string()))))
+            }
+            TextFilter::Subsequence(chars) if !chars.is_empty() && chars.iter().all(char::is_ascii) => {
+                (None, Some(like_pattern(chars.iter().map(char::to_string))))
+            }
+            _ => (None, None),
+        }
+    }
+}
+
+// A LIKE pattern matching the parts in order with anything around them
+fn like_pattern(parts: impl Iterator<Item = String>) -> String {
+    let mut pattern = String::from("%");
+    for part in parts {
+        for c in part.chars() {
+            if matches!(c, '%' | '_' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        pattern.push('%');
+    }
+    pattern
+}
+
+// Columns selected for every item query, in the order `row_to_item` expects
+const ITEM_COLUMNS: &str = "
+    items.id, items.payload, items.timestamp, items.is_favorite,
+    (SELECT json_group_array(tag) FROM item_tags WHERE item_tags.item_id = items.id),
+    items.expires_at, items.source_class, items.source_title,
+    items.content_type, items.code_language, items.ocr_text
+";
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryStatistics {
+    pub total_items: usize,
+    pub favorite_items: usize,
+    pub text_items: usize,
+    pub image_items: usize,
+    pub file_list_items: usize,
+    pub rich_text_items: usize,
+    pub top_tags: Vec<(String, usize)>,
+    /// Collections holding the most items.
+    pub top_collections: Vec<(String, usize)>,
+    /// Window classes clips were copied from most often.
+    pub top_sources: Vec<(String, usize)>,
+    /// Item count per detected content type (`url`, `color`, `code`, ...).
+    pub content_types: BTreeMap<String, usize>,
+    /// Languages guessed for code items, most common first.
+    pub code_languages: Vec<(String, usize)>,
+    pub earliest_timestamp: u64,
+    pub latest_timestamp: u64,
+}
+
+/// SQLite-backed clipboard history with a full-text index over item text.
+///
+/// The history is either a database file, an in-memory database (while an
+/// encrypted history is unlocked, see `vault`), or locked, in which case
+/// every query fails.
+pub struct HistoryDb {
+    conn: Mutex<Option<Connection>>,
+}
+
+// Lock guard over an open connection
+struct ConnGuard<'a>(MutexGuard<'a, Option<Connection>>);
+
+impl Deref for ConnGuard<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.0.as_ref().expect("checked in HistoryDb::conn")
+    }
+}
+
+impl DerefMut for ConnGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.0.as_mut().expect("checked in HistoryDb::conn")
+    }
+}
+
+impl HistoryDb {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        Ok(HistoryDb { conn: Mutex::new(Some(connect(Some(path))?)) })
+    }
+
+    /// A history with no database behind it until `reopen` is called.
+    pub fn locked() -> Self {
+        HistoryDb { conn: Mutex::new(None) }
+    }
+
+    /// Swap in a fresh database holding `items` (newest first, as `list`
+    /// returns them), `trash` and `collections`: the file at `path`, or an
+    /// in-memory database when `path` is `None`. The previous database, if
+    /// any, is wiped.
+    pub fn reopen(
+        &self,
+        path: Option<&Path>,
+        items: &[ClipboardItem],
+        trash: &[TrashedItem],
+        collections: &[Collection],
+    ) -> rusqlite::Result<()> {
+        let mut conn = connect(path)?;
+        let tx = conn.transaction()?;
+        // Insert oldest first so items sharing a timestamp keep their order
+        for item in items.iter().rev() {
+            insert_item(&tx, item)?;
+        }
+        for trashed in trash {
+            insert_trashed(&tx, &trashed.item, trashed.deleted_at, &trashed.reason)?;
+        }
+        for collection in collections {
+            insert_collection(&tx, collection)?;
+        }
+        tx.commit()?;
+
+        let mut slot = self.slot();
+        if let Some(old) = slot.take() {
+            wipe(&old)?;
+        }
+        *slot = Some(conn);
+        Ok(())
+    }
+
+    /// Wipe and close the database. Queries fail until `reopen` is called.
+    pub fn lock(&self) -> rusqlite::Result<()> {
+        match self.slot().take() {
+            Some(conn) => wipe(&conn),
+            None => Ok(()),
+        }
+    }
+
+    /// Running count of rows changed since the database was opened, used to
+    /// tell whether it needs saving.
+    pub fn changes(&self) -> rusqlite::Result<u64> {
+        Ok(self.conn()?.total_changes())
+    }
+
+    fn slot(&self) -> MutexGuard<'_, Option<Connection>> {
+        // A panic while holding the lock can't leave SQLite in a bad state,
+        // so keep going with the inner connection
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn conn(&self) -> rusqlite::Result<ConnGuard<'_>> {
+        let slot = self.slot();
+        if slot.is_none() {
+            return Err(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_AUTH),
+                Some("clipboard history is locked".to_string()),
+            ));
+        }
+        Ok(ConnGuard(slot))
+    }
+
+    /// Insert many items in one transaction, skipping duplicates by ID or
+    /// content. Returns the IDs of the items added.
+    pub fn insert_many(&self, items: &[ClipboardItem]) -> rusqlite::Result<Vec<Uuid>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let mut added = Vec::new();
+        for item in items {
+            if insert_item(&tx, item)? {
+                added.push(item.id);
+            }
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Whether an image with the same pixels is already stored, or, when
+    /// `max_distance` is set, one whose perceptual hash is within that many
+    /// bits of `perceptual_hash`.
+    pub fn has_image(&self, pixel_hash: &str, perceptual_hash: u64, max_distance: Option<u32>) -> rusqlite::Result<bool> {
+        let conn = self.conn()?;
+        let exact = conn
+            .query_row("SELECT 1 FROM items WHERE content_hash = ?1 LIMIT 1", params![pixel_hash], |_| Ok(()))
+            .optional()?;
+        if exact.is_some() {
+            return Ok(true);
+        }
+
+        let Some(max_distance) = max_distance else {
+            return Ok(false);
+        };
+        let mut stmt = conn.prepare("SELECT perceptual_hash FROM items WHERE perceptual_hash IS NOT NULL")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let stored = row.get::<_, i64>(0)? as u64;
+            if imagehash::hamming_distance(stored, perceptual_hash) <= max_distance {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn get(&self, id: Uuid) -> rusqlite::Result<Option<ClipboardItem>> {
+        let conn = self.conn()?;
+        item_by_id(&conn, id)
+    }
+
+    /// Items newest first.
+    pub fn list(&self, limit: Option<usize>, offset: usize) -> rusqlite::Result<Vec<ClipboardItem>> {
+        let conn = self.conn()?;
+        let sql = format!(
+            "SELECT {} FROM items ORDER BY timestamp DESC, rowid DESC LIMIT ?1 OFFSET ?2",
+            ITEM_COLUMNS
+        );
+        let limit = limit.map(|l| l as i64).unwrap_or(-1);
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params![limit, offset as i64], row_to_item)?;
+        rows.collect()
+    }
+
+    /// Items matching `filters`, newest first or in the order of the
+    /// collection they're filtered by. `contains` narrows them further like
+    /// `TextFilter::Contains`.
+    pub fn search_candidates(&self, contains: Option<&str>, filters: &SearchFilters) -> rusqlite::Result<Vec<ClipboardItem>> {
+        let text = contains.map_or(TextFilter::Any, TextFilter::Contains);
+        let mut items = Vec::new();
+        self.scan_candidates(&text, filters, 0, None, |item| items.push(item))?;
+        Ok(items)
+    }
+
+    /// Hand the items matching `text` and `filters` to `visit` one at a
+    /// time, in `search_candidates` order, skipping `offset` of them and
+    /// stopping after `limit`. Rows are read as they're visited, so a search
+    /// over a large history never holds more than the hits it keeps.
+    pub fn scan_candidates(
+        &self,
+        text: &TextFilter,
+        filters: &SearchFilters,
+        offset: usize,
+        limit: Option<usize>,
+        mut visit: impl FnMut(ClipboardItem),
+    ) -> rusqlite::Result<()> {
+        let conn = self.conn()?;
+        let sql = format!(
+            "SELECT {} FROM items WHERE {}
+             ORDER BY (SELECT position FROM collection_items WHERE collection_id = ?7 AND item_id = items.id),
+                      items.timestamp DESC, items.rowid DESC
+             LIMIT ?9 OFFSET ?10",
+            ITEM_COLUMNS, CANDIDATE_FILTERS
+        );
+        let (fts_query, like_pattern) = text.to_sql();
+        let limit = limit.map(|l| l as i64).unwrap_or(-1);
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(params![
+            fts_query,
+            filters.content_type,
+            filters.favorite,
+            filters.since.map(|t| t as i64),
+            filters.until.map(|t| t as i64),
+            filters.tag,
+            filters.collection.map(|id| id.to_string()),
+            like_pattern,
+            limit,
+            offset as i64,
+        ])?;
+        while let Some(row) = rows.next()? {
+            visit(row_to_item(row)?);
+        }
+        Ok(())
+    }
+
+    /// How many items `scan_candidates` would visit without an offset or
+    /// limit.
+    pub fn count_candidates(&self, text: &TextFilter, filters: &SearchFilters) -> rusqlite::Result<usize> {
+        let conn = self.conn()?;
+        let sql = format!("SELECT COUNT(*) FROM items WHERE {}", CANDIDATE_FILTERS);
+        let (fts_query, like_pattern) = text.to_sql();
+        conn.query_row(
+            &sql,
+            params![
+                fts_query,
+                filters.content_type,
+                filters.favorite,
+                filters.since.map(|t| t as i64),
+                filters.until.map(|t| t as i64),
+                filters.tag,
+                filters.collection.map(|id| id.to_string()),
+                like_pattern,
+            ],
+            |row| row.get::<_, i64>(0).map(|count| count as usize),
+        )
+    }
+
+    pub fn favorites(&self) -> rusqlite::Result<Vec<ClipboardItem>> {
+        let conn = self.conn()?;
+        let sql = format!(
+            "SELECT {} FROM items WHERE is_favorite = 1 ORDER BY timestamp DESC",
+            ITEM_COLUMNS
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map([], row_to_item)?;
+        rows.collect()
+    }
+
+    /// The newest image item that hasn't been through OCR yet.
+    pub fn next_ocr_candidate(&self) -> rusqlite::Result<Option<ClipboardItem>> {
+        let conn = self.conn()?;
+        let sql = format!(
+            "SELECT {} FROM items WHERE kind = 'image' AND ocr_text IS NULL ORDER BY timestamp DESC LIMIT 1",
+            ITEM_COLUMNS
+        );
+        conn.query_row(&sql, [], row_to_item).optional()
+    }
+
+    /// How many image items are waiting for OCR.
+    pub fn pending_ocr(&self) -> rusqlite::Result<usize> {
+        let conn = self.conn()?;
+        conn.query_row("SELECT COUNT(*) FROM items WHERE kind = 'image' AND ocr_text IS NULL", [], |row| {
+            row.get::<_, i64>(0).map(|count| count as usize)
+        })
+    }
+
+    /// Store the text recognized in an item and add it to the full-text
+    /// index. Returns false if the item doesn't exist.
+    pub fn set_ocr_text(&self, id: Uuid, text: &str) -> rusqlite::Result<bool> {
+        let conn = self.conn()?;
+        let Some(mut item) = item_by_id(&conn, id)? else {
+            return Ok(false);
+        };
+        item.ocr_text = Some(text.to_string());
+        conn.execute(
+            "UPDATE items SET ocr_text = ?1, search_text = ?2 WHERE id = ?3",
+            params![text, indexed_text(&item), id.to_string()],
+        )?;
+        Ok(true)
+    }
+
+    /// Flip the favorite flag. Returns the new value, or `None` if the item
+    /// doesn't exist.
+    pub fn toggle_favorite(&self, id: Uuid) -> rusqlite::Result<Option<bool>> {
+        let conn = self.conn()?;
+        conn.query_row(
+            "UPDATE items SET is_favorite = 1 - is_favorite WHERE id = ?1 RETURNING is_favorite",
+            params![id.to_string()],
+            |row| row.get::<_, bool>(0),
+        )
+        .optional()
+    }
+
+    /// Returns false if the item doesn't exist.
+    pub fn add_tag(&self, id: Uuid, tag: &str) -> rusqlite::Result<bool> {
+        let conn = self.conn()?;
+        if !item_exists(&conn, id)? {
+            return Ok(false);
+        }
+        conn.execute(
+            "INSERT OR IGNORE INTO item_tags (item_id, tag) VALUES (?1, ?2)",
+            params![id.to_string(), tag],
+        )?;
+        Ok(true)
+    }
+
+    /// Returns false if the item doesn't exist.
+    pub fn remove_tag(&self, id: Uuid, tag: &str) -> rusqlite::Result<bool> {
+        let conn = self.conn()?;
+        if !item_exists(&conn, id)? {
+            return Ok(false);
+        }
+        conn.execute(
+            "DELETE FROM item_tags WHERE item_id = ?1 AND tag = ?2",
+            params![id.to_string(), tag],
+        )?;
+        Ok(true)
+    }
+
+    pub fn all_tags(&self) -> rusqlite::Result<Vec<String>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT DISTINCT tag FROM item_tags ORDER BY tag")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// Every collection by name. Members in the trash are listed only with
+    /// `with_trashed`, which a full copy of the history needs.
+    pub fn collections(&self, with_trashed: bool) -> rusqlite::Result<Vec<Collection>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, created_at, (
+                 SELECT json_group_array(item_id) FROM (
+                     SELECT item_id FROM collection_items
+                     WHERE collection_id = collections.id
+                     AND (?1 OR item_id IN (SELECT id FROM items))
+                     ORDER BY position
+                 )
+             )
+             FROM collections ORDER BY name COLLATE NOCASE",
+        )?;
+        let rows = stmt.query_map(params![with_trashed], |row| {
+            let id: String = row.get(0)?;
+            let item_ids: String = row.get(3)?;
+            Ok(Collection {
+                id: Uuid::parse_str(&id).map_err(|e| conversion_error(Box::new(e)))?,
+                name: row.get(1)?,
+                created_at: row.get::<_, i64>(2)? as u64,
+                item_ids: serde_json::from_str(&item_ids).map_err(|e| conversion_error(Box::new(e)))?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Add a collection with its members. Members that aren't in the history
+    /// are left out. Fails if the name is taken.
+    pub fn create_collection(&self, collection: &Collection) -> rusqlite::Result<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let item_ids = existing_ids(&tx, &collection.item_ids)?;
+        insert_collection(&tx, &Collection { item_ids, ..collection.clone() })?;
+        tx.commit()
+    }
+
+    /// Returns false if the collection doesn't exist. Fails if the name is
+    /// taken.
+    pub fn rename_collection(&self, id: Uuid, name: &str) -> rusqlite::Result<bool> {
+        let conn = self.conn()?;
+        let renamed = conn.execute("UPDATE collections SET name = ?1 WHERE id = ?2", params![name, id.to_string()])?;
+        Ok(renamed > 0)
+    }
+
+    /// Delete a collection, keeping its items in the history. Returns false
+    /// if it doesn't exist.
+    pub fn delete_collection(&self, id: Uuid) -> rusqlite::Result<bool> {
+        let conn = self.conn()?;
+        Ok(conn.execute("DELETE FROM collections WHERE id = ?1", params![id.to_string()])? > 0)
+    }
+
+    /// Append items to the end of a collection. Items already in it or not in
+    /// the history are skipped. Returns how many were added, or `None` if the
+    /// collection doesn't exist.
+    pub fn add_to_collection(&self, id: Uuid, item_ids: &[Uuid]) -> rusqlite::Result<Option<usize>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        if !collection_exists(&tx, id)? {
+            return Ok(None);
+        }
+        let added = append_members(&tx, id, &existing_ids(&tx, item_ids)?)?;
+        tx.commit()?;
+        Ok(Some(added))
+    }
+
+    /// Take items out of a collection, keeping them in the history. Returns
+    /// how many were removed, or `None` if the collection doesn't exist.
+    pub fn remove_from_collection(&self, id: Uuid, item_ids: &[Uuid]) -> rusqlite::Result<Option<usize>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        if !collection_exists(&tx, id)? {
+            return Ok(None);
+        }
+        let mut removed = 0;
+        for item_id in item_ids {
+            removed += tx.execute(
+                "DELETE FROM collection_items WHERE collection_id = ?1 AND item_id = ?2",
+                params![id.to_string(), item_id.to_string()],
+            )?;
+        }
+        tx.commit()?;
+        Ok(Some(removed))
+    }
+
+    /// Put a collection's members in the order of `item_ids`. Members left
+    /// out, such as ones in the trash, keep their order after the listed
+    /// ones; ids that aren't members are ignored. Returns false if the
+    /// collection doesn't exist.
+    pub fn reorder_collection(&self, id: Uuid, item_ids: &[Uuid]) -> rusqlite::Result<bool> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        if !collection_exists(&tx, id)? {
+            return Ok(false);
+        }
+        let current = query_ids(
+            &tx,
+            "SELECT item_id FROM collection_items WHERE collection_id = ?1 ORDER BY position",
+            params![id.to_string()],
+        )?;
+        let mut order: Vec<Uuid> = Vec::with_capacity(current.len());
+        for item_id in item_ids.iter().chain(&current) {
+            if current.contains(item_id) && !order.contains(item_id) {
+                order.push(*item_id);
+            }
+        }
+        for (position, item_id) in order.iter().enumerate() {
+            tx.execute(
+                "UPDATE collection_items SET position = ?1 WHERE collection_id = ?2 AND item_id = ?3",
+                params![position as i64, id.to_string(), item_id.to_string()],
+            )?;
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Match items from elsewhere, such as an imported archive, to the
+    /// history: the item with the same ID, or else one with the same
+    /// content. Returns their IDs here keyed by their own; items with
+    /// neither are left out.
+    pub fn resolve_ids(&self, items: &[ClipboardItem]) -> rusqlite::Result<HashMap<Uuid, Uuid>> {
+        let conn = self.conn()?;
+        let mut resolved = HashMap::with_capacity(items.len());
+        for item in items {
+            let id: Option<String> = conn
+                .query_row(
+                    "SELECT id FROM items WHERE id = ?1 OR content_hash = ?2 ORDER BY id = ?1 DESC LIMIT 1",
+                    params![item.id.to_string(), item.payload.content_hash()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(id) = id {
+                resolved.insert(item.id, Uuid::parse_str(&id).map_err(|e| conversion_error(Box::new(e)))?);
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Counts for one collection's items in the history, or `None` if the
+    /// collection doesn't exist.
+    pub fn collection_statistics(&self, id: Uuid) -> rusqlite::Result<Option<CollectionStatistics>> {
+        let conn = self.conn()?;
+        if !collection_exists(&conn, id)? {
+            return Ok(None);
+        }
+        let members = "SELECT item_id FROM collection_items WHERE collection_id = ?1";
+
+        let (total_items, favorite_items, total_bytes, earliest_timestamp, latest_timestamp) = conn.query_row(
+            &format!(
+                "SELECT COUNT(*), COALESCE(SUM(is_favorite), 0), COALESCE(SUM(size_bytes), 0),
+                        COALESCE(MIN(timestamp), 0), COALESCE(MAX(timestamp), 0)
+                 FROM items WHERE id IN ({})",
+                members
+            ),
+            params![id.to_string()],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)? as usize,
+                    row.get::<_, i64>(1)? as usize,
+                    row.get::<_, i64>(2)? as u64,
+                    row.get::<_, i64>(3)? as u64,
+                    row.get::<_, i64>(4)? as u64,
+                ))
+            },
+        )?;
+        let trashed_items = conn.query_row(
+            &format!("SELECT COUNT(*) FROM trash WHERE id IN ({})", members),
+            params![id.to_string()],
+            |row| row.get::<_, i64>(0).map(|count| count as usize),
+        )?;
+
+        let mut stmt = conn.prepare(&format!("SELECT kind, COUNT(*) FROM items WHERE id IN ({}) GROUP BY kind", members))?;
+        let kinds = stmt
+            .query_map(params![id.to_string()], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize)))?
+            .collect::<rusqlite::Result<BTreeMap<_, _>>>()?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT tag, COUNT(*) AS uses FROM item_tags WHERE item_id IN ({})
+             GROUP BY tag ORDER BY uses DESC, tag LIMIT 5",
+            members
+        ))?;
+        let top_tags = stmt
+            .query_map(params![id.to_string()], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(Some(CollectionStatistics {
+            total_items,
+            favorite_items,
+            trashed_items,
+            text_items: kinds.get("text").copied().unwrap_or(0),
+            image_items: kinds.get("image").copied().unwrap_or(0),
+            file_list_items: kinds.get("file_list").copied().unwrap_or(0),
+            rich_text_items: kinds.get("rich_text").copied().unwrap_or(0),
+            total_bytes,
+            top_tags,
+            earliest_timestamp,
+            latest_timestamp,
+        }))
+    }
+
+    /// Move an item to the trash. Returns false if the item doesn't exist.
+    pub fn delete(&self, id: Uuid, now: u64) -> rusqlite::Result<bool> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let moved = move_to_trash(&tx, &[id], now, "deleted")?;
+        tx.commit()?;
+        Ok(!moved.is_empty())
+    }
+
+    /// Move every item to the trash, recording it as the last bulk
+    /// operation.
+    pub fn clear(&self, now: u64) -> rusqlite::Result<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let ids = query_ids(&tx, "SELECT id FROM items", [])?;
+        let moved = move_to_trash(&tx, &ids, now, "cleared")?;
+        record_operation(&tx, BulkOperation::Clear, &moved, now)?;
+        tx.commit()
+    }
+
+    /// Move whatever `policy` no longer allows to the trash, in one
+    /// transaction: items past a tag TTL, items past their kind's age limit,
+    /// then the oldest items beyond the count and byte limits. Items in a
+    /// collection are left alone and don't count toward the limits. Trash older
+    /// than `trash_retention_seconds` is deleted for good. With `undoable`,
+    /// the evictions are recorded as the last bulk operation.
+    pub fn apply_retention(&self, policy: &RetentionPolicy, now: u64, undoable: bool) -> rusqlite::Result<Vec<Eviction>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        // Collected items are never evicted
+        let exempt = format!(
+            "AND id NOT IN (SELECT item_id FROM collection_items) {}",
+            if policy.exempt_favorites { "AND is_favorite = 0" } else { "" }
+        );
+        let mut evictions = Vec::new();
+        let mut evict = |ids: Vec<Uuid>, reason: EvictionReason| -> rusqlite::Result<()> {
+            let moved = move_to_trash(&tx, &ids, now, reason.name())?;
+            evictions.extend(moved.into_iter().map(|id| Eviction { id, reason: reason.clone() }));
+            Ok(())
+        };
+
+        for (tag, ttl) in &policy.tag_ttl_seconds {
+            let ids = query_ids(
+                &tx,
+                &format!(
+                    "SELECT id FROM items WHERE timestamp < ?1 {}
+                     AND id IN (SELECT item_id FROM item_tags WHERE tag = ?2)",
+                    exempt
+                ),
+                params![now.saturating_sub(*ttl) as i64, tag],
+            )?;
+            evict(ids, EvictionReason::TagTtl { tag: tag.clone() })?;
+        }
+
+        for kind in ["text", "rich_text", "image", "file_list"] {
+            let Some(max_age) = policy.max_age_for(kind) else {
+                continue;
+            };
+            let ids = query_ids(
+                &tx,
+                &format!("SELECT id FROM items WHERE kind = ?1 AND timestamp < ?2 {}", exempt),
+                params![kind, now.saturating_sub(max_age) as i64],
+            )?;
+            evict(ids, EvictionReason::MaxAge { kind: kind.to_string() })?;
+        }
+
+        if let Some(max_items) = policy.max_items {
+            let ids = query_ids(
+                &tx,
+                &format!(
+                    "SELECT id FROM items WHERE 1 {}
+                     ORDER BY timestamp DESC, rowid DESC LIMIT -1 OFFSET ?1",
+                    exempt
+                ),
+                params![max_items as i64],
+            )?;
+            evict(ids, EvictionReason::MaxItems)?;
+        }
+
+        if let Some(max_total_bytes) = policy.max_total_bytes {
+            // Keep the newest items whose sizes add up to the limit
+            let ids = query_ids(
+                &tx,
+                &format!(
+                    "SELECT id FROM (
+                         SELECT id, SUM(size_bytes) OVER (ORDER BY timestamp DESC, rowid DESC) AS running
+                         FROM items WHERE 1 {}
+                     ) WHERE running > ?1",
+                    exempt
+                ),
+                params![max_total_bytes as i64],
+            )?;
+            evict(ids, EvictionReason::MaxTotalBytes)?;
+        }
+
+        tx.execute(
+            "DELETE FROM trash WHERE deleted_at < ?1",
+            params![now.saturating_sub(policy.trash_retention_seconds) as i64],
+        )?;
+        if undoable && !evictions.is_empty() {
+            let ids: Vec<Uuid> = evictions.iter().map(|eviction| eviction.id).collect();
+            record_operation(&tx, BulkOperation::Retention, &ids, now)?;
+        }
+        tx.commit()?;
+        Ok(evictions)
+    }
+
+    /// Remember that `ids` were just imported, so the import can be undone.
+    /// An empty import leaves the last operation as it was.
+    pub fn record_import(&self, ids: &[Uuid], now: u64) -> rusqlite::Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let conn = self.conn()?;
+        record_operation(&conn, BulkOperation::Import, ids, now)
+    }
+
+    /// Trashed items, most recently deleted first.
+    pub fn trash(&self) -> rusqlite::Result<Vec<TrashedItem>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT item, deleted_at, reason FROM trash ORDER BY deleted_at DESC, rowid DESC")?;
+        let rows = stmt.query_map([], |row| {
+            let item: String = row.get(0)?;
+            Ok(TrashedItem {
+                item: serde_json::from_str(&item).map_err(|e| conversion_error(Box::new(e)))?,
+                deleted_at: row.get::<_, i64>(1)? as u64,
+                reason: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Move items from the trash back into the history. Returns the IDs
+    /// restored; items whose content is back in the history already are
+    /// just dropped from the trash.
+    pub fn restore(&self, ids: &[Uuid]) -> rusqlite::Result<Vec<Uuid>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let restored = restore_from_trash(&tx, ids)?;
+        tx.commit()?;
+        Ok(restored)
+    }
+
+    /// Delete everything in the trash for good. Returns how many items were
+    /// deleted. A clear or clean-up can't be undone afterwards.
+    pub fn empty_trash(&self) -> rusqlite::Result<usize> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let removed = tx.execute("DELETE FROM trash", [])?;
+        tx.execute("DELETE FROM last_operation WHERE kind != ?1", params![BulkOperation::Import.as_str()])?;
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    pub fn last_operation(&self) -> rusqlite::Result<Option<LastOperation>> {
+        let conn = self.conn()?;
+        Ok(last_operation(&conn)?.map(|(operation, _)| operation))
+    }
+
+    /// Undo the most recent bulk operation: restore what a clear or
+    /// clean-up removed, or move imported items to the trash. Returns the
+    /// operation undone, counting the items actually affected.
+    pub fn undo_last_operation(&self, now: u64) -> rusqlite::Result<Option<LastOperation>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let Some((mut operation, ids)) = last_operation(&tx)? else {
+            return Ok(None);
+        };
+        let affected = match operation.kind {
+            BulkOperation::Clear | BulkOperation::Retention => restore_from_trash(&tx, &ids)?,
+            BulkOperation::Import => move_to_trash(&tx, &ids, now, "undone_import")?,
+        };
+        tx.execute("DELETE FROM last_operation", [])?;
+        tx.commit()?;
+        operation.items = affected.len();
+        Ok(Some(operation))
+    }
+
+    /// Delete items whose expiry time has passed, favorites included: an
+    /// expiry is set for secrets, and starring one shouldn't keep it around.
+    /// They skip the trash for the same reason, and expired items already
+    /// in the trash are deleted from it so they can't be restored. Returns
+    /// the IDs removed from the history.
+    pub fn delete_expired(&self, now: u64) -> rusqlite::Result<Vec<Uuid>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let ids = query_ids(
+            &tx,
+            "DELETE FROM items WHERE expires_at IS NOT NULL AND expires_at <= ?1 RETURNING id",
+            params![now as i64],
+        )?;
+        tx.execute("DELETE FROM trash WHERE json_extract(item, '$.expires_at') <= ?1", params![now as i64])?;
+        tx.commit()?;
+        Ok(ids)
+    }
+
+    /// Blobs at least one item points at.
+    pub fn blob_paths(&self) -> rusqlite::Result<Vec<String>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT path FROM blob_refs WHERE refs > 0")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// Blobs whose last item was removed, waiting for `forget_blob`.
+    pub fn unreferenced_blobs(&self) -> rusqlite::Result<Vec<String>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT path FROM blob_refs WHERE refs <= 0")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// Stop tracking a blob once its file is gone, unless an item started
+    /// using it again in the meantime.
+    pub fn forget_blob(&self, path: &str) -> rusqlite::Result<()> {
+        self.conn()?.execute("DELETE FROM blob_refs WHERE path = ?1 AND refs <= 0", params![path])?;
+        Ok(())
+    }
+
+    /// Point every item, trashed ones included, using the blob at `old` to
+    /// `new` instead. Returns how many items changed.
+    pub fn replace_blob_path(&self, old: &str, new: &str) -> rusqlite::Result<usize> {
+        let conn = self.conn()?;
+        let items = conn.execute(
+            "UPDATE items SET payload = json_set(payload, '$.blob_path', ?2)
+             WHERE json_extract(payload, '$.blob_path') = ?1",
+            params![old, new],
+        )?;
+        let trashed = conn.execute(
+            "UPDATE trash SET item = json_set(item, '$.payload.blob_path', ?2)
+             WHERE json_extract(item, '$.payload.blob_path') = ?1",
+            params![old, new],
+        )?;
+        Ok(items + trashed)
+    }
+
+    pub fn statistics(&self) -> rusqlite::Result<HistoryStatistics> {
+        let conn = self.conn()?;
+
+        let (total_items, favorite_items, earliest_timestamp, latest_timestamp) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(is_favorite), 0),
+                    COALESCE(MIN(timestamp), 0), COALESCE(MAX(timestamp), 0)
+             FROM items",
+            [],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)? as usize,
+                    row.get::<_, i64>(1)? as usize,
+                    row.get::<_, i64>(2)? as u64,
+                    row.get::<_, i64>(3)? as u64,
+                ))
+            },
+        )?;
+
+        let mut stmt = conn.prepare("SELECT kind, COUNT(*) FROM items GROUP BY kind")?;
+        let kind_counts = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let count_kind = |kind: &str| {
+            kind_counts.iter().find(|(k, _)| k == kind).map(|(_, n)| *n).unwrap_or(0)
+        };
+
+        let mut stmt = conn.prepare(
+            "SELECT tag, COUNT(*) AS uses FROM item_tags GROUP BY tag ORDER BY uses DESC, tag LIMIT 5",
+        )?;
+        let top_tags = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT name, COUNT(items.id) AS uses FROM collections
+             JOIN collection_items ON collection_items.collection_id = collections.id
+             LEFT JOIN items ON items.id = collection_items.item_id
+             GROUP BY collections.id ORDER BY uses DESC, name LIMIT 5",
+        )?;
+        let top_collections = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT source_class, COUNT(*) AS uses FROM items WHERE source_class IS NOT NULL
+             GROUP BY source_class ORDER BY uses DESC, source_class LIMIT 5",
+        )?;
+        let top_sources = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT content_type, COUNT(*) FROM items WHERE content_type IS NOT NULL GROUP BY content_type",
+        )?;
+        let content_types = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize)))?
+            .collect::<rusqlite::Result<BTreeMap<_, _>>>()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT code_language, COUNT(*) AS uses FROM items WHERE code_language IS NOT NULL
+             GROUP BY code_language ORDER BY uses DESC, code_language",
+        )?;
+        let code_languages = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(HistoryStatistics {
+            total_items,
+            favorite_items,
+            text_items: count_kind("text"),
+            image_items: count_kind("image"),
+            file_list_items: count_kind("file_list"),
+            rich_text_items: count_kind("rich_text"),
+            top_tags,
+            top_collections,
+            top_sources,
+            content_types,
+            code_languages,
+            earliest_timestamp,
+            latest_timestamp,
+        })
+    }
+}
+
+// Open a database file, or an in-memory database when `path` is `None`, and
+// bring its schema up to date
+fn connect(path: Option<&Path>) -> rusqlite::Result<Connection> {
+    let conn = match path {
+        Some(path) => {
+            let conn = Connection::open(path)?;
+            conn.pragma_update(None, "journal_mode", "WAL")?;
+            conn
+        }
+        None => Connection::open_in_memory()?,
+    };
+    conn.pragma_update(None, "foreign_keys", "ON")?;
+    conn.execute_batch(SCHEMA)?;
+    let version = migrate(&conn)?;
+    if version < CONTENT_TYPE_VERSION {
+        classify_existing(&conn)?;
+    }
+    fill_missing_sizes(&conn)?;
+    Ok(conn)
+}
+
+// Delete everything with `secure_delete` on, so SQLite overwrites the freed
+// pages with zeros instead of leaving the old content in memory or on disk
+fn wipe(conn: &Connection) -> rusqlite::Result<()> {
+    conn.pragma_update(None, "secure_delete", "ON")?;
+    conn.execute_batch(
+        "DELETE FROM items;
+         DELETE FROM trash;
+         DELETE FROM collection_items;
+         DELETE FROM collections;
+         DELETE FROM last_operation;
+         INSERT INTO items_fts(items_fts) VALUES ('delete-all');",
+    )?;
+    if conn.path().is_some_and(|path| !path.is_empty()) {
+        conn.execute_batch("VACUUM;")?;
+    }
+    Ok(())
+}
+
+// Apply pending migrations. Returns the schema version the database had
+// before, so data fixes that need Rust can run once after their migration.
+fn migrate(conn: &Connection) -> rusqlite::Result<usize> {
+    let applied: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        conn.execute_batch(migration)?;
+        conn.pragma_update(None, "user_version", version + 1)?;
+    }
+    Ok(applied)
+}
+
+// Detect the content type of text items stored before types were tracked
+fn classify_existing(conn: &Connection) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare("SELECT id, payload FROM items WHERE kind IN ('text', 'rich_text')")?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (id, payload_json) in rows {
+        let Some(content_type) = serde_json::from_str::<ClipboardPayload>(&payload_json)
+            .ok()
+            .and_then(|payload| classify::classify_payload(&payload))
+        else {
+            continue;
+        };
+        conn.execute(
+            "UPDATE items SET content_type = ?1, code_language = ?2 WHERE id = ?3",
+            params![content_type.name(), content_type.language(), id],
+        )?;
+    }
+    Ok(())
+}
+
+// Work out `size_bytes` for items stored before sizes were tracked
+fn fill_missing_sizes(conn: &Connection) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare("SELECT id, payload FROM items WHERE size_bytes IS NULL")?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (id, payload_json) in rows {
+        let size = match serde_json::from_str::<ClipboardPayload>(&payload_json) {
+            Ok(payload) => stored_size(&payload, &payload_json),
+            Err(_) => payload_json.len() as u64,
+        };
+        conn.execute("UPDATE items SET size_bytes = ?1 WHERE id = ?2", params![size as i64, id])?;
+    }
+    Ok(())
+}
+
+// Bytes an item takes up: its serialized payload plus its image blob, if any
+fn stored_size(payload: &ClipboardPayload, payload_json: &str) -> u64 {
+    let blob_size = match payload {
+        ClipboardPayload::Image { blob_path, .. } => fs::metadata(blob_path).map(|m| m.len()).unwrap_or(0),
+        _ => 0,
+    };
+    payload_json.len() as u64 + blob_size
+}
+
+fn item_by_id(conn: &Connection, id: Uuid) -> rusqlite::Result<Option<ClipboardItem>> {
+    let sql = format!("SELECT {} FROM items WHERE id = ?1", ITEM_COLUMNS);
+    conn.query_row(&sql, params![id.to_string()], row_to_item).optional()
+}
+
+fn item_exists(conn: &Connection, id: Uuid) -> rusqlite::Result<bool> {
+    conn.query_row("SELECT 1 FROM items WHERE id = ?1", params![id.to_string()], |_| Ok(()))
+        .optional()
+        .map(|found| found.is_some())
+}
+
+fn insert_item(conn: &Connection, item: &ClipboardItem) -> rusqlite::Result<bool> {
+    let content_hash = item.payload.content_hash();
+    let duplicate = conn
+        .query_row(
+            "SELECT 1 FROM items WHERE id = ?1 OR content_hash = ?2 LIMIT 1",
+            params![item.id.to_string(), content_hash],
+            |_| Ok(()),
+        )
+        .optional()?;
+    if duplicate.is_some() {
+        return Ok(false);
+    }
+
+    let payload = serde_json::to_string(&item.payload)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    conn.execute(
+        "INSERT INTO items (id, kind, payload, search_text, content_hash, timestamp, is_favorite, perceptual_hash, expires_at, size_bytes, source_class, source_title, content_type, code_language, ocr_text)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+        params![
+            item.id.to_string(),
+            item.payload.kind(),
+            payload,
+            indexed_text(item),
+            content_hash,
+            item.timestamp as i64,
+            item.is_favorite,
+            item.payload.perceptual_hash().map(|hash| hash as i64),
+            item.expires_at.map(|at| at as i64),
+            stored_size(&item.payload, &payload) as i64,
+            item.source.as_ref().map(|source| &source.class),
+            item.source.as_ref().map(|source| &source.title),
+            item.content_type.as_ref().map(ContentType::name),
+            item.content_type.as_ref().and_then(ContentType::language),
+            item.ocr_text,
+        ],
+    )?;
+    for tag in &item.tags {
+        conn.execute(
+            "INSERT OR IGNORE INTO item_tags (item_id, tag) VALUES (?1, ?2)",
+            params![item.id.to_string(), tag],
+        )?;
+    }
+    Ok(true)
+}
+
+fn insert_collection(conn: &Connection, collection: &Collection) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO collections (id, name, created_at) VALUES (?1, ?2, ?3)",
+        params![collection.id.to_string(), collection.name, collection.created_at as i64],
+    )?;
+    append_members(conn, collection.id, &collection.item_ids)?;
+    Ok(())
+}
+
+fn collection_exists(conn: &Connection, id: Uuid) -> rusqlite::Result<bool> {
+    conn.query_row("SELECT 1 FROM collections WHERE id = ?1", params![id.to_string()], |_| Ok(()))
+        .optional()
+        .map(|found| found.is_some())
+}
+
+// Add items after a collection's last member, skipping ones already in it.
+// Returns how many were added.
+fn append_members(conn: &Connection, id: Uuid, item_ids: &[Uuid]) -> rusqlite::Result<usize> {
+    let mut added = 0;
+    for item_id in item_ids {
+        added += conn.execute(
+            "INSERT OR IGNORE INTO collection_items (collection_id, item_id, position)
+             SELECT ?1, ?2, COALESCE(MAX(position) + 1, 0) FROM collection_items WHERE collection_id = ?1",
+            params![id.to_string(), item_id.to_string()],
+        )?;
+    }
+    Ok(added)
+}
+
+// The IDs that belong to items in the history, in their original order
+fn existing_ids(conn: &Connection, ids: &[Uuid]) -> rusqlite::Result<Vec<Uuid>> {
+    let mut existing = Vec::with_capacity(ids.len());
+    for &id in ids {
+        if item_exists(conn, id)? {
+            existing.push(id);
+        }
+    }
+    Ok(existing)
+}
+
+// Text the full-text index holds for an item: its own text, then the app
+// it was copied from and any text recognized in it
+fn indexed_text(item: &ClipboardItem) -> String {
+    let mut text = item.payload.search_text();
+    if let Some(source) = &item.source {
+        text = format!("{}\n{}\n{}", text, source.class, source.title);
+    }
+    if let Some(ocr_text) = item.ocr_text.as_deref().filter(|text| !text.is_empty()) {
+        text = format!("{}\n{}", text, ocr_text);
+    }
+    text
+}
+
+// Run a statement whose only column is an item ID, such as a `SELECT id` or
+// a `DELETE ... RETURNING id`, and collect the IDs
+fn query_ids(conn: &Connection, sql: &str, params: impl Params) -> rusqlite::Result<Vec<Uuid>> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params, |row| {
+        let id: String = row.get(0)?;
+        Uuid::parse_str(&id).map_err(|e| conversion_error(Box::new(e)))
+    })?;
+    rows.collect()
+}
+
+fn conversion_error(e: Box<dyn std::error::Error + Send + Sync>) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, e)
+}
+
+// Move items to the trash. The trash row is written before the item is
+// deleted so a shared image blob never drops to zero references in between.
+// Returns the IDs of the items that existed.
+fn move_to_trash(conn: &Connection, ids: &[Uuid], now: u64, reason: &str) -> rusqlite::Result<Vec<Uuid>> {
+    let mut moved = Vec::new();
+    for &id in ids {
+        let Some(item) = item_by_id(conn, id)? else {
+            continue;
+        };
+        insert_trashed(conn, &item, now, reason)?;
+        conn.execute("DELETE FROM items WHERE id = ?1", params![id.to_string()])?;
+        moved.push(id);
+    }
+    Ok(moved)
+}
+
+fn insert_trashed(conn: &Connection, item: &ClipboardItem, deleted_at: u64, reason: &str) -> rusqlite::Result<()> {
+    let json = serde_json::to_string(item).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    // Replace by hand rather than with INSERT OR REPLACE, which skips the
+    // delete trigger that releases the old row's blob
+    conn.execute("DELETE FROM trash WHERE id = ?1", params![item.id.to_string()])?;
+    conn.execute(
+        "INSERT INTO trash (id, item, deleted_at, reason) VALUES (?1, ?2, ?3, ?4)",
+        params![item.id.to_string(), json, deleted_at as i64, reason],
+    )?;
+    Ok(())
+}
+
+// Put trashed items back, skipping any whose content is in the history
+// again. Returns the IDs restored.
+fn restore_from_trash(conn: &Connection, ids: &[Uuid]) -> rusqlite::Result<Vec<Uuid>> {
+    let mut restored = Vec::new();
+    for &id in ids {
+        let json: Option<String> = conn
+            .query_row("SELECT item FROM trash WHERE id = ?1", params![id.to_string()], |row| row.get(0))
+            .optional()?;
+        let Some(json) = json else {
+            continue;
+        };
+        let item: ClipboardItem = serde_json::from_str(&json).map_err(|e| conversion_error(Box::new(e)))?;
+        if insert_item(conn, &item)? {
+            restored.push(id);
+        }
+        conn.execute("DELETE FROM trash WHERE id = ?1", params![id.to_string()])?;
+    }
+    Ok(restored)
+}
+
+// Replace the recorded bulk operation; only the latest can be undone
+fn record_operation(conn: &Connection, kind: BulkOperation, ids: &[Uuid], now: u64) -> rusqlite::Result<()> {
+    let ids = serde_json::to_string(ids).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    conn.execute(
+        "INSERT OR REPLACE INTO last_operation (id, kind, item_ids, at) VALUES (1, ?1, ?2, ?3)",
+        params![kind.as_str(), ids, now as i64],
+    )?;
+    Ok(())
+}
+
+// The recorded bulk operation and the IDs of the items it touched
+fn last_operation(conn: &Connection) -> rusqlite::Result<Option<(LastOperation, Vec<Uuid>)>> {
+    let row = conn
+        .query_row("SELECT kind, item_ids, at FROM last_operation WHERE id = 1", [], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)? as u64))
+        })
+        .optional()?;
+    let Some((kind, ids, at)) = row else {
+        return Ok(None);
+    };
+    let Some(kind) = BulkOperation::parse(&kind) else {
+        return Ok(None);
+    };
+    let ids: Vec<Uuid> = serde_json::from_str(&ids).map_err(|e| conversion_error(Box::new(e)))?;
+    Ok(Some((LastOperation { kind, items: ids.len(), at }, ids)))
+}
+
+fn row_to_item(row: &Row) -> rusqlite::Result<ClipboardItem> {
+    let id: String = row.get(0)?;
+    let payload: String = row.get(1)?;
+    let tags: String = row.get(4)?;
+
+    let id = Uuid::parse_str(&id).map_err(|e| conversion_error(Box::new(e)))?;
+    let payload: ClipboardPayload = serde_json::from_str(&payload).map_err(|e| conversion_error(Box::new(e)))?;
+    let tags: Vec<String> = serde_json::from_str(&tags).map_err(|e| conversion_error(Box::new(e)))?;
+
+    Ok(ClipboardItem {
+        id,
+        payload,
+        timestamp: row.get::<_, i64>(2)? as u64,
+        is_favorite: row.get(3)?,
+        tags,
+        expires_at: row.get::<_, Option<i64>>(5)?.map(|at| at as u64),
+        source: match (row.get::<_, Option<String>>(6)?, row.get::<_, Option<String>>(7)?) {
+            (Some(class), title) => Some(SourceApp { class, title: title.unwrap_or_default() }),
+            (None, _) => None,
+        },
+        content_type: match row.get::<_, Option<String>>(8)? {
+            Some(name) => ContentType::from_name(&name, row.get(9)?),
+            None => None,
+        },
+        ocr_text: row.get(10)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+    const NOW: u64 = 1_000 * DAY;
+
+    fn text_item(text: &str, age_days: u64) -> ClipboardItem {
+        let mut item = ClipboardItem::new(ClipboardPayload::Text { text: text.to_string() });
+        item.timestamp = NOW - age_days * DAY;
+        item
+    }
+
+    // An in-memory history; `items` are given oldest first
+    fn history(items: &[ClipboardItem]) -> HistoryDb {
+        let db = HistoryDb::locked();
+        let newest_first: Vec<ClipboardItem> = items.iter().rev().cloned().collect();
+        db.reopen(None, &newest_first, &[], &[]).unwrap();
+        db
+    }
+
+    // Policy with every limit off
+    fn no_limits() -> RetentionPolicy {
+        RetentionPolicy { max_items: None, exempt_favorites: false, ..Default::default() }
+    }
+
+    fn remaining(db: &HistoryDb) -> Vec<String> {
+        db.list(None, 0).unwrap().iter().map(|item| item.payload.search_text()).collect()
+    }
+
+    fn evicted(evictions: &[Eviction], items: &[ClipboardItem]) -> Vec<(String, EvictionReason)> {
+        evictions
+            .iter()
+            .map(|eviction| {
+                let item = items.iter().find(|item| item.id == eviction.id).unwrap();
+                (item.payload.search_text(), eviction.reason.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_newest_items() {
+        let mut favorite = text_item("favorite", 9);
+        favorite.is_favorite = true;
+        let items = [favorite, text_item("a", 8), text_item("b", 7), text_item("c", 6), text_item("d", 5)];
+        let db = history(&items);
+
+        let policy = RetentionPolicy { max_items: Some(2), exempt_favorites: true, ..no_limits() };
+        let evictions = db.apply_retention(&policy, NOW, false).unwrap();
+        assert_eq!(
+            evicted(&evictions, &items),
+            [("b".to_string(), EvictionReason::MaxItems), ("a".to_string(), EvictionReason::MaxItems)]
+        );
+        assert_eq!(remaining(&db), ["d", "c", "favorite"]);
+        // Enforcing it again changes nothing
+        assert!(db.apply_retention(&policy, NOW, false).unwrap().is_empty());
+
+        // Without the exemption the favorite counts and goes first
+        let policy = RetentionPolicy { max_items: Some(2), ..no_limits() };
+        assert_eq!(evicted(&db.apply_retention(&policy, NOW, false).unwrap(), &items).len(), 1);
+        assert_eq!(remaining(&db), ["d", "c"]);
+    }
+
+    #[test]
+    fn collected_items_are_never_evicted() {
+        let items = [text_item("collected", 30), text_item("loose", 30), text_item("new", 0)];
+        let db = history(&items);
+        let collection = Collection { id: Uuid::new_v4(), name: "Keep".to_string(), created_at: NOW, item_ids: vec![items[0].id] };
+        db.create_collection(&collection).unwrap();
+
+        let policy = RetentionPolicy { max_items: Some(1), max_age_seconds: Some(DAY), ..no_limits() };
+        db.apply_retention(&policy, NOW, false).unwrap();
+        assert_eq!(remaining(&db), ["new", "collected"]);
+    }
+
+    #[test]
+    fn ages_out_by_kind() {
+        let rich = |text: &str, age_days| {
+            let mut item = ClipboardItem::new(ClipboardPayload::RichText {
+                text: text.to_string(),
+                html: Some(format!("<b>{}</b>", text)),
+                rtf: None,
+            });
+            item.timestamp = NOW - age_days * DAY;
+            item
+        };
+        let items = [text_item("old text", 10), rich("old rich", 10), text_item("new text", 2), rich("new rich", 2)];
+        let db = history(&items);
+
+        let mut policy = RetentionPolicy { max_age_seconds: Some(5 * DAY), ..no_limits() };
+        policy.max_age_by_kind.insert("rich_text".to_string(), DAY);
+        let evictions = db.apply_retention(&policy, NOW, false).unwrap();
+        let age = |kind: &str| EvictionReason::MaxAge { kind: kind.to_string() };
+        assert_eq!(
+            evicted(&evictions, &items),
+            [
+                ("old text".to_string(), age("text")),
+                ("old rich".to_string(), age("rich_text")),
+                ("new rich".to_string(), age("rich_text")),
+            ]
+        );
+        assert_eq!(remaining(&db), ["new text"]);
+    }
+
+    #[test]
+    fn tag_ttls_override_the_other_limits() {
+        let mut favorite = text_item("starred otp", 2);
+        favorite.is_favorite = true;
+        favorite.tags = vec!["otp".to_string()];
+        let mut old = text_item("old otp", 2);
+        old.tags = vec!["otp".to_string(), "work".to_string()];
+        let mut recent = text_item("recent otp", 0);
+        recent.tags = vec!["otp".to_string()];
+        let items = [favorite, old, recent, text_item("untagged", 2)];
+        let db = history(&items);
+
+        let mut policy = RetentionPolicy { max_items: Some(10), exempt_favorites: true, ..no_limits() };
+        policy.tag_ttl_seconds.insert("otp".to_string(), DAY);
+        let evictions = db.apply_retention(&policy, NOW, false).unwrap();
+        assert_eq!(evicted(&evictions, &items), [("old otp".to_string(), EvictionReason::TagTtl { tag: "otp".to_string() })]);
+        assert_eq!(remaining(&db), ["recent otp", "untagged", "starred otp"]);
+    }
+
+    #[test]
+    fn keeps_the_newest_items_within_the_byte_limit() {
+        let items = [text_item("aaaa", 4), text_item("bbbbbbbb", 3), text_item("cc", 2), text_item("dd", 1)];
+        let size = |item: &ClipboardItem| serde_json::to_string(&item.payload).unwrap().len() as u64;
+        let db = history(&items);
+
+        // Room for the two newest and a byte short of the third
+        let policy = RetentionPolicy { max_total_bytes: Some(size(&items[3]) + size(&items[2]) + size(&items[1]) - 1), ..no_limits() };
+        let evictions = db.apply_retention(&policy, NOW, false).unwrap();
+        assert_eq!(
+            evicted(&evictions, &items),
+            [("bbbbbbbb".to_string(), EvictionReason::MaxTotalBytes), ("aaaa".to_string(), EvictionReason::MaxTotalBytes)]
+        );
+        assert_eq!(remaining(&db), ["dd", "cc"]);
+    }
+
+    #[test]
+    fn evictions_go_to_the_trash_and_can_be_undone() {
+        let items = [text_item("a", 3), text_item("b", 2), text_item("c", 1)];
+        let db = history(&items);
+        let policy = RetentionPolicy { max_items: Some(1), ..no_limits() };
+
+        db.apply_retention(&policy, NOW, true).unwrap();
+        let trash = db.trash().unwrap();
+        assert_eq!(trash.len(), 2);
+        assert!(trash.iter().all(|trashed| trashed.reason == "max_items" && trashed.deleted_at == NOW));
+        let operation = db.last_operation().unwrap().unwrap();
+        assert_eq!((operation.kind, operation.items), (BulkOperation::Retention, 2));
+
+        db.undo_last_operation(NOW).unwrap();
+        assert_eq!(remaining(&db), ["c", "b", "a"]);
+        assert!(db.trash().unwrap().is_empty());
+
+        // Scheduled clean-ups don't replace the last operation
+        db.apply_retention(&policy, NOW, false).unwrap();
+        assert!(db.last_operation().unwrap().is_none());
+    }
+
+    #[test]
+    fn expired_items_are_deleted_from_the_trash_too() {
+        let mut secret = text_item("secret", 0);
+        secret.expires_at = Some(NOW + 60);
+        let mut kept = text_item("kept", 1);
+        kept.expires_at = Some(NOW + 3600);
+        let items = [text_item("plain", 2), kept, secret.clone()];
+        let db = history(&items);
+        db.clear(NOW).unwrap();
+        assert_eq!(db.trash().unwrap().len(), 3);
+
+        assert!(db.delete_expired(NOW + 59).unwrap().is_empty());
+        assert_eq!(db.trash().unwrap().len(), 3);
+        assert!(db.delete_expired(NOW + 60).unwrap().is_empty());
+        let trash: Vec<String> = db.trash().unwrap().iter().map(|trashed| trashed.item.payload.search_text()).collect();
+        assert_eq!(trash.len(), 2);
+        assert!(!trash.contains(&"secret".to_string()));
+
+        // Undoing the clear can't bring it back either
+        db.undo_last_operation(NOW + 61).unwrap();
+        assert_eq!(remaining(&db), ["kept", "plain"]);
+        assert!(db.get(secret.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn importing_only_duplicates_keeps_the_clear_undoable() {
+        let items = [text_item("a", 2), text_item("b", 1)];
+        let db = history(&items);
+        db.clear(NOW).unwrap();
+
+        // Importing what's already there adds nothing
+        db.insert_many(&[text_item("x", 0)]).unwrap();
+        let added = db.insert_many(&[text_item("x", 0)]).unwrap();
+        assert!(added.is_empty());
+        db.record_import(&added, NOW + 1).unwrap();
+        assert_eq!(db.last_operation().unwrap().unwrap().kind, BulkOperation::Clear);
+
+        db.undo_last_operation(NOW + 2).unwrap();
+        assert_eq!(remaining(&db), ["x", "b", "a"]);
+    }
+
+    // A collection's members in order, with ones in the trash as "trashed"
+    fn members(db: &HistoryDb, name: &str, with_trashed: bool) -> Vec<String> {
+        let collection = db.collections(with_trashed).unwrap().into_iter().find(|collection| collection.name == name).unwrap();
+        let text = |id| db.get(id).unwrap().map_or("trashed".to_string(), |item| item.payload.search_text());
+        collection.item_ids.into_iter().map(text).collect()
+    }
+
+    #[test]
+    fn collections_follow_their_items_through_the_trash() {
+        let items = [text_item("a", 3), text_item("b", 2), text_item("c", 1)];
+        let db = history(&items);
+        let id = Uuid::new_v4();
+        let ids: Vec<Uuid> = items.iter().map(|item| item.id).chain([Uuid::new_v4()]).collect();
+        db.create_collection(&Collection { id, name: "Board".to_string(), created_at: NOW, item_ids: ids }).unwrap();
+        assert_eq!(members(&db, "Board", false), ["a", "b", "c"]);
+
+        // A trashed member is hidden but keeps its place
+        db.delete(items[1].id, NOW).unwrap();
+        assert_eq!(members(&db, "Board", false), ["a", "c"]);
+        assert_eq!(members(&db, "Board", true), ["a", "trashed", "c"]);
+        let statistics = db.collection_statistics(id).unwrap().unwrap();
+        assert_eq!((statistics.total_items, statistics.trashed_items, statistics.text_items), (2, 1, 2));
+        assert_eq!((statistics.earliest_timestamp, statistics.latest_timestamp), (NOW - 3 * DAY, NOW - DAY));
+
+        db.restore(&[items[1].id]).unwrap();
+        assert_eq!(members(&db, "Board", false), ["a", "b", "c"]);
+
+        // Once it leaves the trash for good it leaves the collection too
+        db.delete(items[1].id, NOW).unwrap();
+        db.empty_trash().unwrap();
+        assert_eq!(members(&db, "Board", true), ["a", "c"]);
+        assert_eq!(db.collection_statistics(id).unwrap().unwrap().trashed_items, 0);
+        assert!(db.collection_statistics(Uuid::new_v4()).unwrap().is_none());
+    }
+
+    #[test]
+    fn collection_members_can_be_added_removed_and_reordered() {
+        let items = [text_item("a", 4), text_item("b", 3), text_item("c", 2), text_item("d", 1)];
+        let db = history(&items);
+        let id = Uuid::new_v4();
+        db.create_collection(&Collection { id, name: "Board".to_string(), created_at: NOW, item_ids: vec![items[0].id] }).unwrap();
+
+        // Members already in it and unknown items are skipped
+        let added = db.add_to_collection(id, &[items[2].id, items[0].id, Uuid::new_v4(), items[1].id, items[3].id]).unwrap();
+        assert_eq!(added, Some(3));
+        assert_eq!(members(&db, "Board", false), ["a", "c", "b", "d"]);
+        assert_eq!(db.add_to_collection(Uuid::new_v4(), &[items[0].id]).unwrap(), None);
+
+        // Members left out keep their order after the listed ones
+        assert!(db.reorder_collection(id, &[items[3].id, Uuid::new_v4(), items[1].id, items[3].id]).unwrap());
+        assert_eq!(members(&db, "Board", false), ["d", "b", "a", "c"]);
+        assert!(!db.reorder_collection(Uuid::new_v4(), &[]).unwrap());
+
+        assert_eq!(db.remove_from_collection(id, &[items[1].id, Uuid::new_v4()]).unwrap(), Some(1));
+        assert_eq!(members(&db, "Board", false), ["d", "a", "c"]);
+        assert_eq!(remaining(&db), ["d", "c", "b", "a"]);
+        // Appending after a removal goes to the end
+        db.add_to_collection(id, &[items[1].id]).unwrap();
+        assert_eq!(members(&db, "Board", false), ["d", "a", "c", "b"]);
+    }
+
+    #[test]
+    fn merging_collections_from_an_archive() {
+        let items = [text_item("a", 3), text_item("b", 2), text_item("c", 1)];
+        let db = history(&items);
+        let notes = Uuid::new_v4();
+        db.create_collection(&Collection { id: notes, name: "Notes".to_string(), created_at: NOW, item_ids: vec![items[1].id] })
+            .unwrap();
+
+        // The archive's copies have their own IDs and match by content
+        let imported = [text_item("a", 3), text_item("b", 2), text_item("c", 1)];
+        let collections = [
+            Collection {
+                id: Uuid::new_v4(),
+                name: "NOTES".to_string(),
+                created_at: NOW,
+                item_ids: vec![imported[2].id, imported[1].id, Uuid::new_v4(), imported[0].id],
+            },
+            Collection { id: notes, name: "Other".to_string(), created_at: NOW, item_ids: vec![imported[0].id] },
+        ];
+        crate::collections::merge(&db, &imported, &collections).unwrap();
+
+        let merged = db.collections(false).unwrap();
+        assert_eq!(merged.len(), 2);
+        assert_eq!(members(&db, "Notes", false), ["b", "c", "a"]);
+        let other = merged.iter().find(|collection| collection.name == "Other").unwrap();
+        assert_ne!(other.id, notes);
+        assert_eq!(members(&db, "Other", false), ["a"]);
+    }
+
+    #[test]
+    fn old_trash_is_deleted_for_good() {
+        let items = [text_item("a", 3), text_item("b", 2)];
+        let db = history(&items);
+        let policy = RetentionPolicy { max_items: Some(1), trash_retention_seconds: 7 * DAY, ..no_limits() };
+        db.apply_retention(&policy, NOW, false).unwrap();
+        assert_eq!(db.trash().unwrap().len(), 1);
+
+        db.apply_retention(&policy, NOW + 7 * DAY, false).unwrap();
+        assert_eq!(db.trash().unwrap().len(), 1);
+        db.apply_retention(&policy, NOW + 7 * DAY + 1, false).unwrap();
+        assert!(db.trash().unwrap().is_empty());
+        assert_eq!(remaining(&db), ["b"]);
+    }
+}
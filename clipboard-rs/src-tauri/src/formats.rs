@@ -0,0 +1,566 @@
+use crate::item::ClipboardPayload;
+
+pub const MIME_HTML: &str = "text/html";
+pub const MIME_RTF: &str = "text/rtf";
+pub const MIME_URI_LIST: &str = "text/uri-list";
+// Other names applications use for RTF
+const MIME_RTF_ALIASES: &[&str] = &["application/rtf", "text/richtext"];
+// GNOME file managers paste files from this target rather than text/uri-list
+const MIME_GNOME_FILES: &str = "x-special/gnome-copied-files";
+
+/// Every clipboard format the history stores, as read from or written to
+/// the system clipboard in one go.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct ClipboardFormats {
+    pub text: Option<String>,
+    pub html: Option<String>,
+    pub rtf: Option<String>,
+    /// Local paths for `file://` entries, other URIs as they were.
+    pub files: Vec<String>,
+}
+
+impl ClipboardFormats {
+    pub fn is_empty(&self) -> bool {
+        self.text.as_deref().is_none_or(|text| text.trim().is_empty())
+            && self.html.is_none()
+            && self.rtf.is_none()
+            && self.files.is_empty()
+    }
+
+    /// The formats to put back on the clipboard for a history item. Images
+    /// are restored separately and return `None`.
+    pub fn from_payload(payload: &ClipboardPayload) -> Option<Self> {
+        match payload {
+            ClipboardPayload::Text { text } => Some(ClipboardFormats { text: Some(text.clone()), ..Default::default() }),
+            ClipboardPayload::RichText { text, html, rtf } => Some(ClipboardFormats {
+                text: Some(text.clone()),
+                html: html.clone(),
+                rtf: rtf.clone(),
+                files: Vec::new(),
+            }),
+            ClipboardPayload::FileList { paths } => Some(ClipboardFormats {
+                text: Some(paths.join("\n")),
+                files: paths.clone(),
+                ..Default::default()
+            }),
+            ClipboardPayload::Image { .. } => None,
+        }
+    }
+
+    fn uri_list(&self) -> String {
+        self.files.iter().map(|file| path_to_uri(file)).collect::<Vec<_>>().join("\r\n")
+    }
+
+    fn gnome_copied_files(&self) -> String {
+        let uris: Vec<String> = self.files.iter().map(|file| path_to_uri(file)).collect();
+        format!("copy\n{}", uris.join("\n"))
+    }
+}
+
+/// Parse a `text/uri-list` body. `file://` URIs become local paths.
+pub fn parse_uri_list(list: &str) -> Vec<String> {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|uri| match url::Url::parse(uri) {
+            Ok(url) if url.scheme() == "file" => url
+                .to_file_path()
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| uri.to_string()),
+            _ => uri.to_string(),
+        })
+        .collect()
+}
+
+fn path_to_uri(file: &str) -> String {
+    url::Url::from_file_path(file)
+        .map(String::from)
+        .unwrap_or_else(|_| file.to_string())
+}
+
+/// Read every supported format from the clipboard. Uses wlr-data-control on
+/// Wayland and the X selection protocol on X11 so formats the portable
+/// backend can't see (RTF) are captured too.
+pub fn read() -> ClipboardFormats {
+    #[cfg(target_os = "linux")]
+    {
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            if let Ok(formats) = wayland::read() {
+                return formats;
+            }
+        }
+        if std::env::var_os("DISPLAY").is_some() {
+            if let Ok(formats) = x11::read() {
+                return formats;
+            }
+        }
+    }
+
+    portable::read()
+}
+
+/// Replace the clipboard contents with all of `formats` at once.
+pub fn write(formats: &ClipboardFormats) -> Result<(), String> {
+    #[cfg(target_os = "linux")]
+    {
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() && wayland::write(formats).is_ok() {
+            return Ok(());
+        }
+        if std::env::var_os("DISPLAY").is_some() && x11::write(formats).is_ok() {
+            return Ok(());
+        }
+    }
+
+    portable::write(formats)
+}
+
+// Decode clipboard bytes as text. Some browsers hand out text/html as UTF-16
+// with a byte order mark.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn decode_text(bytes: &[u8]) -> Option<String> {
+    if let Some(utf16) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        let units: Vec<u16> = utf16.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+        return String::from_utf16(&units).ok();
+    }
+    let text = String::from_utf8_lossy(bytes);
+    Some(text.trim_end_matches('\0').to_string())
+}
+
+// arboard works everywhere but only knows text, HTML and file lists, and can
+// only set one of HTML or files at a time
+mod portable {
+    use super::ClipboardFormats;
+
+    pub fn read() -> ClipboardFormats {
+        let Ok(mut clipboard) = arboard::Clipboard::new() else {
+            return ClipboardFormats::default();
+        };
+        ClipboardFormats {
+            text: clipboard.get_text().ok(),
+            html: clipboard.get().html().ok(),
+            rtf: None,
+            files: clipboard
+                .get()
+                .file_list()
+                .map(|paths| paths.iter().map(|path| path.to_string_lossy().into_owned()).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn write(formats: &ClipboardFormats) -> Result<(), String> {
+        let mut clipboard = arboard::Clipboard::new().map_err(|e| e.to_string())?;
+        if !formats.files.is_empty() {
+            clipboard.set().file_list(&formats.files).map_err(|e| e.to_string())
+        } else if let Some(html) = &formats.html {
+            clipboard.set_html(html.as_str(), formats.text.as_deref()).map_err(|e| e.to_string())
+        } else {
+            clipboard.set_text(formats.text.clone().unwrap_or_default()).map_err(|e| e.to_string())
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod wayland {
+    use std::collections::HashSet;
+    use std::io::Read;
+
+    use wl_clipboard_rs::copy::{self, MimeSource, Source};
+    use wl_clipboard_rs::paste::{self, ClipboardType, Seat};
+
+    use super::{decode_text, parse_uri_list, ClipboardFormats, MIME_GNOME_FILES, MIME_HTML, MIME_RTF, MIME_RTF_ALIASES, MIME_URI_LIST};
+
+    pub fn read() -> Result<ClipboardFormats, String> {
+        let offered = paste::get_mime_types(ClipboardType::Regular, Seat::Unspecified).map_err(|e| e.to_string())?;
+        let fetch = |mime_type: paste::MimeType| -> Option<String> {
+            let (mut pipe, _) = paste::get_contents(ClipboardType::Regular, Seat::Unspecified, mime_type).ok()?;
+            let mut bytes = Vec::new();
+            pipe.read_to_end(&mut bytes).ok()?;
+            decode_text(&bytes)
+        };
+        let fetch_first = |offered: &HashSet<String>, candidates: &[&str]| {
+            candidates
+                .iter()
+                .find(|mime| offered.contains(**mime))
+                .and_then(|mime| fetch(paste::MimeType::Specific(mime)))
+        };
+
+        let rtf_types: Vec<&str> = std::iter::once(MIME_RTF).chain(MIME_RTF_ALIASES.iter().copied()).collect();
+        Ok(ClipboardFormats {
+            text: fetch(paste::MimeType::Text),
+            html: fetch_first(&offered, &[MIME_HTML]),
+            rtf: fetch_first(&offered, &rtf_types),
+            files: fetch_first(&offered, &[MIME_URI_LIST]).map(|list| parse_uri_list(&list)).unwrap_or_default(),
+        })
+    }
+
+    pub fn write(formats: &ClipboardFormats) -> Result<(), String> {
+        let source = |mime_type: copy::MimeType, data: &str| MimeSource {
+            source: Source::Bytes(data.as_bytes().into()),
+            mime_type,
+        };
+        let specific = |mime: &str| copy::MimeType::Specific(mime.to_string());
+
+        let mut sources = Vec::new();
+        if let Some(text) = &formats.text {
+            sources.push(source(copy::MimeType::Text, text));
+        }
+        if let Some(html) = &formats.html {
+            sources.push(source(specific(MIME_HTML), html));
+        }
+        if let Some(rtf) = &formats.rtf {
+            sources.push(source(specific(MIME_RTF), rtf));
+            for alias in MIME_RTF_ALIASES {
+                sources.push(source(specific(alias), rtf));
+            }
+        }
+        if !formats.files.is_empty() {
+            sources.push(source(specific(MIME_URI_LIST), &formats.uri_list()));
+            sources.push(source(specific(MIME_GNOME_FILES), &formats.gnome_copied_files()));
+        }
+
+        // Serves paste requests from a background thread until another
+        // client takes the selection
+        copy::Options::new().copy_multi(sources).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod x11 {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use x11rb::connection::{Connection, RequestConnection as _};
+    use x11rb::protocol::xproto::{
+        Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, CreateWindowAux, EventMask, PropMode, Property,
+        PropertyNotifyEvent, SelectionNotifyEvent, SelectionRequestEvent, Window, WindowClass, SELECTION_NOTIFY_EVENT,
+    };
+    use x11rb::protocol::Event;
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::wrapper::ConnectionExt as _;
+    use x11rb::{COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT, CURRENT_TIME, NONE};
+
+    use super::{decode_text, parse_uri_list, ClipboardFormats};
+
+    x11rb::atom_manager! {
+        Atoms: AtomsCookie {
+            CLIPBOARD,
+            TARGETS,
+            INCR,
+            UTF8_STRING,
+            STRING,
+            TEXT,
+            TEXT_PLAIN_UTF8: b"text/plain;charset=utf-8",
+            TEXT_PLAIN: b"text/plain",
+            TEXT_HTML: b"text/html",
+            TEXT_RTF: b"text/rtf",
+            APPLICATION_RTF: b"application/rtf",
+            TEXT_RICHTEXT: b"text/richtext",
+            URI_LIST: b"text/uri-list",
+            GNOME_COPIED_FILES: b"x-special/gnome-copied-files",
+            CLIPBOARD_RS_DATA,
+        }
+    }
+
+    // How long to wait for the selection owner before giving up
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    // A paste too large for one property, sent in chunks with INCR: each
+    // chunk follows the requestor deleting the previous one
+    struct Transfer {
+        requestor: Window,
+        property: Atom,
+        // Index into the served entries
+        entry: usize,
+        sent: usize,
+    }
+
+    // Connect and create an invisible window to receive selection data on
+    fn connect() -> Result<(RustConnection, Window, Atoms), String> {
+        let (conn, screen_num) = RustConnection::connect(None).map_err(|e| e.to_string())?;
+        let atoms = Atoms::new(&conn)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?;
+
+        let root = conn.setup().roots[screen_num].root;
+        let window = conn.generate_id().map_err(|e| e.to_string())?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            COPY_FROM_PARENT,
+            &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )
+        .map_err(|e| e.to_string())?;
+        Ok((conn, window, atoms))
+    }
+
+    pub fn read() -> Result<ClipboardFormats, String> {
+        let (conn, window, atoms) = connect()?;
+
+        let Some(targets) = convert(&conn, window, &atoms, atoms.TARGETS)? else {
+            return Ok(ClipboardFormats::default());
+        };
+        let targets: Vec<Atom> = targets
+            .chunks_exact(4)
+            .map(|atom| u32::from_ne_bytes([atom[0], atom[1], atom[2], atom[3]]))
+            .collect();
+        let fetch = |candidates: &[Atom]| -> Result<Option<String>, String> {
+            match candidates.iter().find(|atom| targets.contains(atom)) {
+                Some(&target) if target == atoms.STRING => {
+                    Ok(convert(&conn, window, &atoms, target)?.map(|bytes| latin1_decode(&bytes)))
+                }
+                Some(&target) => Ok(convert(&conn, window, &atoms, target)?.and_then(|bytes| decode_text(&bytes))),
+                None => Ok(None),
+            }
+        };
+
+        Ok(ClipboardFormats {
+            text: fetch(&[atoms.UTF8_STRING, atoms.TEXT_PLAIN_UTF8, atoms.STRING, atoms.TEXT_PLAIN])?,
+            html: fetch(&[atoms.TEXT_HTML])?,
+            rtf: fetch(&[atoms.TEXT_RTF, atoms.APPLICATION_RTF, atoms.TEXT_RICHTEXT])?,
+            files: fetch(&[atoms.URI_LIST])?.map(|list| parse_uri_list(&list)).unwrap_or_default(),
+        })
+    }
+
+    // Ask the owner to convert CLIPBOARD to `target` and collect the result,
+    // following the INCR protocol for large transfers
+    fn convert(conn: &RustConnection, window: Window, atoms: &Atoms, target: Atom) -> Result<Option<Vec<u8>>, String> {
+        conn.convert_selection(window, atoms.CLIPBOARD, target, atoms.CLIPBOARD_RS_DATA, CURRENT_TIME)
+            .map_err(|e| e.to_string())?;
+        conn.flush().map_err(|e| e.to_string())?;
+
+        let notify = wait_for(conn, |event| match event {
+            Event::SelectionNotify(notify) if notify.requestor == window => Some(*notify),
+            _ => None,
+        })?;
+        if notify.property == NONE {
+            return Ok(None);
+        }
+
+        let reply = conn
+            .get_property(true, window, atoms.CLIPBOARD_RS_DATA, AtomEnum::ANY, 0, u32::MAX / 4)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?;
+        conn.flush().map_err(|e| e.to_string())?;
+        if reply.type_ != atoms.INCR {
+            return Ok(Some(reply.value));
+        }
+
+        // Deleting the INCR property above told the owner to start sending
+        // chunks; each arrives as a new property value, and an empty one
+        // ends the transfer
+        let mut data = Vec::new();
+        loop {
+            wait_for(conn, |event| match event {
+                Event::PropertyNotify(notify)
+                    if notify.window == window
+                        && notify.atom == atoms.CLIPBOARD_RS_DATA
+                        && notify.state == Property::NEW_VALUE =>
+                {
+                    Some(())
+                }
+                _ => None,
+            })?;
+            let chunk = conn
+                .get_property(true, window, atoms.CLIPBOARD_RS_DATA, AtomEnum::ANY, 0, u32::MAX / 4)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| e.to_string())?;
+            conn.flush().map_err(|e| e.to_string())?;
+            if chunk.value.is_empty() {
+                return Ok(Some(data));
+            }
+            data.extend(chunk.value);
+        }
+    }
+
+    fn wait_for<T>(conn: &RustConnection, mut matches: impl FnMut(&Event) -> Option<T>) -> Result<T, String> {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            match conn.poll_for_event().map_err(|e| e.to_string())? {
+                Some(event) => {
+                    if let Some(found) = matches(&event) {
+                        return Ok(found);
+                    }
+                }
+                None if Instant::now() >= deadline => return Err("Clipboard owner did not respond".to_string()),
+                None => thread::sleep(Duration::from_millis(5)),
+            }
+        }
+    }
+
+    /// Take ownership of CLIPBOARD and serve `formats` from a background
+    /// thread until another client takes it over.
+    pub fn write(formats: &ClipboardFormats) -> Result<(), String> {
+        let (conn, window, atoms) = connect()?;
+
+        let mut entries: Vec<(Atom, Vec<u8>)> = Vec::new();
+        if let Some(text) = &formats.text {
+            for target in [atoms.UTF8_STRING, atoms.TEXT_PLAIN_UTF8, atoms.TEXT, atoms.TEXT_PLAIN] {
+                entries.push((target, text.as_bytes().to_vec()));
+            }
+            entries.push((atoms.STRING, latin1_encode(text)));
+        }
+        if let Some(html) = &formats.html {
+            entries.push((atoms.TEXT_HTML, html.as_bytes().to_vec()));
+        }
+        if let Some(rtf) = &formats.rtf {
+            for target in [atoms.TEXT_RTF, atoms.APPLICATION_RTF, atoms.TEXT_RICHTEXT] {
+                entries.push((target, rtf.as_bytes().to_vec()));
+            }
+        }
+        if !formats.files.is_empty() {
+            entries.push((atoms.URI_LIST, formats.uri_list().into_bytes()));
+            entries.push((atoms.GNOME_COPIED_FILES, formats.gnome_copied_files().into_bytes()));
+        }
+
+        conn.set_selection_owner(window, atoms.CLIPBOARD, CURRENT_TIME).map_err(|e| e.to_string())?;
+        let owner = conn
+            .get_selection_owner(atoms.CLIPBOARD)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?
+            .owner;
+        if owner != window {
+            return Err("Could not take ownership of the clipboard".to_string());
+        }
+
+        thread::spawn(move || {
+            let mut transfers = Vec::new();
+            while let Ok(event) = conn.wait_for_event() {
+                // A failed reply means the connection is gone. Transfers
+                // still running when another client takes over are dropped.
+                let served = match event {
+                    Event::SelectionRequest(request) => respond(&conn, &atoms, &entries, &request, &mut transfers),
+                    Event::PropertyNotify(notify) if notify.state == Property::DELETE => {
+                        send_chunk(&conn, &entries, &notify, &mut transfers)
+                    }
+                    Event::SelectionClear(_) => break,
+                    _ => Ok(()),
+                };
+                if served.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(())
+    }
+
+    // Largest property value to send in one request
+    fn chunk_len(conn: &RustConnection) -> usize {
+        conn.maximum_request_bytes().saturating_sub(64)
+    }
+
+    // Answer one paste request. Data larger than a single request is sent
+    // with INCR, starting once the requestor deletes the INCR property.
+    fn respond(
+        conn: &RustConnection,
+        atoms: &Atoms,
+        entries: &[(Atom, Vec<u8>)],
+        request: &SelectionRequestEvent,
+        transfers: &mut Vec<Transfer>,
+    ) -> Result<(), x11rb::errors::ReplyOrIdError> {
+        // Obsolete clients leave the property unset and expect the target name
+        let mut property = if request.property == NONE { request.target } else { request.property };
+
+        if request.target == atoms.TARGETS {
+            let targets: Vec<Atom> = std::iter::once(atoms.TARGETS).chain(entries.iter().map(|(atom, _)| *atom)).collect();
+            conn.change_property32(PropMode::REPLACE, request.requestor, property, AtomEnum::ATOM, &targets)?;
+        } else {
+            match entries.iter().position(|(atom, _)| *atom == request.target) {
+                Some(entry) if entries[entry].1.len() > chunk_len(conn) => {
+                    // Watch for the deletes before announcing the transfer
+                    let watch = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+                    conn.change_window_attributes(request.requestor, &watch)?;
+                    let len = entries[entry].1.len() as u32;
+                    conn.change_property32(PropMode::REPLACE, request.requestor, property, atoms.INCR, &[len])?;
+                    transfers.retain(|transfer| (transfer.requestor, transfer.property) != (request.requestor, property));
+                    transfers.push(Transfer { requestor: request.requestor, property, entry, sent: 0 });
+                }
+                Some(entry) => {
+                    let (target, data) = &entries[entry];
+                    conn.change_property8(PropMode::REPLACE, request.requestor, property, *target, data)?;
+                }
+                None => property = NONE,
+            }
+        }
+
+        conn.send_event(
+            false,
+            request.requestor,
+            EventMask::NO_EVENT,
+            SelectionNotifyEvent {
+                response_type: SELECTION_NOTIFY_EVENT,
+                sequence: 0,
+                time: request.time,
+                requestor: request.requestor,
+                selection: request.selection,
+                target: request.target,
+                property,
+            },
+        )?;
+        conn.flush()?;
+        Ok(())
+    }
+
+    // Send the next chunk of a transfer once the requestor deleted the last
+    // one. An empty chunk ends it.
+    fn send_chunk(
+        conn: &RustConnection,
+        entries: &[(Atom, Vec<u8>)],
+        notify: &PropertyNotifyEvent,
+        transfers: &mut Vec<Transfer>,
+    ) -> Result<(), x11rb::errors::ReplyOrIdError> {
+        let Some(index) = transfers
+            .iter()
+            .position(|transfer| transfer.requestor == notify.window && transfer.property == notify.atom)
+        else {
+            return Ok(());
+        };
+        let transfer = &mut transfers[index];
+        let (target, data) = &entries[transfer.entry];
+        let end = (transfer.sent + chunk_len(conn)).min(data.len());
+        let chunk = &data[transfer.sent..end];
+        conn.change_property8(PropMode::REPLACE, transfer.requestor, transfer.property, *target, chunk)?;
+        transfer.sent = end;
+        if chunk.is_empty() {
+            let requestor = transfers.remove(index).requestor;
+            if !transfers.iter().any(|transfer| transfer.requestor == requestor) {
+                let unwatch = ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT);
+                conn.change_window_attributes(requestor, &unwatch)?;
+            }
+        }
+        conn.flush()?;
+        Ok(())
+    }
+
+    // STRING is Latin-1 by definition. Characters it can't hold become `?`.
+    fn latin1_encode(text: &str) -> Vec<u8> {
+        text.chars().map(|c| u8::try_from(c).unwrap_or(b'?')).collect()
+    }
+
+    fn latin1_decode(bytes: &[u8]) -> String {
+        bytes.iter().map(|&byte| char::from(byte)).collect::<String>().trim_end_matches('\0').to_string()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn string_is_latin1() {
+            assert_eq!(latin1_encode("café"), b"caf\xe9");
+            assert_eq!(latin1_encode("naïve → 日本"), b"na\xefve ? ??");
+            assert_eq!(latin1_decode(b"caf\xe9\0"), "café");
+            assert_eq!(latin1_decode(&latin1_encode("£5 ¿qué?")), "£5 ¿qué?");
+        }
+    }
+}
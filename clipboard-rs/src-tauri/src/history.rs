@@ -0,0 +1,272 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+use uuid::Uuid;
+
+use crate::blobs::BlobStore;
+use crate::collections::{self, Collection};
+use crate::db::HistoryDb;
+use crate::item::{now_secs, ClipboardItem};
+use crate::retention::Eviction;
+use crate::settings;
+use crate::trash::LastOperation;
+
+/// Event emitted after every change, with a `HistoryChange` payload.
+pub const UPDATE_EVENT: &str = "clipboard-update";
+
+/// What a change did to the history.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HistoryChange {
+    /// New items, newest first, and older items the retention policy
+    /// evicted to make room.
+    Added { items: Vec<ClipboardItem>, evicted: Vec<Eviction> },
+    /// Items whose favorite flag, tags or recognized text changed.
+    Updated { items: Vec<ClipboardItem> },
+    /// Items that were deleted or moved to the trash.
+    Removed { ids: Vec<Uuid> },
+    /// Items the retention policy evicted, with the limit behind each.
+    Evicted { evictions: Vec<Eviction> },
+    /// Every item was moved to the trash.
+    Cleared,
+    /// Collections were created, renamed, deleted or had their items
+    /// changed. Lists every collection as it is now.
+    Collections { collections: Vec<Collection> },
+    /// The whole history was swapped out (locked, unlocked, encryption
+    /// turned on or off), items came back from the trash or an archive
+    /// brought in collections, and it should be reloaded.
+    Reloaded,
+}
+
+type Job = Box<dyn FnOnce(&tauri::AppHandle) + Send>;
+
+/// The only writer of the history. Changes are queued on a channel and run
+/// one at a time on a dedicated thread, so a multi-step change like
+/// enabling encryption can't interleave with a clip being captured. Each
+/// change emits one `clipboard-update` event describing it. Reads go to
+/// `HistoryDb` directly.
+pub struct History {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl History {
+    pub fn start(app: tauri::AppHandle) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            for job in queue {
+                // A panicking change fails its own request (its reply is
+                // dropped) but mustn't take the writer down with it
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| job(&app)));
+            }
+        });
+        History { jobs }
+    }
+
+    // Queue `job` on the writer thread and wait for its result
+    fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&tauri::AppHandle) -> Result<T, String> + Send + 'static,
+    ) -> Result<T, String> {
+        let (reply, result) = mpsc::sync_channel(1);
+        self.jobs
+            .send(Box::new(move |app| {
+                let _ = reply.send(job(app));
+            }))
+            .map_err(|_| "History writer has stopped".to_string())?;
+        result.recv().map_err(|_| "History change failed".to_string())?
+    }
+
+    // Run a change that returns its result and what it changed, if anything
+    // the frontend shows. Blobs it orphaned are collected and the change is
+    // announced before the caller gets the result back.
+    fn apply<T: Send + 'static>(
+        &self,
+        change: impl FnOnce(&tauri::AppHandle, &HistoryDb) -> rusqlite::Result<(T, Option<HistoryChange>)> + Send + 'static,
+    ) -> Result<T, String> {
+        self.run(move |app| {
+            let db = app.state::<HistoryDb>();
+            let (value, change) = change(app, &db).map_err(|e| e.to_string())?;
+            let _ = app.state::<BlobStore>().collect_garbage(&db);
+            if let Some(change) = change {
+                let _ = app.emit(UPDATE_EVENT, change);
+            }
+            Ok(value)
+        })
+    }
+
+    /// Insert an item unless an identical one is stored. Returns whether it
+    /// was added.
+    pub fn insert(&self, item: ClipboardItem) -> Result<bool, String> {
+        self.insert_many(vec![item]).map(|added| added > 0)
+    }
+
+    /// Insert items, skipping duplicates, then enforce the retention policy.
+    /// Returns how many were added.
+    pub fn insert_many(&self, items: Vec<ClipboardItem>) -> Result<usize, String> {
+        self.add(items, Vec::new(), false)
+    }
+
+    /// Insert items like `insert_many`, recording them as the last bulk
+    /// operation so the import can be undone.
+    pub fn import(&self, items: Vec<ClipboardItem>) -> Result<usize, String> {
+        self.add(items, Vec::new(), true)
+    }
+
+    /// Import items like `import`, along with collections of them (see
+    /// `collections::merge`). Undoing the import leaves the collections.
+    pub fn import_with_collections(&self, items: Vec<ClipboardItem>, collections: Vec<Collection>) -> Result<usize, String> {
+        self.add(items, collections, true)
+    }
+
+    fn add(&self, items: Vec<ClipboardItem>, collections: Vec<Collection>, record_import: bool) -> Result<usize, String> {
+        self.apply(move |app, db| {
+            let added = db.insert_many(&items)?;
+            // An import that added nothing mustn't replace the undo of an
+            // earlier clear or clean-up
+            if record_import && !added.is_empty() {
+                db.record_import(&added, now_secs())?;
+            }
+            // Before retention runs, so the collected items are kept
+            if !collections.is_empty() {
+                collections::merge(db, &items, &collections)?;
+            }
+            if added.is_empty() {
+                return Ok((0, (!collections.is_empty()).then_some(HistoryChange::Reloaded)));
+            }
+            let evicted = db.apply_retention(&settings::load_settings(app).retention, now_secs(), false)?;
+            if !collections.is_empty() {
+                return Ok((added.len(), Some(HistoryChange::Reloaded)));
+            }
+            let mut items: Vec<ClipboardItem> = items.into_iter().filter(|item| added.contains(&item.id)).collect();
+            items.sort_by_key(|item| std::cmp::Reverse(item.timestamp));
+            Ok((added.len(), Some(HistoryChange::Added { items, evicted })))
+        })
+    }
+
+    /// Move an item to the trash. Returns false if the item doesn't exist.
+    pub fn delete(&self, id: Uuid) -> Result<bool, String> {
+        self.apply(move |_, db| {
+            let deleted = db.delete(id, now_secs())?;
+            Ok((deleted, deleted.then(|| HistoryChange::Removed { ids: vec![id] })))
+        })
+    }
+
+    /// Move every item to the trash.
+    pub fn clear(&self) -> Result<(), String> {
+        self.apply(|_, db| {
+            db.clear(now_secs())?;
+            Ok(((), Some(HistoryChange::Cleared)))
+        })
+    }
+
+    /// Evict whatever the retention policy no longer allows. With
+    /// `undoable`, the evictions can be restored by `undo`.
+    pub fn enforce_retention(&self, undoable: bool) -> Result<Vec<Eviction>, String> {
+        self.apply(move |app, db| {
+            let evictions = db.apply_retention(&settings::load_settings(app).retention, now_secs(), undoable)?;
+            let change = (!evictions.is_empty()).then(|| HistoryChange::Evicted { evictions: evictions.clone() });
+            Ok((evictions, change))
+        })
+    }
+
+    /// Delete items whose expiry time has passed. Returns how many were
+    /// removed.
+    pub fn delete_expired(&self, now: u64) -> Result<usize, String> {
+        self.apply(move |_, db| {
+            let ids = db.delete_expired(now)?;
+            let count = ids.len();
+            Ok((count, (count > 0).then_some(HistoryChange::Removed { ids })))
+        })
+    }
+
+    /// Move items from the trash back into the history. Returns how many
+    /// were restored.
+    pub fn restore(&self, ids: Vec<Uuid>) -> Result<usize, String> {
+        self.apply(move |_, db| {
+            let restored = db.restore(&ids)?.len();
+            Ok((restored, (restored > 0).then_some(HistoryChange::Reloaded)))
+        })
+    }
+
+    /// Delete the trash for good. Returns how many items were deleted.
+    pub fn empty_trash(&self) -> Result<usize, String> {
+        self.apply(|_, db| Ok((db.empty_trash()?, None)))
+    }
+
+    /// Undo the most recent clear, clean-up or import. Returns `None` when
+    /// there is nothing to undo.
+    pub fn undo(&self) -> Result<Option<LastOperation>, String> {
+        self.apply(|_, db| {
+            let operation = db.undo_last_operation(now_secs())?;
+            let change = operation.as_ref().filter(|operation| operation.items > 0).map(|_| HistoryChange::Reloaded);
+            Ok((operation, change))
+        })
+    }
+
+    /// Flip the favorite flag. Returns the new value, or `None` if the item
+    /// doesn't exist.
+    pub fn toggle_favorite(&self, id: Uuid) -> Result<Option<bool>, String> {
+        self.apply(move |_, db| {
+            let is_favorite = db.toggle_favorite(id)?;
+            Ok((is_favorite, updated(db, id)?))
+        })
+    }
+
+    /// Returns false if the item doesn't exist.
+    pub fn add_tag(&self, id: Uuid, tag: String) -> Result<bool, String> {
+        self.apply(move |_, db| {
+            let found = db.add_tag(id, &tag)?;
+            Ok((found, updated(db, id)?))
+        })
+    }
+
+    /// Returns false if the item doesn't exist.
+    pub fn remove_tag(&self, id: Uuid, tag: String) -> Result<bool, String> {
+        self.apply(move |_, db| {
+            let found = db.remove_tag(id, &tag)?;
+            Ok((found, updated(db, id)?))
+        })
+    }
+
+    /// Store the text OCR found in an image item. Returns false if the item
+    /// doesn't exist.
+    pub fn set_ocr_text(&self, id: Uuid, text: String) -> Result<bool, String> {
+        self.apply(move |_, db| {
+            let found = db.set_ocr_text(id, &text)?;
+            Ok((found, updated(db, id)?))
+        })
+    }
+
+    /// Run a change to the collections and announce every collection as it
+    /// is afterwards.
+    pub fn edit_collections<T: Send + 'static>(
+        &self,
+        change: impl FnOnce(&HistoryDb) -> Result<T, String> + Send + 'static,
+    ) -> Result<T, String> {
+        self.run(move |app| {
+            let db = app.state::<HistoryDb>();
+            let value = change(&db)?;
+            let collections = db.collections(false).map_err(|e| e.to_string())?;
+            let _ = app.emit(UPDATE_EVENT, HistoryChange::Collections { collections });
+            Ok(value)
+        })
+    }
+
+    /// Run a change that replaces the whole database, such as locking or
+    /// unlocking an encrypted history, and tell the frontend to reload.
+    pub fn replace(&self, change: impl FnOnce(&tauri::AppHandle) -> Result<(), String> + Send + 'static) -> Result<(), String> {
+        self.run(move |app| {
+            change(app)?;
+            let _ = app.emit(UPDATE_EVENT, HistoryChange::Reloaded);
+            Ok(())
+        })
+    }
+}
+
+// The item as it is now, if it still exists
+fn updated(db: &HistoryDb, id: Uuid) -> rusqlite::Result<Option<HistoryChange>> {
+    Ok(db.get(id)?.map(|item| HistoryChange::Updated { items: vec![item] }))
+}
@@ -0,0 +1,354 @@
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageOutputFormat, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+use uuid::Uuid;
+
+use crate::blobs::BlobStore;
+use crate::classify;
+use crate::db::HistoryDb;
+use crate::history::History;
+use crate::imagehash;
+use crate::item::{ClipboardItem, ClipboardPayload};
+use crate::thumbnails;
+use crate::vault::Vault;
+
+// Largest width or height an edit may produce
+const MAX_DIMENSION: u32 = 16384;
+
+// Longest side of the images `preview_image_edit` returns
+const PREVIEW_SIZE: u32 = 1024;
+
+/// A rectangle in pixels. Coordinates refer to the image as it is at the
+/// step that uses it, after any earlier crops or resizes.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlipAxis {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactStyle {
+    #[default]
+    Pixelate,
+    Blur,
+}
+
+/// One step of an image edit. Steps apply in order, each to the result of
+/// the one before.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ImageOperation {
+    /// Keep only `rect`, clipped to the image.
+    Crop { rect: Rect },
+    /// Scale to `width` × `height`. With only one of them set the other
+    /// follows the aspect ratio.
+    Resize {
+        #[serde(default)]
+        width: Option<u32>,
+        #[serde(default)]
+        height: Option<u32>,
+    },
+    /// Rotate clockwise by 90, 180 or 270 degrees.
+    Rotate { degrees: u32 },
+    Flip { axis: FlipAxis },
+    /// Hide what's in `rect`. `strength` is the block size for pixelation
+    /// or the blur radius, in pixels.
+    Redact {
+        rect: Rect,
+        #[serde(default)]
+        style: RedactStyle,
+        #[serde(default)]
+        strength: Option<u32>,
+    },
+    /// Draw an arrow pointing at `to`. `color` is any CSS-style color
+    /// `parse_color` understands.
+    Arrow {
+        from: Point,
+        to: Point,
+        #[serde(default = "default_color")]
+        color: String,
+        #[serde(default = "default_thickness")]
+        thickness: u32,
+    },
+    /// Draw the outline of `rect`.
+    Box {
+        rect: Rect,
+        #[serde(default = "default_color")]
+        color: String,
+        #[serde(default = "default_thickness")]
+        thickness: u32,
+    },
+}
+
+fn default_color() -> String {
+    "#ff0000".to_string()
+}
+
+fn default_thickness() -> u32 {
+    4
+}
+
+/// Encoding of a saved edit.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    /// Lossy, without transparency; `quality` applies.
+    Jpeg,
+    /// Lossless WebP.
+    Webp,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Webp => "webp",
+        }
+    }
+
+    pub fn encode(self, img: &RgbaImage, quality: u8) -> Result<Vec<u8>, String> {
+        let (img, format) = match self {
+            OutputFormat::Png => (DynamicImage::ImageRgba8(img.clone()), ImageOutputFormat::Png),
+            // JPEG has no alpha channel
+            OutputFormat::Jpeg => (
+                DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(img.clone()).to_rgb8()),
+                ImageOutputFormat::Jpeg(quality.clamp(1, 100)),
+            ),
+            OutputFormat::Webp => (DynamicImage::ImageRgba8(img.clone()), ImageOutputFormat::WebP),
+        };
+        let mut buffer = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut buffer), format)
+            .map_err(|e| format!("Failed to encode image: {}", e))?;
+        Ok(buffer)
+    }
+}
+
+impl ImageOperation {
+    pub fn apply(&self, mut img: RgbaImage) -> Result<RgbaImage, String> {
+        match self {
+            ImageOperation::Crop { rect } => {
+                let (x, y, width, height) = clip(&img, rect)?;
+                Ok(imageops::crop_imm(&img, x, y, width, height).to_image())
+            }
+            ImageOperation::Resize { width, height } => {
+                let (old_width, old_height) = img.dimensions();
+                let scaled = |size: u32, from: u32, to: u32| ((size as u64 * to as u64) / from.max(1) as u64) as u32;
+                let (width, height) = match (*width, *height) {
+                    (Some(width), Some(height)) => (width, height),
+                    (Some(width), None) => (width, scaled(old_height, old_width, width)),
+                    (None, Some(height)) => (scaled(old_width, old_height, height), height),
+                    (None, None) => return Err("Resize needs a width or a height".to_string()),
+                };
+                if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+                    return Err(format!("Images can be resized to between 1 and {} pixels a side", MAX_DIMENSION));
+                }
+                Ok(imageops::resize(&img, width, height, FilterType::Lanczos3))
+            }
+            ImageOperation::Rotate { degrees } => match degrees % 360 {
+                0 => Ok(img),
+                90 => Ok(imageops::rotate90(&img)),
+                180 => Ok(imageops::rotate180(&img)),
+                270 => Ok(imageops::rotate270(&img)),
+                _ => Err("Images can only be rotated by multiples of 90 degrees".to_string()),
+            },
+            ImageOperation::Flip { axis: FlipAxis::Horizontal } => Ok(imageops::flip_horizontal(&img)),
+            ImageOperation::Flip { axis: FlipAxis::Vertical } => Ok(imageops::flip_vertical(&img)),
+            ImageOperation::Redact { rect, style, strength } => {
+                let (x, y, width, height) = clip(&img, rect)?;
+                let region = imageops::crop_imm(&img, x, y, width, height).to_image();
+                let hidden = match style {
+                    RedactStyle::Pixelate => {
+                        let block = strength.unwrap_or(12).max(2);
+                        let small = imageops::resize(&region, width.div_ceil(block), height.div_ceil(block), FilterType::Triangle);
+                        imageops::resize(&small, width, height, FilterType::Nearest)
+                    }
+                    RedactStyle::Blur => imageops::blur(&region, strength.unwrap_or(8).max(1) as f32),
+                };
+                imageops::replace(&mut img, &hidden, x as i64, y as i64);
+                Ok(img)
+            }
+            ImageOperation::Arrow { from, to, color, thickness } => {
+                draw_arrow(&mut img, *from, *to, parse_color(color)?, stroke_width(*thickness));
+                Ok(img)
+            }
+            ImageOperation::Box { rect, color, thickness } => {
+                draw_box(&mut img, rect, parse_color(color)?, stroke_width(*thickness));
+                Ok(img)
+            }
+        }
+    }
+}
+
+// The part of `rect` inside the image, as x, y, width and height
+fn clip(img: &RgbaImage, rect: &Rect) -> Result<(u32, u32, u32, u32), String> {
+    let (width, height) = img.dimensions();
+    let right = rect.x.saturating_add(rect.width).min(width);
+    let bottom = rect.y.saturating_add(rect.height).min(height);
+    if rect.x >= right || rect.y >= bottom {
+        return Err("The rectangle is outside the image".to_string());
+    }
+    Ok((rect.x, rect.y, right - rect.x, bottom - rect.y))
+}
+
+fn parse_color(color: &str) -> Result<Rgba<u8>, String> {
+    let color = classify::parse_color(color).ok_or_else(|| format!("Invalid color '{}'", color))?;
+    Ok(Rgba([color.r, color.g, color.b, (color.a * 255.0).round() as u8]))
+}
+
+fn stroke_width(thickness: u32) -> f64 {
+    thickness.clamp(1, 200) as f64
+}
+
+fn draw_arrow(img: &mut RgbaImage, from: Point, to: Point, color: Rgba<u8>, width: f64) {
+    let (fx, fy, tx, ty) = (from.x as f64, from.y as f64, to.x as f64, to.y as f64);
+    let length = ((tx - fx).powi(2) + (ty - fy).powi(2)).sqrt();
+    if length < 1.0 {
+        return;
+    }
+    let (dx, dy) = ((tx - fx) / length, (ty - fy) / length);
+
+    // The head is a triangle with its tip at `to`, scaled with the line but
+    // never longer than the arrow
+    let head = (width * 4.0).max(12.0).min(length);
+    let (bx, by) = (tx - dx * head, ty - dy * head);
+    let (nx, ny) = (-dy * head / 2.0, dx * head / 2.0);
+    let corners = [(tx, ty), (bx + nx, by + ny), (bx - nx, by - ny)];
+
+    let margin = head.max(width);
+    let bounds = (fx.min(tx) - margin, fy.min(ty) - margin, fx.max(tx) + margin, fy.max(ty) + margin);
+    paint(img, bounds, color, |x, y| {
+        segment_distance((x, y), (fx, fy), (bx, by)) <= width / 2.0 || in_triangle((x, y), corners)
+    });
+}
+
+fn draw_box(img: &mut RgbaImage, rect: &Rect, color: Rgba<u8>, width: f64) {
+    let (left, top) = (rect.x as f64, rect.y as f64);
+    let (right, bottom) = (left + rect.width as f64, top + rect.height as f64);
+    // The outline is drawn inside the rectangle
+    paint(img, (left, top, right, bottom), color, |x, y| {
+        x < left + width || x >= right - width || y < top + width || y >= bottom - width
+    });
+}
+
+// Blend `color` over every pixel within `bounds` whose center `covers`
+// accepts. Each pixel is blended once, so translucent shapes stay even.
+fn paint(img: &mut RgbaImage, bounds: (f64, f64, f64, f64), color: Rgba<u8>, covers: impl Fn(f64, f64) -> bool) {
+    let (width, height) = img.dimensions();
+    let x0 = bounds.0.floor().max(0.0) as u32;
+    let y0 = bounds.1.floor().max(0.0) as u32;
+    let x1 = (bounds.2.ceil().max(0.0) as u32).min(width);
+    let y1 = (bounds.3.ceil().max(0.0) as u32).min(height);
+    let alpha = color[3] as u32;
+    for y in y0..y1 {
+        for x in x0..x1 {
+            if !covers(x as f64 + 0.5, y as f64 + 0.5) {
+                continue;
+            }
+            let pixel = img.get_pixel_mut(x, y);
+            for channel in 0..3 {
+                pixel[channel] = ((color[channel] as u32 * alpha + pixel[channel] as u32 * (255 - alpha)) / 255) as u8;
+            }
+            pixel[3] = (alpha + pixel[3] as u32 * (255 - alpha) / 255) as u8;
+        }
+    }
+}
+
+fn segment_distance(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (abx, aby) = (b.0 - a.0, b.1 - a.1);
+    let length = abx * abx + aby * aby;
+    let t = if length == 0.0 { 0.0 } else { (((p.0 - a.0) * abx + (p.1 - a.1) * aby) / length).clamp(0.0, 1.0) };
+    ((p.0 - a.0 - t * abx).powi(2) + (p.1 - a.1 - t * aby).powi(2)).sqrt()
+}
+
+fn in_triangle(p: (f64, f64), [a, b, c]: [(f64, f64); 3]) -> bool {
+    let side = |u: (f64, f64), v: (f64, f64)| (v.0 - u.0) * (p.1 - u.1) - (v.1 - u.1) * (p.0 - u.0);
+    let (d1, d2, d3) = (side(a, b), side(b, c), side(c, a));
+    let negative = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+    let positive = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+    !(negative && positive)
+}
+
+// Load an image item and run the operations on it
+fn edited_image(app: &tauri::AppHandle, id: Uuid, operations: &[ImageOperation]) -> Result<RgbaImage, String> {
+    let item = app.state::<HistoryDb>().get(id).map_err(|e| e.to_string())?.ok_or("Item not found")?;
+    let ClipboardPayload::Image { blob_path, .. } = &item.payload else {
+        return Err("Only image items can be edited".to_string());
+    };
+    let bytes = app.state::<Vault>().read_blob(blob_path)?;
+    let mut img = image::load_from_memory(&bytes)
+        .map_err(|e| format!("Failed to load image from file: {}", e))?
+        .to_rgba8();
+    for operation in operations {
+        img = operation.apply(img)?;
+    }
+    Ok(img)
+}
+
+// Function to preview an edit of an image item without saving it. Returns a
+// PNG data URL, scaled down if the result is large.
+#[tauri::command]
+pub fn preview_image_edit(app: tauri::AppHandle, id: Uuid, operations: Vec<ImageOperation>) -> Result<String, String> {
+    let img = DynamicImage::ImageRgba8(edited_image(&app, id, &operations)?);
+    let img = if img.width() > PREVIEW_SIZE || img.height() > PREVIEW_SIZE {
+        img.resize(PREVIEW_SIZE, PREVIEW_SIZE, FilterType::Triangle)
+    } else {
+        img
+    };
+    crate::image_data_url(&img)
+}
+
+// Function to edit an image item and save the result as a new item, in
+// `format` (PNG unless given). With `copy_to_clipboard` the result is also
+// put on the clipboard. Returns the new item, or `None` if an identical
+// image is already in the history.
+#[tauri::command]
+pub fn save_image_edit(
+    app: tauri::AppHandle,
+    id: Uuid,
+    operations: Vec<ImageOperation>,
+    format: Option<OutputFormat>,
+    quality: Option<u8>,
+    copy_to_clipboard: Option<bool>,
+) -> Result<Option<ClipboardItem>, String> {
+    let img = edited_image(&app, id, &operations)?;
+    let format = format.unwrap_or_default();
+    let bytes = format.encode(&img, quality.unwrap_or(90))?;
+
+    // Hash the pixels as stored, which differ from the edit for JPEG
+    let stored = image::load_from_memory(&bytes).map_err(|e| e.to_string())?.to_rgba8();
+    let blob = app.state::<Vault>().write_blob(app.state::<BlobStore>().inner(), &bytes, format.extension())?;
+    let _ = thumbnails::generate(&app.state::<BlobStore>(), &app.state::<Vault>(), &blob.path(), &stored);
+    let item = ClipboardItem::new(ClipboardPayload::Image {
+        width: stored.width(),
+        height: stored.height(),
+        blob_path: blob.path(),
+        pixel_hash: imagehash::rgba_hash(&stored),
+        perceptual_hash: Some(format!("{:016x}", imagehash::perceptual_hash(&stored))),
+    });
+    let added = app.state::<History>().insert(item.clone())?;
+
+    if copy_to_clipboard.unwrap_or(false) {
+        crate::copy_image_from_file_to_clipboard(app.clone(), &blob.path())?;
+    }
+    Ok(added.then_some(item))
+}
@@ -0,0 +1,39 @@
+use image::imageops::FilterType;
+use image::RgbaImage;
+use sha2::{Digest, Sha256};
+
+/// Hex SHA-256 over the dimensions and raw RGBA pixels. Two images hash the
+/// same only if every pixel matches, regardless of the file they came from.
+pub fn rgba_hash(img: &RgbaImage) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(img.width().to_le_bytes());
+    hasher.update(img.height().to_le_bytes());
+    hasher.update(img.as_raw());
+    format!("{:x}", hasher.finalize())
+}
+
+/// 64-bit difference hash: shrink to 9x8 greyscale and record whether each
+/// pixel is brighter than its right neighbour. Visually similar images end up
+/// a small Hamming distance apart.
+pub fn perceptual_hash(img: &RgbaImage) -> u64 {
+    let small = image::imageops::resize(
+        &image::imageops::grayscale(img),
+        9,
+        8,
+        FilterType::Triangle,
+    );
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = small.get_pixel(x, y)[0];
+            let right = small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(left > right);
+        }
+    }
+    hash
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
@@ -0,0 +1,589 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use flate2::read::ZlibDecoder;
+use quick_xml::events::Event;
+use serde::Deserialize;
+use tauri::Manager;
+
+use crate::blobs::{BlobStore, PendingBlob};
+use crate::history::History;
+use crate::imagehash;
+use crate::item::{now_secs, ClipboardItem, ClipboardPayload};
+use crate::vault::Vault;
+
+/// Clipboard managers whose history can be imported.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardManager {
+    /// Tab files (`copyq_tab_*.dat`) from CopyQ's config directory.
+    CopyQ,
+    /// `history.xml` from GPaste's data directory.
+    GPaste,
+}
+
+impl ClipboardManager {
+    /// Files read when no path is given: every CopyQ tab, or GPaste's
+    /// default history.
+    pub fn default_files(self) -> Result<Vec<PathBuf>, String> {
+        match self {
+            ClipboardManager::CopyQ => {
+                let dir = xdg_dir("XDG_CONFIG_HOME", ".config")?.join("copyq");
+                let entries = std::fs::read_dir(&dir).map_err(|_| format!("No CopyQ history found in {}", dir.display()))?;
+                let mut tabs: Vec<PathBuf> = entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| {
+                        let name = path.file_name().unwrap_or_default().to_string_lossy();
+                        name.starts_with("copyq_tab_") && name.ends_with(".dat")
+                    })
+                    .collect();
+                if tabs.is_empty() {
+                    return Err(format!("No CopyQ history found in {}", dir.display()));
+                }
+                tabs.sort();
+                Ok(tabs)
+            }
+            ClipboardManager::GPaste => Ok(vec![xdg_dir("XDG_DATA_HOME", ".local/share")?.join("gpaste").join("history.xml")]),
+        }
+    }
+
+    /// Parse one history file.
+    pub fn parse(self, data: &[u8]) -> Result<Vec<ForeignClip>, String> {
+        match self {
+            ClipboardManager::CopyQ => parse_copyq(data),
+            ClipboardManager::GPaste => parse_gpaste(&String::from_utf8_lossy(data)),
+        }
+    }
+}
+
+/// A clip read from another clipboard manager, newest first.
+#[derive(Debug, PartialEq)]
+pub enum ForeignClip {
+    Text(String),
+    RichText { text: String, html: String },
+    Files(Vec<String>),
+    /// Encoded image data, with the extension of its format.
+    Image { bytes: Vec<u8>, extension: String },
+    /// An image the other manager keeps in a file of its own.
+    ImageFile(String),
+}
+
+// Function to import the history of another clipboard manager. Without a
+// path, its usual history location is read. Like other imports, items we
+// already have are skipped and the import can be undone.
+#[tauri::command]
+pub fn import_from_clipboard_manager(
+    app: tauri::AppHandle,
+    manager: ClipboardManager,
+    path: Option<String>,
+) -> Result<usize, String> {
+    let files = match path {
+        Some(path) => vec![PathBuf::from(path)],
+        None => manager.default_files()?,
+    };
+    let mut clips = Vec::new();
+    for file in files {
+        let data = std::fs::read(&file).map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+        clips.extend(manager.parse(&data)?);
+    }
+
+    let vault = app.state::<Vault>();
+    let blob_store = app.state::<BlobStore>();
+    let (items, _blobs) = to_items(clips, &vault, &blob_store);
+    app.state::<History>().import(items)
+}
+
+// Turn clips into history items. Neither manager records when something was
+// copied, so items are spaced a second apart to keep their order. Images
+// that can't be read are skipped.
+fn to_items<'a>(clips: Vec<ForeignClip>, vault: &Vault, blob_store: &'a BlobStore) -> (Vec<ClipboardItem>, Vec<PendingBlob<'a>>) {
+    let now = now_secs();
+    let mut items = Vec::with_capacity(clips.len());
+    let mut blobs = Vec::new();
+    for clip in clips {
+        let payload = match clip {
+            ForeignClip::Text(text) => ClipboardPayload::Text { text },
+            ForeignClip::RichText { text, html } => ClipboardPayload::RichText { text, html: Some(html), rtf: None },
+            ForeignClip::Files(paths) => ClipboardPayload::FileList { paths },
+            ForeignClip::Image { bytes, extension } => match image_payload(&bytes, &extension, vault, blob_store) {
+                Ok((payload, blob)) => {
+                    blobs.push(blob);
+                    payload
+                }
+                Err(_) => continue,
+            },
+            ForeignClip::ImageFile(path) => {
+                let extension = Path::new(&path).extension().unwrap_or_default().to_string_lossy().to_string();
+                match std::fs::read(&path).map_err(|e| e.to_string()).and_then(|bytes| image_payload(&bytes, &extension, vault, blob_store)) {
+                    Ok((payload, blob)) => {
+                        blobs.push(blob);
+                        payload
+                    }
+                    Err(_) => continue,
+                }
+            }
+        };
+        let mut item = ClipboardItem::new(payload);
+        item.timestamp = now.saturating_sub(items.len() as u64);
+        items.push(item);
+    }
+    (items, blobs)
+}
+
+// Store an image and describe it the way captured images are, so duplicates
+// of images already in the history are recognized
+fn image_payload<'a>(
+    bytes: &[u8],
+    extension: &str,
+    vault: &Vault,
+    blob_store: &'a BlobStore,
+) -> Result<(ClipboardPayload, PendingBlob<'a>), String> {
+    let img = image::load_from_memory(bytes).map_err(|e| e.to_string())?.to_rgba8();
+    let blob = vault.write_blob(blob_store, bytes, extension)?;
+    let payload = ClipboardPayload::Image {
+        width: img.width(),
+        height: img.height(),
+        blob_path: blob.path(),
+        pixel_hash: imagehash::rgba_hash(&img),
+        perceptual_hash: Some(format!("{:016x}", imagehash::perceptual_hash(&img))),
+    };
+    Ok((payload, blob))
+}
+
+// `$var`, or `~/<fallback>` when it isn't set
+fn xdg_dir(var: &str, fallback: &str) -> Result<PathBuf, String> {
+    match std::env::var_os(var).filter(|dir| !dir.is_empty()) {
+        Some(dir) => Ok(PathBuf::from(dir)),
+        None => std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(fallback))
+            .ok_or_else(|| "Could not find the home directory".to_string()),
+    }
+}
+
+/// Parse a GPaste history file. Both the current layout, where an item's
+/// content is in a `<value>` element, and the older one with the content
+/// directly inside `<item>` are understood. Passwords GPaste was told to
+/// keep are left out.
+pub fn parse_gpaste(xml: &str) -> Result<Vec<ForeignClip>, String> {
+    let invalid = |e: quick_xml::Error| format!("Not a GPaste history file: {}", e);
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut clips = Vec::new();
+    let mut seen_history = false;
+    // Kind and content of the item being read
+    let mut current: Option<(String, String)> = None;
+
+    loop {
+        match reader.read_event().map_err(invalid)? {
+            Event::Start(element) if element.name().as_ref() == b"history" => seen_history = true,
+            Event::Start(element) if element.name().as_ref() == b"item" => {
+                let kind = match element.try_get_attribute("kind").map_err(|e| invalid(e.into()))? {
+                    Some(attribute) => attribute.unescape_value().map_err(invalid)?.to_string(),
+                    None => "Text".to_string(),
+                };
+                current = Some((kind, String::new()));
+            }
+            Event::Text(text) => {
+                if let Some((_, content)) = &mut current {
+                    content.push_str(&text.unescape().map_err(invalid)?);
+                }
+            }
+            Event::CData(data) => {
+                if let Some((_, content)) = &mut current {
+                    content.push_str(&data.decode().map_err(|e| invalid(e.into()))?);
+                }
+            }
+            Event::End(element) if element.name().as_ref() == b"item" => {
+                if let Some((kind, content)) = current.take() {
+                    clips.extend(gpaste_clip(&kind, content));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !seen_history {
+        return Err("Not a GPaste history file: no <history> element".to_string());
+    }
+    Ok(clips)
+}
+
+fn gpaste_clip(kind: &str, content: String) -> Option<ForeignClip> {
+    // Content is indented inside <value> in newer files
+    let content = content.trim();
+    if content.is_empty() {
+        return None;
+    }
+    match kind {
+        "Password" => None,
+        "Image" => Some(ForeignClip::ImageFile(content.to_string())),
+        "Uris" => Some(uri_list(content).unwrap_or_else(|| ForeignClip::Text(content.to_string()))),
+        _ => Some(ForeignClip::Text(content.to_string())),
+    }
+}
+
+// A file list from `file://` URIs, one per line. Lists with anything else
+// in them are kept as text.
+fn uri_list(content: &str) -> Option<ForeignClip> {
+    let paths: Option<Vec<String>> = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let path = url::Url::parse(line).ok()?.to_file_path().ok()?;
+            Some(path.to_string_lossy().to_string())
+        })
+        .collect();
+    paths.filter(|paths| !paths.is_empty()).map(ForeignClip::Files)
+}
+
+/// Parse a CopyQ tab file. These are Qt data streams: an optional
+/// `CopyQ v3` header, the item count, then each item's formats as MIME type
+/// and data pairs. Images, HTML, file lists and plain text are kept;
+/// CopyQ's own metadata formats are ignored.
+pub fn parse_copyq(data: &[u8]) -> Result<Vec<ForeignClip>, String> {
+    let mut stream = QDataStream { data, pos: 0 };
+
+    // Files saved before the header was added start with the item count
+    if looks_like_header(data) {
+        let header = stream.string()?.unwrap_or_default();
+        if header != "CopyQ v3" && header != "CopyQ v2" {
+            return Err(format!("Unsupported CopyQ tab format \"{}\"", header));
+        }
+    }
+
+    let count = stream.i32()?;
+    if count < 0 {
+        return Err("Not a CopyQ tab file".to_string());
+    }
+    let mut clips = Vec::new();
+    for _ in 0..count {
+        let formats = copyq_item(&mut stream)?;
+        clips.extend(copyq_clip(formats));
+    }
+    Ok(clips)
+}
+
+// Whether the stream starts with a string rather than the item count: a
+// UTF-16 string's byte length is followed by "C" (0x00 0x43)
+fn looks_like_header(data: &[u8]) -> bool {
+    data.get(4..6) == Some(&[0, b'C'])
+}
+
+// Read the MIME type and data of each format of one item
+fn copyq_item(stream: &mut QDataStream) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let marker = stream.i32()?;
+    let mut formats = Vec::new();
+    if marker == -2 {
+        // Current layout: each format says whether its data is compressed
+        let count = stream.i32()?;
+        for _ in 0..count {
+            let mime = expand_mime(&stream.string()?.unwrap_or_default());
+            let compressed = stream.bool()?;
+            let bytes = stream.bytes()?;
+            formats.push((mime, if compressed { q_uncompress(&bytes)? } else { bytes }));
+        }
+    } else if marker >= 0 {
+        // Older layout: the marker is the format count and all data is
+        // compressed
+        for _ in 0..marker {
+            let mime = expand_mime(&stream.string()?.unwrap_or_default());
+            let bytes = stream.bytes()?;
+            formats.push((mime, q_uncompress(&bytes)?));
+        }
+    } else {
+        return Err("Unsupported CopyQ item format".to_string());
+    }
+    Ok(formats)
+}
+
+// CopyQ shortens common MIME prefixes to a digit
+fn expand_mime(mime: &str) -> String {
+    let prefix = match mime.chars().next() {
+        Some('0') => "",
+        Some('1') => "application/x-copyq-",
+        Some('2') => "text/",
+        Some('3') => "application/",
+        Some('4') => "image/",
+        _ => return mime.to_string(),
+    };
+    format!("{}{}", prefix, &mime[1..])
+}
+
+fn copyq_clip(formats: Vec<(String, Vec<u8>)>) -> Option<ForeignClip> {
+    let get = |mime: &str| formats.iter().find(|(m, _)| m == mime).map(|(_, bytes)| bytes);
+    let text = |mime: &str| get(mime).map(|bytes| String::from_utf8_lossy(bytes).to_string());
+
+    if let Some(files) = text("text/uri-list").as_deref().and_then(uri_list) {
+        return Some(files);
+    }
+    for (mime, extension) in [("image/png", "png"), ("image/jpeg", "jpg"), ("image/gif", "gif"), ("image/bmp", "bmp")] {
+        if let Some(bytes) = get(mime) {
+            return Some(ForeignClip::Image { bytes: bytes.clone(), extension: extension.to_string() });
+        }
+    }
+    let plain = text("text/plain").filter(|text| !text.is_empty());
+    match (plain, text("text/html")) {
+        (Some(text), Some(html)) => Some(ForeignClip::RichText { text, html }),
+        (Some(text), None) => Some(ForeignClip::Text(text)),
+        (None, _) => None,
+    }
+}
+
+// `qCompress` output: the uncompressed size as a big-endian u32, then zlib
+// data
+fn q_uncompress(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    if bytes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let size = bytes.get(..4).ok_or("Corrupted CopyQ data")?;
+    let size = u32::from_be_bytes(size.try_into().unwrap_or_default()) as u64;
+    let mut data = Vec::new();
+    ZlibDecoder::new(&bytes[4..])
+        .take(size)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Corrupted CopyQ data: {}", e))?;
+    Ok(data)
+}
+
+// Reads values the way Qt's QDataStream writes them: big-endian, with
+// strings as UTF-16 and both strings and byte arrays prefixed by their byte
+// length (all ones for null)
+struct QDataStream<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl QDataStream<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], String> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or("Unexpected end of CopyQ tab file")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap_or_default()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.take(1)?[0] != 0)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, String> {
+        match self.u32()? {
+            u32::MAX => Ok(Vec::new()),
+            len => Ok(self.take(len as usize)?.to_vec()),
+        }
+    }
+
+    fn string(&mut self) -> Result<Option<String>, String> {
+        let len = match self.u32()? {
+            u32::MAX => return Ok(None),
+            len => len as usize,
+        };
+        let units: Vec<u16> = self.take(len)?.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+        Ok(Some(String::from_utf16_lossy(&units)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    // Builds CopyQ tab files the way QDataStream writes them
+    #[derive(Default)]
+    struct Tab(Vec<u8>);
+
+    impl Tab {
+        fn i32(mut self, value: i32) -> Self {
+            self.0.extend_from_slice(&value.to_be_bytes());
+            self
+        }
+
+        fn string(mut self, value: &str) -> Self {
+            let units: Vec<u16> = value.encode_utf16().collect();
+            self.0.extend_from_slice(&(units.len() as u32 * 2).to_be_bytes());
+            for unit in units {
+                self.0.extend_from_slice(&unit.to_be_bytes());
+            }
+            self
+        }
+
+        fn bytes(mut self, value: &[u8]) -> Self {
+            self.0.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            self.0.extend_from_slice(value);
+            self
+        }
+
+        // A format in the current layout
+        fn format(self, mime: &str, data: &[u8], compressed: bool) -> Self {
+            let mut tab = self.string(mime);
+            tab.0.push(compressed as u8);
+            if compressed {
+                tab.bytes(&q_compress(data))
+            } else {
+                tab.bytes(data)
+            }
+        }
+    }
+
+    fn q_compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        let mut compressed = (data.len() as u32).to_be_bytes().to_vec();
+        compressed.extend(encoder.finish().unwrap());
+        compressed
+    }
+
+    #[test]
+    fn copyq_current_layout() {
+        let tab = Tab::default()
+            .string("CopyQ v3")
+            .i32(4)
+            // Plain text with CopyQ's own metadata alongside
+            .i32(-2)
+            .i32(2)
+            .format("2plain", b"hello", false)
+            .format("1owner", b"firefox", false)
+            // Rich text, compressed
+            .i32(-2)
+            .i32(2)
+            .format("text/plain", "caf\u{e9}".as_bytes(), true)
+            .format("2html", b"<b>caf\xc3\xa9</b>", true)
+            // An image wins over its text
+            .i32(-2)
+            .i32(2)
+            .format("4png", &[0x89, b'P', b'N', b'G'], false)
+            .format("2plain", b"screenshot", false)
+            // Nothing we can use
+            .i32(-2)
+            .i32(1)
+            .format("1tags", b"x", false);
+
+        assert_eq!(
+            parse_copyq(&tab.0).unwrap(),
+            [
+                ForeignClip::Text("hello".to_string()),
+                ForeignClip::RichText { text: "caf\u{e9}".to_string(), html: "<b>caf\u{e9}</b>".to_string() },
+                ForeignClip::Image { bytes: vec![0x89, b'P', b'N', b'G'], extension: "png".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn copyq_old_layout_without_header() {
+        let tab = Tab::default()
+            .i32(1)
+            .i32(1)
+            .string("text/plain")
+            .bytes(&q_compress(b"from an old tab"));
+        assert_eq!(parse_copyq(&tab.0).unwrap(), [ForeignClip::Text("from an old tab".to_string())]);
+        assert_eq!(ClipboardManager::CopyQ.parse(&tab.0).unwrap().len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copyq_file_lists() {
+        let tab = Tab::default()
+            .string("CopyQ v3")
+            .i32(1)
+            .i32(-2)
+            .i32(2)
+            .format("2uri-list", b"file:///home/me/a%20b.txt\r\nfile:///tmp/c\r\n", false)
+            .format("2plain", b"a b.txt", false);
+        assert_eq!(
+            parse_copyq(&tab.0).unwrap(),
+            [ForeignClip::Files(vec!["/home/me/a b.txt".to_string(), "/tmp/c".to_string()])]
+        );
+    }
+
+    #[test]
+    fn copyq_rejects_bad_files() {
+        let tab = Tab::default().string("CopyQ v9").i32(0);
+        assert_eq!(parse_copyq(&tab.0).unwrap_err(), "Unsupported CopyQ tab format \"CopyQ v9\"");
+        assert!(parse_copyq(&Tab::default().i32(-5).0).is_err());
+        assert!(parse_copyq(&Tab::default().i32(1).i32(-3).0).is_err());
+        assert!(parse_copyq(b"").is_err());
+
+        // Truncated in the middle of an item
+        let tab = Tab::default().string("CopyQ v3").i32(1).i32(-2).i32(1).format("2plain", b"hello", false);
+        assert!(parse_copyq(&tab.0[..tab.0.len() - 2]).is_err());
+        // Compressed data that isn't zlib
+        let tab = Tab::default().i32(1).i32(1).string("text/plain").bytes(&[0, 0, 0, 5, 1, 2, 3]);
+        assert!(parse_copyq(&tab.0).is_err());
+    }
+
+    #[test]
+    fn copyq_mime_prefixes() {
+        assert_eq!(expand_mime("0text/x-custom"), "text/x-custom");
+        assert_eq!(expand_mime("1tags"), "application/x-copyq-tags");
+        assert_eq!(expand_mime("2plain"), "text/plain");
+        assert_eq!(expand_mime("3json"), "application/json");
+        assert_eq!(expand_mime("4png"), "image/png");
+        assert_eq!(expand_mime("text/html"), "text/html");
+        assert_eq!(expand_mime(""), "");
+    }
+
+    #[test]
+    fn gpaste_current_layout() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<history version="2.0">
+  <item kind="Text" uuid="1" size="15">
+    <value><![CDATA[hello & <world>]]></value>
+  </item>
+  <item kind="Password" uuid="2" name="bank">
+    <value><![CDATA[hunter2]]></value>
+  </item>
+  <item kind="Image" uuid="3" date="1700000000">
+    <value><![CDATA[/home/me/.local/share/gpaste/images/a.png]]></value>
+  </item>
+  <item kind="Uris" uuid="4">
+    <value><![CDATA[https://example.com]]></value>
+  </item>
+  <item kind="Text" uuid="5"><value>  </value></item>
+</history>"#;
+        assert_eq!(
+            parse_gpaste(xml).unwrap(),
+            [
+                ForeignClip::Text("hello & <world>".to_string()),
+                ForeignClip::ImageFile("/home/me/.local/share/gpaste/images/a.png".to_string()),
+                ForeignClip::Text("https://example.com".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn gpaste_old_layout() {
+        let xml = r#"<history version="1.0"><item kind="Text"><![CDATA[old style]]></item><item>esc &amp; aped</item></history>"#;
+        assert_eq!(
+            ClipboardManager::GPaste.parse(xml.as_bytes()).unwrap(),
+            [ForeignClip::Text("old style".to_string()), ForeignClip::Text("esc & aped".to_string())]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn gpaste_file_lists() {
+        let xml = "<history><item kind=\"Uris\"><value><![CDATA[file:///home/me/a%20b.txt\nfile:///tmp/c]]></value></item></history>";
+        assert_eq!(
+            parse_gpaste(xml).unwrap(),
+            [ForeignClip::Files(vec!["/home/me/a b.txt".to_string(), "/tmp/c".to_string()])]
+        );
+    }
+
+    #[test]
+    fn gpaste_rejects_other_files() {
+        assert!(parse_gpaste("<settings><item>x</item></settings>").is_err());
+        assert!(parse_gpaste("").is_err());
+        assert!(parse_gpaste("<history><item>unclosed</history>").is_err());
+    }
+}
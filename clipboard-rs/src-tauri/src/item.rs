@@ -0,0 +1,286 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::classify::{self, ContentType};
+use crate::source::SourceApp;
+
+/// The content of a history entry. Serialized with a `kind` tag so the
+/// frontend can switch on it instead of parsing a packed string.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ClipboardPayload {
+    Text {
+        text: String,
+    },
+    Image {
+        width: u32,
+        height: u32,
+        blob_path: String,
+        /// Hex SHA-256 of the decoded pixels (see `imagehash::rgba_hash`).
+        /// Empty for items captured before pixels were hashed.
+        #[serde(default)]
+        pixel_hash: String,
+        /// Hex difference hash used for near-duplicate detection.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        perceptual_hash: Option<String>,
+    },
+    FileList {
+        paths: Vec<String>,
+    },
+    /// Formatted text with its plain-text fallback. Items stored before RTF
+    /// was captured were tagged `html`.
+    #[serde(alias = "html")]
+    RichText {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        html: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rtf: Option<String>,
+    },
+}
+
+impl ClipboardPayload {
+    /// Short name of the payload variant, matching the serialized `kind` tag.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ClipboardPayload::Text { .. } => "text",
+            ClipboardPayload::Image { .. } => "image",
+            ClipboardPayload::FileList { .. } => "file_list",
+            ClipboardPayload::RichText { .. } => "rich_text",
+        }
+    }
+
+    /// Plain text used for searching and previews.
+    pub fn search_text(&self) -> String {
+        match self {
+            ClipboardPayload::Text { text } => text.clone(),
+            ClipboardPayload::Image { width, height, .. } => format!("Image {}x{}", width, height),
+            ClipboardPayload::FileList { paths } => paths.join("\n"),
+            ClipboardPayload::RichText { text, .. } => text.clone(),
+        }
+    }
+
+    /// Hash used to detect duplicates. Images use their pixel hash so the
+    /// same picture matches whichever file it was stored in; everything else
+    /// hashes the serialized payload.
+    pub fn content_hash(&self) -> String {
+        if let ClipboardPayload::Image { pixel_hash, .. } = self {
+            if !pixel_hash.is_empty() {
+                return pixel_hash.clone();
+            }
+        }
+        let bytes = serde_json::to_vec(self).unwrap_or_default();
+        format!("{:x}", Sha256::digest(bytes))
+    }
+
+    /// Perceptual hash of an image payload, if one was computed.
+    pub fn perceptual_hash(&self) -> Option<u64> {
+        match self {
+            ClipboardPayload::Image { perceptual_hash: Some(hash), .. } => u64::from_str_radix(hash, 16).ok(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ClipboardItem {
+    pub id: Uuid,
+    pub payload: ClipboardPayload,
+    pub timestamp: u64,
+    pub is_favorite: bool,
+    pub tags: Vec<String>,
+    /// Unix time after which the item is deleted automatically. Set for
+    /// clips the sensitive-content policy stores with an expiry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Window the clip was copied from, when the monitor could tell.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceApp>,
+    /// What the text looks like (URL, color, code, ...), detected on capture.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<ContentType>,
+    /// Text recognized in an image item (see `ocr`). Empty if none was
+    /// found; `None` until the image has been through OCR.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr_text: Option<String>,
+}
+
+impl ClipboardItem {
+    pub fn new(payload: ClipboardPayload) -> Self {
+        ClipboardItem {
+            id: Uuid::new_v4(),
+            content_type: classify::classify_payload(&payload),
+            payload,
+            timestamp: now_secs(),
+            is_favorite: false,
+            tags: vec![],
+            expires_at: None,
+            source: None,
+            ocr_text: None,
+        }
+    }
+}
+
+// Current unix time in seconds, falling back to 0 if the clock is before the epoch
+pub fn now_secs() -> u64 {
+    match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+// Shape of history entries written before items carried an ID and a typed payload
+#[derive(Deserialize)]
+struct LegacyClipboardItem {
+    content: String,
+    timestamp: u64,
+    #[serde(default)]
+    is_favorite: bool,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+impl From<LegacyClipboardItem> for ClipboardItem {
+    fn from(legacy: LegacyClipboardItem) -> Self {
+        let payload = parse_legacy_content(&legacy.content);
+        ClipboardItem {
+            id: Uuid::new_v4(),
+            content_type: classify::classify_payload(&payload),
+            payload,
+            timestamp: legacy.timestamp,
+            is_favorite: legacy.is_favorite,
+            tags: legacy.tags,
+            expires_at: None,
+            source: None,
+            ocr_text: None,
+        }
+    }
+}
+
+// Parse a pipe-encoded `content` string. Text was stored as `{text}|{timestamp}`
+// and images as `[Image] {w}x{h}|{path}|{timestamp}` (or `[Image] Unknown|...`).
+// The timestamp is always the last segment, so split from the right to keep any
+// `|` inside the text or path intact.
+fn parse_legacy_content(content: &str) -> ClipboardPayload {
+    let without_timestamp = match content.rsplit_once('|') {
+        Some((rest, ts)) if ts.parse::<u64>().is_ok() => rest,
+        _ => content,
+    };
+
+    if let Some(image) = without_timestamp.strip_prefix("[Image] ") {
+        if let Some((dimensions, path)) = image.split_once('|') {
+            let (width, height) = dimensions
+                .split_once('x')
+                .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                .unwrap_or((0, 0));
+            return ClipboardPayload::Image {
+                width,
+                height,
+                blob_path: path.to_string(),
+                pixel_hash: String::new(),
+                perceptual_hash: None,
+            };
+        }
+    }
+
+    ClipboardPayload::Text {
+        text: without_timestamp.to_string(),
+    }
+}
+
+/// Decode a stored history array, converting any legacy entries in place.
+/// Returns the items and whether a migration happened so the caller can
+/// write the upgraded list back.
+pub fn decode_history(value: serde_json::Value) -> (Vec<ClipboardItem>, bool) {
+    let entries = match value {
+        serde_json::Value::Array(entries) => entries,
+        _ => return (Vec::new(), false),
+    };
+
+    let mut migrated = false;
+    let mut history = Vec::with_capacity(entries.len());
+    for entry in entries {
+        if entry.get("content").is_some() {
+            if let Ok(legacy) = serde_json::from_value::<LegacyClipboardItem>(entry) {
+                history.push(ClipboardItem::from(legacy));
+                migrated = true;
+            }
+        } else if let Ok(mut item) = serde_json::from_value::<ClipboardItem>(entry) {
+            // Exports from before content types were detected
+            if item.content_type.is_none() {
+                item.content_type = classify::classify_payload(&item.payload);
+            }
+            history.push(item);
+        }
+    }
+    (history, migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> ClipboardPayload {
+        ClipboardPayload::Text { text: text.to_string() }
+    }
+
+    fn image(width: u32, height: u32, blob_path: &str) -> ClipboardPayload {
+        ClipboardPayload::Image {
+            width,
+            height,
+            blob_path: blob_path.to_string(),
+            pixel_hash: String::new(),
+            perceptual_hash: None,
+        }
+    }
+
+    #[test]
+    fn legacy_text_keeps_its_pipes() {
+        assert_eq!(parse_legacy_content("hello|1700000000"), text("hello"));
+        assert_eq!(parse_legacy_content("a | b || c|1700000000"), text("a | b || c"));
+        assert_eq!(parse_legacy_content("trailing pipe||1700000000"), text("trailing pipe|"));
+        assert_eq!(parse_legacy_content("|1700000000"), text(""));
+    }
+
+    #[test]
+    fn legacy_text_without_a_timestamp_is_kept_whole() {
+        assert_eq!(parse_legacy_content("no timestamp"), text("no timestamp"));
+        assert_eq!(parse_legacy_content("cat a.txt | grep b"), text("cat a.txt | grep b"));
+        assert_eq!(parse_legacy_content("ends with a pipe|"), text("ends with a pipe|"));
+        assert_eq!(parse_legacy_content("version|-1"), text("version|-1"));
+        assert_eq!(parse_legacy_content(""), text(""));
+    }
+
+    #[test]
+    fn legacy_images() {
+        assert_eq!(parse_legacy_content("[Image] 640x480|/tmp/a.png|1700000000"), image(640, 480, "/tmp/a.png"));
+        assert_eq!(parse_legacy_content("[Image] 640x480|/tmp/a|b.png|1700000000"), image(640, 480, "/tmp/a|b.png"));
+        assert_eq!(parse_legacy_content("[Image] 640x480|/tmp/a.png"), image(640, 480, "/tmp/a.png"));
+        assert_eq!(parse_legacy_content("[Image] Unknown|/tmp/a.png|1700000000"), image(0, 0, "/tmp/a.png"));
+        // Without a path it's just text that looks like an image label
+        assert_eq!(parse_legacy_content("[Image] 640x480|1700000000"), text("[Image] 640x480"));
+    }
+
+    #[test]
+    fn decodes_legacy_and_current_entries() {
+        let current = ClipboardItem::new(text("current"));
+        let value = serde_json::json!([
+            { "content": "old|text|1600000000", "timestamp": 1600000000, "is_favorite": true, "tags": ["work"] },
+            { "content": "[Image] 2x3|/tmp/b.png|1600000001", "timestamp": 1600000001 },
+            serde_json::to_value(&current).unwrap(),
+            { "content": "no timestamp field" },
+            { "unrelated": true },
+        ]);
+        let (items, migrated) = decode_history(value);
+        assert!(migrated);
+        let payloads: Vec<&ClipboardPayload> = items.iter().map(|item| &item.payload).collect();
+        assert_eq!(payloads, [&text("old|text"), &image(2, 3, "/tmp/b.png"), &text("current")]);
+        assert_eq!((items[0].timestamp, items[0].is_favorite, items[0].tags.as_slice()), (1600000000, true, &["work".to_string()][..]));
+        assert_eq!(items[2].id, current.id);
+
+        let (items, migrated) = decode_history(serde_json::json!([serde_json::to_value(&current).unwrap()]));
+        assert_eq!((items.len(), migrated), (1, false));
+        assert_eq!(decode_history(serde_json::json!({ "content": "x|1" })).0.len(), 0);
+    }
+}
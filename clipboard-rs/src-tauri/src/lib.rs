@@ -1,19 +1,175 @@
-use tauri::{Emitter};
+use tauri::Manager;
 use tauri_plugin_clipboard_manager::ClipboardExt;
 use tauri_plugin_store::StoreExt;
-use serde::{Deserialize, Serialize};
-use std::time::Duration;
 use std::thread;
 use uuid::Uuid;
-use image::{ImageFormat, RgbaImage, GenericImageView};
+use image::{DynamicImage, ImageFormat, RgbaImage};
 use base64::{Engine as _, engine::general_purpose};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-struct ClipboardItem {
-    content: String,
-    timestamp: u64,
-    is_favorite: bool,
-    tags: Vec<String>,
+mod archive;
+mod barcode;
+mod blobs;
+mod classify;
+mod collections;
+mod db;
+mod formats;
+mod history;
+mod imageedit;
+mod imagehash;
+mod importers;
+mod item;
+mod ocr;
+mod pause;
+mod qr;
+mod qrdecode;
+mod queue;
+mod retention;
+mod scan;
+mod search;
+#[cfg(unix)]
+pub mod rpc;
+mod sensitive;
+mod settings;
+mod snippets;
+mod source;
+mod thumbnails;
+mod transform;
+mod trash;
+mod vault;
+mod watcher;
+
+use blobs::{BlobStore, PendingBlob};
+use classify::ContentType;
+use db::{HistoryDb, HistoryStatistics};
+use item::{decode_history, now_secs, ClipboardItem, ClipboardPayload};
+use formats::ClipboardFormats;
+use history::History;
+use pause::Pause;
+use queue::{ClipContent, PasteQueue};
+use search::{SearchFilters, SearchMode, SearchResults};
+use sensitive::Verdict;
+use source::SourceApp;
+use vault::Vault;
+
+// Store used for history before it moved to SQLite
+const LEGACY_HISTORY_STORE: &str = "clipboard-history.bin";
+
+// Open the history, its vault and the image blob store from the app data
+// directory. An encrypted history starts out locked until `unlock_history`
+// is called.
+fn open_history(app: &tauri::AppHandle) -> Result<(HistoryDb, Vault, BlobStore), String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&data_dir).map_err(|e| e.to_string())?;
+    let db_path = data_dir.join("history.sqlite3");
+    let vault = Vault::open(&data_dir, db_path.clone());
+    let blob_store = BlobStore::open(&data_dir)?;
+
+    if vault.is_enabled() {
+        return Ok((HistoryDb::locked(), vault, blob_store));
+    }
+    let db = HistoryDb::open(&db_path).map_err(|e| e.to_string())?;
+    migrate_legacy_store(app, &db)?;
+
+    // Older versions kept image blobs in the temp directory
+    blob_store.adopt(&db, &vault)?;
+    blob_store.sweep(&db)?;
+    Ok((db, vault, blob_store))
+}
+
+// Move history from the old `clipboard-history.bin` store into the database.
+// Pipe-encoded entries from even older versions are converted on the way.
+fn migrate_legacy_store(app: &tauri::AppHandle, db: &HistoryDb) -> Result<(), String> {
+    let store = app.store(LEGACY_HISTORY_STORE).map_err(|e| e.to_string())?;
+    if let Some(value) = store.get("history") {
+        let (history, _) = decode_history(value);
+        db.insert_many(&history).map_err(|e| e.to_string())?;
+        store.delete("history");
+        store.save().map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Add an image to the history unless the same pixels (or, if enabled in the
+// settings, a near-duplicate) are already stored. `pixel_hash` is the image's
+// `imagehash::rgba_hash`. `write_blob` saves the image to the blob store; it
+// only runs for images that will be kept.
+fn push_history_image<'a>(
+    app: &tauri::AppHandle,
+    img: &RgbaImage,
+    pixel_hash: String,
+    source: Option<SourceApp>,
+    write_blob: impl FnOnce() -> Result<PendingBlob<'a>, String>,
+) -> Result<bool, String> {
+    let perceptual_hash = imagehash::perceptual_hash(img);
+    let max_distance = settings::load_settings(app).near_duplicate_image_distance;
+
+    let duplicate = app.state::<HistoryDb>()
+        .has_image(&pixel_hash, perceptual_hash, max_distance)
+        .map_err(|e| e.to_string())?;
+    if duplicate {
+        return Ok(false);
+    }
+
+    let blob = write_blob()?;
+    // Render the thumbnails now rather than while the history is scrolled.
+    // If that fails they're rendered when first shown.
+    let _ = thumbnails::generate(&app.state::<BlobStore>(), &app.state::<Vault>(), &blob.path(), img);
+    let mut item = ClipboardItem::new(ClipboardPayload::Image {
+        width: img.width(),
+        height: img.height(),
+        blob_path: blob.path(),
+        pixel_hash,
+        perceptual_hash: Some(format!("{:016x}", perceptual_hash)),
+    });
+    item.source = source;
+    push_history_item(app, item)
+}
+
+// Add text to the history after screening it for secrets. Depending on the
+// sensitive-content policy the text is dropped, masked or stored with an
+// expiry. `password_manager_hint` is whether the clipboard owner marked the
+// text as coming from a password manager.
+fn push_history_text(
+    app: &tauri::AppHandle,
+    text: &str,
+    password_manager_hint: bool,
+    source: Option<SourceApp>,
+) -> Result<bool, String> {
+    push_history_rich_text(app, text, None, None, password_manager_hint, source)
+}
+
+// Add formatted text to the history, screened like plain text. If masking
+// changed the plain text, the HTML and RTF copies are dropped rather than
+// stored with the secret still in them.
+fn push_history_rich_text(
+    app: &tauri::AppHandle,
+    text: &str,
+    html: Option<String>,
+    rtf: Option<String>,
+    password_manager_hint: bool,
+    source: Option<SourceApp>,
+) -> Result<bool, String> {
+    let policy = settings::load_settings(app).sensitive;
+    match sensitive::screen(text, password_manager_hint, &policy) {
+        Verdict::Skip => Ok(false),
+        Verdict::Store { text: stored, expire_after } => {
+            let payload = if stored == text && (html.is_some() || rtf.is_some()) {
+                ClipboardPayload::RichText { text: stored, html, rtf }
+            } else {
+                ClipboardPayload::Text { text: stored }
+            };
+            let mut item = ClipboardItem::new(payload);
+            item.expires_at = expire_after.map(|seconds| item.timestamp + seconds);
+            item.source = source;
+            push_history_item(app, item)
+        }
+    }
+}
+
+// Add a new item to the history unless an identical payload is already
+// stored. Returns whether the item was added.
+fn push_history_item(app: &tauri::AppHandle, new_item: ClipboardItem) -> Result<bool, String> {
+    app.state::<History>().insert(new_item)
 }
 
 #[tauri::command]
@@ -21,9 +177,28 @@ fn read_clipboard_text(app: tauri::AppHandle) -> Result<String, String> {
     app.clipboard().read_text().map_err(|e| e.to_string())
 }
 
+// Function to write text to the clipboard, with optional HTML and RTF
+// versions offered alongside it
 #[tauri::command]
-fn write_clipboard_text(app: tauri::AppHandle, text: &str) -> Result<(), String> {
-    app.clipboard().write_text(text).map_err(|e| e.to_string())
+fn write_clipboard_text(app: tauri::AppHandle, text: &str, html: Option<String>, rtf: Option<String>) -> Result<(), String> {
+    if html.is_none() && rtf.is_none() {
+        return app.clipboard().write_text(text).map_err(|e| e.to_string());
+    }
+    formats::write(&ClipboardFormats { text: Some(text.to_string()), html, rtf, files: Vec::new() })
+}
+
+// Function to put a history item back on the clipboard with every format it
+// was captured with
+#[tauri::command]
+fn restore_clipboard_item(app: tauri::AppHandle, db: tauri::State<'_, HistoryDb>, id: Uuid) -> Result<(), String> {
+    let item = db.get(id).map_err(|e| e.to_string())?.ok_or("Item not found")?;
+    match ClipboardFormats::from_payload(&item.payload) {
+        Some(formats) => formats::write(&formats),
+        None => match &item.payload {
+            ClipboardPayload::Image { blob_path, .. } => copy_image_from_file_to_clipboard(app, blob_path),
+            _ => Ok(()),
+        },
+    }
 }
 
 #[tauri::command]
@@ -43,355 +218,194 @@ async fn save_clipboard_history(app: tauri::AppHandle, text: &str) -> Result<(),
     if text.trim().is_empty() {
         return Ok(());
     }
-    
-    let store = app.store("clipboard-history.bin").map_err(|e| e.to_string())?;
-    let mut history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-    
-    // Check if the text already exists in history (for uniqueness)
-    let text_exists = history.iter().any(|item| {
-        // Extract the text part (before the timestamp)
-        let text_part = item.content.split('|').next().unwrap_or(&item.content);
-        text_part == text
-    });
-    
-    // Only add if it doesn't already exist
-    if !text_exists {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map_err(|e| e.to_string())?
-            .as_secs();
-        
-        let new_item = ClipboardItem {
-            content: format!("{}|{}", text, timestamp),
-            timestamp,
-            is_favorite: false,
-            tags: vec![],
-        };
-        
-        // Add the new entry to the beginning of the history
-        history.insert(0, new_item);
-        
-        // Limit history to 50 items
-        if history.len() > 50 {
-            history.truncate(50);
-        }
-        
-        store.set("history", serde_json::to_value(&history).map_err(|e| e.to_string()).unwrap());
-        store.save().map_err(|e| e.to_string())?;
-        
-        // Emit event to notify frontend of clipboard update
-        let _ = app.emit("clipboard-update", ());
-    }
+
+    push_history_text(&app, text, false, None)?;
     Ok(())
 }
 
 #[tauri::command]
-fn load_clipboard_history(app: tauri::AppHandle) -> Result<Vec<ClipboardItem>, String> {
-    let store = app.store("clipboard-history.bin").map_err(|e| e.to_string())?;
-    let history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-    Ok(history)
+fn load_clipboard_history(db: tauri::State<'_, HistoryDb>, limit: Option<usize>, offset: Option<usize>) -> Result<Vec<ClipboardItem>, String> {
+    db.list(limit, offset.unwrap_or(0)).map_err(|e| e.to_string())
 }
 
+// Function to fetch a single history item
 #[tauri::command]
-fn clear_clipboard_history(app: tauri::AppHandle) -> Result<(), String> {
-    let store = app.store("clipboard-history.bin").map_err(|e| e.to_string())?;
-    store.delete("history");
-    store.save().map_err(|e| e.to_string())?;
+fn get_clipboard_item(db: tauri::State<'_, HistoryDb>, id: Uuid) -> Result<ClipboardItem, String> {
+    db.get(id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Item not found".to_string())
+}
+
+// Function to move a single history item to the trash
+#[tauri::command]
+fn delete_clipboard_item(history: tauri::State<'_, History>, id: Uuid) -> Result<(), String> {
+    if !history.delete(id)? {
+        return Err("Item not found".to_string());
+    }
     Ok(())
 }
 
-// Function to search clipboard history
+// Function to move the whole history to the trash. Can be undone with
+// `undo_last_operation`.
 #[tauri::command]
-fn search_clipboard_history(app: tauri::AppHandle, query: &str) -> Result<Vec<String>, String> {
-    let store = app.store("clipboard-history.bin").map_err(|e| e.to_string())?;
-    let history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-    let string_history: Vec<String> = history.into_iter()
-        .filter(|item| {
-            // Extract the text part (before the timestamp)
-            let text_part = item.content.split('|').next().unwrap_or(&item.content);
-            text_part.to_lowercase().contains(&query.to_lowercase())
-        })
-        .map(|item| item.content)
-        .collect();
-    Ok(string_history)
+fn clear_clipboard_history(history: tauri::State<'_, History>) -> Result<(), String> {
+    history.clear()
 }
 
-// Function to toggle favorite status of an item
+// Function to search clipboard history. `mode` is `substring` (the default),
+// `fuzzy` or `regex`, and `filters` narrow the search by tag, favorite,
+// content type (`url`, `email`, `path`, `color`, `json`, `uuid`, `phone` or
+// `code`) and date range. Hits come back ranked, a page at a time, with the
+// byte ranges that matched.
 #[tauri::command]
-fn toggle_favorite(app: tauri::AppHandle, item_content: &str) -> Result<bool, String> {
-    let store = app.store("clipboard-history.bin").map_err(|e| e.to_string())?;
-    let mut history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-    
-    let mut is_now_favorite = false;
-    
-    // Find the item and toggle its favorite status
-    for item in history.iter_mut() {
-        if item.content == item_content {
-            item.is_favorite = !item.is_favorite;
-            is_now_favorite = item.is_favorite;
-            break;
+fn search_clipboard_history(
+    db: tauri::State<'_, HistoryDb>,
+    query: &str,
+    mode: Option<SearchMode>,
+    filters: Option<SearchFilters>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<SearchResults, String> {
+    let filters = filters.unwrap_or_default();
+    if let Some(content_type) = filters.content_type.as_deref() {
+        if !ContentType::NAMES.contains(&content_type) {
+            return Err(format!("Unknown content type '{}'", content_type));
         }
     }
-    
-    store.set("history", serde_json::to_value(history).map_err(|e| e.to_string())?);
-    store.save().map_err(|e| e.to_string())?;
-    
-    Ok(is_now_favorite)
+    search::search(&db, query, mode.unwrap_or_default(), &filters, offset.unwrap_or(0), limit)
 }
 
-// Function to load favorite items
+// Function to toggle favorite status of an item
 #[tauri::command]
-fn load_favorites(app: tauri::AppHandle) -> Result<Vec<String>, String> {
-    let store = app.store("clipboard-history.bin").map_err(|e| e.to_string())?;
-    let history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-    let favorites: Vec<String> = history.into_iter()
-        .filter(|item| item.is_favorite)
-        .map(|item| item.content)
-        .collect();
-    Ok(favorites)
+fn toggle_favorite(history: tauri::State<'_, History>, id: Uuid) -> Result<bool, String> {
+    history.toggle_favorite(id)?
+        .ok_or_else(|| "Item not found".to_string())
 }
 
-// Function to clean up old clipboard items
+// Function to load favorite items
 #[tauri::command]
-fn cleanup_old_items(app: tauri::AppHandle, max_age_seconds: u64) -> Result<usize, String> {
-    let store = app.store("clipboard-history.bin").map_err(|e| e.to_string())?;
-    let history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-    
-    let current_time = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map_err(|e| e.to_string())?
-        .as_secs();
-    
-    let original_count = history.len();
-    
-    // Filter out items older than max_age_seconds, but keep favorites
-    let filtered_history: Vec<ClipboardItem> = history.into_iter()
-        .filter(|item| {
-            // Keep favorites regardless of age
-            item.is_favorite || (current_time - item.timestamp) <= max_age_seconds
-        })
-        .collect();
-    
-    let removed_count = original_count - filtered_history.len();
-    
-    store.set("history", serde_json::to_value(filtered_history).map_err(|e| e.to_string()).unwrap());
-    store.save().map_err(|e| e.to_string())?;
-    
-    // Emit event to notify frontend of clipboard update
-    let _ = app.emit("clipboard-update", ());
-    
-    Ok(removed_count)
+fn load_favorites(db: tauri::State<'_, HistoryDb>) -> Result<Vec<ClipboardItem>, String> {
+    db.favorites().map_err(|e| e.to_string())
 }
 
 // Function to export clipboard history to JSON
 #[tauri::command]
-fn export_history(app: tauri::AppHandle) -> Result<String, String> {
-    let store = app.store("clipboard-history.bin").map_err(|e| e.to_string())?;
-    let history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-    
+fn export_history(db: tauri::State<'_, HistoryDb>) -> Result<String, String> {
+    let history = db.list(None, 0).map_err(|e| e.to_string())?;
+
     serde_json::to_string_pretty(&history).map_err(|e| e.to_string())
 }
 
-// Function to import clipboard history from JSON
+// Function to import clipboard history from JSON. Files exported by older
+// versions still use the pipe-encoded format and are converted on the way in.
 #[tauri::command]
-fn import_history(app: tauri::AppHandle, json_data: &str) -> Result<usize, String> {
-    let imported_history: Vec<ClipboardItem> = serde_json::from_str(json_data).map_err(|e| e.to_string())?;
-    
-    let store = app.store("clipboard-history.bin").map_err(|e| e.to_string())?;
-    let mut existing_history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-    
-    let original_count = existing_history.len();
-    
-    // Add imported items to existing history
-    existing_history.extend(imported_history);
-    
-    // Remove duplicates based on content
-    existing_history.sort_by_key(|item| item.content.clone());
-    existing_history.dedup_by_key(|item| item.content.clone());
-    
-    // Limit to 100 items
-    if existing_history.len() > 100 {
-        existing_history.truncate(100);
-    }
-    
-    let added_count = existing_history.len() - original_count;
-    
-    store.set("history", serde_json::to_value(&existing_history).map_err(|e| e.to_string()).unwrap());
-    store.save().map_err(|e| e.to_string())?;
-    
-    // Emit event to notify frontend of clipboard update
-    let _ = app.emit("clipboard-update", ());
-    
-    Ok(added_count)
+fn import_history(history: tauri::State<'_, History>, json_data: &str) -> Result<usize, String> {
+    let value: serde_json::Value = serde_json::from_str(json_data).map_err(|e| e.to_string())?;
+    let (imported_history, _) = decode_history(value);
+
+    // Add imported items, skipping ones we already have by ID or content.
+    // The import can be undone with `undo_last_operation`.
+    history.import(imported_history)
 }
 
 // Function to add a tag to an item
 #[tauri::command]
-fn add_tag_to_item(app: tauri::AppHandle, item_content: &str, tag: &str) -> Result<(), String> {
-    let store = app.store("clipboard-history.bin").map_err(|e| e.to_string())?;
-    let mut history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-    
-    // Find the item and add the tag
-    for item in history.iter_mut() {
-        if item.content == item_content {
-            // Check if tag already exists
-            if !item.tags.contains(&tag.to_string()) {
-                item.tags.push(tag.to_string());
-            }
-            break;
-        }
+fn add_tag_to_item(history: tauri::State<'_, History>, id: Uuid, tag: &str) -> Result<(), String> {
+    if !history.add_tag(id, tag.to_string())? {
+        return Err("Item not found".to_string());
     }
-    
-    store.set("history", serde_json::to_value(&history).map_err(|e| e.to_string()).unwrap());
-    store.save().map_err(|e| e.to_string())?;
-    
-    // Emit event to notify frontend of clipboard update
-    let _ = app.emit("clipboard-update", ());
-    
     Ok(())
 }
 
 // Function to remove a tag from an item
 #[tauri::command]
-fn remove_tag_from_item(app: tauri::AppHandle, item_content: &str, tag: &str) -> Result<(), String> {
-    let store = app.store("clipboard-history.bin").map_err(|e| e.to_string())?;
-    let mut history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-    
-    // Find the item and remove the tag
-    for item in history.iter_mut() {
-        if item.content == item_content {
-            item.tags.retain(|t| t != tag);
-            break;
-        }
+fn remove_tag_from_item(history: tauri::State<'_, History>, id: Uuid, tag: &str) -> Result<(), String> {
+    if !history.remove_tag(id, tag.to_string())? {
+        return Err("Item not found".to_string());
     }
-    
-    store.set("history", serde_json::to_value(&history).map_err(|e| e.to_string()).unwrap());
-    store.save().map_err(|e| e.to_string())?;
-    
-    // Emit event to notify frontend of clipboard update
-    let _ = app.emit("clipboard-update", ());
-    
     Ok(())
 }
 
 // Function to get all unique tags
 #[tauri::command]
-fn get_all_tags(app: tauri::AppHandle) -> Result<Vec<String>, String> {
-    let store = app.store("clipboard-history.bin").map_err(|e| e.to_string())?;
-    let history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-    
-    let mut tags: Vec<String> = Vec::new();
-    for item in history {
-        tags.extend(item.tags);
-    }
-    
-    // Remove duplicates
-    tags.sort();
-    tags.dedup();
-    
-    Ok(tags)
+fn get_all_tags(db: tauri::State<'_, HistoryDb>) -> Result<Vec<String>, String> {
+    db.all_tags().map_err(|e| e.to_string())
 }
 
 // Function to get clipboard statistics
 #[tauri::command]
-fn get_clipboard_statistics(app: tauri::AppHandle) -> Result<serde_json::Value, String> {
-    let store = app.store("clipboard-history.bin").map_err(|e| e.to_string())?;
-    let history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-    
-    let total_items = history.len();
-    let favorite_items = history.iter().filter(|item| item.is_favorite).count();
-    let text_items = history.iter().filter(|item| !item.content.starts_with("[Image]")).count();
-    let image_items = history.iter().filter(|item| item.content.starts_with("[Image]")).count();
-    
-    // Count tags
-    let mut tag_counts: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
-    for item in &history {
-        for tag in &item.tags {
-            *tag_counts.entry(tag.clone()).or_insert(0) += 1;
-        }
-    }
-    
-    // Get most used tags (top 5)
-    let mut sorted_tags: Vec<(String, usize)> = tag_counts.into_iter().collect();
-    sorted_tags.sort_by(|a, b| b.1.cmp(&a.1));
-    let top_tags = sorted_tags.into_iter().take(5).collect::<Vec<_>>();
-    
-    // Calculate date range
-    let earliest_timestamp = history.iter().map(|item| item.timestamp).min().unwrap_or(0);
-    let latest_timestamp = history.iter().map(|item| item.timestamp).max().unwrap_or(0);
-    
-    let stats = serde_json::json!({
-        "totalItems": total_items,
-        "favoriteItems": favorite_items,
-        "textItems": text_items,
-        "imageItems": image_items,
-        "topTags": top_tags,
-        "earliestTimestamp": earliest_timestamp,
-        "latestTimestamp": latest_timestamp,
-    });
-    
-    Ok(stats)
+fn get_clipboard_statistics(db: tauri::State<'_, HistoryDb>) -> Result<HistoryStatistics, String> {
+    db.statistics().map_err(|e| e.to_string())
+}
+
+// Encode an RGBA image as PNG and add it to the blob store, encrypted if
+// history encryption is enabled
+fn save_rgba_image_blob<'a>(app: &'a tauri::AppHandle, img: &RgbaImage) -> Result<PendingBlob<'a>, String> {
+    let mut buffer: Vec<u8> = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut buffer), ImageFormat::Png)
+        .map_err(|e| format!("Failed to save image: {}", e))?;
+
+    app.state::<Vault>().write_blob(app.state::<BlobStore>().inner(), &buffer, "png")
 }
 
-// Function to save image data to a temporary file and return the file path
+// Function to save image data to the blob store and return the file path.
+// Nothing references the blob, so it's removed the next time the app starts.
 #[tauri::command]
 fn save_clipboard_image_data(app: tauri::AppHandle) -> Result<String, String> {
     match app.clipboard().read_image() {
         Ok(image) => {
-            // Create a unique filename for the image
-            let filename = format!("{}.png", Uuid::new_v4());
-            let temp_dir = std::env::temp_dir();
-            let file_path = temp_dir.join(filename);
-            
-            // Convert clipboard image to RGBA image
-            let width = image.width();
-            let height = image.height();
-            let rgba_data = image.rgba();
-            
             // Create RGBA image from clipboard data
-            let img = RgbaImage::from_raw(width, height, rgba_data.to_vec())
+            let img = RgbaImage::from_raw(image.width(), image.height(), image.rgba().to_vec())
                 .ok_or("Failed to create image from clipboard data")?;
-            
-            // Save the image to file
-            img.save_with_format(&file_path, ImageFormat::Png)
-                .map_err(|e| format!("Failed to save image: {}", e))?;
-            
-            // Return the file path
-            Ok(file_path.to_string_lossy().to_string())
+
+            save_rgba_image_blob(&app, &img).map(|blob| blob.path())
         },
         Err(e) => Err(e.to_string())
     }
 }
 
-// Function to copy an image file to a temporary location
-fn copy_image_file_to_temp(file_path: String) -> Result<String, String> {
+// Function to copy an image file into the blob store
+fn copy_image_file_to_blob<'a>(app: &'a tauri::AppHandle, file_path: String) -> Result<PendingBlob<'a>, String> {
     let source_path = std::path::Path::new(&file_path);
     if !source_path.exists() {
         return Err("Source file does not exist".to_string());
     }
     
-    // Create a unique filename for the copied image
-    let filename = format!("{}.{}", Uuid::new_v4(), source_path.extension().unwrap_or_default().to_string_lossy());
-    let temp_dir = std::env::temp_dir();
-    let dest_path = temp_dir.join(filename);
-    
-    // Copy the file
-    std::fs::copy(source_path, &dest_path)
+    // Copy the file, encrypting it if history encryption is enabled
+    let bytes = std::fs::read(source_path)
         .map_err(|e| format!("Failed to copy image file: {}", e))?;
+    let extension = source_path.extension().unwrap_or_default().to_string_lossy();
+    app.state::<Vault>().write_blob(app.state::<BlobStore>().inner(), &bytes, &extension)
+}
+
+// Function to decode an image blob, decrypting it if needed
+fn load_image_blob(app: &tauri::AppHandle, file_path: &str) -> Result<DynamicImage, String> {
+    let bytes = app.state::<Vault>().read_blob(file_path)?;
+    image::load_from_memory(&bytes)
+        .map_err(|e| format!("Failed to load image from file: {}", e))
+}
+
+// Function to encode an image as a base64 PNG data URL
+fn image_data_url(img: &DynamicImage) -> Result<String, String> {
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut cursor = std::io::Cursor::new(&mut buffer);
+    img.write_to(&mut cursor, ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode image: {}", e))?;
     
-    // Return the destination file path
-    Ok(dest_path.to_string_lossy().to_string())
+    let base64_data = general_purpose::STANDARD.encode(&buffer);
+    Ok(format!("data:image/png;base64,{}", base64_data))
 }
 
 // Function to load image from file and copy it to clipboard
 #[tauri::command(rename_all = "snake_case")]
 fn copy_image_from_file_to_clipboard(app: tauri::AppHandle, file_path: &str) -> Result<(), String> {
     // Load image from file
-    let img = image::open(file_path)
-        .map_err(|e| format!("Failed to load image from file: {}", e))?;
-    
-    // Convert to RGBA
-    let rgba_img = img.to_rgba8();
+    let img = load_image_blob(&app, file_path)?;
+    write_clipboard_image(&app, img.to_rgba8())
+}
+
+// Function to put decoded pixels on the clipboard
+fn write_clipboard_image(app: &tauri::AppHandle, rgba_img: RgbaImage) -> Result<(), String> {
     let (width, height) = rgba_img.dimensions();
     
     // Get the raw image data
@@ -408,247 +422,141 @@ fn copy_image_from_file_to_clipboard(app: tauri::AppHandle, file_path: &str) ->
     Ok(())
 }
 
-// Function to generate base64 thumbnail for image preview
+// Function to load a full-size image as a data URL for the preview dialog.
+// Blobs may be encrypted, so the webview can't load them from disk itself.
 #[tauri::command]
-fn get_image_thumbnail(_app: tauri::AppHandle, file_path: &str) -> Result<String, String> {
-    // Load image from file
-    let img = image::open(file_path)
-        .map_err(|e| format!("Failed to load image from file: {}", e))?;
-    
-    // Calculate dimensions for thumbnail while preserving aspect ratio
-    let (width, height) = img.dimensions();
-    let max_size = 200u32;
-    
-    let scale_factor = if width > height {
-        max_size as f32 / width as f32
-    } else {
-        max_size as f32 / height as f32
-    };
-    
-    let new_width = (width as f32 * scale_factor) as u32;
-    let new_height = (height as f32 * scale_factor) as u32;
-    
-    // Resize to thumbnail size while preserving aspect ratio
-    let thumbnail = img.resize(new_width, new_height, image::imageops::FilterType::Lanczos3);
-    
-    // Encode as base64 PNG
-    let mut buffer: Vec<u8> = Vec::new();
-    let mut cursor = std::io::Cursor::new(&mut buffer);
-    thumbnail.write_to(&mut cursor, ImageFormat::Png)
-        .map_err(|e| format!("Failed to encode image: {}", e))?;
-    
-    let base64_data = general_purpose::STANDARD.encode(&buffer);
-    Ok(format!("data:image/png;base64,{}", base64_data))
+fn get_image_data_url(app: tauri::AppHandle, file_path: &str) -> Result<String, String> {
+    image_data_url(&load_image_blob(&app, file_path)?)
+}
+
+// Check if a path points to an existing image file, judging by its extension
+fn is_image_file(path: &str) -> bool {
+    let path = std::path::Path::new(path);
+    if !path.is_file() {
+        return false;
+    }
+    match path.extension() {
+        Some(extension) => {
+            let ext = extension.to_string_lossy().to_lowercase();
+            matches!(ext.as_str(), "png" | "jpg" | "jpeg" | "gif" | "bmp" | "webp" | "tiff" | "tif")
+        }
+        None => false,
+    }
+}
+
+// Add an image file to the history. Decoding it lets us hash the pixels, so
+// copying the same file twice is caught as a duplicate.
+fn capture_image_file(app: &tauri::AppHandle, path: &str, source: Option<SourceApp>) {
+    match image::open(path) {
+        Ok(img) => {
+            let img = img.to_rgba8();
+            let pixel_hash = imagehash::rgba_hash(&img);
+            let _ = push_history_image(app, &img, pixel_hash, source, || copy_image_file_to_blob(app, path.to_string()));
+        }
+        Err(_) => {
+            // If we can't load the image, still copy it but use placeholder dimensions
+            if let Ok(blob) = copy_image_file_to_blob(app, path.to_string()) {
+                let mut item = ClipboardItem::new(ClipboardPayload::Image {
+                    width: 0,
+                    height: 0,
+                    blob_path: blob.path(),
+                    pixel_hash: String::new(),
+                    perceptual_hash: None,
+                });
+                item.source = source;
+                let _ = push_history_item(app, item);
+            }
+        }
+    }
 }
 
 // Function to start clipboard monitoring in a background thread
 fn start_clipboard_monitoring(app_handle: tauri::AppHandle) {
     thread::spawn(move || {
-        let mut last_clipboard_content = String::new();
+        let mut last_formats = ClipboardFormats::default();
         let mut last_image_hash = String::new();
-        
+
+        // Prefer selection-change notifications over polling when the
+        // session supports them
+        let mut watcher = watcher::detect();
+
+        // Whatever is on the clipboard at startup was copied before we were
+        // watching, so the active window says nothing about where it came from
+        let mut startup = true;
+
         loop {
-            // Sleep for a short duration to avoid excessive CPU usage
-            thread::sleep(Duration::from_millis(500));
-            
-            // Try to read clipboard content
-            let text_result = app_handle.clipboard().read_text();
-            match text_result {
-                Ok(current_content) => {
-                    // Check if clipboard content has changed and is not empty
-                    if current_content != last_clipboard_content && !current_content.trim().is_empty() {
-                        // Update last clipboard content
-                        last_clipboard_content = current_content.clone();
-                        
-                        // Check if the text content is a file path to an image file
-                        let is_image_file = {
-                            let path = std::path::Path::new(&current_content);
-                            if path.exists() && path.is_file() {
-                                // Check if it's an image file by extension
-                                if let Some(extension) = path.extension() {
-                                    let ext = extension.to_string_lossy().to_lowercase();
-                                    matches!(ext.as_str(), "png" | "jpg" | "jpeg" | "gif" | "bmp" | "webp" | "tiff" | "tif")
-                                } else {
-                                    false
-                                }
-                            } else {
-                                false
-                            }
-                        };
-                        
-                        if is_image_file {
-                            // Handle file path to image file
-                            // Create a simple hash of the file path to detect changes
-                            let image_info = format!("ImageFile:{}", current_content);
-                            
-                            if image_info != last_image_hash {
-                                last_image_hash = image_info.clone();
-                                
-                                // Try to load the image to get its dimensions
-                                match image::open(&current_content) {
-                                    Ok(img) => {
-                                        let (width, height) = img.dimensions();
-                                        
-                                        // Copy the image file to our temp directory
-                                        if let Ok(file_path) = copy_image_file_to_temp(current_content.clone()) {
-                                            if let Ok(store) = app_handle.store("clipboard-history.bin") {
-                                                let mut history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-                                                
-                                                // Add timestamp to the image entry
-                                                let timestamp = match std::time::SystemTime::now()
-                                                    .duration_since(std::time::UNIX_EPOCH) {
-                                                        Ok(duration) => duration.as_secs(),
-                                                        Err(_) => 0, // fallback to 0 if timestamp fails
-                                                    };
-                                                
-                                                let image_entry = ClipboardItem {
-                                                    content: format!("[Image] {}x{}|{}|{}", width, height, file_path, timestamp),
-                                                    timestamp,
-                                                    is_favorite: false,
-                                                    tags: vec![],
-                                                };
-                                                history.insert(0, image_entry);
-                                                
-                                                // Limit history to 50 items
-                                                if history.len() > 50 {
-                                                    history.truncate(50);
-                                                }
-                                                
-                                                store.set("history", serde_json::to_value(history).map_err(|e| e.to_string()).unwrap());
-                                                let _ = store.save();
-                                                
-                                                // Emit event to notify frontend of clipboard update
-                                                let _ = app_handle.emit("clipboard-update", ());
-                                            }
-                                        }
-                                    },
-                                    Err(_) => {
-                                        // If we can't load the image, still copy it but use placeholder dimensions
-                                        if let Ok(file_path) = copy_image_file_to_temp(current_content.clone()) {
-                                            if let Ok(store) = app_handle.store("clipboard-history.bin") {
-                                                let mut history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-                                                
-                                                // Add timestamp to the image entry
-                                                let timestamp = match std::time::SystemTime::now()
-                                                    .duration_since(std::time::UNIX_EPOCH) {
-                                                        Ok(duration) => duration.as_secs(),
-                                                        Err(_) => 0, // fallback to 0 if timestamp fails
-                                                    };
-                                                
-                                                let image_entry = ClipboardItem {
-                                                    content: format!("[Image] Unknown|{}|{}", file_path, timestamp),
-                                                    timestamp,
-                                                    is_favorite: false,
-                                                    tags: vec![],
-                                                };
-                                                history.insert(0, image_entry);
-                                                
-                                                // Limit history to 50 items
-                                                if history.len() > 50 {
-                                                    history.truncate(50);
-                                                }
-                                                
-                                                store.set("history", serde_json::to_value(history).map_err(|e| e.to_string()).unwrap());
-                                                let _ = store.save();
-                                                
-                                                // Emit event to notify frontend of clipboard update
-                                                let _ = app_handle.emit("clipboard-update", ());
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        } else {
-                            // Handle regular text content
-                            // Save to history (with uniqueness check)
-                            if let Ok(store) = app_handle.store("clipboard-history.bin") {
-                                let mut history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-                                
-                                // Check if the text already exists in history (for uniqueness)
-                                let text_exists = history.iter().any(|item| {
-                                    // Extract the text part (before the timestamp)
-                                    let text_part = item.content.split('|').next().unwrap_or(&item.content);
-                                    text_part == &current_content
-                                });
-                                
-                                // Only add if it doesn't already exist
-                                if !text_exists {
-                                    let timestamp = match std::time::SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH) {
-                                            Ok(duration) => duration.as_secs(),
-                                            Err(_) => 0, // fallback to 0 if timestamp fails
-                                        };
-                                    
-                                    let new_item = ClipboardItem {
-                                        content: format!("{}|{}", current_content, timestamp),
-                                        timestamp,
-                                        is_favorite: false,
-                                        tags: vec![],
-                                    };
-                                    
-                                    // Add the new entry to the beginning of the history
-                                    history.insert(0, new_item);
-                                    
-                                    // Limit history to 50 items
-                                    if history.len() > 50 {
-                                        history.truncate(50);
-                                    }
-                                    
-                                    store.set("history", serde_json::to_value(history).map_err(|e| e.to_string()).unwrap());
-                                    let _ = store.save();
-                                    
-                                    // Emit event to notify frontend of clipboard update
-                                    let _ = app_handle.emit("clipboard-update", ());
-                                }
-                            }
-                        }
-                    }
+            // Password managers tag secrets with a MIME hint; it gets
+            // screened like any other sensitive category
+            let password_manager_hint = watcher
+                .offered_mime_types()
+                .iter()
+                .any(|mime| mime == sensitive::PASSWORD_MANAGER_HINT);
+
+            // Clips copied while paused or from excluded apps are noted as
+            // seen but never saved, so resuming doesn't pick them up late
+            let source = if startup { None } else { watcher.source_app() };
+            let excluded = app_handle.state::<Pause>().is_paused()
+                || source
+                    .as_ref()
+                    .is_some_and(|app| settings::load_settings(&app_handle).exclude.excludes(app));
+            startup = false;
+
+            // Read every text-like format the clipboard offers
+            let current_formats = formats::read();
+
+            // Check if clipboard content has changed and is not empty
+            if current_formats != last_formats && !current_formats.is_empty() {
+                // Update last clipboard content
+                last_formats = current_formats.clone();
+
+                // A single copied image file, or its path copied as text, is
+                // stored as the image itself
+                let image_file = match current_formats.files.as_slice() {
+                    [file] => Some(file.clone()),
+                    [] => current_formats.text.clone(),
+                    _ => None,
                 }
-                Err(_) => {
-                    // Ignore text reading errors
+                .filter(|path| is_image_file(path));
+
+                // Items the paste queue loaded are already in the history
+                let queued = app_handle
+                    .state::<PasteQueue>()
+                    .claims(&app_handle, &ClipContent::Formats(current_formats.clone()));
+
+                let ClipboardFormats { text, html, rtf, files } = current_formats;
+                if excluded || queued {
+                    // Leave it out of the history entirely
+                } else if let Some(path) = image_file {
+                    capture_image_file(&app_handle, &path, source.clone());
+                } else if !files.is_empty() {
+                    let mut item = ClipboardItem::new(ClipboardPayload::FileList { paths: files });
+                    item.source = source.clone();
+                    let _ = push_history_item(&app_handle, item);
+                } else if html.is_some() || rtf.is_some() {
+                    // Save formatted text along with its plain-text fallback
+                    let text = text.unwrap_or_default();
+                    let _ = push_history_rich_text(&app_handle, &text, html, rtf, password_manager_hint, source.clone());
+                } else if let Some(text) = text {
+                    // Handle regular text content
+                    // Save to history (with uniqueness and sensitive-content checks)
+                    let _ = push_history_text(&app_handle, &text, password_manager_hint, source.clone());
                 }
             }
-            
+
             // Always try to read image data
             match app_handle.clipboard().read_image() {
                 Ok(image) => {
-                    // Create a simple hash of the image data to detect changes
-                    // Using dimensions for simplicity
-                    let image_info = format!("Image:{}x{}", image.width(), image.height());
-                    
-                    if image_info != last_image_hash {
-                        last_image_hash = image_info.clone();
-                        
-                        // Save the image data to a file
-                        if let Ok(file_path) = save_clipboard_image_data(app_handle.clone()) {
-                            if let Ok(store) = app_handle.store("clipboard-history.bin") {
-                                let mut history: Vec<ClipboardItem> = store.get("history").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_else(Vec::new);
-                                
-                                // Add timestamp to the image entry
-                                let timestamp = match std::time::SystemTime::now()
-                                    .duration_since(std::time::UNIX_EPOCH) {
-                                        Ok(duration) => duration.as_secs(),
-                                        Err(_) => 0, // fallback to 0 if timestamp fails
-                                    };
-                                
-                                let image_entry = ClipboardItem {
-                                    content: format!("[Image] {}x{}|{}|{}", image.width(), image.height(), file_path, timestamp),
-                                    timestamp,
-                                    is_favorite: false,
-                                    tags: vec![],
-                                };
-                                history.insert(0, image_entry);
-                                
-                                // Limit history to 50 items
-                                if history.len() > 50 {
-                                    history.truncate(50);
-                                }
-                                
-                                store.set("history", serde_json::to_value(history).map_err(|e| e.to_string()).unwrap());
-                                let _ = store.save();
-                                
-                                // Emit event to notify frontend of clipboard update
-                                let _ = app_handle.emit("clipboard-update", ());
+                    if let Some(img) = RgbaImage::from_raw(image.width(), image.height(), image.rgba().to_vec()) {
+                        // Skip the history lookup while the same image stays on the clipboard
+                        let image_hash = imagehash::rgba_hash(&img);
+                        if image_hash != last_image_hash {
+                            last_image_hash = image_hash.clone();
+
+                            let queued = app_handle
+                                .state::<PasteQueue>()
+                                .claims(&app_handle, &ClipContent::Image(image_hash.clone()));
+                            if !excluded && !queued {
+                                let _ = push_history_image(&app_handle, &img, image_hash, source, || save_rgba_image_blob(&app_handle, &img));
                             }
                         }
                     }
@@ -657,10 +565,32 @@ fn start_clipboard_monitoring(app_handle: tauri::AppHandle) {
                     // Ignore errors silently to avoid spamming logs
                 }
             }
+
+            // Block until the clipboard changes. If the watcher's connection
+            // goes away, fall back to polling for the rest of the session.
+            if watcher.wait().is_err() {
+                watcher = Box::new(watcher::PollingWatcher::default());
+            }
         }
     });
 }
 
+// Function to delete expired sensitive items once a second
+fn start_expiry_sweeper(app_handle: tauri::AppHandle) {
+    thread::spawn(move || loop {
+        let _ = app_handle.state::<History>().delete_expired(now_secs());
+        thread::sleep(std::time::Duration::from_secs(1));
+    });
+}
+
+// Function to write an unlocked encrypted history to disk every few seconds
+fn start_vault_autosave(app_handle: tauri::AppHandle) {
+    thread::spawn(move || loop {
+        thread::sleep(std::time::Duration::from_secs(2));
+        let _ = app_handle.state::<Vault>().save(&app_handle.state::<HistoryDb>());
+    });
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -668,32 +598,126 @@ pub fn run() {
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_store::Builder::default().build())
         .setup(|app| {
-            // Start clipboard monitoring when the app starts
+            // Open the history database and bring over history written by
+            // older versions before anything reads it
             let app_handle = app.handle().clone();
-            start_clipboard_monitoring(app_handle);
+            let (db, vault, blob_store) = open_history(&app_handle)?;
+            app.manage(db);
+            app.manage(vault);
+            app.manage(blob_store);
+
+            // Every change to the history goes through one writer thread
+            app.manage(History::start(app_handle.clone()));
+            start_vault_autosave(app_handle.clone());
+
+            // Capture may have been left paused by the last session
+            app.manage(Pause::load(&app_handle));
+            pause::start_timer(app_handle.clone());
+
+            // Queued items are pasted one after another
+            app.manage(PasteQueue::default());
+            queue::start_watcher(app_handle.clone());
+
+            // Start clipboard monitoring when the app starts
+            start_expiry_sweeper(app_handle.clone());
+            retention::start_scheduler(app_handle.clone());
+            ocr::start_worker(app_handle.clone());
+            start_clipboard_monitoring(app_handle.clone());
+
+            // Let scripts and `clipctl` drive the history over a local socket
+            #[cfg(unix)]
+            rpc::start_server(app_handle);
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             read_clipboard_text, 
             write_clipboard_text,
+            restore_clipboard_item,
             read_clipboard_image,
             save_clipboard_history,
             load_clipboard_history,
+            get_clipboard_item,
+            delete_clipboard_item,
             clear_clipboard_history,
             search_clipboard_history,
+            classify::get_item_actions,
+            classify::run_item_action,
             toggle_favorite,
             load_favorites,
-            cleanup_old_items,
+            retention::apply_retention_policy,
+            trash::list_trash,
+            trash::restore_from_trash,
+            trash::empty_trash,
+            trash::get_last_operation,
+            trash::undo_last_operation,
             export_history,
             import_history,
+            archive::export_history_archive,
+            archive::import_history_archive,
+            importers::import_from_clipboard_manager,
+            snippets::list_snippets,
+            snippets::save_snippet,
+            snippets::delete_snippet,
+            snippets::get_snippet_variables,
+            snippets::expand_snippet,
+            snippets::export_snippets,
+            snippets::import_snippets,
             add_tag_to_item,
             remove_tag_from_item,
             get_all_tags,
+            collections::get_collections,
+            collections::create_collection,
+            collections::create_collection_from_tag,
+            collections::rename_collection,
+            collections::delete_collection,
+            collections::add_to_collection,
+            collections::remove_from_collection,
+            collections::reorder_collection,
+            collections::get_collection_statistics,
+            collections::export_collection_archive,
             get_clipboard_statistics,
             save_clipboard_image_data,
             copy_image_from_file_to_clipboard,
-            get_image_thumbnail
+            thumbnails::get_image_thumbnail,
+            get_image_data_url,
+            imageedit::preview_image_edit,
+            imageedit::save_image_edit,
+            ocr::copy_recognized_text,
+            ocr::get_ocr_status,
+            qr::render_qr_code,
+            qr::copy_qr_code,
+            qr::save_qr_code,
+            qr::scan_image_codes,
+            qr::add_decoded_text,
+            settings::get_settings,
+            settings::update_settings,
+            pause::get_pause_state,
+            pause::pause_capture,
+            pause::resume_capture,
+            queue::get_paste_queue,
+            queue::enqueue_paste_items,
+            queue::reorder_paste_queue,
+            queue::advance_paste_queue,
+            queue::clear_paste_queue,
+            transform::list_transforms,
+            transform::transform_clipboard_item,
+            transform::apply_transform_preset,
+            transform::save_transform_preset,
+            transform::delete_transform_preset,
+            vault::get_encryption_status,
+            vault::enable_encryption,
+            vault::disable_encryption,
+            vault::unlock_history,
+            vault::lock_history
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app_handle, event| {
+            // Don't lose the last few seconds of an encrypted history
+            if let tauri::RunEvent::Exit = event {
+                let _ = app_handle.state::<Vault>().save(&app_handle.state::<HistoryDb>());
+                #[cfg(unix)]
+                rpc::stop_server();
+            }
+        });
 }
\ No newline at end of file
@@ -0,0 +1,221 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use image::imageops::FilterType;
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use uuid::Uuid;
+
+use crate::db::HistoryDb;
+use crate::history::History;
+use crate::item::{ClipboardItem, ClipboardPayload};
+use crate::settings;
+use crate::vault::Vault;
+
+// How long the worker waits before looking for images again when there was
+// nothing to do or Tesseract isn't usable
+const IDLE_INTERVAL: Duration = Duration::from_secs(10);
+
+// Images smaller than this on their longest side are doubled in size first;
+// Tesseract reads text at screen resolution poorly
+const ENLARGE_BELOW: u32 = 2000;
+
+/// Text recognition for image items through a local Tesseract install.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct OcrSettings {
+    /// Recognize text in new and existing images in the background.
+    pub enabled: bool,
+    /// Tesseract languages joined with `+`, like `eng+deu`.
+    pub languages: String,
+    /// The `tesseract` executable, if it isn't on the `PATH`.
+    pub tesseract_path: Option<String>,
+}
+
+impl Default for OcrSettings {
+    fn default() -> Self {
+        OcrSettings {
+            enabled: false,
+            languages: "eng".to_string(),
+            tesseract_path: None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct OcrStatus {
+    pub enabled: bool,
+    /// Tesseract's version, if it runs and has every configured language.
+    pub version: Option<String>,
+    /// Why text can't be recognized, if it can't.
+    pub error: Option<String>,
+    /// Image items that haven't been through OCR yet.
+    pub pending: usize,
+}
+
+/// Recognize text in image items in the background, newest first, while
+/// OCR is enabled in the settings. Each image is read once: ones Tesseract
+/// can't read are stored with empty text rather than retried.
+pub fn start_worker(app: tauri::AppHandle) {
+    thread::spawn(move || {
+        // Settings Tesseract was last found working with
+        let mut checked: Option<OcrSettings> = None;
+        loop {
+            let settings = settings::load_settings(&app).ocr;
+            if settings.enabled && checked.as_ref() != Some(&settings) && check(&settings).is_ok() {
+                checked = Some(settings.clone());
+            }
+            let busy = settings.enabled
+                && checked.as_ref() == Some(&settings)
+                && recognize_next(&app, &settings).unwrap_or_else(|_| {
+                    // Check Tesseract again before the next image
+                    checked = None;
+                    false
+                });
+            if !busy {
+                thread::sleep(IDLE_INTERVAL);
+            }
+        }
+    });
+}
+
+// Recognize the newest image waiting for OCR. Returns whether there was one.
+// Fails if the history is locked or Tesseract couldn't be started.
+fn recognize_next(app: &tauri::AppHandle, settings: &OcrSettings) -> Result<bool, String> {
+    let Some(item) = app.state::<HistoryDb>().next_ocr_candidate().map_err(|e| e.to_string())? else {
+        return Ok(false);
+    };
+    let text = match prepare(app, &item) {
+        Ok(png) => recognize(settings, &png)?.unwrap_or_default(),
+        Err(_) => String::new(),
+    };
+    app.state::<History>().set_ocr_text(item.id, text)?;
+    Ok(true)
+}
+
+// Make sure Tesseract runs and has data for every configured language.
+// Returns its version.
+fn check(settings: &OcrSettings) -> Result<String, String> {
+    let languages: Vec<&str> = settings.languages.split('+').collect();
+    let valid = |language: &str| !language.is_empty() && language.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !languages.iter().all(|language| valid(language)) {
+        return Err(format!("Invalid OCR languages '{}'", settings.languages));
+    }
+
+    // Older versions print to stderr instead of stdout
+    let output = tesseract(settings, &["--version"], &[])?;
+    let version = output_text(&output).lines().next().unwrap_or_default().trim().to_string();
+
+    let output = tesseract(settings, &["--list-langs"], &[])?;
+    let listed = output_text(&output);
+    let installed: Vec<&str> = listed.lines().map(str::trim).collect();
+    if let Some(missing) = languages.iter().find(|language| !installed.contains(language)) {
+        return Err(format!("Tesseract has no data for the language '{}'", missing));
+    }
+    Ok(version)
+}
+
+// The item's image as a grayscale PNG for Tesseract, enlarged if it's small
+fn prepare(app: &tauri::AppHandle, item: &ClipboardItem) -> Result<Vec<u8>, String> {
+    let ClipboardPayload::Image { blob_path, .. } = &item.payload else {
+        return Err("Only image items can be read".to_string());
+    };
+    let img = image::load_from_memory(&app.state::<Vault>().read_blob(blob_path)?)
+        .map_err(|e| format!("Failed to load image from file: {}", e))?
+        .grayscale();
+    let img = if img.width().max(img.height()) < ENLARGE_BELOW {
+        img.resize(img.width() * 2, img.height() * 2, FilterType::CatmullRom)
+    } else {
+        img
+    };
+
+    let mut buffer = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut buffer), ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode image: {}", e))?;
+    Ok(buffer)
+}
+
+// Run Tesseract on a PNG. Returns the text, or `None` if Tesseract failed on
+// this image; fails only if Tesseract couldn't be started.
+fn recognize(settings: &OcrSettings, png: &[u8]) -> Result<Option<String>, String> {
+    let output = tesseract(settings, &["stdin", "stdout", "-l", &settings.languages], png)?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    // Pages end in a form feed, lines in trailing spaces
+    let text = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(Some(text.trim_matches(|c: char| c.is_whitespace() || c == '\u{c}').to_string()))
+}
+
+// Run Tesseract with `args`, feeding it `input`
+fn tesseract(settings: &OcrSettings, args: &[&str], input: &[u8]) -> Result<Output, String> {
+    let program = settings.tesseract_path.as_deref().unwrap_or("tesseract");
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Couldn't run {}: {}", program, e))?;
+
+    // Write from another thread so a full output pipe can't stall both sides
+    let mut stdin = child.stdin.take().ok_or("Tesseract's input isn't piped")?;
+    let input = input.to_vec();
+    let writer = thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    let _ = writer.join();
+    Ok(output)
+}
+
+fn output_text(output: &Output) -> String {
+    format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr))
+}
+
+// Function to report whether OCR is enabled and working, and how many
+// images it still has to read
+#[tauri::command]
+pub fn get_ocr_status(app: tauri::AppHandle) -> OcrStatus {
+    let settings = settings::load_settings(&app).ocr;
+    let (version, error) = match check(&settings) {
+        Ok(version) => (Some(version), None),
+        Err(e) => (None, Some(e)),
+    };
+    OcrStatus {
+        enabled: settings.enabled,
+        version,
+        error,
+        pending: app.state::<HistoryDb>().pending_ocr().unwrap_or(0),
+    }
+}
+
+// Function to copy the text recognized in an image item to the clipboard.
+// Images that haven't been through OCR yet are read now, even with
+// background OCR turned off. Returns the text.
+#[tauri::command]
+pub fn copy_recognized_text(app: tauri::AppHandle, id: Uuid) -> Result<String, String> {
+    let item = app.state::<HistoryDb>().get(id).map_err(|e| e.to_string())?.ok_or("Item not found")?;
+    let text = match item.ocr_text.clone() {
+        Some(text) => text,
+        None => {
+            let settings = settings::load_settings(&app).ocr;
+            check(&settings)?;
+            let text = recognize(&settings, &prepare(&app, &item)?)?.ok_or("Tesseract couldn't read this image")?;
+            app.state::<History>().set_ocr_text(id, text.clone())?;
+            text
+        }
+    };
+    if text.is_empty() {
+        return Err("No text was found in this image".to_string());
+    }
+
+    app.clipboard().write_text(text.clone()).map_err(|e| e.to_string())?;
+    Ok(text)
+}
@@ -0,0 +1,135 @@
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+use tauri_plugin_store::StoreExt;
+
+use crate::item::now_secs;
+use crate::settings;
+
+/// Event emitted whenever capture is paused or resumed, with a `PauseState`
+/// payload.
+pub const PAUSE_EVENT: &str = "pause-state";
+
+const PAUSE_STORE: &str = "pause.json";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct PauseState {
+    pub paused: bool,
+    /// Unix time at which capture resumes by itself. `None` while paused
+    /// means paused until resumed by hand.
+    #[serde(default)]
+    pub resume_at: Option<u64>,
+}
+
+impl PauseState {
+    fn is_due(&self, now: u64) -> bool {
+        self.paused && self.resume_at.is_some_and(|at| at <= now)
+    }
+}
+
+/// Whether the monitor records new clips. Shared between the monitor loop,
+/// the commands that change it and the auto-resume timer.
+pub struct Pause {
+    state: Mutex<PauseState>,
+}
+
+impl Pause {
+    /// Start capturing, or paused as the last session left it when the
+    /// `remember_pause` setting is on.
+    pub fn load(app: &tauri::AppHandle) -> Self {
+        let mut state = PauseState::default();
+        if settings::load_settings(app).remember_pause {
+            let stored = app
+                .store(PAUSE_STORE)
+                .ok()
+                .and_then(|store| store.get("state"))
+                .and_then(|v| serde_json::from_value::<PauseState>(v).ok())
+                .unwrap_or_default();
+            if !stored.is_due(now_secs()) {
+                state = stored;
+            }
+        }
+        Pause { state: Mutex::new(state) }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PauseState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn state(&self) -> PauseState {
+        *self.lock()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.lock().paused
+    }
+
+    /// Pause capture, for `duration_seconds` if given or until resumed.
+    pub fn pause(&self, app: &tauri::AppHandle, duration_seconds: Option<u64>) -> Result<PauseState, String> {
+        let state = PauseState {
+            paused: true,
+            resume_at: duration_seconds.map(|seconds| now_secs() + seconds),
+        };
+        *self.lock() = state;
+        announce(app, state)
+    }
+
+    pub fn resume(&self, app: &tauri::AppHandle) -> Result<PauseState, String> {
+        let state = PauseState::default();
+        *self.lock() = state;
+        announce(app, state)
+    }
+
+    // Resume if the auto-resume time has passed. Checked under the lock so a
+    // pause set in the meantime isn't cut short.
+    fn resume_if_due(&self, app: &tauri::AppHandle, now: u64) {
+        let mut state = self.lock();
+        if state.is_due(now) {
+            *state = PauseState::default();
+            drop(state);
+            let _ = announce(app, PauseState::default());
+        }
+    }
+}
+
+// Save the new state for the next session and tell the frontend
+fn announce(app: &tauri::AppHandle, state: PauseState) -> Result<PauseState, String> {
+    let _ = app.emit(PAUSE_EVENT, state);
+    let store = app.store(PAUSE_STORE).map_err(|e| e.to_string())?;
+    store.set("state", serde_json::to_value(state).map_err(|e| e.to_string())?);
+    store.save().map_err(|e| e.to_string())?;
+    Ok(state)
+}
+
+/// Resume capture once a timed pause runs out.
+pub fn start_timer(app: tauri::AppHandle) {
+    thread::spawn(move || loop {
+        app.state::<Pause>().resume_if_due(&app, now_secs());
+        thread::sleep(Duration::from_secs(1));
+    });
+}
+
+#[tauri::command]
+pub fn get_pause_state(pause: tauri::State<'_, Pause>) -> PauseState {
+    pause.state()
+}
+
+// Function to stop recording new clips, optionally resuming by itself after
+// `duration_seconds`
+#[tauri::command]
+pub fn pause_capture(
+    app: tauri::AppHandle,
+    pause: tauri::State<'_, Pause>,
+    duration_seconds: Option<u64>,
+) -> Result<PauseState, String> {
+    pause.pause(&app, duration_seconds)
+}
+
+// Function to start recording new clips again
+#[tauri::command]
+pub fn resume_capture(app: tauri::AppHandle, pause: tauri::State<'_, Pause>) -> Result<PauseState, String> {
+    pause.resume(&app)
+}
@@ -0,0 +1,117 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use qrcode::types::QrError;
+use qrcode::{Color, EcLevel, QrCode};
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use uuid::Uuid;
+
+use crate::db::HistoryDb;
+use crate::imagehash;
+use crate::item::ClipboardPayload;
+use crate::scan::{self, DecodedCode};
+
+// Rendered codes are about this many pixels a side
+const TARGET_SIZE: u32 = 512;
+
+// Each module is at least this many pixels, so long texts stay scannable
+const MIN_MODULE_PIXELS: u32 = 4;
+
+// Light modules around the code, as the standard asks for
+const QUIET_ZONE: u32 = 4;
+
+/// How much of a rendered QR code can be damaged and still read. Higher
+/// levels make larger codes for the same text.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorCorrection {
+    /// About 7%.
+    Low,
+    /// About 15%.
+    #[default]
+    Medium,
+    /// About 25%.
+    Quartile,
+    /// About 30%.
+    High,
+}
+
+impl ErrorCorrection {
+    fn level(self) -> EcLevel {
+        match self {
+            ErrorCorrection::Low => EcLevel::L,
+            ErrorCorrection::Medium => EcLevel::M,
+            ErrorCorrection::Quartile => EcLevel::Q,
+            ErrorCorrection::High => EcLevel::H,
+        }
+    }
+}
+
+// The QR code of a text item, dark modules on white
+fn render(app: &tauri::AppHandle, id: Uuid, error_correction: Option<ErrorCorrection>) -> Result<RgbaImage, String> {
+    let item = app.state::<HistoryDb>().get(id).map_err(|e| e.to_string())?.ok_or("Item not found")?;
+    let text = match &item.payload {
+        ClipboardPayload::Text { text } | ClipboardPayload::RichText { text, .. } => text,
+        _ => return Err("Only text items can be shown as QR codes".to_string()),
+    };
+    let code = QrCode::with_error_correction_level(text.as_bytes(), error_correction.unwrap_or_default().level())
+        .map_err(|e| match e {
+            QrError::DataTooLong => "Text is too long for a QR code".to_string(),
+            e => e.to_string(),
+        })?;
+
+    let modules = code.width() as u32;
+    let colors = code.to_colors();
+    let scale = (TARGET_SIZE / (modules + 2 * QUIET_ZONE)).max(MIN_MODULE_PIXELS);
+    let size = (modules + 2 * QUIET_ZONE) * scale;
+    Ok(RgbaImage::from_fn(size, size, |x, y| {
+        let (column, row) = (x / scale, y / scale);
+        let inside = (QUIET_ZONE..QUIET_ZONE + modules).contains(&column) && (QUIET_ZONE..QUIET_ZONE + modules).contains(&row);
+        let dark = inside && colors[((row - QUIET_ZONE) * modules + column - QUIET_ZONE) as usize] == Color::Dark;
+        if dark { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) }
+    }))
+}
+
+// Function to render a text item as a QR code for the preview dialog, as a
+// PNG data URL
+#[tauri::command]
+pub fn render_qr_code(app: tauri::AppHandle, id: Uuid, error_correction: Option<ErrorCorrection>) -> Result<String, String> {
+    crate::image_data_url(&DynamicImage::ImageRgba8(render(&app, id, error_correction)?))
+}
+
+// Function to copy a text item's QR code to the clipboard as an image
+#[tauri::command]
+pub fn copy_qr_code(app: tauri::AppHandle, id: Uuid, error_correction: Option<ErrorCorrection>) -> Result<(), String> {
+    let img = render(&app, id, error_correction)?;
+    let (width, height) = img.dimensions();
+    let image = tauri::image::Image::new(img.as_raw(), width, height);
+    app.clipboard()
+        .write_image(&image)
+        .map_err(|e| format!("Failed to write image to clipboard: {}", e))
+}
+
+// Function to add a text item's QR code to the history as an image item.
+// Returns false if the same code is already there.
+#[tauri::command]
+pub fn save_qr_code(app: tauri::AppHandle, id: Uuid, error_correction: Option<ErrorCorrection>) -> Result<bool, String> {
+    let img = render(&app, id, error_correction)?;
+    crate::push_history_image(&app, &img, imagehash::rgba_hash(&img), None, || crate::save_rgba_image_blob(&app, &img))
+}
+
+// Function to find the QR codes and barcodes in an image item and decode
+// them
+#[tauri::command]
+pub fn scan_image_codes(app: tauri::AppHandle, id: Uuid) -> Result<Vec<DecodedCode>, String> {
+    let item = app.state::<HistoryDb>().get(id).map_err(|e| e.to_string())?.ok_or("Item not found")?;
+    let ClipboardPayload::Image { blob_path, .. } = &item.payload else {
+        return Err("Only image items can be scanned".to_string());
+    };
+    Ok(scan::scan(&crate::load_image_blob(&app, blob_path)?.to_luma8()))
+}
+
+// Function to add a decoded payload to the history as a text item, screened
+// for secrets like copied text. Returns false if it wasn't added.
+#[tauri::command]
+pub fn add_decoded_text(app: tauri::AppHandle, text: String) -> Result<bool, String> {
+    crate::push_history_text(&app, &text, false, None)
+}
@@ -0,0 +1,1002 @@
+use crate::scan::{BitMatrix, CodeFormat, DecodedCode};
+
+/// Decode every QR code in a binarized image. Codes are located by their
+/// three finder patterns and sampled through a perspective transform, so
+/// screenshots, scaled, rotated and mirrored images and moderately skewed
+/// photos all read. Kanji-mode text and Micro QR codes aren't supported.
+pub fn decode_all(bits: &BitMatrix) -> Vec<DecodedCode> {
+    let finders = find_finders(bits);
+    let mut used = vec![false; finders.len()];
+    let mut codes = Vec::new();
+
+    for i in 0..finders.len() {
+        for j in i + 1..finders.len() {
+            for k in j + 1..finders.len() {
+                if used[i] || used[j] || used[k] {
+                    continue;
+                }
+                let Some(corners) = arrange(finders[i], finders[j], finders[k]) else {
+                    continue;
+                };
+                if let Some(text) = decode_at(bits, &corners) {
+                    used[i] = true;
+                    used[j] = true;
+                    used[k] = true;
+                    codes.push(DecodedCode { format: CodeFormat::QrCode, text });
+                }
+            }
+        }
+    }
+    codes
+}
+
+// Largest number of finder pattern candidates tried in threes
+const MAX_FINDERS: usize = 15;
+
+// A finder pattern: the center of a corner square and its module size
+#[derive(Clone, Copy, Debug)]
+struct Finder {
+    x: f64,
+    y: f64,
+    module: f64,
+    // How many scan rows found it
+    count: u32,
+}
+
+// Scan rows for the 1:1:3:1:1 dark-light-dark-light-dark runs across a
+// finder pattern, check them vertically and horizontally through the
+// center, and merge the hits on each pattern
+fn find_finders(bits: &BitMatrix) -> Vec<Finder> {
+    let mut finders: Vec<Finder> = Vec::new();
+    // Finder patterns are 7 modules tall and codes have at most 177 modules
+    // a side, so rows can be skipped on large images
+    let step = (3 * bits.height / (4 * 177)).max(1);
+
+    let mut y = step - 1;
+    while y < bits.height {
+        let mut runs = [0usize; 5];
+        let mut state = 0;
+        for x in 0..=bits.width {
+            let dark = x < bits.width && bits.get(x, y);
+            if dark {
+                if state % 2 == 1 {
+                    state += 1;
+                }
+                runs[state] += 1;
+            } else if state % 2 == 1 {
+                runs[state] += 1;
+            } else if state < 4 {
+                state += 1;
+                runs[state] += 1;
+            } else {
+                if is_finder_ratio(&runs) {
+                    let center_x = x as f64 - runs[4] as f64 - runs[3] as f64 - runs[2] as f64 / 2.0;
+                    if let Some(finder) = check_finder(bits, center_x, y, &runs) {
+                        add_finder(&mut finders, finder);
+                        runs = [0; 5];
+                        state = 0;
+                        continue;
+                    }
+                }
+                // Keep the last dark-light-dark runs as the start of the next try
+                runs = [runs[2], runs[3], runs[4], 1, 0];
+                state = 3;
+            }
+        }
+        y += step;
+    }
+
+    finders.sort_by_key(|finder| std::cmp::Reverse(finder.count));
+    finders.truncate(MAX_FINDERS);
+    finders
+}
+
+fn is_finder_ratio(runs: &[usize; 5]) -> bool {
+    let total: usize = runs.iter().sum();
+    if total < 7 {
+        return false;
+    }
+    let module = total as f64 / 7.0;
+    let tolerance = module / 2.0;
+    [1.0, 1.0, 3.0, 1.0, 1.0]
+        .iter()
+        .zip(runs)
+        .all(|(&modules, &run)| (modules * module - run as f64).abs() < modules * tolerance)
+}
+
+// Confirm a finder pattern found along a row by crossing it vertically and
+// then horizontally again through its center
+fn check_finder(bits: &BitMatrix, center_x: f64, y: usize, row_runs: &[usize; 5]) -> Option<Finder> {
+    let row_total: usize = row_runs.iter().sum();
+    let (vertical, center_y) = cross_check(bits, center_x as usize, y, (0, 1), row_runs[2], row_total)?;
+    let (horizontal, center_x) = cross_check(bits, center_x as usize, center_y as usize, (1, 0), row_runs[2], row_total)?;
+    let module = (vertical + horizontal) as f64 / 14.0;
+    Some(Finder { x: center_x, y: center_y, module, count: 1 })
+}
+
+// Runs of a finder pattern along the line through (x, y), which must be in
+// its center square, in direction `step`. Returns the pattern's total length
+// and the position of its center along the line.
+fn cross_check(
+    bits: &BitMatrix,
+    x: usize,
+    y: usize,
+    step: (isize, isize),
+    max_run: usize,
+    expected_total: usize,
+) -> Option<(usize, f64)> {
+    let at = |i: isize| -> Option<bool> {
+        let (px, py) = (x as isize + step.0 * i, y as isize + step.1 * i);
+        (px >= 0 && py >= 0 && (px as usize) < bits.width && (py as usize) < bits.height).then(|| bits.get(px as usize, py as usize))
+    };
+    let mut runs = [0usize; 5];
+
+    // Back from the center through the light ring to the outer edge
+    let mut i = 0;
+    while at(i) == Some(true) {
+        runs[2] += 1;
+        i -= 1;
+    }
+    while at(i) == Some(false) && runs[1] <= max_run {
+        runs[1] += 1;
+        i -= 1;
+    }
+    if at(i).is_none() || runs[1] > max_run {
+        return None;
+    }
+    while at(i) == Some(true) && runs[0] <= max_run {
+        runs[0] += 1;
+        i -= 1;
+    }
+    if runs[0] > max_run {
+        return None;
+    }
+
+    // And forward to the other edge
+    let mut i = 1;
+    while at(i) == Some(true) {
+        runs[2] += 1;
+        i += 1;
+    }
+    while at(i) == Some(false) && runs[3] <= max_run {
+        runs[3] += 1;
+        i += 1;
+    }
+    if at(i).is_none() || runs[3] > max_run {
+        return None;
+    }
+    while at(i) == Some(true) && runs[4] <= max_run {
+        runs[4] += 1;
+        i += 1;
+    }
+    if runs[4] > max_run {
+        return None;
+    }
+
+    // Seen across, the pattern should be about as large as along the row
+    let total: usize = runs.iter().sum();
+    if 5 * total.abs_diff(expected_total) >= 2 * expected_total || !is_finder_ratio(&runs) {
+        return None;
+    }
+    let origin = if step.0 != 0 { x } else { y } as f64;
+    Some((total, origin + i as f64 - runs[4] as f64 - runs[3] as f64 - runs[2] as f64 / 2.0))
+}
+
+// Merge a finder pattern into one already found at the same place, or add it
+fn add_finder(finders: &mut Vec<Finder>, finder: Finder) {
+    let same = finders.iter_mut().find(|other| {
+        (finder.x - other.x).abs() <= other.module
+            && (finder.y - other.y).abs() <= other.module
+            && ((finder.module - other.module).abs() <= 1.0 || (finder.module - other.module).abs() <= other.module)
+    });
+    match same {
+        Some(other) => {
+            let count = other.count as f64;
+            other.x = (other.x * count + finder.x) / (count + 1.0);
+            other.y = (other.y * count + finder.y) / (count + 1.0);
+            other.module = (other.module * count + finder.module) / (count + 1.0);
+            other.count += 1;
+        }
+        None => finders.push(finder),
+    }
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+// Finder patterns are measured along rows and columns, which cross a
+// rotated code on a slant and make its modules look up to √2 times larger.
+// This is the factor from the measured size to the true one for a code with
+// a side running from `from` to `to`.
+fn slant(from: &Finder, to: &Finder) -> f64 {
+    let (dx, dy) = ((to.x - from.x).abs(), (to.y - from.y).abs());
+    dx.max(dy) / dx.hypot(dy)
+}
+
+// Order three finder patterns as the bottom-left, top-left and top-right
+// corners of a code, if they could be one
+fn arrange(a: Finder, b: Finder, c: Finder) -> Option<[Finder; 3]> {
+    let modules = [a.module, b.module, c.module];
+    let (smallest, largest) = modules.iter().fold((f64::MAX, 0.0f64), |(min, max), &m| (min.min(m), max.max(m)));
+    // Nearer corners look larger in photos taken at an angle
+    if largest > smallest * 2.0 {
+        return None;
+    }
+
+    // The top-left corner is opposite the longest side
+    let (ab, bc, ac) = (distance((a.x, a.y), (b.x, b.y)), distance((b.x, b.y), (c.x, c.y)), distance((a.x, a.y), (c.x, c.y)));
+    let (mut first, top_left, mut last, hypotenuse, legs) = if bc >= ab && bc >= ac {
+        (b, a, c, bc, (ab, ac))
+    } else if ac >= ab && ac >= bc {
+        (a, b, c, ac, (ab, bc))
+    } else {
+        (a, c, b, ab, (ac, bc))
+    };
+
+    // Roughly a right isosceles triangle, at least a version 1 code apart
+    let module = (a.module + b.module + c.module) / 3.0 * slant(&top_left, &first);
+    if legs.0.max(legs.1) > legs.0.min(legs.1) * 1.6 || legs.0.min(legs.1) < 12.0 * module {
+        return None;
+    }
+    let squares = legs.0 * legs.0 + legs.1 * legs.1;
+    if (hypotenuse * hypotenuse - squares).abs() > 0.3 * squares {
+        return None;
+    }
+
+    // Going from the bottom-left corner to the top-right one turns clockwise
+    // around the top-left one (y points down)
+    let cross = (last.x - top_left.x) * (first.y - top_left.y) - (last.y - top_left.y) * (first.x - top_left.x);
+    if cross < 0.0 {
+        std::mem::swap(&mut first, &mut last);
+    }
+    Some([first, top_left, last])
+}
+
+// Try to read a code at the given corners, at its estimated size and the
+// sizes around it
+fn decode_at(bits: &BitMatrix, corners: &[Finder; 3]) -> Option<String> {
+    let [bottom_left, top_left, top_right] = corners;
+    let module = (bottom_left.module + top_left.module + top_right.module) / 3.0;
+    let across = distance((top_left.x, top_left.y), (top_right.x, top_right.y));
+    let down = distance((top_left.x, top_left.y), (bottom_left.x, bottom_left.y));
+    // Finder pattern centers are 3.5 modules in from the edges
+    let estimate = ((across + down) / 2.0 / (module * slant(top_left, top_right))).round() as usize + 7;
+
+    // Sizes are 17 plus a multiple of 4
+    let nearest = match estimate % 4 {
+        0 => vec![estimate + 1],
+        1 => vec![estimate],
+        2 => vec![estimate - 1],
+        _ => vec![estimate + 2, estimate - 2],
+    };
+    let mut sizes = nearest.clone();
+    for offset in [4, 8] {
+        for &size in &nearest {
+            sizes.push(size + offset);
+            sizes.push(size.saturating_sub(offset));
+        }
+    }
+
+    sizes.into_iter().filter(|size| (21..=177).contains(size)).find_map(|size| {
+        // Larger codes have an alignment pattern near the fourth corner,
+        // which tells how much the image is skewed. Data can look like one
+        // too, so the nearest few are tried before doing without.
+        let mut alignments: Vec<Option<(f64, f64)>> = Vec::new();
+        if size > 21 {
+            let found = find_alignments(bits, alignment_estimate(corners, size), module, ALIGNMENT_REACH);
+            alignments.extend(found.into_iter().take(MAX_ALIGNMENTS).map(Some));
+        }
+        alignments.push(None);
+        alignments.into_iter().find_map(|alignment| {
+            // A code seen in a mirror or through the back of a window samples
+            // transposed, with its finder patterns in the same corners
+            let grid = sample(bits, corners, size, alignment)?;
+            decode_grid(&grid).or_else(|| decode_grid(&grid.transposed()))
+        })
+    })
+}
+
+// Alignment patterns are looked for this many modules around where one
+// would be without perspective
+const ALIGNMENT_REACH: f64 = 12.0;
+
+// Largest number of alignment pattern candidates tried per size
+const MAX_ALIGNMENTS: usize = 6;
+
+// Without perspective the fourth corner completes a parallelogram
+fn parallelogram(corners: &[Finder; 3]) -> (f64, f64) {
+    let [bottom_left, top_left, top_right] = corners;
+    (top_right.x - top_left.x + bottom_left.x, top_right.y - top_left.y + bottom_left.y)
+}
+
+// Where the bottom-right alignment pattern of a code `size` modules a side
+// would be without perspective: 3 modules further in than the corner
+// finder pattern centers would suggest
+fn alignment_estimate(corners: &[Finder; 3], size: usize) -> (f64, f64) {
+    let top_left = corners[1];
+    let fourth = parallelogram(corners);
+    let inwards = 1.0 - 3.0 / (size as f64 - 7.0);
+    (top_left.x + inwards * (fourth.0 - top_left.x), top_left.y + inwards * (fourth.1 - top_left.y))
+}
+
+// Read the modules of a code `size` modules a side, using the center of its
+// bottom-right alignment pattern if it was found
+fn sample(bits: &BitMatrix, corners: &[Finder; 3], size: usize, alignment: Option<(f64, f64)>) -> Option<BitMatrix> {
+    let [bottom_left, top_left, top_right] = corners;
+    let edge = size as f64 - 3.5;
+    let fourth = match alignment {
+        Some(center) => ((edge - 3.0, edge - 3.0), center),
+        None => ((edge, edge), parallelogram(corners)),
+    };
+
+    let transform = Transform::between(
+        [(3.5, 3.5), (edge, 3.5), fourth.0, (3.5, edge)],
+        [(top_left.x, top_left.y), (top_right.x, top_right.y), fourth.1, (bottom_left.x, bottom_left.y)],
+    );
+
+    let mut grid = BitMatrix::new(size, size);
+    for my in 0..size {
+        for mx in 0..size {
+            let (x, y) = transform.apply(mx as f64 + 0.5, my as f64 + 0.5);
+            // Allow a pixel of slack at the image edges
+            if x < -1.0 || y < -1.0 || x > bits.width as f64 || y > bits.height as f64 {
+                return None;
+            }
+            let x = (x.max(0.0) as usize).min(bits.width - 1);
+            let y = (y.max(0.0) as usize).min(bits.height - 1);
+            grid.set(mx, my, bits.get(x, y));
+        }
+    }
+    Some(grid)
+}
+
+// Look for alignment patterns, a dark module in a light ring in a dark ring,
+// within `reach` modules of `estimate`. Returns their centers, nearest first.
+fn find_alignments(bits: &BitMatrix, estimate: (f64, f64), module: f64, reach: f64) -> Vec<(f64, f64)> {
+    let radius = reach * module;
+    let left = (estimate.0 - radius).max(0.0) as usize;
+    let right = ((estimate.0 + radius) as usize).min(bits.width - 1);
+    let top = (estimate.1 - radius).max(0.0) as usize;
+    let bottom = ((estimate.1 + radius) as usize).min(bits.height - 1);
+    if right <= left || bottom <= top || ((right - left) as f64) < module * 3.0 {
+        return Vec::new();
+    }
+    let about_one_module = |run: usize| (run as f64 - module).abs() < module / 2.0 + 0.5;
+
+    let mut found: Vec<(f64, f64)> = Vec::new();
+    for y in top..=bottom {
+        // Runs along the row, starting at `left`
+        let mut runs: Vec<(bool, usize, usize)> = Vec::new();
+        for x in left..=right {
+            let dark = bits.get(x, y);
+            match runs.last_mut() {
+                Some((shade, _, length)) if *shade == dark => *length += 1,
+                _ => runs.push((dark, x, 1)),
+            }
+        }
+        for window in runs.windows(5) {
+            let [(first, ..), (_, _, before), (center, start, length), (_, _, after), (last, ..)] = *window else {
+                continue;
+            };
+            if !first || !center || !last || !about_one_module(before) || !about_one_module(length) || !about_one_module(after) {
+                continue;
+            }
+            let x = start as f64 + length as f64 / 2.0;
+            let Some(y) = check_alignment(bits, x as usize, y, &about_one_module) else {
+                continue;
+            };
+            // Each pattern is crossed by several rows
+            if !found.iter().any(|&other| distance(other, (x, y)) < module) {
+                found.push((x, y));
+            }
+        }
+    }
+    found.sort_by(|&a, &b| distance(a, estimate).total_cmp(&distance(b, estimate)));
+    found
+}
+
+// Check an alignment pattern candidate vertically: one module dark at the
+// center, one light on either side, then dark again. Returns the center.
+fn check_alignment(bits: &BitMatrix, x: usize, y: usize, about_one_module: &dyn Fn(usize) -> bool) -> Option<f64> {
+    let mut top = y;
+    while top > 0 && bits.get(x, top - 1) {
+        top -= 1;
+    }
+    let mut bottom = y;
+    while bottom + 1 < bits.height && bits.get(x, bottom + 1) {
+        bottom += 1;
+    }
+    if !about_one_module(bottom - top + 1) {
+        return None;
+    }
+
+    let mut above = 0;
+    while top > above && !bits.get(x, top - above - 1) {
+        above += 1;
+    }
+    let mut below = 0;
+    while bottom + below + 1 < bits.height && !bits.get(x, bottom + below + 1) {
+        below += 1;
+    }
+    // The light runs must end in dark, not the image edge
+    let bounded = top > above && bottom + below + 1 < bits.height;
+    (bounded && about_one_module(above) && about_one_module(below)).then(|| (top + bottom + 1) as f64 / 2.0)
+}
+
+// A projective transform from module coordinates to image coordinates
+struct Transform([[f64; 3]; 3]);
+
+impl Transform {
+    // The transform taking each of the `from` corners to the matching `to`
+    // corner. Corners go clockwise from the top-left.
+    fn between(from: [(f64, f64); 4], to: [(f64, f64); 4]) -> Self {
+        Transform(multiply(&square_to(to), &adjugate(&square_to(from))))
+    }
+
+    fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let m = &self.0;
+        let w = m[2][0] * x + m[2][1] * y + m[2][2];
+        ((m[0][0] * x + m[0][1] * y + m[0][2]) / w, (m[1][0] * x + m[1][1] * y + m[1][2]) / w)
+    }
+}
+
+// The transform taking the unit square's corners, clockwise from (0, 0), to
+// `corners`
+fn square_to(corners: [(f64, f64); 4]) -> [[f64; 3]; 3] {
+    let [(x0, y0), (x1, y1), (x2, y2), (x3, y3)] = corners;
+    let (dx3, dy3) = (x0 - x1 + x2 - x3, y0 - y1 + y2 - y3);
+    if dx3.abs() < 1e-9 && dy3.abs() < 1e-9 {
+        // A parallelogram needs no perspective
+        return [[x1 - x0, x3 - x0, x0], [y1 - y0, y3 - y0, y0], [0.0, 0.0, 1.0]];
+    }
+    let (dx1, dx2, dy1, dy2) = (x1 - x2, x3 - x2, y1 - y2, y3 - y2);
+    let denominator = dx1 * dy2 - dx2 * dy1;
+    let g = (dx3 * dy2 - dx2 * dy3) / denominator;
+    let h = (dx1 * dy3 - dx3 * dy1) / denominator;
+    [
+        [x1 - x0 + g * x1, x3 - x0 + h * x3, x0],
+        [y1 - y0 + g * y1, y3 - y0 + h * y3, y0],
+        [g, h, 1.0],
+    ]
+}
+
+// The inverse of a matrix, up to scale, which is all a projective transform
+// needs
+fn adjugate(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let cofactor = |r: usize, c: usize| {
+        let (r1, r2) = ((r + 1) % 3, (r + 2) % 3);
+        let (c1, c2) = ((c + 1) % 3, (c + 2) % 3);
+        m[r1][c1] * m[r2][c2] - m[r1][c2] * m[r2][c1]
+    };
+    let mut result = [[0.0; 3]; 3];
+    for (r, row) in result.iter_mut().enumerate() {
+        for (c, value) in row.iter_mut().enumerate() {
+            *value = cofactor(c, r);
+        }
+    }
+    result
+}
+
+fn multiply(a: &[[f64; 3]; 3], b: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut result = [[0.0; 3]; 3];
+    for (r, row) in result.iter_mut().enumerate() {
+        for (c, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[r][k] * b[k][c]).sum();
+        }
+    }
+    result
+}
+
+#[derive(Clone, Copy)]
+enum EcLevel {
+    L,
+    M,
+    Q,
+    H,
+}
+
+// Read a sampled grid: format, then the masked codewords, then the error
+// corrected data
+fn decode_grid(grid: &BitMatrix) -> Option<String> {
+    let size = grid.width;
+    let version = (size - 17) / 4;
+    let (level, mask) = read_format(grid)?;
+    if version >= 7 {
+        // Larger codes state their version; a mismatch means the size
+        // estimate was wrong
+        if read_version(grid).is_some_and(|stated| stated != version) {
+            return None;
+        }
+    }
+
+    let function = function_modules(version);
+    let codewords = read_codewords(grid, &function, mask);
+    let data = correct(&codewords, version, level)?;
+    parse_segments(&data, version)
+}
+
+// BCH code over the 5 format bits, as placed in the code
+fn format_code(data: u32) -> u32 {
+    ((data << 10) | remainder(data << 10, 0x537)) ^ 0x5412
+}
+
+// BCH code over the 6 version bits
+fn version_code(version: u32) -> u32 {
+    (version << 12) | remainder(version << 12, 0x1f25)
+}
+
+// Remainder of polynomial division over GF(2)
+fn remainder(mut value: u32, generator: u32) -> u32 {
+    let degree = 31 - generator.leading_zeros();
+    while value != 0 && 31 - value.leading_zeros() >= degree {
+        value ^= generator << (31 - value.leading_zeros() - degree);
+    }
+    value
+}
+
+// The code word nearest to either reading, if it's within 3 bits
+fn nearest_code(readings: [u32; 2], codes: impl Iterator<Item = (u32, u32)>) -> Option<u32> {
+    codes
+        .map(|(value, code)| (readings.iter().map(|reading| (reading ^ code).count_ones()).min().unwrap_or(u32::MAX), value))
+        .min()
+        .filter(|&(distance, _)| distance <= 3)
+        .map(|(_, value)| value)
+}
+
+// Error correction level and mask, from either copy of the format bits
+fn read_format(grid: &BitMatrix) -> Option<(EcLevel, usize)> {
+    let size = grid.width;
+    let read = |modules: &mut dyn Iterator<Item = (usize, usize)>| modules.fold(0, |bits, (x, y)| (bits << 1) | grid.get(x, y) as u32);
+
+    // Around the top-left finder pattern, skipping the timing patterns
+    let mut around = (0..6).map(|x| (x, 8)).chain([(7, 8), (8, 8), (8, 7)]).chain((0..6).rev().map(|y| (8, y)));
+    // Split between the other two
+    let mut split = (size - 7..size).rev().map(|y| (8, y)).chain((size - 8..size).map(|x| (x, 8)));
+    let data = nearest_code([read(&mut around), read(&mut split)], (0..32).map(|data| (data, format_code(data))))?;
+
+    let level = match data >> 3 {
+        0 => EcLevel::M,
+        1 => EcLevel::L,
+        2 => EcLevel::H,
+        _ => EcLevel::Q,
+    };
+    Some((level, (data & 7) as usize))
+}
+
+// The version stated next to the top-right and bottom-left finder patterns
+fn read_version(grid: &BitMatrix) -> Option<usize> {
+    let size = grid.width;
+    let mut top_right = 0;
+    let mut bottom_left = 0;
+    for a in (0..6).rev() {
+        for b in (size - 11..size - 8).rev() {
+            top_right = (top_right << 1) | grid.get(b, a) as u32;
+            bottom_left = (bottom_left << 1) | grid.get(a, b) as u32;
+        }
+    }
+    nearest_code([top_right, bottom_left], (7..=40).map(|version| (version, version_code(version)))).map(|version| version as usize)
+}
+
+// Centers of the alignment patterns along each axis
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version < 2 {
+        return Vec::new();
+    }
+    let count = version / 7 + 2;
+    let last = 4 * version + 10;
+    let step = if version == 32 { 26 } else { (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2 };
+    let mut positions: Vec<usize> = (0..count - 1).map(|i| last - i * step).collect();
+    positions.push(6);
+    positions.reverse();
+    positions
+}
+
+// Modules that hold finder, timing and alignment patterns and format and
+// version bits rather than data
+fn function_modules(version: usize) -> BitMatrix {
+    let size = version * 4 + 17;
+    let mut function = BitMatrix::new(size, size);
+    let mut fill = |x0: usize, y0: usize, width: usize, height: usize| {
+        for y in y0..y0 + height {
+            for x in x0..x0 + width {
+                function.set(x, y, true);
+            }
+        }
+    };
+
+    // Finder patterns with their separators and format bits
+    fill(0, 0, 9, 9);
+    fill(size - 8, 0, 8, 9);
+    fill(0, size - 8, 9, 8);
+
+    let positions = alignment_positions(version);
+    for &x in &positions {
+        for &y in &positions {
+            // None where the finder patterns are
+            let corner = |p: usize| p == 6;
+            let far = |p: usize| p == size - 7;
+            if (corner(x) && corner(y)) || (corner(x) && far(y)) || (far(x) && corner(y)) {
+                continue;
+            }
+            fill(x - 2, y - 2, 5, 5);
+        }
+    }
+
+    // Timing patterns
+    fill(6, 9, 1, size - 17);
+    fill(9, 6, size - 17, 1);
+
+    if version >= 7 {
+        fill(size - 11, 0, 3, 6);
+        fill(0, size - 11, 6, 3);
+    }
+    function
+}
+
+fn is_masked(mask: usize, x: usize, y: usize) -> bool {
+    let (row, column) = (y, x);
+    match mask {
+        0 => (row + column) % 2 == 0,
+        1 => row % 2 == 0,
+        2 => column % 3 == 0,
+        3 => (row + column) % 3 == 0,
+        4 => (row / 2 + column / 3) % 2 == 0,
+        5 => (row * column) % 2 + (row * column) % 3 == 0,
+        6 => ((row * column) % 2 + (row * column) % 3) % 2 == 0,
+        _ => ((row + column) % 2 + (row * column) % 3) % 2 == 0,
+    }
+}
+
+// Read the codewords in placement order: up and down two-module columns from
+// the right, skipping the vertical timing pattern
+fn read_codewords(grid: &BitMatrix, function: &BitMatrix, mask: usize) -> Vec<u8> {
+    let size = grid.width;
+    let mut codewords = Vec::new();
+    let (mut byte, mut bits) = (0u8, 0);
+    let mut upwards = true;
+
+    let mut right = size - 1;
+    while right > 0 {
+        if right == 6 {
+            right -= 1;
+        }
+        for i in 0..size {
+            let y = if upwards { size - 1 - i } else { i };
+            for x in [right, right - 1] {
+                if function.get(x, y) {
+                    continue;
+                }
+                byte = (byte << 1) | (grid.get(x, y) ^ is_masked(mask, x, y)) as u8;
+                bits += 1;
+                if bits == 8 {
+                    codewords.push(byte);
+                    (byte, bits) = (0, 0);
+                }
+            }
+        }
+        upwards = !upwards;
+        right = right.saturating_sub(2);
+    }
+    codewords
+}
+
+// Error correction codewords per block, then the number of blocks and data
+// codewords in each of the shorter blocks and the number of blocks with one
+// more, by version and level (L, M, Q, H). ISO/IEC 18004, table 9.
+const BLOCKS: [[(usize, usize, usize, usize); 4]; 40] = [
+    [(7, 1, 19, 0), (10, 1, 16, 0), (13, 1, 13, 0), (17, 1, 9, 0)],
+    [(10, 1, 34, 0), (16, 1, 28, 0), (22, 1, 22, 0), (28, 1, 16, 0)],
+    [(15, 1, 55, 0), (26, 1, 44, 0), (18, 2, 17, 0), (22, 2, 13, 0)],
+    [(20, 1, 80, 0), (18, 2, 32, 0), (26, 2, 24, 0), (16, 4, 9, 0)],
+    [(26, 1, 108, 0), (24, 2, 43, 0), (18, 2, 15, 2), (22, 2, 11, 2)],
+    [(18, 2, 68, 0), (16, 4, 27, 0), (24, 4, 19, 0), (28, 4, 15, 0)],
+    [(20, 2, 78, 0), (18, 4, 31, 0), (18, 2, 14, 4), (26, 4, 13, 1)],
+    [(24, 2, 97, 0), (22, 2, 38, 2), (22, 4, 18, 2), (26, 4, 14, 2)],
+    [(30, 2, 116, 0), (22, 3, 36, 2), (20, 4, 16, 4), (24, 4, 12, 4)],
+    [(18, 2, 68, 2), (26, 4, 43, 1), (24, 6, 19, 2), (28, 6, 15, 2)],
+    [(20, 4, 81, 0), (30, 1, 50, 4), (28, 4, 22, 4), (24, 3, 12, 8)],
+    [(24, 2, 92, 2), (22, 6, 36, 2), (26, 4, 20, 6), (28, 7, 14, 4)],
+    [(26, 4, 107, 0), (22, 8, 37, 1), (24, 8, 20, 4), (22, 12, 11, 4)],
+    [(30, 3, 115, 1), (24, 4, 40, 5), (20, 11, 16, 5), (24, 11, 12, 5)],
+    [(22, 5, 87, 1), (24, 5, 41, 5), (30, 5, 24, 7), (24, 11, 12, 7)],
+    [(24, 5, 98, 1), (28, 7, 45, 3), (24, 15, 19, 2), (30, 3, 15, 13)],
+    [(28, 1, 107, 5), (28, 10, 46, 1), (28, 1, 22, 15), (28, 2, 14, 17)],
+    [(30, 5, 120, 1), (26, 9, 43, 4), (28, 17, 22, 1), (28, 2, 14, 19)],
+    [(28, 3, 113, 4), (26, 3, 44, 11), (26, 17, 21, 4), (26, 9, 13, 16)],
+    [(28, 3, 107, 5), (26, 3, 41, 13), (30, 15, 24, 5), (28, 15, 15, 10)],
+    [(28, 4, 116, 4), (26, 17, 42, 0), (28, 17, 22, 6), (30, 19, 16, 6)],
+    [(28, 2, 111, 7), (28, 17, 46, 0), (30, 7, 24, 16), (24, 34, 13, 0)],
+    [(30, 4, 121, 5), (28, 4, 47, 14), (30, 11, 24, 14), (30, 16, 15, 14)],
+    [(30, 6, 117, 4), (28, 6, 45, 14), (30, 11, 24, 16), (30, 30, 16, 2)],
+    [(26, 8, 106, 4), (28, 8, 47, 13), (30, 7, 24, 22), (30, 22, 15, 13)],
+    [(28, 10, 114, 2), (28, 19, 46, 4), (28, 28, 22, 6), (30, 33, 16, 4)],
+    [(30, 8, 122, 4), (28, 22, 45, 3), (30, 8, 23, 26), (30, 12, 15, 28)],
+    [(30, 3, 117, 10), (28, 3, 45, 23), (30, 4, 24, 31), (30, 11, 15, 31)],
+    [(30, 7, 116, 7), (28, 21, 45, 7), (30, 1, 23, 37), (30, 19, 15, 26)],
+    [(30, 5, 115, 10), (28, 19, 47, 10), (30, 15, 24, 25), (30, 23, 15, 25)],
+    [(30, 13, 115, 3), (28, 2, 46, 29), (30, 42, 24, 1), (30, 23, 15, 28)],
+    [(30, 17, 115, 0), (28, 10, 46, 23), (30, 10, 24, 35), (30, 19, 15, 35)],
+    [(30, 17, 115, 1), (28, 14, 46, 21), (30, 29, 24, 19), (30, 11, 15, 46)],
+    [(30, 13, 115, 6), (28, 14, 46, 23), (30, 44, 24, 7), (30, 59, 16, 1)],
+    [(30, 12, 121, 7), (28, 12, 47, 26), (30, 39, 24, 14), (30, 22, 15, 41)],
+    [(30, 6, 121, 14), (28, 6, 47, 34), (30, 46, 24, 10), (30, 2, 15, 64)],
+    [(30, 17, 122, 4), (28, 29, 46, 14), (30, 49, 24, 10), (30, 24, 15, 46)],
+    [(30, 4, 122, 18), (28, 13, 46, 32), (30, 48, 24, 14), (30, 42, 15, 32)],
+    [(30, 20, 117, 4), (28, 40, 47, 7), (30, 43, 24, 22), (30, 10, 15, 67)],
+    [(30, 19, 118, 6), (28, 18, 47, 31), (30, 34, 24, 34), (30, 20, 15, 61)],
+];
+
+// Split the codewords into their interleaved blocks, correct each and join
+// their data
+fn correct(codewords: &[u8], version: usize, level: EcLevel) -> Option<Vec<u8>> {
+    let (ec, short_blocks, short_data, long_blocks) = BLOCKS[version - 1][level as usize];
+    let block_count = short_blocks + long_blocks;
+    let data_length = |block: usize| short_data + (block >= short_blocks) as usize;
+    let total = short_blocks * (short_data + ec) + long_blocks * (short_data + 1 + ec);
+    if codewords.len() < total {
+        return None;
+    }
+
+    // Data codewords go round the blocks in turn, then the error correction
+    let mut blocks: Vec<Vec<u8>> = (0..block_count).map(|block| Vec::with_capacity(data_length(block) + ec)).collect();
+    let mut next = codewords.iter().copied();
+    for i in 0..short_data + 1 {
+        for (block, codewords) in blocks.iter_mut().enumerate() {
+            if i < data_length(block) {
+                codewords.push(next.next()?);
+            }
+        }
+    }
+    for _ in 0..ec {
+        for codewords in blocks.iter_mut() {
+            codewords.push(next.next()?);
+        }
+    }
+
+    let mut data = Vec::new();
+    for (block, mut codewords) in blocks.into_iter().enumerate() {
+        reed_solomon::correct(&mut codewords, ec)?;
+        data.extend_from_slice(&codewords[..data_length(block)]);
+    }
+    Some(data)
+}
+
+const ALPHANUMERIC: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn remaining(&self) -> usize {
+        self.bytes.len() * 8 - self.position
+    }
+
+    fn read(&mut self, count: usize) -> Option<u32> {
+        if count > self.remaining() {
+            return None;
+        }
+        let mut value = 0;
+        for _ in 0..count {
+            let bit = self.bytes[self.position / 8] >> (7 - self.position % 8) & 1;
+            value = (value << 1) | bit as u32;
+            self.position += 1;
+        }
+        Some(value)
+    }
+}
+
+// Decode the data segments into text. Byte segments are UTF-8 unless an ECI
+// says ISO-8859-1 or they aren't valid UTF-8.
+fn parse_segments(data: &[u8], version: usize) -> Option<String> {
+    let mut reader = BitReader { bytes: data, position: 0 };
+    // Length field sizes grow with the version
+    let size_class = match version {
+        1..=9 => 0,
+        10..=26 => 1,
+        _ => 2,
+    };
+    let mut bytes = Vec::new();
+    let mut latin1 = false;
+
+    while reader.remaining() >= 4 {
+        match reader.read(4)? {
+            // Terminator
+            0 => break,
+            // Numeric: three digits in 10 bits
+            1 => {
+                let mut count = reader.read([10, 12, 14][size_class])?;
+                while count > 0 {
+                    let digits = count.min(3);
+                    let value = reader.read([0, 4, 7, 10][digits as usize])?;
+                    if value >= 10u32.pow(digits) {
+                        return None;
+                    }
+                    bytes.extend(format!("{:0width$}", value, width = digits as usize).bytes());
+                    count -= digits;
+                }
+            }
+            // Alphanumeric: two characters in 11 bits
+            2 => {
+                let mut count = reader.read([9, 11, 13][size_class])?;
+                while count > 0 {
+                    let (characters, bits) = if count >= 2 { (2, 11) } else { (1, 6) };
+                    let value = reader.read(bits)? as usize;
+                    if characters == 2 {
+                        bytes.push(*ALPHANUMERIC.get(value / 45)?);
+                    }
+                    bytes.push(*ALPHANUMERIC.get(value % 45)?);
+                    count -= characters;
+                }
+            }
+            // Structured append header; the parts are read as separate codes
+            3 => {
+                reader.read(16)?;
+            }
+            4 => {
+                let count = reader.read([8, 16, 16][size_class])?;
+                for _ in 0..count {
+                    bytes.push(reader.read(8)? as u8);
+                }
+            }
+            // FNC1 markers for GS1 and application data
+            5 => {}
+            9 => {
+                reader.read(8)?;
+            }
+            // ECI designator, in one to three bytes
+            7 => {
+                let first = reader.read(8)?;
+                let eci = if first & 0x80 == 0 {
+                    first
+                } else if first & 0xc0 == 0x80 {
+                    ((first & 0x3f) << 8) | reader.read(8)?
+                } else if first & 0xe0 == 0xc0 {
+                    ((first & 0x1f) << 16) | reader.read(16)?
+                } else {
+                    return None;
+                };
+                latin1 = matches!(eci, 1 | 3);
+            }
+            // Kanji and anything unknown
+            _ => return None,
+        }
+    }
+
+    let text = match String::from_utf8(bytes) {
+        Ok(text) if !latin1 => text,
+        Ok(text) => text.bytes().map(char::from).collect(),
+        Err(e) => e.into_bytes().into_iter().map(char::from).collect(),
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+// Reed-Solomon error correction over GF(256), as used by QR codes
+mod reed_solomon {
+    // Powers of the generator 2 modulo x^8 + x^4 + x^3 + x^2 + 1, repeated
+    // so products can index without reducing, and their logarithms
+    struct Field {
+        exp: [u8; 512],
+        log: [u8; 256],
+    }
+
+    impl Field {
+        fn new() -> Self {
+            let mut field = Field { exp: [0; 512], log: [0; 256] };
+            let mut value: u32 = 1;
+            for i in 0..255 {
+                field.exp[i] = value as u8;
+                field.log[value as usize] = i as u8;
+                value <<= 1;
+                if value & 0x100 != 0 {
+                    value ^= 0x11d;
+                }
+            }
+            for i in 255..512 {
+                field.exp[i] = field.exp[i - 255];
+            }
+            field
+        }
+
+        fn mul(&self, a: u8, b: u8) -> u8 {
+            if a == 0 || b == 0 {
+                return 0;
+            }
+            self.exp[self.log[a as usize] as usize + self.log[b as usize] as usize]
+        }
+
+        fn div(&self, a: u8, b: u8) -> u8 {
+            if a == 0 {
+                return 0;
+            }
+            self.exp[self.log[a as usize] as usize + 255 - self.log[b as usize] as usize]
+        }
+
+        // 2^power
+        fn pow(&self, power: usize) -> u8 {
+            self.exp[power % 255]
+        }
+
+        // Evaluate a polynomial with lowest-order coefficients first
+        fn eval(&self, poly: &[u8], x: u8) -> u8 {
+            poly.iter().rev().fold(0, |acc, &c| self.mul(acc, x) ^ c)
+        }
+    }
+
+    /// Correct up to `ec / 2` wrong codewords in a block whose last `ec`
+    /// codewords are error correction, in place. Fails if there are more.
+    pub fn correct(block: &mut [u8], ec: usize) -> Option<()> {
+        let field = Field::new();
+        let n = block.len();
+        // The block is a polynomial with the first codeword highest
+        let value_at = |block: &[u8], x: u8| block.iter().fold(0, |acc, &c| field.mul(acc, x) ^ c);
+
+        let syndromes: Vec<u8> = (0..ec).map(|i| value_at(block, field.pow(i))).collect();
+        if syndromes.iter().all(|&s| s == 0) {
+            return Some(());
+        }
+
+        // Berlekamp-Massey for the error locator, lowest order first
+        let mut locator = vec![1u8];
+        let mut previous = vec![1u8];
+        let mut errors = 0;
+        let mut shift = 1;
+        let mut previous_discrepancy = 1u8;
+        for step in 0..ec {
+            let discrepancy = (1..=errors).fold(syndromes[step], |acc, i| acc ^ field.mul(*locator.get(i).unwrap_or(&0), syndromes[step - i]));
+            if discrepancy == 0 {
+                shift += 1;
+                continue;
+            }
+            let scale = field.div(discrepancy, previous_discrepancy);
+            let mut updated = locator.clone();
+            updated.resize(updated.len().max(previous.len() + shift), 0);
+            for (i, &c) in previous.iter().enumerate() {
+                updated[i + shift] ^= field.mul(scale, c);
+            }
+            if 2 * errors <= step {
+                previous = std::mem::replace(&mut locator, updated);
+                errors = step + 1 - errors;
+                previous_discrepancy = discrepancy;
+                shift = 1;
+            } else {
+                locator = updated;
+                shift += 1;
+            }
+        }
+        if 2 * errors > ec {
+            return None;
+        }
+
+        // Error positions are the powers whose inverses are roots
+        let positions: Vec<usize> = (0..n).filter(|&p| field.eval(&locator, field.pow(255 - p % 255)) == 0).collect();
+        if positions.len() != errors {
+            return None;
+        }
+
+        // Forney: magnitudes from the evaluator S(x) * locator(x) mod x^ec
+        let mut evaluator = vec![0u8; ec];
+        for (i, &s) in syndromes.iter().enumerate() {
+            for (j, &l) in locator.iter().enumerate() {
+                if i + j < ec {
+                    evaluator[i + j] ^= field.mul(s, l);
+                }
+            }
+        }
+        let derivative: Vec<u8> = locator.iter().enumerate().skip(1).map(|(i, &c)| if i % 2 == 1 { c } else { 0 }).collect();
+        for &p in &positions {
+            let x = field.pow(p);
+            let x_inverse = field.pow(255 - p % 255);
+            let denominator = field.eval(&derivative, x_inverse);
+            if denominator == 0 {
+                return None;
+            }
+            let magnitude = field.mul(x, field.div(field.eval(&evaluator, x_inverse), denominator));
+            block[n - 1 - p] ^= magnitude;
+        }
+
+        (0..ec).all(|i| value_at(block, field.pow(i)) == 0).then_some(())
+    }
+}
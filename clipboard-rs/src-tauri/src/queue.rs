@@ -0,0 +1,490 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+use uuid::Uuid;
+
+use crate::db::HistoryDb;
+use crate::formats::{self, ClipboardFormats};
+use crate::imagehash;
+use crate::item::{ClipboardItem, ClipboardPayload};
+
+/// Event emitted whenever the paste queue changes, with a `QueueStatus`
+/// payload.
+pub const QUEUE_EVENT: &str = "paste-queue";
+
+// How long after a paste the next item goes on the clipboard. Apps read the
+// clipboard after the key press, so loading it straight away could paste
+// the wrong item.
+const PASTE_SETTLE: Duration = Duration::from_millis(300);
+
+// How often the paste watcher checks for pastes while the queue is loaded,
+// and while it isn't
+const ACTIVE_INTERVAL: Duration = Duration::from_millis(20);
+const IDLE_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Serialize, Clone, Debug)]
+pub struct QueueStatus {
+    /// Queued items in paste order. The first is the one on the clipboard
+    /// while `loaded`.
+    pub items: Vec<ClipboardItem>,
+    /// Whether the first item is on the clipboard. Copying something else
+    /// takes the queue off until it is advanced or changed.
+    pub loaded: bool,
+    /// Whether pastes are noticed by themselves, which only works on X11
+    /// (see `detect`). Without that the queue only moves on through
+    /// `advance_paste_queue`.
+    pub detects_pastes: bool,
+}
+
+/// Clipboard content in the shape the monitor reads it, so the queue can
+/// tell its own items apart from anything copied since.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClipContent {
+    Formats(ClipboardFormats),
+    /// Pixel hash of an image (see `imagehash::rgba_hash`).
+    Image(String),
+}
+
+impl ClipContent {
+    // Whether `seen` is this content read back. Files are compared by their
+    // paths, since file managers offer different text alongside them, and
+    // HTML and RTF only where the clipboard offers them: the portable
+    // backend can't read RTF.
+    fn read_back_as(&self, seen: &ClipContent) -> bool {
+        match (self, seen) {
+            (ClipContent::Image(loaded), ClipContent::Image(seen)) => loaded == seen,
+            (ClipContent::Formats(loaded), ClipContent::Formats(seen)) if !loaded.files.is_empty() => loaded.files == seen.files,
+            (ClipContent::Formats(loaded), ClipContent::Formats(seen)) => {
+                let same = |loaded: &Option<String>, seen: &Option<String>| seen.is_none() || loaded == seen;
+                loaded.text == seen.text
+                    && seen.files.is_empty()
+                    && same(&loaded.html, &seen.html)
+                    && same(&loaded.rtf, &seen.rtf)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    ids: VecDeque<Uuid>,
+    // What the queue last put on the clipboard, while it's still there
+    loaded: Option<ClipContent>,
+}
+
+/// Items waiting to be pasted one after another. Each paste puts the next
+/// one on the clipboard with every format it was captured with.
+#[derive(Default)]
+pub struct PasteQueue {
+    state: Mutex<QueueState>,
+    detects_pastes: AtomicBool,
+}
+
+impl PasteQueue {
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn status(&self, app: &tauri::AppHandle) -> QueueStatus {
+        let state = self.lock();
+        let db = app.state::<HistoryDb>();
+        QueueStatus {
+            items: state.ids.iter().filter_map(|&id| db.get(id).ok().flatten()).collect(),
+            loaded: state.loaded.is_some(),
+            detects_pastes: self.detects_pastes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Add items to the end of the queue, loading the first one if the
+    /// queue wasn't on the clipboard.
+    pub fn push(&self, app: &tauri::AppHandle, ids: &[Uuid]) -> Result<QueueStatus, String> {
+        let db = app.state::<HistoryDb>();
+        for &id in ids {
+            db.get(id).map_err(|e| e.to_string())?.ok_or("Item not found")?;
+        }
+
+        let mut state = self.lock();
+        state.ids.extend(ids);
+        if state.loaded.is_none() {
+            load_first(app, &mut state)?;
+        }
+        drop(state);
+        announce(app, self.status(app))
+    }
+
+    /// Put the queue in the order of `ids`, which must all be queued
+    /// already. Items left out are removed.
+    pub fn reorder(&self, app: &tauri::AppHandle, ids: &[Uuid]) -> Result<QueueStatus, String> {
+        let mut state = self.lock();
+        if let Some(missing) = ids.iter().find(|id| !state.ids.contains(id)) {
+            return Err(format!("Item {} isn't queued", missing));
+        }
+        let first = state.ids.front().copied();
+        state.ids = ids.iter().copied().collect();
+        if state.ids.front().copied() != first {
+            load_first(app, &mut state)?;
+        }
+        drop(state);
+        announce(app, self.status(app))
+    }
+
+    /// Empty the queue. Whatever is on the clipboard stays there.
+    pub fn clear(&self, app: &tauri::AppHandle) -> Result<QueueStatus, String> {
+        *self.lock() = QueueState::default();
+        announce(app, self.status(app))
+    }
+
+    /// Drop the first item and put the next one on the clipboard. If the
+    /// queue was taken off the clipboard, this loads the first item again
+    /// instead.
+    pub fn advance(&self, app: &tauri::AppHandle) -> Result<QueueStatus, String> {
+        let mut state = self.lock();
+        if state.loaded.is_some() {
+            state.ids.pop_front();
+        }
+        load_first(app, &mut state)?;
+        drop(state);
+        announce(app, self.status(app))
+    }
+
+    // Move on after a paste, unless something else has been copied since
+    fn pasted(&self, app: &tauri::AppHandle) -> Result<(), String> {
+        if self.lock().loaded.is_some() {
+            self.advance(app)?;
+        }
+        Ok(())
+    }
+
+    /// Called by the monitor for every new clipboard content. Returns
+    /// whether the queue put it there, in which case it isn't recorded
+    /// again. Anything else takes the queue off the clipboard.
+    pub fn claims(&self, app: &tauri::AppHandle, seen: &ClipContent) -> bool {
+        let mut state = self.lock();
+        match &state.loaded {
+            Some(loaded) if loaded.read_back_as(seen) => true,
+            Some(_) => {
+                state.loaded = None;
+                drop(state);
+                let _ = announce(app, self.status(app));
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+// Put the first queued item on the clipboard, dropping any deleted from the
+// history since they were queued. The content is noted before it's written
+// so the monitor knows it when it sees it.
+fn load_first(app: &tauri::AppHandle, state: &mut QueueState) -> Result<(), String> {
+    let db = app.state::<HistoryDb>();
+    while let Some(&id) = state.ids.front() {
+        let Some(item) = db.get(id).map_err(|e| e.to_string())? else {
+            state.ids.pop_front();
+            continue;
+        };
+        if let Some(formats) = ClipboardFormats::from_payload(&item.payload) {
+            state.loaded = Some(ClipContent::Formats(formats.clone()));
+            return formats::write(&formats);
+        }
+        if let ClipboardPayload::Image { blob_path, .. } = &item.payload {
+            // Hash the pixels being written rather than trusting the stored
+            // hash, which images captured before pixels were hashed lack
+            let img = crate::load_image_blob(app, blob_path)?.to_rgba8();
+            state.loaded = Some(ClipContent::Image(imagehash::rgba_hash(&img)));
+            return crate::write_clipboard_image(app, img);
+        }
+        state.ids.pop_front();
+    }
+    state.loaded = None;
+    Ok(())
+}
+
+fn announce(app: &tauri::AppHandle, status: QueueStatus) -> Result<QueueStatus, String> {
+    let _ = app.emit(QUEUE_EVENT, status.clone());
+    Ok(status)
+}
+
+/// Notices when the user pastes, so the queue can move on.
+pub trait PasteDetector: Send {
+    /// Start or stop watching for pastes. The queue only watches while it's
+    /// on the clipboard.
+    fn set_active(&mut self, active: bool) -> Result<(), String>;
+
+    /// Whether the user pasted since the last call. Doesn't block. An error
+    /// means the detector can no longer see pastes.
+    fn pasted(&mut self) -> Result<bool, String>;
+}
+
+/// Pick a paste detector for the current session. Pastes are noticed from
+/// the paste shortcuts on X11 only: Wayland doesn't let clients see other
+/// windows' key presses, and there's no detector for Windows or macOS yet.
+/// Without one the queue reports `detects_pastes: false` and the UI asks
+/// for Next after each paste.
+pub fn detect() -> Option<Box<dyn PasteDetector>> {
+    #[cfg(target_os = "linux")]
+    {
+        if std::env::var_os("WAYLAND_DISPLAY").is_none() && std::env::var_os("DISPLAY").is_some() {
+            if let Ok(detector) = x11::X11PasteDetector::connect() {
+                return Some(Box::new(detector));
+            }
+        }
+    }
+    None
+}
+
+/// Advance the queue after every paste, when pastes can be detected.
+pub fn start_watcher(app: tauri::AppHandle) {
+    thread::spawn(move || {
+        let Some(mut detector) = detect() else {
+            return;
+        };
+        let queue = app.state::<PasteQueue>();
+        queue.detects_pastes.store(true, Ordering::Relaxed);
+
+        let mut active = false;
+        loop {
+            let loaded = queue.lock().loaded.is_some();
+            let result = if loaded != active {
+                active = loaded;
+                detector.set_active(active).map(|_| false)
+            } else if active {
+                detector.pasted()
+            } else {
+                Ok(false)
+            };
+            match result {
+                Ok(true) => {
+                    thread::sleep(PASTE_SETTLE);
+                    let _ = queue.pasted(&app);
+                }
+                Ok(false) => thread::sleep(if active { ACTIVE_INTERVAL } else { IDLE_INTERVAL }),
+                Err(_) => {
+                    // Fall back to advancing by hand
+                    queue.detects_pastes.store(false, Ordering::Relaxed);
+                    let _ = announce(&app, queue.status(&app));
+                    return;
+                }
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_paste_queue(app: tauri::AppHandle, queue: tauri::State<'_, PasteQueue>) -> QueueStatus {
+    queue.status(&app)
+}
+
+// Function to add history items to the end of the paste queue. The first
+// queued item goes on the clipboard straight away.
+#[tauri::command]
+pub fn enqueue_paste_items(
+    app: tauri::AppHandle,
+    queue: tauri::State<'_, PasteQueue>,
+    ids: Vec<Uuid>,
+) -> Result<QueueStatus, String> {
+    queue.push(&app, &ids)
+}
+
+// Function to reorder the paste queue; queued items left out of `ids` are
+// removed
+#[tauri::command]
+pub fn reorder_paste_queue(
+    app: tauri::AppHandle,
+    queue: tauri::State<'_, PasteQueue>,
+    ids: Vec<Uuid>,
+) -> Result<QueueStatus, String> {
+    queue.reorder(&app, &ids)
+}
+
+// Function to skip to the next queued item, for pastes that weren't noticed
+#[tauri::command]
+pub fn advance_paste_queue(app: tauri::AppHandle, queue: tauri::State<'_, PasteQueue>) -> Result<QueueStatus, String> {
+    queue.advance(&app)
+}
+
+// Function to empty the paste queue
+#[tauri::command]
+pub fn clear_paste_queue(app: tauri::AppHandle, queue: tauri::State<'_, PasteQueue>) -> Result<QueueStatus, String> {
+    queue.clear(&app)
+}
+
+#[cfg(target_os = "linux")]
+mod x11 {
+    use std::collections::HashSet;
+
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xinput::{ConnectionExt as _, Device, EventMask, XIEventMask};
+    use x11rb::protocol::xproto::{ConnectionExt as _, Keycode, Window};
+    use x11rb::protocol::Event;
+    use x11rb::rust_connection::RustConnection;
+
+    use super::PasteDetector;
+
+    // Keysyms of the keys in the paste shortcuts
+    const XK_V_LOWER: u32 = 0x0076;
+    const XK_V_UPPER: u32 = 0x0056;
+    const XK_INSERT: u32 = 0xff63;
+    const XK_KP_INSERT: u32 = 0xff9e;
+    const XK_SHIFT_L: u32 = 0xffe1;
+    const XK_SHIFT_R: u32 = 0xffe2;
+    const XK_CONTROL_L: u32 = 0xffe3;
+    const XK_CONTROL_R: u32 = 0xffe4;
+
+    /// Watches raw key events from XInput2 for Ctrl+V, Ctrl+Shift+V and
+    /// Shift+Insert. Only key codes are looked at, and only while active.
+    /// X11 only: XWayland doesn't forward key presses made in native
+    /// Wayland windows, so `detect` never picks this under Wayland.
+    pub struct X11PasteDetector {
+        conn: RustConnection,
+        root: Window,
+        v: HashSet<Keycode>,
+        insert: HashSet<Keycode>,
+        control: HashSet<Keycode>,
+        shift: HashSet<Keycode>,
+        // Modifier keys held down right now
+        held: HashSet<Keycode>,
+    }
+
+    impl X11PasteDetector {
+        pub fn connect() -> Result<Self, String> {
+            let (conn, screen_num) = RustConnection::connect(None).map_err(|e| e.to_string())?;
+            let version = conn
+                .xinput_xi_query_version(2, 0)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| e.to_string())?;
+            if version.major_version < 2 {
+                return Err("XInput2 is not available".to_string());
+            }
+            let root = conn.setup().roots[screen_num].root;
+
+            // Find the key codes that carry each keysym in any group
+            let (min, max) = (conn.setup().min_keycode, conn.setup().max_keycode);
+            let mapping = conn
+                .get_keyboard_mapping(min, max - min + 1)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| e.to_string())?;
+            let per_keycode = mapping.keysyms_per_keycode.max(1) as usize;
+            let keys_with = |keysyms: &[u32]| -> HashSet<Keycode> {
+                mapping
+                    .keysyms
+                    .chunks(per_keycode)
+                    .enumerate()
+                    .filter(|(_, syms)| syms.iter().any(|sym| keysyms.contains(sym)))
+                    .map(|(i, _)| min + i as Keycode)
+                    .collect()
+            };
+
+            Ok(X11PasteDetector {
+                v: keys_with(&[XK_V_LOWER, XK_V_UPPER]),
+                insert: keys_with(&[XK_INSERT, XK_KP_INSERT]),
+                control: keys_with(&[XK_CONTROL_L, XK_CONTROL_R]),
+                shift: keys_with(&[XK_SHIFT_L, XK_SHIFT_R]),
+                held: HashSet::new(),
+                conn,
+                root,
+            })
+        }
+    }
+
+    impl PasteDetector for X11PasteDetector {
+        fn set_active(&mut self, active: bool) -> Result<(), String> {
+            let mask = if active { vec![XIEventMask::RAW_KEY_PRESS | XIEventMask::RAW_KEY_RELEASE] } else { vec![0u32.into()] };
+            self.conn
+                .xinput_xi_select_events(self.root, &[EventMask { deviceid: Device::ALL_MASTER.into(), mask }])
+                .map_err(|e| e.to_string())?;
+            self.conn.flush().map_err(|e| e.to_string())?;
+            self.held.clear();
+            Ok(())
+        }
+
+        fn pasted(&mut self) -> Result<bool, String> {
+            let mut pasted = false;
+            while let Some(event) = self.conn.poll_for_event().map_err(|e| e.to_string())? {
+                match event {
+                    Event::XinputRawKeyPress(event) => {
+                        let Ok(key) = Keycode::try_from(event.detail) else {
+                            continue;
+                        };
+                        let control = self.held.iter().any(|key| self.control.contains(key));
+                        let shift = self.held.iter().any(|key| self.shift.contains(key));
+                        if (control && self.v.contains(&key)) || (shift && self.insert.contains(&key)) {
+                            pasted = true;
+                        } else if self.control.contains(&key) || self.shift.contains(&key) {
+                            self.held.insert(key);
+                        }
+                    }
+                    Event::XinputRawKeyRelease(event) => {
+                        if let Ok(key) = Keycode::try_from(event.detail) {
+                            self.held.remove(&key);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(pasted)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formats(text: &str, html: Option<&str>, rtf: Option<&str>) -> ClipContent {
+        ClipContent::Formats(ClipboardFormats {
+            text: Some(text.to_string()),
+            html: html.map(str::to_string),
+            rtf: rtf.map(str::to_string),
+            files: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn text_read_back() {
+        let loaded = formats("hello", None, None);
+        assert!(loaded.read_back_as(&formats("hello", None, None)));
+        assert!(!loaded.read_back_as(&formats("hello ", None, None)));
+        assert!(!loaded.read_back_as(&formats("hello", Some("<b>hello</b>"), None)));
+        assert!(!loaded.read_back_as(&ClipContent::Image("hello".to_string())));
+    }
+
+    #[test]
+    fn rich_text_read_back() {
+        let loaded = formats("hello", Some("<b>hello</b>"), Some(r"{\rtf1 hello}"));
+        assert!(loaded.read_back_as(&formats("hello", Some("<b>hello</b>"), Some(r"{\rtf1 hello}"))));
+        // Backends that can't read RTF
+        assert!(loaded.read_back_as(&formats("hello", Some("<b>hello</b>"), None)));
+        // The same text copied with other formatting
+        assert!(!loaded.read_back_as(&formats("hello", Some("<i>hello</i>"), None)));
+        assert!(!loaded.read_back_as(&formats("hello!", Some("<b>hello</b>"), None)));
+    }
+
+    #[test]
+    fn files_and_images_read_back() {
+        let files = |paths: &[&str], text: &str| {
+            ClipContent::Formats(ClipboardFormats {
+                text: Some(text.to_string()),
+                files: paths.iter().map(|path| path.to_string()).collect(),
+                ..Default::default()
+            })
+        };
+        let loaded = files(&["/tmp/a", "/tmp/b"], "/tmp/a\n/tmp/b");
+        assert!(loaded.read_back_as(&files(&["/tmp/a", "/tmp/b"], "file:///tmp/a\r\nfile:///tmp/b")));
+        assert!(!loaded.read_back_as(&files(&["/tmp/b", "/tmp/a"], "/tmp/a\n/tmp/b")));
+        assert!(!loaded.read_back_as(&formats("/tmp/a\n/tmp/b", None, None)));
+
+        let image = ClipContent::Image("ab12".to_string());
+        assert!(image.read_back_as(&ClipContent::Image("ab12".to_string())));
+        assert!(!image.read_back_as(&ClipContent::Image("cd34".to_string())));
+        assert!(!image.read_back_as(&formats("ab12", None, None)));
+    }
+}
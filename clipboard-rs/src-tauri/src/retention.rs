@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+use uuid::Uuid;
+
+use crate::history::History;
+
+// How often the scheduler enforces age limits and tag TTLs. Count and size
+// limits are also enforced on every insert.
+const ENFORCE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How much history to keep. Part of the persisted settings; limits left
+/// unset don't apply.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Most items to keep; the oldest go first.
+    pub max_items: Option<usize>,
+    /// Most bytes to keep, counting each item's payload and image blob.
+    pub max_total_bytes: Option<u64>,
+    /// Age limit for items of any kind without an entry in `max_age_by_kind`.
+    pub max_age_seconds: Option<u64>,
+    /// Age limits per payload kind (`text`, `rich_text`, `image`, `file_list`).
+    pub max_age_by_kind: BTreeMap<String, u64>,
+    /// Items carrying one of these tags are deleted this many seconds after
+    /// they were copied, regardless of the other limits.
+    pub tag_ttl_seconds: BTreeMap<String, u64>,
+    /// Favorites are never evicted and don't count toward the limits.
+    pub exempt_favorites: bool,
+    /// How long deleted and evicted items stay in the trash.
+    pub trash_retention_seconds: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy {
+            max_items: Some(50_000),
+            max_total_bytes: None,
+            max_age_seconds: None,
+            max_age_by_kind: BTreeMap::new(),
+            tag_ttl_seconds: BTreeMap::new(),
+            exempt_favorites: true,
+            trash_retention_seconds: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// Age limit for one payload kind, if any.
+    pub fn max_age_for(&self, kind: &str) -> Option<u64> {
+        self.max_age_by_kind.get(kind).copied().or(self.max_age_seconds)
+    }
+}
+
+/// Which limit evicted an item.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum EvictionReason {
+    MaxItems,
+    MaxTotalBytes,
+    MaxAge { kind: String },
+    TagTtl { tag: String },
+}
+
+impl EvictionReason {
+    /// The `reason` tag, recorded on the evicted item in the trash.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EvictionReason::MaxItems => "max_items",
+            EvictionReason::MaxTotalBytes => "max_total_bytes",
+            EvictionReason::MaxAge { .. } => "max_age",
+            EvictionReason::TagTtl { .. } => "tag_ttl",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Eviction {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub reason: EvictionReason,
+}
+
+/// Enforce the retention policy in the background. Evictions reach the
+/// frontend as an `evicted` history change listing each item and the limit
+/// that removed it. Routine clean-ups like these can't be undone, but the
+/// items stay in the trash.
+pub fn start_scheduler(app: tauri::AppHandle) {
+    thread::spawn(move || loop {
+        // Fails while an encrypted history is locked; try again next round
+        let _ = app.state::<History>().enforce_retention(false);
+        thread::sleep(ENFORCE_INTERVAL);
+    });
+}
+
+// Function to apply the retention policy now and list what it evicted. The
+// clean-up can be undone with `undo_last_operation`.
+#[tauri::command]
+pub fn apply_retention_policy(history: tauri::State<'_, History>) -> Result<Vec<Eviction>, String> {
+    history.enforce_retention(true)
+}
@@ -0,0 +1,216 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::thread;
+
+use clipctl::{socket_path, RpcError};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::Manager;
+use uuid::Uuid;
+
+use crate::search::{SearchFilters, SearchMode};
+
+// Standard JSON-RPC 2.0 error codes, plus one for errors from the history
+// itself
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const COMMAND_FAILED: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize)]
+struct ListParams {
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    query: String,
+    mode: Option<SearchMode>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    // `tag`, `collection`, `favorite`, `content_type`, `since` and `until`
+    #[serde(flatten)]
+    filters: SearchFilters,
+}
+
+#[derive(Deserialize)]
+struct ItemParams {
+    id: Uuid,
+}
+
+#[derive(Deserialize)]
+struct PauseParams {
+    seconds: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct TagParams {
+    id: Uuid,
+    tag: String,
+}
+
+/// Serve newline-delimited JSON-RPC 2.0 on `socket_path()` in a background
+/// thread. Does nothing if another instance is already listening there or
+/// the socket can't be created; `clipctl` then reports it can't connect.
+pub fn start_server(app: tauri::AppHandle) {
+    thread::spawn(move || {
+        let Ok(listener) = bind() else {
+            return;
+        };
+        for stream in listener.incoming().flatten() {
+            let app = app.clone();
+            thread::spawn(move || serve(&app, stream));
+        }
+    });
+}
+
+/// Remove the socket file on exit.
+pub fn stop_server() {
+    let _ = fs::remove_file(socket_path());
+}
+
+// Bind the socket in a directory only the current user can use, replacing
+// a stale file left by a crashed instance
+fn bind() -> std::io::Result<UnixListener> {
+    let path = socket_path();
+    if std::env::var_os("CLIPBOARD_RS_SOCKET").is_none() {
+        clipctl::create_socket_dir()?;
+    }
+    if UnixStream::connect(&path).is_ok() {
+        return Err(std::io::Error::new(std::io::ErrorKind::AddrInUse, "another instance is running"));
+    }
+    let _ = fs::remove_file(&path);
+
+    // Have the socket created with mode 0600 rather than tightening it after
+    // binding, which would leave a moment for others to connect
+    // SAFETY: umask has no preconditions and can't fail
+    let previous = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(&path);
+    unsafe { libc::umask(previous) };
+    listener
+}
+
+// Answer requests on one connection, one JSON object per line, until the
+// client hangs up
+fn serve(app: &tauri::AppHandle, stream: UnixStream) {
+    let Ok(reader) = stream.try_clone() else {
+        return;
+    };
+    let mut writer = stream;
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle(app, &line) {
+            let Ok(response) = serde_json::to_string(&response) else {
+                continue;
+            };
+            if writeln!(writer, "{}", response).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+// Run one request. Notifications (requests without an id) get no response.
+fn handle(app: &tauri::AppHandle, line: &str) -> Option<Response> {
+    let request: Request = match serde_json::from_str::<Value>(line) {
+        Err(e) => return Some(error_response(Value::Null, RpcError::new(PARSE_ERROR, e.to_string()))),
+        Ok(value) => match serde_json::from_value(value) {
+            Ok(request) => request,
+            Err(e) => return Some(error_response(Value::Null, RpcError::new(INVALID_REQUEST, e.to_string()))),
+        },
+    };
+
+    let result = dispatch(app, &request.method, request.params);
+    let id = request.id?;
+    Some(match result {
+        Ok(result) => Response { jsonrpc: "2.0", id, result: Some(result), error: None },
+        Err(error) => error_response(id, error),
+    })
+}
+
+fn error_response(id: Value, error: RpcError) -> Response {
+    Response { jsonrpc: "2.0", id, result: None, error: Some(error) }
+}
+
+// Map a method to the command behind it, so scripts see exactly what the GUI does
+fn dispatch(app: &tauri::AppHandle, method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        "list" => {
+            let p: ListParams = parse_params(params)?;
+            to_result(crate::load_clipboard_history(app.state(), p.limit, p.offset))
+        }
+        "search" => {
+            let p: SearchParams = parse_params(params)?;
+            to_result(crate::search_clipboard_history(app.state(), &p.query, p.mode, Some(p.filters), p.offset, p.limit))
+        }
+        "get" => {
+            let p: ItemParams = parse_params(params)?;
+            to_result(crate::get_clipboard_item(app.state(), p.id))
+        }
+        "copy" => {
+            let p: ItemParams = parse_params(params)?;
+            to_result(crate::restore_clipboard_item(app.clone(), app.state(), p.id))
+        }
+        "favorite" => {
+            let p: ItemParams = parse_params(params)?;
+            to_result(crate::toggle_favorite(app.state(), p.id))
+        }
+        "tag" => {
+            let p: TagParams = parse_params(params)?;
+            to_result(crate::add_tag_to_item(app.state(), p.id, &p.tag))
+        }
+        "untag" => {
+            let p: TagParams = parse_params(params)?;
+            to_result(crate::remove_tag_from_item(app.state(), p.id, &p.tag))
+        }
+        "delete" => {
+            let p: ItemParams = parse_params(params)?;
+            to_result(crate::delete_clipboard_item(app.state(), p.id))
+        }
+        "pause" => {
+            let p: PauseParams = parse_params(params)?;
+            to_result(crate::pause::pause_capture(app.clone(), app.state(), p.seconds))
+        }
+        "resume" => to_result(crate::pause::resume_capture(app.clone(), app.state())),
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    // Methods without required params may be called with none at all
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn to_result<T: Serialize>(result: Result<T, String>) -> Result<Value, RpcError> {
+    let value = result.map_err(|e| RpcError::new(COMMAND_FAILED, e))?;
+    serde_json::to_value(value).map_err(|e| RpcError::new(COMMAND_FAILED, e.to_string()))
+}
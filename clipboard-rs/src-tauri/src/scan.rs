@@ -0,0 +1,304 @@
+use image::GrayImage;
+use serde::Serialize;
+
+use crate::barcode;
+use crate::qrdecode;
+
+/// Symbology of a decoded code.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeFormat {
+    QrCode,
+    Ean13,
+    Ean8,
+    UpcA,
+    Code128,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct DecodedCode {
+    pub format: CodeFormat,
+    pub text: String,
+}
+
+/// Find and decode the QR codes and barcodes in an image, in no particular
+/// order. Each payload is listed once.
+pub fn scan(img: &GrayImage) -> Vec<DecodedCode> {
+    let bits = BitMatrix::binarize(img);
+    let mut codes = qrdecode::decode_all(&bits);
+    if codes.is_empty() {
+        // Light on dark, as some apps draw them in dark mode
+        codes = qrdecode::decode_all(&bits.inverted());
+    }
+    // Barcodes may run across the image or up it
+    codes.extend(barcode::decode_all(&bits));
+    codes.extend(barcode::decode_all(&bits.transposed()));
+
+    let mut unique: Vec<DecodedCode> = Vec::new();
+    for code in codes {
+        if !unique.contains(&code) {
+            unique.push(code);
+        }
+    }
+    unique
+}
+
+/// A black-and-white image, or a grid of QR code modules. `true` is dark.
+pub struct BitMatrix {
+    pub width: usize,
+    pub height: usize,
+    bits: Vec<bool>,
+}
+
+// Side of the square blocks `binarize` thresholds together
+const BLOCK: usize = 8;
+
+// Blocks whose darkest and lightest pixels differ by at most this much are
+// taken to be all one shade
+const MIN_CONTRAST: u32 = 24;
+
+impl BitMatrix {
+    pub fn new(width: usize, height: usize) -> Self {
+        BitMatrix { width, height, bits: vec![false; width * height] }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.bits[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, dark: bool) {
+        self.bits[y * self.width + x] = dark;
+    }
+
+    fn inverted(&self) -> Self {
+        BitMatrix { width: self.width, height: self.height, bits: self.bits.iter().map(|&dark| !dark).collect() }
+    }
+
+    /// Swap rows and columns. Vertical barcodes can then be read along rows,
+    /// and a mirrored QR code's grid turns back into the real one.
+    pub fn transposed(&self) -> Self {
+        let mut transposed = BitMatrix::new(self.height, self.width);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                transposed.set(y, x, self.get(x, y));
+            }
+        }
+        transposed
+    }
+
+    /// Threshold a grayscale image. Each block of pixels is compared against
+    /// the average level of the blocks around it, so shadows and gradients
+    /// in photos don't swallow the code; small images use one threshold.
+    pub fn binarize(img: &GrayImage) -> Self {
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let mut bits = BitMatrix::new(width, height);
+        let level = |x: usize, y: usize| img.get_pixel(x as u32, y as u32)[0] as u32;
+
+        if width < BLOCK * 5 || height < BLOCK * 5 {
+            let (min, max) = img.pixels().fold((u32::MAX, 0), |(min, max), p| (min.min(p[0] as u32), max.max(p[0] as u32)));
+            for y in 0..height {
+                for x in 0..width {
+                    bits.set(x, y, max - min > MIN_CONTRAST && level(x, y) <= (min + max) / 2);
+                }
+            }
+            return bits;
+        }
+
+        // Blocks at the right and bottom edges overlap the ones before them
+        let (columns, rows) = (width.div_ceil(BLOCK), height.div_ceil(BLOCK));
+        let origin = |block: usize, size: usize| (block * BLOCK).min(size - BLOCK);
+
+        let mut levels = vec![0u32; columns * rows];
+        for row in 0..rows {
+            for column in 0..columns {
+                let (x0, y0) = (origin(column, width), origin(row, height));
+                let (mut min, mut max, mut sum) = (u32::MAX, 0, 0);
+                for y in y0..y0 + BLOCK {
+                    for x in x0..x0 + BLOCK {
+                        let value = level(x, y);
+                        min = min.min(value);
+                        max = max.max(value);
+                        sum += value;
+                    }
+                }
+                let mut average = sum / (BLOCK * BLOCK) as u32;
+                if max - min <= MIN_CONTRAST {
+                    // A flat block is most likely background, so it's light
+                    // unless the blocks before it were darker
+                    average = min / 2;
+                    if row > 0 && column > 0 {
+                        let above = levels[(row - 1) * columns + column];
+                        let left = levels[row * columns + column - 1];
+                        let diagonal = levels[(row - 1) * columns + column - 1];
+                        let neighbours = (above + 2 * left + diagonal) / 4;
+                        if min < neighbours {
+                            average = neighbours;
+                        }
+                    }
+                }
+                levels[row * columns + column] = average;
+            }
+        }
+
+        for row in 0..rows {
+            for column in 0..columns {
+                // The 5x5 blocks around this one, shifted inwards at the edges
+                let (cx, cy) = (column.clamp(2, columns - 3), row.clamp(2, rows - 3));
+                let mut sum = 0;
+                for y in cy - 2..=cy + 2 {
+                    for x in cx - 2..=cx + 2 {
+                        sum += levels[y * columns + x];
+                    }
+                }
+                let threshold = sum / 25;
+
+                let (x0, y0) = (origin(column, width), origin(row, height));
+                for y in y0..y0 + BLOCK {
+                    for x in x0..x0 + BLOCK {
+                        bits.set(x, y, level(x, y) <= threshold);
+                    }
+                }
+            }
+        }
+        bits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{imageops, Luma};
+    use qrcode::{EcLevel, QrCode, Version};
+
+    use super::*;
+
+    const LEVELS: [EcLevel; 4] = [EcLevel::L, EcLevel::M, EcLevel::Q, EcLevel::H];
+    const URL: &str = "https://example.com/some/path?query=1&x=2";
+
+    // Draw a code with `scale` pixels per module and a four-module quiet zone
+    fn render(code: &QrCode, scale: u32) -> GrayImage {
+        let width = code.width() as u32;
+        let colors = code.to_colors();
+        GrayImage::from_fn((width + 8) * scale, (width + 8) * scale, |x, y| {
+            let (x, y) = (x / scale, y / scale);
+            let inside = (4..width + 4).contains(&x) && (4..width + 4).contains(&y);
+            let dark = inside && colors[((y - 4) * width + x - 4) as usize] == qrcode::Color::Dark;
+            Luma([if dark { 0 } else { 255 }])
+        })
+    }
+
+    // Turn an image about its center onto a white canvas large enough to
+    // hold it, blending neighbouring pixels like a photo would
+    fn rotate(img: &GrayImage, degrees: f64) -> GrayImage {
+        let (width, height) = (img.width() as f64, img.height() as f64);
+        let size = width.hypot(height).ceil() as u32;
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let center = size as f64 / 2.0;
+        GrayImage::from_fn(size, size, |x, y| {
+            let (dx, dy) = (x as f64 - center, y as f64 - center);
+            let (sx, sy) = (cos * dx + sin * dy + width / 2.0, -sin * dx + cos * dy + height / 2.0);
+            if sx < 0.0 || sy < 0.0 || sx >= width - 1.0 || sy >= height - 1.0 {
+                return Luma([255]);
+            }
+            let (x0, y0) = (sx.floor() as u32, sy.floor() as u32);
+            let (fx, fy) = (sx.fract(), sy.fract());
+            let at = |x, y| img.get_pixel(x, y)[0] as f64;
+            let value = at(x0, y0) * (1.0 - fx) * (1.0 - fy)
+                + at(x0 + 1, y0) * fx * (1.0 - fy)
+                + at(x0, y0 + 1) * (1.0 - fx) * fy
+                + at(x0 + 1, y0 + 1) * fx * fy;
+            Luma([value.round() as u8])
+        })
+    }
+
+    fn texts(img: &GrayImage) -> Vec<String> {
+        scan(img).into_iter().map(|code| code.text).collect()
+    }
+
+    #[test]
+    fn qr_every_error_correction_level_and_size() {
+        for level in LEVELS {
+            for version in [1, 2, 7, 25, 40] {
+                let code = QrCode::with_version("CLIPBOARD", Version::Normal(version), level).unwrap();
+                let img = render(&code, if version > 20 { 2 } else { 3 });
+                let expected = DecodedCode { format: CodeFormat::QrCode, text: "CLIPBOARD".to_string() };
+                assert_eq!(scan(&img), [expected], "version {} {:?}", version, level);
+            }
+        }
+    }
+
+    #[test]
+    fn qr_every_segment_mode() {
+        for text in ["0123456789012345678901234567890", "HELLO WORLD 123 $%*+-./:", "ORDER 12345 for Zoë 🚀 ñ", URL] {
+            let code = QrCode::new(text.as_bytes()).unwrap();
+            assert_eq!(texts(&render(&code, 4)), [text]);
+            // Screenshots of small codes can have one pixel per module
+            assert_eq!(texts(&render(&code, 1)), [text]);
+        }
+    }
+
+    #[test]
+    fn qr_rotated() {
+        for level in LEVELS {
+            let img = render(&QrCode::with_error_correction_level(URL, level).unwrap(), 4);
+            for (name, turned) in [
+                ("90", imageops::rotate90(&img)),
+                ("180", imageops::rotate180(&img)),
+                ("270", imageops::rotate270(&img)),
+                ("30", rotate(&img, 30.0)),
+                ("45", rotate(&img, 45.0)),
+                ("200", rotate(&img, 200.0)),
+            ] {
+                assert_eq!(texts(&turned), [URL], "{:?} rotated {}", level, name);
+            }
+        }
+    }
+
+    #[test]
+    fn qr_mirrored() {
+        for level in LEVELS {
+            let img = render(&QrCode::with_error_correction_level(URL, level).unwrap(), 4);
+            let mirrored = imageops::flip_horizontal(&img);
+            for (name, flipped) in [
+                ("horizontally", mirrored.clone()),
+                ("vertically", imageops::flip_vertical(&img)),
+                ("and rotated 90", imageops::rotate90(&mirrored)),
+                ("and rotated 30", rotate(&mirrored, 30.0)),
+            ] {
+                assert_eq!(texts(&flipped), [URL], "{:?} mirrored {}", level, name);
+            }
+        }
+    }
+
+    #[test]
+    fn qr_light_on_dark() {
+        let mut img = render(&QrCode::new(URL).unwrap(), 4);
+        imageops::invert(&mut img);
+        assert_eq!(texts(&img), [URL]);
+    }
+
+    #[test]
+    fn several_codes_listed_once_each() {
+        let first = render(&QrCode::new(URL).unwrap(), 4);
+        let second = render(&QrCode::new("WIFI:T:WPA;S:Home;P:secret;;").unwrap(), 3);
+        let mut canvas = GrayImage::from_pixel(1200, 500, Luma([240]));
+        imageops::overlay(&mut canvas, &first, 20, 20);
+        imageops::overlay(&mut canvas, &second, 400, 100);
+        imageops::overlay(&mut canvas, &first, 800, 40);
+
+        let mut found = texts(&canvas);
+        found.sort();
+        assert_eq!(found, ["WIFI:T:WPA;S:Home;P:secret;;", URL]);
+    }
+
+    #[test]
+    fn nothing_in_noise_or_flat_images() {
+        let mut seed = 42u64;
+        let noise = GrayImage::from_fn(200, 150, |_, _| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            Luma([(seed >> 56) as u8])
+        });
+        assert!(scan(&noise).is_empty());
+        assert!(scan(&GrayImage::from_pixel(300, 200, Luma([128]))).is_empty());
+        assert!(scan(&GrayImage::new(1, 1)).is_empty());
+    }
+}
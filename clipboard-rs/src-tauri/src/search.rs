@@ -0,0 +1,598 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::{HistoryDb, TextFilter};
+use crate::item::ClipboardItem;
+
+/// How a query is matched against item text.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMode {
+    /// The query as one piece, ignoring case.
+    #[default]
+    Substring,
+    /// The query's characters in order but not necessarily adjacent,
+    /// ignoring case and whitespace, so `gcm` finds `git commit -m`.
+    Fuzzy,
+    /// A regular expression. Case-sensitive unless it starts with `(?i)`.
+    Regex,
+}
+
+/// Narrows a search to some items. Fields left unset match everything.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct SearchFilters {
+    pub tag: Option<String>,
+    /// Only items in this collection. They come in the collection's order
+    /// where the search doesn't rank them otherwise.
+    pub collection: Option<Uuid>,
+    pub favorite: Option<bool>,
+    /// Content type name such as `url` or `code` (see `ContentType::NAMES`).
+    pub content_type: Option<String>,
+    /// Only items copied at or after this unix time.
+    pub since: Option<u64>,
+    /// Only items copied at or before this unix time.
+    pub until: Option<u64>,
+}
+
+/// Byte range of a match in the item's text, as returned by
+/// `ClipboardPayload::search_text`.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct MatchRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub item: ClipboardItem,
+    /// Higher is better. Only comparable between hits of the same search.
+    pub score: i64,
+    /// Where the query matched the item's text. Empty for an empty query
+    /// and for items found only by their source app or recognized text.
+    pub matches: Vec<MatchRange>,
+    /// Where the query matched the item's `ocr_text`, for images whose
+    /// recognized text matched.
+    pub ocr_matches: Vec<MatchRange>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SearchResults {
+    /// The requested page, best match first.
+    pub hits: Vec<SearchHit>,
+    /// How many items matched in all.
+    pub total: usize,
+}
+
+// At most this many ranges are returned per item, so a one-letter query
+// against a huge clip doesn't flood the response
+const MAX_MATCHES: usize = 100;
+
+// Hits set aside while ranking are trimmed to the page once there are twice
+// the page's worth, or twice this many for small pages
+const MIN_KEPT_HITS: usize = 256;
+
+/// Search the history. Hits are ranked by score and then newest first; an
+/// empty query lists every item the filters allow, newest first (or in
+/// collection order when filtered by a collection). `limit`
+/// and `offset` select a page of the ranked hits.
+pub fn search(
+    db: &HistoryDb,
+    query: &str,
+    mode: SearchMode,
+    filters: &SearchFilters,
+    offset: usize,
+    limit: Option<usize>,
+) -> Result<SearchResults, String> {
+    let matcher = Matcher::new(query, mode)?;
+    let text = match &matcher {
+        // Nothing to rank, so the database can count and page
+        Matcher::All => {
+            let total = db.count_candidates(&TextFilter::Any, filters).map_err(|e| e.to_string())?;
+            let mut hits = Vec::new();
+            db.scan_candidates(&TextFilter::Any, filters, offset, limit, |item| {
+                hits.push(SearchHit { item, score: 0, matches: Vec::new(), ocr_matches: Vec::new() });
+            })
+            .map_err(|e| e.to_string())?;
+            return Ok(SearchResults { hits, total });
+        }
+        Matcher::Pattern(_) if mode == SearchMode::Substring => TextFilter::Contains(query.trim()),
+        Matcher::Pattern(_) => TextFilter::Any,
+        Matcher::Fuzzy(chars) => TextFilter::Subsequence(chars),
+    };
+
+    // Rank candidates as they're read, only holding on to hits that could
+    // still make the page
+    let keep = limit.map(|limit| offset.saturating_add(limit));
+    let mut hits: Vec<SearchHit> = Vec::new();
+    let mut total = 0;
+    db.scan_candidates(&text, filters, 0, None, |item| {
+        let Some(hit) = matcher.rank(item) else {
+            return;
+        };
+        total += 1;
+        hits.push(hit);
+        if let Some(keep) = keep.filter(|&keep| hits.len() >= keep.max(MIN_KEPT_HITS) * 2) {
+            // Stable, so equal scores stay in candidate order
+            hits.sort_by_key(|hit| std::cmp::Reverse(hit.score));
+            hits.truncate(keep);
+        }
+    })
+    .map_err(|e| e.to_string())?;
+    // Stable, so equal scores stay newest first
+    hits.sort_by_key(|hit| std::cmp::Reverse(hit.score));
+
+    let hits = hits.into_iter().skip(offset).take(limit.unwrap_or(usize::MAX)).collect();
+    Ok(SearchResults { hits, total })
+}
+
+enum Matcher {
+    // Empty query: everything matches
+    All,
+    // Substring and regex searches
+    Pattern(Regex),
+    // Folded query characters, whitespace removed
+    Fuzzy(Vec<char>),
+}
+
+impl Matcher {
+    fn new(query: &str, mode: SearchMode) -> Result<Self, String> {
+        if query.trim().is_empty() {
+            return Ok(Matcher::All);
+        }
+        let pattern = match mode {
+            SearchMode::Substring => RegexBuilder::new(&regex::escape(query.trim())).case_insensitive(true).build(),
+            SearchMode::Regex => RegexBuilder::new(query).size_limit(1 << 20).build(),
+            SearchMode::Fuzzy => {
+                return Ok(Matcher::Fuzzy(query.chars().filter(|c| !c.is_whitespace()).map(fold).collect()));
+            }
+        };
+        pattern.map(Matcher::Pattern).map_err(|e| format!("Invalid regex: {}", e))
+    }
+
+    // Score an item, or `None` if it doesn't match
+    fn rank(&self, item: ClipboardItem) -> Option<SearchHit> {
+        let (score, matches, ocr_matches) = if let Some((score, matches)) = self.find(&item.payload.search_text()) {
+            (score, matches, Vec::new())
+        } else if let Some((score, ocr_matches)) = item.ocr_text.as_deref().and_then(|text| self.find(text)) {
+            // Text recognized in an image ranks like the item's own text
+            (score, Vec::new(), ocr_matches)
+        } else {
+            // Items can also be found by the app they were copied from,
+            // ranked below any match in the text
+            let source = item.source.as_ref()?;
+            self.find(&format!("{}\n{}", source.class, source.title))?;
+            (0, Vec::new(), Vec::new())
+        };
+        Some(SearchHit { item, score, matches, ocr_matches })
+    }
+
+    fn find(&self, text: &str) -> Option<(i64, Vec<MatchRange>)> {
+        match self {
+            Matcher::All => Some((0, Vec::new())),
+            Matcher::Pattern(pattern) => pattern_match(pattern, text),
+            Matcher::Fuzzy(query) => fuzzy_match(query, text),
+        }
+    }
+}
+
+// Matches of a substring or regex. The whole text matching ranks highest,
+// then a match at the start or at a word, then more and earlier matches.
+fn pattern_match(pattern: &Regex, text: &str) -> Option<(i64, Vec<MatchRange>)> {
+    let matches: Vec<MatchRange> = pattern
+        .find_iter(text)
+        .filter(|m| !m.is_empty())
+        .take(MAX_MATCHES)
+        .map(|m| MatchRange { start: m.start(), end: m.end() })
+        .collect();
+    let first = *matches.first()?;
+
+    let mut score = 100 + 5 * matches.len().min(10) as i64;
+    if first.start == 0 && first.end == text.len() {
+        score += 200;
+    } else if first.start == 0 {
+        score += 50;
+    } else if is_word_start(text, first.start) {
+        score += 25;
+    }
+    score -= (first.start.min(200) / 10) as i64;
+    Some((score, matches))
+}
+
+// Match the query's characters in order, scoring each matched character
+// with bonuses for following the previous one or starting a word and a
+// penalty for the gap before it. The best-scoring alignment is used; texts
+// too large for that get the shortest alignment ending where the leftmost
+// match ends.
+fn fuzzy_match(query: &[char], text: &str) -> Option<(i64, Vec<MatchRange>)> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let positions = if chars.len().saturating_mul(query.len()) <= MAX_FUZZY_CELLS {
+        best_alignment(query, &chars)?
+    } else {
+        shortest_alignment(query, &chars)?
+    };
+
+    let mut score = if positions[0] == 0 { START_BONUS } else { 0 };
+    for (n, &i) in positions.iter().enumerate() {
+        score += CHAR_SCORE + word_bonus(&chars, i);
+        if n > 0 {
+            score += transition(positions[n - 1], i);
+        }
+    }
+
+    // Merge runs of adjacent characters into one range each
+    let mut ranges: Vec<MatchRange> = Vec::new();
+    for &i in &positions {
+        let (byte, c) = chars[i];
+        match ranges.last_mut() {
+            Some(range) if range.end == byte => range.end = byte + c.len_utf8(),
+            _ => ranges.push(MatchRange { start: byte, end: byte + c.len_utf8() }),
+        }
+    }
+    // Keep text matches above source-only ones (score 0)
+    Some((score.max(1), ranges))
+}
+
+// Fuzzy scoring weights
+const CHAR_SCORE: i64 = 16;
+const CONSECUTIVE_BONUS: i64 = 12;
+const WORD_BONUS: i64 = 8;
+const START_BONUS: i64 = 8;
+const GAP_PENALTY: i64 = 3;
+// Gaps longer than this cost no more, so matching the starts of words far
+// apart isn't punished for the words in between
+const MAX_GAP: usize = 8;
+
+// Largest text length times query length `best_alignment` runs on
+const MAX_FUZZY_CELLS: usize = 1 << 20;
+
+// Score for matching at `i` right after matching at `p`: a bonus when
+// adjacent, otherwise a penalty growing with the gap up to `MAX_GAP`
+fn transition(p: usize, i: usize) -> i64 {
+    if i == p + 1 {
+        CONSECUTIVE_BONUS
+    } else {
+        -(GAP_PENALTY + (i - p - 1).min(MAX_GAP) as i64)
+    }
+}
+
+fn word_bonus(chars: &[(usize, char)], i: usize) -> i64 {
+    let starts_word = i == 0 || {
+        let (before, c) = (chars[i - 1].1, chars[i].1);
+        !before.is_alphanumeric() || (before.is_lowercase() && c.is_uppercase())
+    };
+    if starts_word { WORD_BONUS } else { 0 }
+}
+
+// Positions of the highest-scoring alignment, by dynamic programming over
+// query and text positions
+fn best_alignment(query: &[char], chars: &[(usize, char)]) -> Option<Vec<usize>> {
+    const NONE: i64 = i64::MIN / 2;
+    let n = chars.len();
+    let matches_at = |i: usize, q: usize| fold(chars[i].1) == query[q];
+
+    // Best score with query[q] matched at i, and where query[q - 1] was
+    // matched for that score
+    let mut row: Vec<i64> = (0..n)
+        .map(|i| if matches_at(i, 0) { CHAR_SCORE + word_bonus(chars, i) + if i == 0 { START_BONUS } else { 0 } } else { NONE })
+        .collect();
+    let mut back = vec![0u32; n * query.len()];
+
+    for q in 1..query.len() {
+        let mut next = vec![NONE; n];
+        // Best predecessor more than `MAX_GAP` back, where the gap penalty
+        // no longer grows
+        let mut far = (NONE, 0);
+        for i in 1..n {
+            if i >= MAX_GAP + 2 && row[i - 2 - MAX_GAP] > far.0 {
+                far = (row[i - 2 - MAX_GAP], i - 2 - MAX_GAP);
+            }
+            if !matches_at(i, q) {
+                continue;
+            }
+            // Nearer predecessors win ties
+            let mut best = (NONE, 0);
+            for p in (i.saturating_sub(MAX_GAP + 1)..i).rev() {
+                if row[p] > NONE && row[p] + transition(p, i) > best.0 {
+                    best = (row[p] + transition(p, i), p);
+                }
+            }
+            if far.0 > NONE && far.0 - GAP_PENALTY - MAX_GAP as i64 > best.0 {
+                best = (far.0 - GAP_PENALTY - MAX_GAP as i64, far.1);
+            }
+            if best.0 > NONE {
+                next[i] = best.0 + CHAR_SCORE + word_bonus(chars, i);
+                back[q * n + i] = best.1 as u32;
+            }
+        }
+        row = next;
+    }
+
+    let (mut i, _) = row.iter().enumerate().filter(|(_, &score)| score > NONE).max_by_key(|&(i, &score)| (score, std::cmp::Reverse(i)))?;
+    let mut positions = vec![i; query.len()];
+    for q in (1..query.len()).rev() {
+        i = back[q * n + i] as usize;
+        positions[q - 1] = i;
+    }
+    Some(positions)
+}
+
+// Positions of the shortest alignment ending where the leftmost one ends.
+// Linear, for texts too large for `best_alignment`.
+fn shortest_alignment(query: &[char], chars: &[(usize, char)]) -> Option<Vec<usize>> {
+    let matches_at = |i: usize, q: usize| fold(chars[i].1) == query[q];
+
+    let mut q = 0;
+    let end = (0..chars.len()).find(|&i| {
+        if matches_at(i, q) {
+            q += 1;
+        }
+        q == query.len()
+    })?;
+
+    // Walk back from there to the latest start
+    let mut q = query.len();
+    let start = (0..=end).rev().find(|&i| {
+        if matches_at(i, q - 1) {
+            q -= 1;
+        }
+        q == 0
+    })?;
+
+    let mut positions = Vec::with_capacity(query.len());
+    for i in start..=end {
+        if positions.len() < query.len() && matches_at(i, positions.len()) {
+            positions.push(i);
+        }
+    }
+    Some(positions)
+}
+
+fn is_word_start(text: &str, byte: usize) -> bool {
+    text[..byte].chars().next_back().is_none_or(|c| !c.is_alphanumeric())
+}
+
+// Lowercase a character for case-insensitive comparison
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::ClipboardPayload;
+    use crate::source::SourceApp;
+
+    fn text_item(text: &str, timestamp: u64) -> ClipboardItem {
+        let mut item = ClipboardItem::new(ClipboardPayload::Text { text: text.to_string() });
+        item.timestamp = timestamp;
+        item
+    }
+
+    // An in-memory history; `items` are given oldest first
+    fn history(items: &[ClipboardItem]) -> HistoryDb {
+        let db = HistoryDb::locked();
+        let newest_first: Vec<ClipboardItem> = items.iter().rev().cloned().collect();
+        db.reopen(None, &newest_first, &[], &[]).unwrap();
+        db
+    }
+
+    fn texts(results: &SearchResults) -> Vec<String> {
+        results.hits.iter().map(|hit| hit.item.payload.search_text()).collect()
+    }
+
+    fn find(db: &HistoryDb, query: &str, mode: SearchMode) -> Vec<String> {
+        texts(&search(db, query, mode, &SearchFilters::default(), 0, None).unwrap())
+    }
+
+    fn ranges(matches: &[MatchRange]) -> Vec<(usize, usize)> {
+        matches.iter().map(|m| (m.start, m.end)).collect()
+    }
+
+    #[test]
+    fn pattern_scores() {
+        let pattern = RegexBuilder::new("cat").case_insensitive(true).build().unwrap();
+        let score = |text: &str| pattern_match(&pattern, text).map(|(score, _)| score);
+        let whole = score("Cat").unwrap();
+        let start = score("catalog").unwrap();
+        let word = score("the cat").unwrap();
+        let inside = score("concatenate").unwrap();
+        assert!(whole > start && start > word && word > inside);
+        assert!(score("cat cat cat").unwrap() > start);
+        assert!(score("a concatenation").unwrap() > score(&format!("{}concatenation", "a".repeat(100))).unwrap());
+        assert_eq!(score("dog"), None);
+
+        let (_, matches) = pattern_match(&pattern, "CAT, cat and Cat").unwrap();
+        assert_eq!(ranges(&matches), [(0, 3), (5, 8), (13, 16)]);
+        let (_, matches) = pattern_match(&pattern, &"cat".repeat(500)).unwrap();
+        assert_eq!(matches.len(), MAX_MATCHES);
+    }
+
+    #[test]
+    fn fuzzy_matches() {
+        let query = |q: &str| -> Vec<char> { q.chars().map(fold).collect() };
+        let (_, matches) = fuzzy_match(&query("gcm"), "git commit -m").unwrap();
+        assert_eq!(ranges(&matches), [(0, 1), (4, 5), (12, 13)]);
+        // Adjacent characters make one range, and the run beats scattered letters
+        let (_, matches) = fuzzy_match(&query("com"), "cargo build --message commit").unwrap();
+        assert_eq!(ranges(&matches), [(22, 25)]);
+        // Byte offsets for multi-byte text, and case folding
+        let (_, matches) = fuzzy_match(&query("ÉT"), "café été").unwrap();
+        assert_eq!(ranges(&matches), [(6, 9)]);
+        assert_eq!(fuzzy_match(&query("mcg"), "git commit -m"), None);
+        assert_eq!(fuzzy_match(&query("gitt"), "git"), None);
+
+        let score = |q: &str, text: &str| fuzzy_match(&query(q), text).unwrap().0;
+        assert!(score("gc", "git commit") > score("gc", "digital clock"));
+        assert!(score("abc", "abc") > score("abc", "a_b_c"));
+        assert!(score("ab", "ab") > score("ab", "xab"));
+        assert!(score("fb", "fooBar") > score("fb", "foobar"));
+        // Word starts far apart aren't punished for the words in between
+        assert_eq!(score("ab", &format!("a {} b", "x".repeat(20))), score("ab", &format!("a {} b", "x".repeat(40))));
+    }
+
+    #[test]
+    fn huge_texts_use_the_shortest_alignment() {
+        let text = format!("{} needle {}", "x".repeat(MAX_FUZZY_CELLS), "y".repeat(10));
+        let query: Vec<char> = "nedl".chars().collect();
+        let (score, matches) = fuzzy_match(&query, &text).unwrap();
+        let start = MAX_FUZZY_CELLS + 1;
+        assert_eq!(ranges(&matches), [(start, start + 2), (start + 3, start + 5)]);
+        assert!(score > 0);
+
+        let chars: Vec<(usize, char)> = "a--b-ab".char_indices().collect();
+        let query: Vec<char> = "ab".chars().collect();
+        assert_eq!(shortest_alignment(&query, &chars), Some(vec![0, 3]));
+        assert_eq!(best_alignment(&query, &chars), Some(vec![5, 6]));
+    }
+
+    #[test]
+    fn ranks_by_score_then_newest_first() {
+        let db = history(&[
+            text_item("log", 1),
+            text_item("the log file", 2),
+            text_item("catalog", 3),
+            text_item("log rotation", 4),
+            text_item("logbook", 5),
+            text_item("nothing here", 6),
+        ]);
+        let found = find(&db, "LOG", SearchMode::Substring);
+        assert_eq!(found, ["log", "logbook", "log rotation", "the log file", "catalog"]);
+
+        let results = search(&db, "log", SearchMode::Substring, &SearchFilters::default(), 1, Some(2)).unwrap();
+        assert_eq!(texts(&results), ["logbook", "log rotation"]);
+        assert_eq!(results.total, 5);
+        assert_eq!(ranges(&results.hits[0].matches), [(0, 3)]);
+    }
+
+    #[test]
+    fn empty_query_lists_newest_first() {
+        let db = history(&[text_item("a", 1), text_item("b", 2), text_item("c", 3)]);
+        let results = search(&db, "  ", SearchMode::Regex, &SearchFilters::default(), 1, Some(5)).unwrap();
+        assert_eq!(texts(&results), ["b", "a"]);
+        assert_eq!(results.total, 3);
+        assert!(results.hits.iter().all(|hit| hit.score == 0 && hit.matches.is_empty()));
+    }
+
+    #[test]
+    fn substring_queries_of_any_length() {
+        let db = history(&[
+            text_item("100% done", 1),
+            text_item("1000 done", 2),
+            text_item("snake_case", 3),
+            text_item("snakeXcase", 4),
+            text_item("C:\\temp", 5),
+            text_item("Ärger", 6),
+            text_item("ärgerlich", 7),
+            text_item("x", 8),
+        ]);
+        // Characters LIKE treats specially are matched literally
+        assert_eq!(find(&db, "0%", SearchMode::Substring), ["100% done"]);
+        assert_eq!(find(&db, "e_c", SearchMode::Substring), ["snake_case"]);
+        assert_eq!(find(&db, ":\\", SearchMode::Substring), ["C:\\temp"]);
+        // Short queries skip the full-text index, non-ASCII ones the LIKE filter
+        assert_eq!(find(&db, "X", SearchMode::Substring), ["x", "snakeXcase"]);
+        assert_eq!(find(&db, "är", SearchMode::Substring), ["ärgerlich", "Ärger"]);
+        assert_eq!(find(&db, "ärgerlich", SearchMode::Substring), ["ärgerlich"]);
+        assert_eq!(find(&db, " done ", SearchMode::Substring), ["1000 done", "100% done"]);
+    }
+
+    #[test]
+    fn fuzzy_and_regex_modes() {
+        let db = history(&[
+            text_item("git commit -m 'fix'", 1),
+            text_item("grep -c main", 2),
+            text_item("Git Commit", 3),
+            text_item("cargo build", 4),
+        ]);
+        // Shorter gaps between word starts rank higher
+        assert_eq!(find(&db, "g c m", SearchMode::Fuzzy), ["grep -c main", "git commit -m 'fix'", "Git Commit"]);
+        assert_eq!(find(&db, "GCM", SearchMode::Fuzzy).len(), 3);
+        assert!(find(&db, "zzz", SearchMode::Fuzzy).is_empty());
+
+        assert_eq!(find(&db, "^Git", SearchMode::Regex), ["Git Commit"]);
+        assert_eq!(find(&db, "(?i)^git", SearchMode::Regex), ["Git Commit", "git commit -m 'fix'"]);
+        assert_eq!(find(&db, r"-\w\b", SearchMode::Regex), ["grep -c main", "git commit -m 'fix'"]);
+        let error = search(&db, "(unclosed", SearchMode::Regex, &SearchFilters::default(), 0, None).unwrap_err();
+        assert!(error.starts_with("Invalid regex"));
+    }
+
+    #[test]
+    fn finds_recognized_text_and_source_apps() {
+        let mut image = ClipboardItem::new(ClipboardPayload::Image {
+            width: 10,
+            height: 10,
+            blob_path: "blob.png".to_string(),
+            pixel_hash: "ab".to_string(),
+            perceptual_hash: None,
+        });
+        image.timestamp = 1;
+        image.ocr_text = Some("Invoice 2024".to_string());
+        let mut from_terminal = text_item("ls -la", 2);
+        from_terminal.source = Some(SourceApp { class: "Alacritty".to_string(), title: "invoices".to_string() });
+        let db = history(&[image, from_terminal, text_item("my invoice", 3)]);
+
+        let results = search(&db, "invoice", SearchMode::Substring, &SearchFilters::default(), 0, None).unwrap();
+        assert_eq!(texts(&results), ["Image 10x10", "my invoice", "ls -la"]);
+        assert!(results.hits[0].matches.is_empty());
+        assert_eq!(ranges(&results.hits[0].ocr_matches), [(0, 7)]);
+        assert_eq!(results.hits[2].score, 0);
+        assert!(results.hits[2].matches.is_empty() && results.hits[2].ocr_matches.is_empty());
+    }
+
+    #[test]
+    fn filters_apply_to_every_mode() {
+        let mut favorite = text_item("https://example.com/favorite", 10);
+        favorite.is_favorite = true;
+        let db = history(&[
+            text_item("https://example.com/old", 5),
+            favorite,
+            text_item("example text", 20),
+            text_item("https://example.com/new", 30),
+        ]);
+        let id = |text: &str| db.list(None, 0).unwrap().into_iter().find(|item| item.payload.search_text() == text).unwrap().id;
+        db.add_tag(id("https://example.com/new"), "work").unwrap();
+
+        let run = |query: &str, mode: SearchMode, filters: SearchFilters| {
+            texts(&search(&db, query, mode, &filters, 0, None).unwrap())
+        };
+        for mode in [SearchMode::Substring, SearchMode::Fuzzy, SearchMode::Regex] {
+            let urls = SearchFilters { content_type: Some("url".to_string()), ..Default::default() };
+            assert_eq!(run("example", mode, urls).len(), 3, "{:?}", mode);
+            let favorites = SearchFilters { favorite: Some(true), ..Default::default() };
+            assert_eq!(run("example", mode, favorites), ["https://example.com/favorite"], "{:?}", mode);
+            let tagged = SearchFilters { tag: Some("work".to_string()), ..Default::default() };
+            assert_eq!(run("example", mode, tagged), ["https://example.com/new"], "{:?}", mode);
+            let window = SearchFilters { since: Some(10), until: Some(20), ..Default::default() };
+            assert_eq!(run("example", mode, window).len(), 2, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn pages_stay_ranked_past_the_kept_hits() {
+        // Enough matches that hits are trimmed while streaming, with the
+        // best ones spread through the history
+        let count = MIN_KEPT_HITS * 5;
+        let items: Vec<ClipboardItem> = (0..count)
+            .map(|i| text_item(&if i % 97 == 0 { format!("needle {}", i) } else { format!("hay needle {}", i) }, i as u64))
+            .collect();
+        let db = history(&items);
+
+        let all = search(&db, "needle", SearchMode::Substring, &SearchFilters::default(), 0, None).unwrap();
+        assert_eq!(all.total, count);
+        let best = (0..count).filter(|i| i % 97 == 0).count();
+        assert!(all.hits[..best].iter().all(|hit| hit.matches[0].start == 0));
+        assert!(all.hits.windows(2).all(|pair| {
+            pair[0].score > pair[1].score || (pair[0].score == pair[1].score && pair[0].item.timestamp > pair[1].item.timestamp)
+        }));
+
+        for (offset, limit) in [(0, 10), (3, 1), (best - 2, 5), (700, 50), (count - 3, 10)] {
+            let page = search(&db, "needle", SearchMode::Substring, &SearchFilters::default(), offset, Some(limit)).unwrap();
+            let expected: Vec<Uuid> = all.hits.iter().skip(offset).take(limit).map(|hit| hit.item.id).collect();
+            assert_eq!(page.hits.iter().map(|hit| hit.item.id).collect::<Vec<_>>(), expected, "{} {}", offset, limit);
+            assert_eq!(page.total, count);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+use tauri_plugin_store::StoreExt;
+
+use crate::history::History;
+use crate::ocr::OcrSettings;
+use crate::retention::RetentionPolicy;
+use crate::sensitive::SensitivePolicy;
+use crate::source::ExcludePolicy;
+use crate::transform::Transform;
+
+const SETTINGS_STORE: &str = "settings.json";
+
+/// User preferences, persisted in `settings.json` through the store plugin.
+/// Missing fields fall back to their defaults so older files keep loading.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Settings {
+    /// Images whose perceptual hashes differ by at most this many bits are
+    /// treated as duplicates. `None` only drops exact pixel matches.
+    pub near_duplicate_image_distance: Option<u32>,
+    /// How to handle clips that look like passwords, keys or card numbers.
+    pub sensitive: SensitivePolicy,
+    /// Apps the monitor never records clips from.
+    pub exclude: ExcludePolicy,
+    /// Keep capture paused across restarts instead of resuming on launch.
+    pub remember_pause: bool,
+    /// How many items, bytes and how much age the history may keep.
+    pub retention: RetentionPolicy,
+    /// Named chains of transforms to apply when copying an item back.
+    pub transform_presets: BTreeMap<String, Vec<Transform>>,
+    /// Whether and how to recognize text in image items.
+    pub ocr: OcrSettings,
+}
+
+pub fn load_settings(app: &tauri::AppHandle) -> Settings {
+    app.store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get("settings"))
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_settings(app: &tauri::AppHandle, settings: &Settings) -> Result<(), String> {
+    let store = app.store(SETTINGS_STORE).map_err(|e| e.to_string())?;
+    store.set("settings", serde_json::to_value(settings).map_err(|e| e.to_string())?);
+    store.save().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_settings(app: tauri::AppHandle) -> Settings {
+    load_settings(&app)
+}
+
+#[tauri::command]
+pub fn update_settings(app: tauri::AppHandle, settings: Settings) -> Result<(), String> {
+    let retention_changed = load_settings(&app).retention != settings.retention;
+    save_settings(&app, &settings)?;
+
+    // Apply a tightened policy right away instead of on the next scheduled run
+    if retention_changed {
+        let _ = app.state::<History>().enforce_retention(true);
+    }
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use chrono::format::{Item, StrftimeItems};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_store::StoreExt;
+use uuid::Uuid;
+
+use crate::item::now_secs;
+
+const SNIPPETS_STORE: &str = "snippets.json";
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+// `{date}`, `{date:<strftime format>}`, `{uuid}`, `{clipboard}` and
+// `{input:<name>}`. Anything else in braces is left as it is, so JSON and code
+// snippets don't need escaping.
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{(date|uuid|clipboard|input)(?::([^{}]*))?\}").unwrap());
+
+/// A curated piece of text kept apart from the history, so retention never
+/// evicts it. Stored in `snippets.json` through the store plugin.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Snippet {
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+    pub name: String,
+    /// Text with placeholders, expanded by `expand_snippet`.
+    pub body: String,
+    #[serde(default)]
+    pub updated_at: u64,
+}
+
+fn load_snippets(app: &tauri::AppHandle) -> Vec<Snippet> {
+    app.store(SNIPPETS_STORE)
+        .ok()
+        .and_then(|store| store.get("snippets"))
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+fn save_snippets(app: &tauri::AppHandle, snippets: &[Snippet]) -> Result<(), String> {
+    let store = app.store(SNIPPETS_STORE).map_err(|e| e.to_string())?;
+    store.set("snippets", serde_json::to_value(snippets).map_err(|e| e.to_string())?);
+    store.save().map_err(|e| e.to_string())
+}
+
+fn find_snippet(app: &tauri::AppHandle, id: Uuid) -> Result<Snippet, String> {
+    load_snippets(app)
+        .into_iter()
+        .find(|snippet| snippet.id == id)
+        .ok_or_else(|| "Snippet not found".to_string())
+}
+
+// Name of an `{input}` placeholder; a bare `{input}` is called "input"
+fn input_name<'a>(caps: &'a Captures) -> &'a str {
+    caps.get(2).map(|name| name.as_str().trim()).filter(|name| !name.is_empty()).unwrap_or("input")
+}
+
+/// Names of the prompted variables in `body`, in order of first use.
+pub fn variables(body: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for caps in PLACEHOLDER.captures_iter(body).filter(|caps| &caps[1] == "input") {
+        let name = input_name(&caps);
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// Replace the placeholders in `body`. `clipboard` is only called if the
+/// body uses `{clipboard}`. Fails on a missing variable or an invalid date
+/// format.
+pub fn expand(
+    body: &str,
+    values: &HashMap<String, String>,
+    mut clipboard: impl FnMut() -> Result<String, String>,
+) -> Result<String, String> {
+    let mut result = String::with_capacity(body.len());
+    let mut last = 0;
+    for caps in PLACEHOLDER.captures_iter(body) {
+        let placeholder = caps.get(0).unwrap();
+        result.push_str(&body[last..placeholder.start()]);
+        last = placeholder.end();
+
+        match &caps[1] {
+            "date" => {
+                let format = caps.get(2).map_or(DEFAULT_DATE_FORMAT, |f| f.as_str());
+                let items: Vec<Item> = StrftimeItems::new(format).collect();
+                if items.contains(&Item::Error) {
+                    return Err(format!("Invalid date format '{}'", format));
+                }
+                result.push_str(&chrono::Local::now().format_with_items(items.into_iter()).to_string());
+            }
+            "uuid" => result.push_str(&Uuid::new_v4().to_string()),
+            "clipboard" => result.push_str(&clipboard()?),
+            _ => {
+                let name = input_name(&caps);
+                let value = values.get(name).ok_or_else(|| format!("Missing value for '{}'", name))?;
+                result.push_str(value);
+            }
+        }
+    }
+    result.push_str(&body[last..]);
+    Ok(result)
+}
+
+// Function to list snippets by name
+#[tauri::command]
+pub fn list_snippets(app: tauri::AppHandle) -> Vec<Snippet> {
+    let mut snippets = load_snippets(&app);
+    snippets.sort_by_key(|snippet| snippet.name.to_lowercase());
+    snippets
+}
+
+// Function to add a snippet, or replace the one with the same ID
+#[tauri::command]
+pub fn save_snippet(app: tauri::AppHandle, mut snippet: Snippet) -> Result<Snippet, String> {
+    snippet.name = snippet.name.trim().to_string();
+    if snippet.name.is_empty() {
+        return Err("Snippet name can't be empty".to_string());
+    }
+    snippet.updated_at = now_secs();
+
+    let mut snippets = load_snippets(&app);
+    match snippets.iter_mut().find(|existing| existing.id == snippet.id) {
+        Some(existing) => *existing = snippet.clone(),
+        None => snippets.push(snippet.clone()),
+    }
+    save_snippets(&app, &snippets)?;
+    Ok(snippet)
+}
+
+#[tauri::command]
+pub fn delete_snippet(app: tauri::AppHandle, id: Uuid) -> Result<(), String> {
+    let mut snippets = load_snippets(&app);
+    let before = snippets.len();
+    snippets.retain(|snippet| snippet.id != id);
+    if snippets.len() == before {
+        return Err("Snippet not found".to_string());
+    }
+    save_snippets(&app, &snippets)
+}
+
+// Function to list the variables a snippet prompts for before expanding
+#[tauri::command]
+pub fn get_snippet_variables(app: tauri::AppHandle, id: Uuid) -> Result<Vec<String>, String> {
+    Ok(variables(&find_snippet(&app, id)?.body))
+}
+
+// Function to expand a snippet and put the result on the clipboard
+#[tauri::command]
+pub fn expand_snippet(
+    app: tauri::AppHandle,
+    id: Uuid,
+    values: Option<HashMap<String, String>>,
+) -> Result<String, String> {
+    let snippet = find_snippet(&app, id)?;
+    let text = expand(&snippet.body, &values.unwrap_or_default(), || {
+        app.clipboard().read_text().map_err(|e| e.to_string())
+    })?;
+    crate::write_clipboard_text(app, &text, None, None)?;
+    Ok(text)
+}
+
+// Function to export snippets to JSON
+#[tauri::command]
+pub fn export_snippets(app: tauri::AppHandle) -> Result<String, String> {
+    serde_json::to_string_pretty(&list_snippets(app)).map_err(|e| e.to_string())
+}
+
+// Function to import snippets from JSON, skipping ones we already have by ID
+// or by name and body
+#[tauri::command]
+pub fn import_snippets(app: tauri::AppHandle, json_data: &str) -> Result<usize, String> {
+    let imported: Vec<Snippet> = serde_json::from_str(json_data).map_err(|e| e.to_string())?;
+    let mut snippets = load_snippets(&app);
+    let before = snippets.len();
+    for snippet in imported {
+        let known = snippets
+            .iter()
+            .any(|s| s.id == snippet.id || (s.name == snippet.name && s.body == snippet.body));
+        if !known && !snippet.name.trim().is_empty() {
+            snippets.push(snippet);
+        }
+    }
+    let added = snippets.len() - before;
+    if added > 0 {
+        save_snippets(&app, &snippets)?;
+    }
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn no_clipboard() -> Result<String, String> {
+        panic!("clipboard read for a body without {{clipboard}}")
+    }
+
+    #[test]
+    fn lists_variables_once_in_order() {
+        assert_eq!(variables("{input:to} {input} {input: to } {input:}{input:cc}"), ["to", "input", "cc"]);
+        assert!(variables("{date} {uuid} {clipboard} {name}").is_empty());
+    }
+
+    #[test]
+    fn fills_in_repeated_variables() {
+        let body = "Hi {input:name}, {input:name} again. {input}!";
+        let expanded = expand(body, &values(&[("name", "Ada"), ("input", "Bye")]), no_clipboard).unwrap();
+        assert_eq!(expanded, "Hi Ada, Ada again. Bye!");
+
+        let mut reads = 0;
+        let expanded = expand("{clipboard}/{clipboard}", &HashMap::new(), || {
+            reads += 1;
+            Ok("clip".to_string())
+        });
+        assert_eq!(expanded.unwrap(), "clip/clip");
+        assert_eq!(reads, 2);
+    }
+
+    #[test]
+    fn leaves_other_braces_alone() {
+        for body in [
+            "{name} and {input_name}",
+            r#"{"key": {"nested": [1, 2]}}"#,
+            "fn main() { println!(\"{}\", x); }",
+            "{date",
+            "date}",
+            "{}",
+            "{{}}",
+        ] {
+            assert_eq!(expand(body, &HashMap::new(), no_clipboard).unwrap(), body);
+        }
+        // A placeholder inside literal braces still expands
+        assert_eq!(expand("{{input:x}}", &values(&[("x", "1")]), no_clipboard).unwrap(), "{1}");
+    }
+
+    #[test]
+    fn expands_dates_and_uuids() {
+        let year = chrono::Local::now().format("%Y").to_string();
+        assert_eq!(expand("{date:%Y}", &HashMap::new(), no_clipboard).unwrap(), year);
+        assert_eq!(expand("{date}", &HashMap::new(), no_clipboard).unwrap().len(), "2024-01-01".len());
+        let uuid = expand("{uuid}", &HashMap::new(), no_clipboard).unwrap();
+        assert!(Uuid::parse_str(&uuid).is_ok());
+    }
+
+    #[test]
+    fn reports_bad_formats_and_missing_values() {
+        assert_eq!(expand("{date:%Q}", &HashMap::new(), no_clipboard).unwrap_err(), "Invalid date format '%Q'");
+        assert!(expand("on {date:%Y-%}", &HashMap::new(), no_clipboard).is_err());
+        assert_eq!(expand("{input:to}", &values(&[("input", "x")]), no_clipboard).unwrap_err(), "Missing value for 'to'");
+        assert_eq!(expand("{clipboard}", &HashMap::new(), || Err("empty".to_string())).unwrap_err(), "empty");
+    }
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+/// The window that was active when a clip was captured. Only known on X11.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SourceApp {
+    /// Class part of `WM_CLASS`, e.g. `firefox` or `KeePassXC`.
+    pub class: String,
+    pub title: String,
+}
+
+/// Matches source apps by window class and/or title. Every field that is
+/// set must match; a rule with neither set matches nothing.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct AppRule {
+    /// Compared case-insensitively with the whole window class.
+    pub class: Option<String>,
+    /// Case-insensitive substring of the window title, e.g. `sudo` for
+    /// password prompts in a terminal.
+    pub title_contains: Option<String>,
+}
+
+impl AppRule {
+    fn class(class: &str) -> Self {
+        AppRule { class: Some(class.to_string()), title_contains: None }
+    }
+
+    pub fn matches(&self, app: &SourceApp) -> bool {
+        if self.class.is_none() && self.title_contains.is_none() {
+            return false;
+        }
+        let class_matches = self.class.as_ref().is_none_or(|class| class.eq_ignore_ascii_case(&app.class));
+        let title_matches = self
+            .title_contains
+            .as_ref()
+            .is_none_or(|part| app.title.to_lowercase().contains(&part.to_lowercase()));
+        class_matches && title_matches
+    }
+}
+
+/// Apps whose clips the monitor never records.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ExcludePolicy {
+    pub apps: Vec<AppRule>,
+}
+
+impl Default for ExcludePolicy {
+    fn default() -> Self {
+        ExcludePolicy {
+            apps: ["KeePassXC", "KeePass2", "1Password", "Bitwarden"].into_iter().map(AppRule::class).collect(),
+        }
+    }
+}
+
+impl ExcludePolicy {
+    pub fn excludes(&self, app: &SourceApp) -> bool {
+        self.apps.iter().any(|rule| rule.matches(app))
+    }
+}
@@ -0,0 +1,111 @@
+use std::fs;
+
+use base64::{engine::general_purpose, Engine as _};
+use image::imageops;
+use image::{DynamicImage, ImageFormat, ImageOutputFormat, RgbaImage};
+use tauri::Manager;
+
+use crate::blobs::BlobStore;
+use crate::vault::Vault;
+
+/// Longest side of each cached thumbnail size, smallest first.
+pub const SIZES: [u32; 3] = [64, 200, 400];
+
+/// Extension of cached thumbnails, which are JPEG or WebP (see `encode`).
+pub const EXTENSION: &str = "thumb";
+
+// Quality of JPEG thumbnails
+const JPEG_QUALITY: u8 = 80;
+
+/// The cached size that serves a request for `size`: the smallest one at
+/// least that large, or the largest.
+pub fn fit(size: u32) -> u32 {
+    SIZES.into_iter().find(|&cached| cached >= size).unwrap_or(SIZES[SIZES.len() - 1])
+}
+
+/// Render every thumbnail size of the blob at `blob_path`, whose decoded
+/// image is `img`, and cache them. Returns the rendered thumbnails by size.
+/// Caching is best effort; a thumbnail that couldn't be written is rendered
+/// again when it's next asked for.
+pub fn generate(blob_store: &BlobStore, vault: &Vault, blob_path: &str, img: &RgbaImage) -> Result<Vec<(u32, Vec<u8>)>, String> {
+    let thumbnails = render(img)?;
+    for (size, bytes) in &thumbnails {
+        if let Some(path) = blob_store.thumbnail_path(blob_path, *size) {
+            if let Ok(sealed) = vault.seal_thumbnail(blob_path, bytes.clone()) {
+                let _ = blob_store.put_thumbnail(&path, &sealed);
+            }
+        }
+    }
+    Ok(thumbnails)
+}
+
+/// Thumbnail of the blob at `blob_path` for a request for `size` (see
+/// `fit`), from the cache when it's there. A miss renders and caches every
+/// size.
+pub fn get(blob_store: &BlobStore, vault: &Vault, blob_path: &str, size: u32) -> Result<Vec<u8>, String> {
+    let size = fit(size);
+    if let Some(path) = blob_store.thumbnail_path(blob_path, size) {
+        // A cache file that can't be read is rendered again
+        if let Ok(bytes) = fs::read(&path) {
+            if let Ok(thumbnail) = vault.open_thumbnail(blob_path, bytes) {
+                return Ok(thumbnail.to_vec());
+            }
+        }
+    }
+
+    let img = image::load_from_memory(&vault.read_blob(blob_path)?)
+        .map_err(|e| format!("Failed to load image from file: {}", e))?
+        .to_rgba8();
+    generate(blob_store, vault, blob_path, &img)?
+        .into_iter()
+        .find(|(rendered, _)| *rendered == size)
+        .map(|(_, bytes)| bytes)
+        .ok_or_else(|| "Failed to render thumbnail".to_string())
+}
+
+// Scale `img` to each size, largest first so each smaller one is scaled
+// from the one before. Images are never enlarged.
+fn render(img: &RgbaImage) -> Result<Vec<(u32, Vec<u8>)>, String> {
+    let opaque = img.pixels().all(|pixel| pixel[3] == u8::MAX);
+    let mut source = img.clone();
+    let mut thumbnails = Vec::with_capacity(SIZES.len());
+    for &size in SIZES.iter().rev() {
+        let (width, height) = source.dimensions();
+        if width > size || height > size {
+            let scale = size as f64 / width.max(height) as f64;
+            let width = ((width as f64 * scale).round() as u32).max(1);
+            let height = ((height as f64 * scale).round() as u32).max(1);
+            source = imageops::thumbnail(&source, width, height);
+        }
+        thumbnails.push((size, encode(&source, opaque)?));
+    }
+    thumbnails.reverse();
+    Ok(thumbnails)
+}
+
+// JPEG is several times smaller for photos; images with transparency use
+// lossless WebP to keep it
+fn encode(img: &RgbaImage, opaque: bool) -> Result<Vec<u8>, String> {
+    let mut buffer = Vec::new();
+    let mut cursor = std::io::Cursor::new(&mut buffer);
+    let result = if opaque {
+        DynamicImage::ImageRgba8(img.clone()).to_rgb8().write_to(&mut cursor, ImageOutputFormat::Jpeg(JPEG_QUALITY))
+    } else {
+        img.write_to(&mut cursor, ImageOutputFormat::WebP)
+    };
+    result.map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+    Ok(buffer)
+}
+
+// Function to get a thumbnail of an image blob as a data URL, no larger
+// than `size` pixels a side when given (200 otherwise). Thumbnails are
+// rendered once and cached with the blob.
+#[tauri::command]
+pub fn get_image_thumbnail(app: tauri::AppHandle, file_path: &str, size: Option<u32>) -> Result<String, String> {
+    let thumbnail = get(&app.state::<BlobStore>(), &app.state::<Vault>(), file_path, size.unwrap_or(200))?;
+    let mime = match image::guess_format(&thumbnail) {
+        Ok(ImageFormat::WebP) => "image/webp",
+        _ => "image/jpeg",
+    };
+    Ok(format!("data:{};base64,{}", mime, general_purpose::STANDARD.encode(&thumbnail)))
+}
@@ -0,0 +1,284 @@
+use std::sync::LazyLock;
+
+use base64::{engine::general_purpose, Engine as _};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::HistoryDb;
+use crate::settings;
+
+// CSI and OSC escape sequences as written by terminals and colored CLI output
+static ANSI_ESCAPE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)").unwrap());
+
+/// A named text transformation applied before an item is copied back.
+/// Chains of them can be saved as presets in the `transform_presets` setting.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    Trim,
+    Uppercase,
+    Lowercase,
+    TitleCase,
+    JsonPretty,
+    JsonMinify,
+    Base64Encode,
+    Base64Decode,
+    UrlEncode,
+    UrlDecode,
+    StripAnsi,
+    SortLines,
+}
+
+impl Transform {
+    /// Every transform, in the order the UI lists them.
+    pub const ALL: [Transform; 12] = [
+        Transform::Trim,
+        Transform::Uppercase,
+        Transform::Lowercase,
+        Transform::TitleCase,
+        Transform::JsonPretty,
+        Transform::JsonMinify,
+        Transform::Base64Encode,
+        Transform::Base64Decode,
+        Transform::UrlEncode,
+        Transform::UrlDecode,
+        Transform::StripAnsi,
+        Transform::SortLines,
+    ];
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Transform::Trim => "Remove leading and trailing whitespace",
+            Transform::Uppercase => "Convert to UPPERCASE",
+            Transform::Lowercase => "Convert to lowercase",
+            Transform::TitleCase => "Capitalize Each Word",
+            Transform::JsonPretty => "Pretty-print JSON",
+            Transform::JsonMinify => "Minify JSON",
+            Transform::Base64Encode => "Encode as Base64",
+            Transform::Base64Decode => "Decode Base64",
+            Transform::UrlEncode => "Percent-encode for URLs",
+            Transform::UrlDecode => "Decode percent-encoding",
+            Transform::StripAnsi => "Remove ANSI color and control codes",
+            Transform::SortLines => "Sort lines alphabetically",
+        }
+    }
+
+    /// Fails when the input isn't valid for the transform, such as malformed
+    /// JSON or Base64 that doesn't decode to UTF-8 text.
+    pub fn apply(self, text: &str) -> Result<String, String> {
+        Ok(match self {
+            Transform::Trim => text.trim().to_string(),
+            Transform::Uppercase => text.to_uppercase(),
+            Transform::Lowercase => text.to_lowercase(),
+            Transform::TitleCase => title_case(text),
+            Transform::JsonPretty => serde_json::to_string_pretty(&parse_json(text)?).map_err(|e| e.to_string())?,
+            Transform::JsonMinify => serde_json::to_string(&parse_json(text)?).map_err(|e| e.to_string())?,
+            Transform::Base64Encode => general_purpose::STANDARD.encode(text),
+            Transform::Base64Decode => {
+                // Accept URL-safe and unpadded input as well as the standard alphabet
+                let compact: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+                let standard = compact.replace('-', "+").replace('_', "/");
+                let bytes = general_purpose::STANDARD_NO_PAD
+                    .decode(standard.trim_end_matches('='))
+                    .map_err(|e| format!("Invalid Base64: {}", e))?;
+                String::from_utf8(bytes).map_err(|_| "Decoded Base64 is not text".to_string())?
+            }
+            Transform::UrlEncode => utf8_percent_encode(text, NON_ALPHANUMERIC).to_string(),
+            Transform::UrlDecode => percent_decode_str(&text.replace('+', " "))
+                .decode_utf8()
+                .map_err(|_| "Decoded URL is not text".to_string())?
+                .into_owned(),
+            Transform::StripAnsi => ANSI_ESCAPE.replace_all(text, "").into_owned(),
+            Transform::SortLines => sort_lines(text),
+        })
+    }
+}
+
+/// Run `text` through each transform in turn.
+pub fn apply_chain(text: &str, chain: &[Transform]) -> Result<String, String> {
+    chain.iter().try_fold(text.to_string(), |text, transform| {
+        transform.apply(&text).map_err(|e| format!("{}: {}", transform.description(), e))
+    })
+}
+
+fn parse_json(text: &str) -> Result<serde_json::Value, String> {
+    serde_json::from_str(text.trim()).map_err(|e| format!("Invalid JSON: {}", e))
+}
+
+// Sort lines, keeping the text's line terminator (taken from its first line)
+// and whether it ended with one
+fn sort_lines(text: &str) -> String {
+    let terminator = match text.find('\n') {
+        Some(end) if text[..end].ends_with('\r') => "\r\n",
+        _ => "\n",
+    };
+    let mut lines: Vec<&str> = text.lines().collect();
+    lines.sort_unstable();
+    let mut sorted = lines.join(terminator);
+    if text.ends_with('\n') {
+        sorted.push_str(terminator);
+    }
+    sorted
+}
+
+// Uppercase the first letter of every word and lowercase the rest
+fn title_case(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut word_start = true;
+    for c in text.chars() {
+        if word_start {
+            result.extend(c.to_uppercase());
+        } else {
+            result.extend(c.to_lowercase());
+        }
+        word_start = !c.is_alphanumeric() && c != '\'';
+    }
+    result
+}
+
+#[derive(Serialize)]
+pub struct TransformInfo {
+    name: Transform,
+    description: &'static str,
+}
+
+// Function to list the available transforms
+#[tauri::command]
+pub fn list_transforms() -> Vec<TransformInfo> {
+    Transform::ALL
+        .iter()
+        .map(|&name| TransformInfo { name, description: name.description() })
+        .collect()
+}
+
+// Function to transform a history item's text and put the result on the
+// clipboard. The stored item is left unchanged.
+#[tauri::command]
+pub fn transform_clipboard_item(
+    app: tauri::AppHandle,
+    db: tauri::State<'_, HistoryDb>,
+    id: Uuid,
+    transforms: Vec<Transform>,
+) -> Result<String, String> {
+    let item = db.get(id).map_err(|e| e.to_string())?.ok_or("Item not found")?;
+    if item.payload.kind() == "image" {
+        return Err("Images can't be transformed as text".to_string());
+    }
+    let text = apply_chain(&item.payload.search_text(), &transforms)?;
+    crate::write_clipboard_text(app, &text, None, None)?;
+    Ok(text)
+}
+
+// Function to apply a saved preset to a history item
+#[tauri::command]
+pub fn apply_transform_preset(
+    app: tauri::AppHandle,
+    db: tauri::State<'_, HistoryDb>,
+    id: Uuid,
+    preset: &str,
+) -> Result<String, String> {
+    let transforms = settings::load_settings(&app)
+        .transform_presets
+        .remove(preset)
+        .ok_or_else(|| format!("No preset named '{}'", preset))?;
+    transform_clipboard_item(app, db, id, transforms)
+}
+
+// Function to save a chain of transforms under a name, replacing any preset
+// with the same name
+#[tauri::command]
+pub fn save_transform_preset(app: tauri::AppHandle, name: &str, transforms: Vec<Transform>) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Preset name can't be empty".to_string());
+    }
+    let mut settings = settings::load_settings(&app);
+    settings.transform_presets.insert(name.to_string(), transforms);
+    settings::save_settings(&app, &settings)
+}
+
+// Function to delete a saved preset
+#[tauri::command]
+pub fn delete_transform_preset(app: tauri::AppHandle, name: &str) -> Result<(), String> {
+    let mut settings = settings::load_settings(&app);
+    settings.transform_presets.remove(name);
+    settings::save_settings(&app, &settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(transform: Transform, text: &str) -> String {
+        transform.apply(text).unwrap()
+    }
+
+    #[test]
+    fn case_and_whitespace() {
+        assert_eq!(apply(Transform::Trim, "  \thello world \n"), "hello world");
+        assert_eq!(apply(Transform::Uppercase, "straße"), "STRASSE");
+        assert_eq!(apply(Transform::Lowercase, "HeLLo ÄÖ"), "hello äö");
+        assert_eq!(apply(Transform::TitleCase, "the QUICK brown-fox's den"), "The Quick Brown-Fox's Den");
+        assert_eq!(apply(Transform::TitleCase, ""), "");
+    }
+
+    #[test]
+    fn json() {
+        let input = " {\"a\": [1, 2], \"b\": {\"c\": null}}\n";
+        assert_eq!(apply(Transform::JsonMinify, input), r#"{"a":[1,2],"b":{"c":null}}"#);
+        assert_eq!(
+            apply(Transform::JsonPretty, r#"{"a":[1,2]}"#),
+            "{\n  \"a\": [\n    1,\n    2\n  ]\n}"
+        );
+        assert!(Transform::JsonPretty.apply("{not json").unwrap_err().starts_with("Invalid JSON"));
+        assert!(Transform::JsonMinify.apply("").is_err());
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(apply(Transform::Base64Encode, "hello?>"), "aGVsbG8/Pg==");
+        assert_eq!(apply(Transform::Base64Decode, "aGVsbG8/Pg=="), "hello?>");
+        // URL-safe alphabet, missing padding and wrapped lines
+        assert_eq!(apply(Transform::Base64Decode, "aGVsbG8_Pg"), "hello?>");
+        assert_eq!(apply(Transform::Base64Decode, "aGVs\nbG8/\r\nPg=="), "hello?>");
+        assert!(Transform::Base64Decode.apply("not base64!").unwrap_err().starts_with("Invalid Base64"));
+        assert_eq!(Transform::Base64Decode.apply("//79").unwrap_err(), "Decoded Base64 is not text");
+    }
+
+    #[test]
+    fn url_encoding() {
+        assert_eq!(apply(Transform::UrlEncode, "a b&c=d/é"), "a%20b%26c%3Dd%2F%C3%A9");
+        assert_eq!(apply(Transform::UrlDecode, "a%20b%26c%3Dd%2F%C3%A9"), "a b&c=d/é");
+        assert_eq!(apply(Transform::UrlDecode, "q=one+two"), "q=one two");
+        assert_eq!(Transform::UrlDecode.apply("%FF").unwrap_err(), "Decoded URL is not text");
+    }
+
+    #[test]
+    fn strip_ansi() {
+        assert_eq!(apply(Transform::StripAnsi, "\x1b[1;31merror\x1b[0m: failed"), "error: failed");
+        assert_eq!(apply(Transform::StripAnsi, "\x1b]0;title\x07prompt \x1b]8;;http://x\x1b\\link"), "prompt link");
+        assert_eq!(apply(Transform::StripAnsi, "plain [text]"), "plain [text]");
+    }
+
+    #[test]
+    fn sort_lines() {
+        assert_eq!(apply(Transform::SortLines, "b\na\nc"), "a\nb\nc");
+        assert_eq!(apply(Transform::SortLines, "b\na\nc\n"), "a\nb\nc\n");
+        assert_eq!(apply(Transform::SortLines, "b\r\na\r\nc\r\n"), "a\r\nb\r\nc\r\n");
+        assert_eq!(apply(Transform::SortLines, "b\r\na"), "a\r\nb");
+        assert_eq!(apply(Transform::SortLines, "B\na\n\nb\n"), "\nB\na\nb\n");
+        assert_eq!(apply(Transform::SortLines, "single"), "single");
+        assert_eq!(apply(Transform::SortLines, ""), "");
+    }
+
+    #[test]
+    fn chains_stop_at_the_first_failure() {
+        let chain = [Transform::Trim, Transform::Base64Decode, Transform::Uppercase];
+        assert_eq!(apply_chain("  aGVsbG8=\n", &chain).unwrap(), "HELLO");
+        assert!(apply_chain("%%%", &chain).unwrap_err().starts_with("Decode Base64: Invalid Base64"));
+        assert_eq!(apply_chain("unchanged", &[]).unwrap(), "unchanged");
+    }
+}
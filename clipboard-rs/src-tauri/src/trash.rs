@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::HistoryDb;
+use crate::history::History;
+use crate::item::ClipboardItem;
+
+/// An item removed from the history, kept until the retention policy's
+/// `trash_retention_seconds` runs out or the trash is emptied.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TrashedItem {
+    #[serde(flatten)]
+    pub item: ClipboardItem,
+    pub deleted_at: u64,
+    /// What removed it: `deleted`, `cleared`, `undone_import`, or the
+    /// retention limit that evicted it (`max_items`, `max_age`, ...).
+    pub reason: String,
+}
+
+/// Bulk changes that can be undone. Only the most recent one is kept.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkOperation {
+    /// The history was cleared; undo restores the items from the trash.
+    Clear,
+    /// The retention policy was applied by hand or after a settings change;
+    /// undo restores what it evicted.
+    Retention,
+    /// Items were imported; undo moves them to the trash.
+    Import,
+}
+
+impl BulkOperation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BulkOperation::Clear => "clear",
+            BulkOperation::Retention => "retention",
+            BulkOperation::Import => "import",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "clear" => Some(BulkOperation::Clear),
+            "retention" => Some(BulkOperation::Retention),
+            "import" => Some(BulkOperation::Import),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LastOperation {
+    pub kind: BulkOperation,
+    /// How many items it removed or imported.
+    pub items: usize,
+    pub at: u64,
+}
+
+// Function to list the trash, most recently deleted first
+#[tauri::command]
+pub fn list_trash(db: tauri::State<'_, HistoryDb>) -> Result<Vec<TrashedItem>, String> {
+    db.trash().map_err(|e| e.to_string())
+}
+
+// Function to move items from the trash back into the history. Returns how
+// many were restored; items whose content is already back in the history are
+// just dropped from the trash.
+#[tauri::command]
+pub fn restore_from_trash(history: tauri::State<'_, History>, ids: Vec<Uuid>) -> Result<usize, String> {
+    history.restore(ids)
+}
+
+// Function to permanently delete everything in the trash
+#[tauri::command]
+pub fn empty_trash(history: tauri::State<'_, History>) -> Result<usize, String> {
+    history.empty_trash()
+}
+
+// Function to describe the bulk operation `undo_last_operation` would undo
+#[tauri::command]
+pub fn get_last_operation(db: tauri::State<'_, HistoryDb>) -> Result<Option<LastOperation>, String> {
+    db.last_operation().map_err(|e| e.to_string())
+}
+
+// Function to undo the most recent clear, clean-up or import
+#[tauri::command]
+pub fn undo_last_operation(history: tauri::State<'_, History>) -> Result<LastOperation, String> {
+    history.undo()?.ok_or_else(|| "Nothing to undo".to_string())
+}
@@ -0,0 +1,763 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::blobs::{self, BlobStore, PendingBlob};
+use crate::collections::Collection;
+use crate::db::HistoryDb;
+use crate::history::History;
+use crate::item::{decode_history, ClipboardItem, ClipboardPayload};
+use crate::trash::TrashedItem;
+
+// Encrypted history file layout: MAGIC, VERSION, key source, salt, then the
+// sealed JSON snapshot. The header is authenticated as associated data.
+const MAGIC: &[u8; 8] = b"CRSVAULT";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + 2 + SALT_LEN;
+const NONCE_LEN: usize = 24;
+
+// Associated data for image blobs, so a blob can't be swapped in for the history file
+const BLOB_AAD: &[u8] = b"clipboard-rs blob v1";
+// Associated data for cached thumbnails, so one can't be swapped in for a blob
+const THUMBNAIL_AAD: &[u8] = b"clipboard-rs thumbnail v1";
+pub const BLOB_EXTENSION: &str = "enc";
+
+const KEYRING_SERVICE: &str = "clipboard-rs";
+const KEYRING_USER: &str = "history-key";
+
+type Key = Zeroizing<[u8; 32]>;
+
+// What the history file holds once decrypted. Files written before the trash
+// existed hold a bare item list instead.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    items: Vec<ClipboardItem>,
+    #[serde(default)]
+    trash: Vec<TrashedItem>,
+    #[serde(default)]
+    collections: Vec<Collection>,
+}
+
+impl Snapshot {
+    fn read(db: &HistoryDb) -> Result<Self, String> {
+        Ok(Snapshot {
+            items: db.list(None, 0).map_err(|e| e.to_string())?,
+            trash: db.trash().map_err(|e| e.to_string())?,
+            collections: db.collections(true).map_err(|e| e.to_string())?,
+        })
+    }
+
+    fn decode(json: &[u8]) -> Result<Self, String> {
+        if json.trim_ascii_start().starts_with(b"[") {
+            let value = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+            let (items, _) = decode_history(value);
+            return Ok(Snapshot { items, trash: Vec::new(), collections: Vec::new() });
+        }
+        serde_json::from_slice(json).map_err(|e| e.to_string())
+    }
+
+    // Every item, trashed ones included
+    fn all_items(&mut self) -> impl Iterator<Item = &mut ClipboardItem> {
+        self.items.iter_mut().chain(self.trash.iter_mut().map(|trashed| &mut trashed.item))
+    }
+}
+
+// A snapshot holds the whole decrypted history, so overwrite the clips'
+// contents before the memory is freed
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        for item in self.all_items() {
+            match &mut item.payload {
+                ClipboardPayload::Text { text } => text.zeroize(),
+                ClipboardPayload::RichText { text, html, rtf } => {
+                    text.zeroize();
+                    html.zeroize();
+                    rtf.zeroize();
+                }
+                ClipboardPayload::FileList { paths } => paths.zeroize(),
+                ClipboardPayload::Image { .. } => {}
+            }
+            item.tags.zeroize();
+            item.ocr_text.zeroize();
+        }
+    }
+}
+
+/// Where the encryption key comes from.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    /// Derived from a passphrase with Argon2id.
+    Passphrase,
+    /// A random key stored in the OS keyring.
+    Keyring,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionStatus {
+    pub enabled: bool,
+    pub locked: bool,
+    pub key_source: Option<KeySource>,
+    /// Why the encrypted history can't be opened, if its file is damaged.
+    pub error: Option<String>,
+}
+
+struct Header {
+    source: KeySource,
+    salt: [u8; SALT_LEN],
+}
+
+impl Header {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(match self.source {
+            KeySource::Passphrase => 0,
+            KeySource::Keyring => 1,
+        });
+        bytes.extend_from_slice(&self.salt);
+        bytes
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Header, String> {
+        if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+            return Err("Not an encrypted clipboard history".to_string());
+        }
+        if bytes[MAGIC.len()] != VERSION {
+            return Err(format!("Unsupported history format version {}", bytes[MAGIC.len()]));
+        }
+        let source = match bytes[MAGIC.len() + 1] {
+            0 => KeySource::Passphrase,
+            1 => KeySource::Keyring,
+            other => return Err(format!("Unknown key source {}", other)),
+        };
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&bytes[MAGIC.len() + 2..HEADER_LEN]);
+        Ok(Header { source, salt })
+    }
+}
+
+#[derive(Default)]
+struct VaultState {
+    // Present while encryption is enabled
+    header: Option<Header>,
+    // Present while the history is unlocked
+    key: Option<Key>,
+    // `HistoryDb::changes` at the last save
+    saved_changes: Option<u64>,
+    // Why the history file couldn't be read at startup. The history stays
+    // locked rather than falling back to the plain database.
+    damaged: Option<String>,
+}
+
+/// Encryption at rest for the history and image blobs. While enabled, the
+/// history lives in an in-memory database and is saved to `history.vault`
+/// as a single XChaCha20-Poly1305 sealed snapshot; image blobs are sealed
+/// with the same key.
+pub struct Vault {
+    path: PathBuf,
+    plain_path: PathBuf,
+    state: Mutex<VaultState>,
+}
+
+impl Vault {
+    /// `plain_path` is the unencrypted history database, which is removed
+    /// when encryption is enabled and recreated when it is disabled.
+    /// A history file that can't be read leaves the vault enabled and
+    /// locked, reporting why in `status`.
+    pub fn open(data_dir: &Path, plain_path: PathBuf) -> Self {
+        let path = data_dir.join("history.vault");
+        let (header, damaged) = match fs::read(&path).map_err(|e| e.to_string()).and_then(|bytes| Header::parse(&bytes)) {
+            Ok(header) => (Some(header), None),
+            Err(_) if !path.exists() => (None, None),
+            Err(e) => (None, Some(format!("The encrypted history can't be read: {}", e))),
+        };
+        Vault {
+            path,
+            plain_path,
+            state: Mutex::new(VaultState { header, damaged, ..Default::default() }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, VaultState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        let state = self.state();
+        state.header.is_some() || state.damaged.is_some()
+    }
+
+    pub fn status(&self) -> EncryptionStatus {
+        let state = self.state();
+        let enabled = state.header.is_some() || state.damaged.is_some();
+        EncryptionStatus {
+            enabled,
+            locked: enabled && state.key.is_none(),
+            key_source: state.header.as_ref().map(|header| header.source),
+            error: state.damaged.clone(),
+        }
+    }
+
+    /// Decrypt the history into memory. `passphrase` is required when the
+    /// key was derived from one.
+    pub fn unlock(&self, db: &HistoryDb, passphrase: Option<&str>) -> Result<(), String> {
+        let mut state = self.state();
+        if let Some(damaged) = &state.damaged {
+            return Err(damaged.clone());
+        }
+        let header = state.header.as_ref().ok_or("Encryption is not enabled")?;
+        if state.key.is_some() {
+            return Ok(());
+        }
+
+        let key = match header.source {
+            KeySource::Passphrase => derive_key(passphrase.ok_or("A passphrase is required")?, &header.salt)?,
+            KeySource::Keyring => keyring_key()?,
+        };
+        let bytes = fs::read(&self.path).map_err(|e| e.to_string())?;
+        let header_bytes = bytes.get(..HEADER_LEN).ok_or("The encrypted history is truncated")?;
+        let json = open_sealed(&key, header_bytes, &bytes[HEADER_LEN..])?;
+        let snapshot = Snapshot::decode(&json)?;
+
+        db.reopen(None, &snapshot.items, &snapshot.trash, &snapshot.collections).map_err(|e| e.to_string())?;
+        state.saved_changes = db.changes().ok();
+        state.key = Some(key);
+        Ok(())
+    }
+
+    /// Save any pending changes, then wipe the decrypted history and the key
+    /// from memory.
+    pub fn lock(&self, db: &HistoryDb) -> Result<(), String> {
+        self.save(db)?;
+        let mut state = self.state();
+        if state.header.is_none() {
+            return Err("Encryption is not enabled".to_string());
+        }
+        db.lock().map_err(|e| e.to_string())?;
+        state.key = None;
+        state.saved_changes = None;
+        Ok(())
+    }
+
+    /// Write the history to disk if it changed since the last save. Does
+    /// nothing while encryption is disabled or the history is locked.
+    pub fn save(&self, db: &HistoryDb) -> Result<(), String> {
+        let mut state = self.state();
+        let (Some(header), Some(key)) = (&state.header, &state.key) else {
+            return Ok(());
+        };
+        let changes = db.changes().map_err(|e| e.to_string())?;
+        if state.saved_changes == Some(changes) {
+            return Ok(());
+        }
+
+        let snapshot = Snapshot::read(db)?;
+        let json = Zeroizing::new(serde_json::to_vec(&snapshot).map_err(|e| e.to_string())?);
+        self.write_snapshot(header, key, &json)?;
+        state.saved_changes = Some(changes);
+        Ok(())
+    }
+
+    /// Encrypt the history and existing image blobs. Without a passphrase, a
+    /// random key is generated and stored in the OS keyring.
+    pub fn enable(&self, db: &HistoryDb, blob_store: &BlobStore, passphrase: Option<&str>) -> Result<(), String> {
+        let mut state = self.state();
+        if state.header.is_some() || state.damaged.is_some() {
+            return Err("Encryption is already enabled".to_string());
+        }
+
+        let mut salt = [0u8; SALT_LEN];
+        let (header, key) = match passphrase {
+            Some(passphrase) => {
+                if passphrase.is_empty() {
+                    return Err("The passphrase can't be empty".to_string());
+                }
+                OsRng.fill_bytes(&mut salt);
+                (Header { source: KeySource::Passphrase, salt }, derive_key(passphrase, &salt)?)
+            }
+            None => {
+                let mut key = Key::default();
+                OsRng.fill_bytes(key.as_mut());
+                keyring_entry()?.set_secret(key.as_ref()).map_err(|e| e.to_string())?;
+                (Header { source: KeySource::Keyring, salt }, key)
+            }
+        };
+
+        let plain_blobs = match self.seal_history(db, blob_store, &header, &key) {
+            Ok(plain_blobs) => plain_blobs,
+            Err(e) => {
+                // The key would only unlock a vault that was never finished
+                if header.source == KeySource::Keyring {
+                    let _ = keyring_entry().map(|entry| entry.delete_credential());
+                }
+                return Err(e);
+            }
+        };
+        for path in plain_blobs {
+            for thumbnail in blob_store.thumbnails_of(&path) {
+                shred(&thumbnail);
+            }
+            shred(Path::new(&path));
+        }
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.plain_path.clone().into_os_string();
+            path.push(suffix);
+            shred(Path::new(&path));
+        }
+
+        state.saved_changes = db.changes().ok();
+        state.header = Some(header);
+        state.key = Some(key);
+        Ok(())
+    }
+
+    // Seal the history and its blobs with a new key and switch to the
+    // in-memory database. Plain files are left for the caller to remove once
+    // this succeeds, so a failure part-way never loses history. Returns the
+    // paths of the plain blobs that were sealed.
+    fn seal_history(&self, db: &HistoryDb, blob_store: &BlobStore, header: &Header, key: &Key) -> Result<Vec<String>, String> {
+        let mut snapshot = Snapshot::read(db)?;
+        let mut sealed_blobs = Vec::new();
+        let plain_blobs = rewrite_blobs(snapshot.all_items(), |path| {
+            if is_sealed(path) {
+                return Ok(None);
+            }
+            let plain = Zeroizing::new(fs::read(path).map_err(|e| e.to_string())?);
+            let blob = write_sealed_blob(blob_store, key, &plain, &blobs::plain_extension(path))?;
+            let sealed_path = blob.path();
+            sealed_blobs.push(blob);
+            Ok(Some(sealed_path))
+        })?;
+        let json = Zeroizing::new(serde_json::to_vec(&snapshot).map_err(|e| e.to_string())?);
+        self.write_snapshot(header, key, &json)?;
+
+        if let Err(e) = db.reopen(None, &snapshot.items, &snapshot.trash, &snapshot.collections) {
+            // Otherwise the next start would find the vault and ignore the
+            // plain history it was made from
+            let _ = fs::remove_file(&self.path);
+            return Err(e.to_string());
+        }
+        Ok(plain_blobs)
+    }
+
+    /// Decrypt everything back to plain files. The history must be unlocked.
+    pub fn disable(&self, db: &HistoryDb, blob_store: &BlobStore) -> Result<(), String> {
+        let mut state = self.state();
+        let source = state.header.as_ref().ok_or("Encryption is not enabled")?.source;
+        let key = state.key.as_ref().ok_or("Unlock the history first")?;
+
+        let mut snapshot = Snapshot::read(db)?;
+        let mut plain_blobs = Vec::new();
+        let sealed_blobs = rewrite_blobs(snapshot.all_items(), |path| {
+            if !is_sealed(path) {
+                return Ok(None);
+            }
+            let sealed = fs::read(path).map_err(|e| e.to_string())?;
+            let plain = open_sealed(key, BLOB_AAD, &sealed)?;
+            let extension = blobs::plain_extension(path);
+            let blob = blob_store.put(&blobs::address(&plain, None, &extension), || Ok(plain.to_vec()))?;
+            let plain_path = blob.path();
+            plain_blobs.push(blob);
+            Ok(Some(plain_path))
+        })?;
+
+        db.reopen(Some(&self.plain_path), &snapshot.items, &snapshot.trash, &snapshot.collections).map_err(|e| e.to_string())?;
+        drop(plain_blobs);
+        fs::remove_file(&self.path).map_err(|e| e.to_string())?;
+        for path in sealed_blobs {
+            for thumbnail in blob_store.thumbnails_of(&path) {
+                let _ = fs::remove_file(thumbnail);
+            }
+            let _ = fs::remove_file(path);
+        }
+        if source == KeySource::Keyring {
+            let _ = keyring_entry()?.delete_credential();
+        }
+
+        *state = VaultState::default();
+        Ok(())
+    }
+
+    /// Add an image blob to the blob store, sealed if encryption is enabled.
+    pub fn write_blob<'a>(&self, blob_store: &'a BlobStore, bytes: &[u8], extension: &str) -> Result<PendingBlob<'a>, String> {
+        let state = self.state();
+        match (&state.header, &state.key) {
+            (None, _) => blob_store.put(&blobs::address(bytes, None, extension), || Ok(bytes.to_vec())),
+            (Some(_), Some(key)) => write_sealed_blob(blob_store, key, bytes, extension),
+            (Some(_), None) => Err("clipboard history is locked".to_string()),
+        }
+    }
+
+    /// Read an image blob, decrypting it if it was sealed.
+    pub fn read_blob(&self, path: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Failed to read image file: {}", e))?;
+        if !is_sealed(path) {
+            return Ok(Zeroizing::new(bytes));
+        }
+        let state = self.state();
+        let key = state.key.as_ref().ok_or("clipboard history is locked")?;
+        open_sealed(key, BLOB_AAD, &bytes)
+    }
+
+    /// Prepare a thumbnail of the blob at `blob_path` for the cache: sealed
+    /// if that blob is, as it is otherwise.
+    pub fn seal_thumbnail(&self, blob_path: &str, bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+        if !is_sealed(blob_path) {
+            return Ok(bytes);
+        }
+        let state = self.state();
+        let key = state.key.as_ref().ok_or("clipboard history is locked")?;
+        seal(key, THUMBNAIL_AAD, &bytes)
+    }
+
+    /// Read a cached thumbnail written by `seal_thumbnail`.
+    pub fn open_thumbnail(&self, blob_path: &str, bytes: Vec<u8>) -> Result<Zeroizing<Vec<u8>>, String> {
+        if !is_sealed(blob_path) {
+            return Ok(Zeroizing::new(bytes));
+        }
+        let state = self.state();
+        let key = state.key.as_ref().ok_or("clipboard history is locked")?;
+        open_sealed(key, THUMBNAIL_AAD, &bytes)
+    }
+
+    // Atomically replace the history file with a new sealed snapshot
+    fn write_snapshot(&self, header: &Header, key: &Key, json: &[u8]) -> Result<(), String> {
+        let header_bytes = header.to_bytes();
+        let mut contents = header_bytes.clone();
+        contents.extend(seal(key, &header_bytes, json)?);
+
+        // Flushed before the rename and the rename flushed after, so a crash
+        // leaves either the old file or the new one, never a partial one
+        let tmp_path = self.path.with_extension("vault.tmp");
+        let mut file = fs::File::create(&tmp_path).map_err(|e| e.to_string())?;
+        file.write_all(&contents).map_err(|e| e.to_string())?;
+        file.sync_all().map_err(|e| e.to_string())?;
+        drop(file);
+        fs::rename(&tmp_path, &self.path).map_err(|e| e.to_string())?;
+        sync_dir(&self.path)
+    }
+}
+
+// Flush the directory entry of `path`. Windows can't open directories and
+// makes renames durable by itself.
+fn sync_dir(path: &Path) -> Result<(), String> {
+    match path.parent() {
+        Some(dir) if cfg!(unix) => fs::File::open(dir).and_then(|dir| dir.sync_all()).map_err(|e| e.to_string()),
+        _ => Ok(()),
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, String> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| e.to_string())?;
+    Ok(key)
+}
+
+fn keyring_entry() -> Result<keyring::Entry, String> {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER).map_err(|e| e.to_string())
+}
+
+fn keyring_key() -> Result<Key, String> {
+    let secret = Zeroizing::new(keyring_entry()?.get_secret().map_err(|e| e.to_string())?);
+    let mut key = Key::default();
+    if secret.len() != key.len() {
+        return Err("The key in the OS keyring has the wrong length".to_string());
+    }
+    key.copy_from_slice(&secret);
+    Ok(key)
+}
+
+// Encrypt with a random nonce, returned in front of the ciphertext
+fn seal(key: &Key, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let cipher = XChaCha20Poly1305::new(key.as_ref().into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext, aad })
+        .map_err(|_| "Encryption failed".to_string())?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(sealed)
+}
+
+fn open_sealed(key: &Key, aad: &[u8], sealed: &[u8]) -> Result<Zeroizing<Vec<u8>>, String> {
+    if sealed.len() < NONCE_LEN {
+        return Err("Encrypted data is truncated".to_string());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let cipher = XChaCha20Poly1305::new(key.as_ref().into());
+    cipher
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+        .map(Zeroizing::new)
+        .map_err(|_| "Wrong passphrase or corrupted data".to_string())
+}
+
+// Store a blob sealed with `key`. Its address is keyed too, so the file name
+// doesn't give away the plain image's hash.
+fn write_sealed_blob<'a>(blob_store: &'a BlobStore, key: &Key, bytes: &[u8], extension: &str) -> Result<PendingBlob<'a>, String> {
+    let name = format!("{}.{}", blobs::address(bytes, Some(&key[..]), extension), BLOB_EXTENSION);
+    blob_store.put(&name, || seal(key, BLOB_AAD, bytes))
+}
+
+fn is_sealed(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|ext| ext == BLOB_EXTENSION)
+}
+
+// Apply `convert` to the blob of every image item and point the item at the
+// new path it returns. Missing blobs are left alone. Returns the old paths
+// of the converted blobs.
+fn rewrite_blobs<'a>(
+    items: impl Iterator<Item = &'a mut ClipboardItem>,
+    mut convert: impl FnMut(&str) -> Result<Option<String>, String>,
+) -> Result<Vec<String>, String> {
+    let mut replaced = Vec::new();
+    for item in items {
+        let ClipboardPayload::Image { blob_path, .. } = &mut item.payload else {
+            continue;
+        };
+        if !Path::new(blob_path.as_str()).exists() {
+            continue;
+        }
+        if let Some(new_path) = convert(blob_path)? {
+            replaced.push(std::mem::replace(blob_path, new_path));
+        }
+    }
+    Ok(replaced)
+}
+
+// Overwrite a file with zeros before removing it. Best effort: journaling and
+// copy-on-write filesystems may keep the old blocks around.
+fn shred(path: &Path) {
+    if let Ok(metadata) = fs::metadata(path) {
+        let _ = fs::write(path, vec![0u8; metadata.len() as usize]);
+        let _ = fs::remove_file(path);
+    }
+}
+
+#[tauri::command]
+pub fn get_encryption_status(vault: tauri::State<'_, Vault>) -> EncryptionStatus {
+    vault.status()
+}
+
+// Changes that swap out the whole database run on the history writer, so a
+// clip captured meanwhile is never lost between reading and replacing it
+
+#[tauri::command]
+pub fn enable_encryption(history: tauri::State<'_, History>, passphrase: Option<String>) -> Result<(), String> {
+    let passphrase = passphrase.map(Zeroizing::new);
+    history.replace(move |app| {
+        let passphrase = passphrase.as_deref().map(String::as_str);
+        app.state::<Vault>().enable(&app.state::<HistoryDb>(), &app.state::<BlobStore>(), passphrase)
+    })
+}
+
+#[tauri::command]
+pub fn disable_encryption(history: tauri::State<'_, History>) -> Result<(), String> {
+    history.replace(|app| app.state::<Vault>().disable(&app.state::<HistoryDb>(), &app.state::<BlobStore>()))
+}
+
+#[tauri::command]
+pub fn unlock_history(history: tauri::State<'_, History>, passphrase: Option<String>) -> Result<(), String> {
+    let passphrase = passphrase.map(Zeroizing::new);
+    history.replace(move |app| {
+        let (vault, db, blob_store) = (app.state::<Vault>(), app.state::<HistoryDb>(), app.state::<BlobStore>());
+        vault.unlock(&db, passphrase.as_deref().map(String::as_str))?;
+
+        // Bring blobs from before the blob store into it, and drop ones the
+        // last saved snapshot doesn't know about
+        blob_store.adopt(&db, &vault)?;
+        blob_store.sweep(&db)?;
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn lock_history(history: tauri::State<'_, History>) -> Result<(), String> {
+    history.replace(|app| app.state::<Vault>().lock(&app.state::<HistoryDb>()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A scratch directory removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("clipboard-rs-vault-{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn random_key() -> Key {
+        let mut key = Key::default();
+        OsRng.fill_bytes(key.as_mut());
+        key
+    }
+
+    fn texts(db: &HistoryDb) -> Vec<String> {
+        db.list(None, 0).unwrap().iter().map(|item| item.payload.search_text()).collect()
+    }
+
+    #[test]
+    fn seal_round_trip() {
+        let key = random_key();
+        let sealed = seal(&key, b"aad", b"secret clip").unwrap();
+        assert_eq!(sealed.len(), NONCE_LEN + b"secret clip".len() + 16);
+        assert_eq!(open_sealed(&key, b"aad", &sealed).unwrap().as_slice(), b"secret clip");
+        // A fresh nonce every time
+        assert_ne!(seal(&key, b"aad", b"secret clip").unwrap(), sealed);
+        assert_eq!(open_sealed(&key, b"aad", &seal(&key, b"aad", b"").unwrap()).unwrap().as_slice(), b"");
+    }
+
+    #[test]
+    fn wrong_keys_and_damaged_data_are_rejected() {
+        let key = random_key();
+        let sealed = seal(&key, BLOB_AAD, b"secret clip").unwrap();
+        assert_eq!(open_sealed(&random_key(), BLOB_AAD, &sealed).unwrap_err(), "Wrong passphrase or corrupted data");
+        // A blob can't stand in for a thumbnail
+        assert!(open_sealed(&key, THUMBNAIL_AAD, &sealed).is_err());
+
+        for cut in [0, NONCE_LEN - 1] {
+            assert_eq!(open_sealed(&key, BLOB_AAD, &sealed[..cut]).unwrap_err(), "Encrypted data is truncated");
+        }
+        for cut in [NONCE_LEN, NONCE_LEN + 5, sealed.len() - 1] {
+            assert!(open_sealed(&key, BLOB_AAD, &sealed[..cut]).is_err(), "{}", cut);
+        }
+        for i in [0, NONCE_LEN, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[i] ^= 1;
+            assert!(open_sealed(&key, BLOB_AAD, &tampered).is_err(), "{}", i);
+        }
+    }
+
+    #[test]
+    fn passphrase_keys() {
+        let salt = [7u8; SALT_LEN];
+        let key = derive_key("correct horse", &salt).unwrap();
+        assert_eq!(*derive_key("correct horse", &salt).unwrap(), *key);
+        assert_ne!(*derive_key("correct horse!", &salt).unwrap(), *key);
+        assert_ne!(*derive_key("correct horse", &[8u8; SALT_LEN]).unwrap(), *key);
+
+        let sealed = seal(&key, b"", b"clip").unwrap();
+        let wrong = derive_key("wrong", &salt).unwrap();
+        assert!(open_sealed(&wrong, b"", &sealed).is_err());
+    }
+
+    #[test]
+    fn header_parse() {
+        let header = Header { source: KeySource::Keyring, salt: [3u8; SALT_LEN] };
+        let bytes = header.to_bytes();
+        assert_eq!(bytes.len(), HEADER_LEN);
+        let parsed = Header::parse(&bytes).unwrap();
+        assert_eq!((parsed.source, parsed.salt), (KeySource::Keyring, [3u8; SALT_LEN]));
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(Header::parse(&bad_magic).err().unwrap(), "Not an encrypted clipboard history");
+        let mut bad_version = bytes.clone();
+        bad_version[MAGIC.len()] = VERSION + 1;
+        assert_eq!(Header::parse(&bad_version).err().unwrap(), format!("Unsupported history format version {}", VERSION + 1));
+        let mut bad_source = bytes.clone();
+        bad_source[MAGIC.len() + 1] = 9;
+        assert_eq!(Header::parse(&bad_source).err().unwrap(), "Unknown key source 9");
+        assert!(Header::parse(&bytes[..HEADER_LEN - 1]).is_err());
+        assert!(Header::parse(b"").is_err());
+    }
+
+    #[test]
+    fn passphrase_vault_round_trip() {
+        let dir = TempDir::new();
+        let plain_path = dir.0.join("history.sqlite3");
+        let db = HistoryDb::open(&plain_path).unwrap();
+        db.insert_many(&[ClipboardItem::new(ClipboardPayload::Text { text: "secret clip".to_string() })]).unwrap();
+        let blob_store = BlobStore::open(&dir.0).unwrap();
+
+        let vault = Vault::open(&dir.0, plain_path.clone());
+        assert!(!vault.is_enabled());
+        assert!(vault.enable(&db, &blob_store, Some("")).is_err());
+        vault.enable(&db, &blob_store, Some("correct horse")).unwrap();
+        assert!(!plain_path.exists());
+        let sealed = fs::read(dir.0.join("history.vault")).unwrap();
+        assert!(sealed.starts_with(MAGIC));
+        assert!(!sealed.windows(b"secret clip".len()).any(|window| window == b"secret clip"));
+        assert_eq!(texts(&db), ["secret clip"]);
+
+        db.insert_many(&[ClipboardItem::new(ClipboardPayload::Text { text: "later clip".to_string() })]).unwrap();
+        vault.lock(&db).unwrap();
+        assert!(db.list(None, 0).is_err());
+        assert!(vault.status().locked);
+
+        // A fresh start reads what was saved on locking
+        let vault = Vault::open(&dir.0, plain_path.clone());
+        let status = vault.status();
+        assert!(status.enabled && status.locked && status.error.is_none());
+        assert_eq!(status.key_source, Some(KeySource::Passphrase));
+        assert_eq!(vault.unlock(&db, Some("wrong horse")).unwrap_err(), "Wrong passphrase or corrupted data");
+        assert_eq!(vault.unlock(&db, None).unwrap_err(), "A passphrase is required");
+        vault.unlock(&db, Some("correct horse")).unwrap();
+        assert_eq!(texts(&db), ["later clip", "secret clip"]);
+
+        vault.disable(&db, &blob_store).unwrap();
+        assert!(plain_path.exists() && !dir.0.join("history.vault").exists());
+        assert!(!dir.0.join("history.vault.tmp").exists());
+        assert_eq!(texts(&HistoryDb::open(&plain_path).unwrap()), ["later clip", "secret clip"]);
+    }
+
+    #[test]
+    fn damaged_vaults_stay_locked() {
+        let dir = TempDir::new();
+        let plain_path = dir.0.join("history.sqlite3");
+        let db = HistoryDb::open(&plain_path).unwrap();
+        db.insert_many(&[ClipboardItem::new(ClipboardPayload::Text { text: "secret clip".to_string() })]).unwrap();
+        let blob_store = BlobStore::open(&dir.0).unwrap();
+        Vault::open(&dir.0, plain_path.clone()).enable(&db, &blob_store, Some("pw")).unwrap();
+        let vault_path = dir.0.join("history.vault");
+        let sealed = fs::read(&vault_path).unwrap();
+
+        // Tampered or cut-off ciphertext fails to unlock
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        for contents in [tampered, sealed[..HEADER_LEN + NONCE_LEN + 4].to_vec(), sealed[..HEADER_LEN].to_vec()] {
+            fs::write(&vault_path, contents).unwrap();
+            let vault = Vault::open(&dir.0, plain_path.clone());
+            assert!(vault.status().error.is_none());
+            assert!(vault.unlock(&HistoryDb::locked(), Some("pw")).is_err());
+        }
+
+        // A damaged header leaves it locked, saying why, rather than failing
+        for contents in [b"".to_vec(), sealed[..HEADER_LEN - 1].to_vec(), [b"NOTVAULT", &sealed[MAGIC.len()..]].concat()] {
+            fs::write(&vault_path, contents).unwrap();
+            let vault = Vault::open(&dir.0, plain_path.clone());
+            let status = vault.status();
+            assert!(status.enabled && status.locked);
+            let error = status.error.unwrap();
+            assert!(error.starts_with("The encrypted history can't be read"), "{}", error);
+            assert_eq!(vault.unlock(&HistoryDb::locked(), Some("pw")).unwrap_err(), error);
+            assert!(vault.enable(&HistoryDb::locked(), &blob_store, Some("pw")).is_err());
+        }
+    }
+}
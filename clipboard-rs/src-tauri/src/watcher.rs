@@ -0,0 +1,441 @@
+use std::thread;
+use std::time::Duration;
+
+use crate::source::SourceApp;
+
+/// Source of "the clipboard may have changed" notifications for the monitor
+/// loop. Watchers only signal that something happened; the monitor still
+/// reads and compares the clipboard contents itself.
+pub trait ClipboardWatcher: Send {
+    /// Block until the clipboard may have changed. An error means the
+    /// watcher can no longer deliver notifications.
+    fn wait(&mut self) -> Result<(), String>;
+
+    /// MIME types offered by the current clipboard owner, when the watcher
+    /// can see them. Used to honour hints like
+    /// `x-kde-passwordManagerHint`.
+    fn offered_mime_types(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The window that is active right now, taken to be the one the
+    /// current clip was copied from, when the watcher can see it.
+    fn source_app(&mut self) -> Option<SourceApp> {
+        None
+    }
+}
+
+/// Fallback that wakes up on a fixed interval.
+pub struct PollingWatcher {
+    interval: Duration,
+}
+
+impl Default for PollingWatcher {
+    fn default() -> Self {
+        PollingWatcher { interval: Duration::from_millis(500) }
+    }
+}
+
+impl ClipboardWatcher for PollingWatcher {
+    fn wait(&mut self) -> Result<(), String> {
+        thread::sleep(self.interval);
+        Ok(())
+    }
+}
+
+/// Pick the best watcher for the current session: wlr-data-control on
+/// Wayland, XFixes on X11, and polling when neither is available.
+pub fn detect() -> Box<dyn ClipboardWatcher> {
+    #[cfg(target_os = "linux")]
+    {
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            if let Ok(watcher) = wayland::WaylandWatcher::connect() {
+                return Box::new(watcher);
+            }
+        }
+        if std::env::var_os("DISPLAY").is_some() {
+            if let Ok(watcher) = x11::X11Watcher::connect() {
+                return Box::new(watcher);
+            }
+        }
+    }
+
+    Box::new(PollingWatcher::default())
+}
+
+#[cfg(target_os = "linux")]
+mod x11 {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xfixes::{ConnectionExt as _, SelectionEventMask};
+    use x11rb::protocol::xproto::{
+        Atom, AtomEnum, ConnectionExt as _, CreateWindowAux, GetPropertyReply, Window, WindowClass,
+    };
+    use x11rb::protocol::Event;
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::{COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT, CURRENT_TIME, NONE};
+
+    use super::ClipboardWatcher;
+    use crate::source::SourceApp;
+
+    /// Listens for XFixes selection-owner changes on CLIPBOARD.
+    pub struct X11Watcher {
+        conn: RustConnection,
+        root: Window,
+        window: Window,
+        clipboard: Atom,
+        targets: Atom,
+        property: Atom,
+        net_active_window: Atom,
+        net_wm_name: Atom,
+        utf8_string: Atom,
+        // Set when a selection change arrives while we're waiting for a
+        // TARGETS reply, so the next `wait` doesn't miss it
+        pending_change: bool,
+    }
+
+    impl X11Watcher {
+        pub fn connect() -> Result<Self, String> {
+            let (conn, screen_num) = RustConnection::connect(None).map_err(|e| e.to_string())?;
+            conn.xfixes_query_version(5, 0)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| e.to_string())?;
+
+            // XFixes delivers selection events to a window, so create an
+            // invisible one to receive them
+            let root = conn.setup().roots[screen_num].root;
+            let window = conn.generate_id().map_err(|e| e.to_string())?;
+            conn.create_window(
+                COPY_DEPTH_FROM_PARENT,
+                window,
+                root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_ONLY,
+                COPY_FROM_PARENT,
+                &CreateWindowAux::new(),
+            )
+            .map_err(|e| e.to_string())?;
+
+            let intern = |name: &[u8]| -> Result<Atom, String> {
+                Ok(conn
+                    .intern_atom(false, name)
+                    .map_err(|e| e.to_string())?
+                    .reply()
+                    .map_err(|e| e.to_string())?
+                    .atom)
+            };
+            let clipboard = intern(b"CLIPBOARD")?;
+            let targets = intern(b"TARGETS")?;
+            let property = intern(b"CLIPBOARD_RS_TARGETS")?;
+            let net_active_window = intern(b"_NET_ACTIVE_WINDOW")?;
+            let net_wm_name = intern(b"_NET_WM_NAME")?;
+            let utf8_string = intern(b"UTF8_STRING")?;
+            conn.xfixes_select_selection_input(
+                window,
+                clipboard,
+                SelectionEventMask::SET_SELECTION_OWNER
+                    | SelectionEventMask::SELECTION_WINDOW_DESTROY
+                    | SelectionEventMask::SELECTION_CLIENT_CLOSE,
+            )
+            .map_err(|e| e.to_string())?;
+            conn.flush().map_err(|e| e.to_string())?;
+
+            Ok(X11Watcher {
+                conn,
+                root,
+                window,
+                clipboard,
+                targets,
+                property,
+                net_active_window,
+                net_wm_name,
+                utf8_string,
+                pending_change: false,
+            })
+        }
+
+        // Ask the owner to convert CLIPBOARD to TARGETS and wait briefly for
+        // the answer. Owners that never reply just give an empty list.
+        fn query_targets(&mut self) -> Result<Vec<String>, String> {
+            self.conn
+                .convert_selection(self.window, self.clipboard, self.targets, self.property, CURRENT_TIME)
+                .map_err(|e| e.to_string())?;
+            self.conn.flush().map_err(|e| e.to_string())?;
+
+            let deadline = Instant::now() + Duration::from_millis(500);
+            let notify = loop {
+                match self.conn.poll_for_event().map_err(|e| e.to_string())? {
+                    Some(Event::SelectionNotify(notify)) if notify.requestor == self.window => break notify,
+                    Some(Event::XfixesSelectionNotify(_)) => self.pending_change = true,
+                    Some(_) => {}
+                    None if Instant::now() >= deadline => return Ok(Vec::new()),
+                    None => thread::sleep(Duration::from_millis(10)),
+                }
+            };
+            if notify.property == NONE {
+                return Ok(Vec::new());
+            }
+
+            let reply = self.conn
+                .get_property(true, self.window, self.property, AtomEnum::ATOM, 0, 1024)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| e.to_string())?;
+            let atoms: Vec<Atom> = reply.value32().map(|values| values.collect()).unwrap_or_default();
+
+            // Send every name lookup before reading any reply
+            let cookies = atoms
+                .iter()
+                .map(|&atom| self.conn.get_atom_name(atom))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?;
+            Ok(cookies
+                .into_iter()
+                .filter_map(|cookie| cookie.reply().ok())
+                .map(|reply| String::from_utf8_lossy(&reply.name).into_owned())
+                .collect())
+        }
+
+        // A window property, or `None` if it isn't set
+        fn property(&self, window: Window, property: Atom, type_: Atom) -> Result<Option<GetPropertyReply>, String> {
+            let reply = self.conn
+                .get_property(false, window, property, type_, 0, 1024)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| e.to_string())?;
+            Ok((reply.type_ != NONE).then_some(reply))
+        }
+
+        // Class and title of the window the window manager reports as active
+        fn active_window_app(&self) -> Result<Option<SourceApp>, String> {
+            let Some(active) = self.property(self.root, self.net_active_window, AtomEnum::WINDOW.into())? else {
+                return Ok(None);
+            };
+            let Some(window) = active.value32().and_then(|mut windows| windows.next()).filter(|&w| w != NONE) else {
+                return Ok(None);
+            };
+
+            // WM_CLASS holds the instance and class names, each NUL-terminated
+            let Some(wm_class) = self.property(window, AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into())? else {
+                return Ok(None);
+            };
+            let mut names = wm_class.value.split(|&b| b == 0).filter(|name| !name.is_empty());
+            let instance = names.next();
+            let Some(class) = names.next().or(instance) else {
+                return Ok(None);
+            };
+
+            let title = match self.property(window, self.net_wm_name, self.utf8_string)? {
+                Some(title) => title.value,
+                None => self
+                    .property(window, AtomEnum::WM_NAME.into(), AtomEnum::ANY.into())?
+                    .map(|title| title.value)
+                    .unwrap_or_default(),
+            };
+            Ok(Some(SourceApp {
+                class: String::from_utf8_lossy(class).into_owned(),
+                title: String::from_utf8_lossy(&title).into_owned(),
+            }))
+        }
+    }
+
+    impl ClipboardWatcher for X11Watcher {
+        fn wait(&mut self) -> Result<(), String> {
+            if std::mem::take(&mut self.pending_change) {
+                return Ok(());
+            }
+            loop {
+                if let Event::XfixesSelectionNotify(_) = self.conn.wait_for_event().map_err(|e| e.to_string())? {
+                    return Ok(());
+                }
+            }
+        }
+
+        fn offered_mime_types(&mut self) -> Vec<String> {
+            self.query_targets().unwrap_or_default()
+        }
+
+        fn source_app(&mut self) -> Option<SourceApp> {
+            self.active_window_app().ok().flatten()
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod wayland {
+    use std::collections::HashMap;
+
+    use wayland_client::backend::ObjectId;
+    use wayland_client::globals::{registry_queue_init, GlobalListContents};
+    use wayland_client::protocol::wl_registry::WlRegistry;
+    use wayland_client::protocol::wl_seat::WlSeat;
+    use wayland_client::{event_created_child, Connection, Dispatch, EventQueue, Proxy, QueueHandle};
+    use wayland_protocols_wlr::data_control::v1::client::zwlr_data_control_device_v1::{
+        self, ZwlrDataControlDeviceV1,
+    };
+    use wayland_protocols_wlr::data_control::v1::client::zwlr_data_control_manager_v1::ZwlrDataControlManagerV1;
+    use wayland_protocols_wlr::data_control::v1::client::zwlr_data_control_offer_v1::{
+        self, ZwlrDataControlOfferV1,
+    };
+
+    use super::ClipboardWatcher;
+
+    #[derive(Default)]
+    struct State {
+        changed: bool,
+        finished: bool,
+        offer: Option<ZwlrDataControlOfferV1>,
+        // Offer for the primary selection, which isn't watched but has to be
+        // released like the other
+        primary_offer: Option<ZwlrDataControlOfferV1>,
+        // MIME types announced for offers that haven't become the selection yet
+        announced: HashMap<ObjectId, Vec<String>>,
+        mime_types: Vec<String>,
+    }
+
+    /// Listens for selection events from the wlr-data-control protocol.
+    pub struct WaylandWatcher {
+        queue: EventQueue<State>,
+        state: State,
+        _device: ZwlrDataControlDeviceV1,
+    }
+
+    impl WaylandWatcher {
+        pub fn connect() -> Result<Self, String> {
+            let conn = Connection::connect_to_env().map_err(|e| e.to_string())?;
+            let (globals, mut queue) = registry_queue_init::<State>(&conn).map_err(|e| e.to_string())?;
+            let qh = queue.handle();
+
+            let manager: ZwlrDataControlManagerV1 = globals.bind(&qh, 1..=1, ()).map_err(|e| e.to_string())?;
+            let seat: WlSeat = globals.bind(&qh, 1..=9, ()).map_err(|e| e.to_string())?;
+            let device = manager.get_data_device(&seat, &qh, ());
+
+            // The compositor announces the current selection straight away.
+            // The monitor reads the clipboard before its first wait, so
+            // swallow that announcement.
+            let mut state = State::default();
+            queue.roundtrip(&mut state).map_err(|e| e.to_string())?;
+            state.changed = false;
+
+            Ok(WaylandWatcher { queue, state, _device: device })
+        }
+    }
+
+    impl ClipboardWatcher for WaylandWatcher {
+        fn wait(&mut self) -> Result<(), String> {
+            while !self.state.changed {
+                if self.state.finished {
+                    return Err("wlr-data-control device was invalidated".to_string());
+                }
+                self.queue.blocking_dispatch(&mut self.state).map_err(|e| e.to_string())?;
+            }
+            self.state.changed = false;
+            Ok(())
+        }
+
+        fn offered_mime_types(&mut self) -> Vec<String> {
+            self.state.mime_types.clone()
+        }
+    }
+
+    impl Dispatch<WlRegistry, GlobalListContents> for State {
+        fn event(
+            _state: &mut Self,
+            _proxy: &WlRegistry,
+            _event: <WlRegistry as Proxy>::Event,
+            _data: &GlobalListContents,
+            _conn: &Connection,
+            _qh: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    impl Dispatch<WlSeat, ()> for State {
+        fn event(
+            _state: &mut Self,
+            _proxy: &WlSeat,
+            _event: <WlSeat as Proxy>::Event,
+            _data: &(),
+            _conn: &Connection,
+            _qh: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    impl Dispatch<ZwlrDataControlManagerV1, ()> for State {
+        fn event(
+            _state: &mut Self,
+            _proxy: &ZwlrDataControlManagerV1,
+            _event: <ZwlrDataControlManagerV1 as Proxy>::Event,
+            _data: &(),
+            _conn: &Connection,
+            _qh: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    impl Dispatch<ZwlrDataControlDeviceV1, ()> for State {
+        fn event(
+            state: &mut Self,
+            _proxy: &ZwlrDataControlDeviceV1,
+            event: zwlr_data_control_device_v1::Event,
+            _data: &(),
+            _conn: &Connection,
+            _qh: &QueueHandle<Self>,
+        ) {
+            match event {
+                zwlr_data_control_device_v1::Event::Selection { id } => {
+                    state.mime_types = id
+                        .as_ref()
+                        .and_then(|offer| state.announced.remove(&offer.id()))
+                        .unwrap_or_default();
+                    state.announced.clear();
+
+                    // Each selection comes with a fresh offer; release the old one
+                    if let Some(old) = std::mem::replace(&mut state.offer, id) {
+                        old.destroy();
+                    }
+                    state.changed = true;
+                }
+                zwlr_data_control_device_v1::Event::PrimarySelection { id } => {
+                    if let Some(offer) = &id {
+                        state.announced.remove(&offer.id());
+                    }
+                    if let Some(old) = std::mem::replace(&mut state.primary_offer, id) {
+                        old.destroy();
+                    }
+                }
+                zwlr_data_control_device_v1::Event::Finished => {
+                    state.finished = true;
+                }
+                _ => {}
+            }
+        }
+
+        event_created_child!(State, ZwlrDataControlDeviceV1, [
+            zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ()),
+        ]);
+    }
+
+    impl Dispatch<ZwlrDataControlOfferV1, ()> for State {
+        fn event(
+            state: &mut Self,
+            proxy: &ZwlrDataControlOfferV1,
+            event: zwlr_data_control_offer_v1::Event,
+            _data: &(),
+            _conn: &Connection,
+            _qh: &QueueHandle<Self>,
+        ) {
+            if let zwlr_data_control_offer_v1::Event::Offer { mime_type } = event {
+                state.announced.entry(proxy.id()).or_default().push(mime_type);
+            }
+        }
+    }
+}
@@ -40,6 +40,8 @@ import {
   Modal,
   Backdrop,
   Fade,
+  Menu,
+  MenuItem,
 } from "@mui/material";
 import {
   ContentCopy,
@@ -57,8 +59,352 @@ import {
   Close as CloseIcon,
   Brightness4 as Brightness4Icon,
   Brightness7 as Brightness7Icon,
+  Lock as LockIcon,
+  LockOpen as LockOpenIcon,
+  AutoFixHigh as AutoFixHighIcon,
+  TextSnippet as TextSnippetIcon,
+  Edit as EditIcon,
+  Pause as PauseIcon,
+  PlayArrow as PlayArrowIcon,
+  RestoreFromTrash as RestoreFromTrashIcon,
+  Bolt as BoltIcon,
+  DocumentScanner as DocumentScannerIcon,
+  QrCode2 as QrCodeIcon,
+  QrCodeScanner as QrCodeScannerIcon,
+  PlaylistAdd as PlaylistAddIcon,
+  ArrowUpward as ArrowUpwardIcon,
+  ArrowDownward as ArrowDownwardIcon,
+  SkipNext as SkipNextIcon,
+  CollectionsBookmark as CollectionsBookmarkIcon,
+  LibraryAdd as LibraryAddIcon,
+  RemoveCircleOutline as RemoveCircleOutlineIcon,
+  FileDownload as FileDownloadIcon,
 } from "@mui/icons-material";
 
+type ClipboardPayload =
+  | { kind: "text"; text: string }
+  | { kind: "image"; width: number; height: number; blob_path: string; pixel_hash: string; perceptual_hash?: string }
+  | { kind: "file_list"; paths: string[] }
+  | { kind: "rich_text"; text: string; html?: string; rtf?: string };
+
+interface EncryptionStatus {
+  enabled: boolean;
+  locked: boolean;
+  keySource: "passphrase" | "keyring" | null;
+  // Set when the encrypted history file is damaged and can't be unlocked
+  error: string | null;
+}
+
+interface ClipboardItem {
+  id: string;
+  payload: ClipboardPayload;
+  timestamp: number;
+  is_favorite: boolean;
+  tags: string[];
+  expires_at?: number;
+  source?: SourceApp;
+  content_type?: ContentType;
+  // Text recognized in an image; empty if none was found, missing until OCR ran
+  ocr_text?: string;
+}
+
+// What a text item looks like, detected when it was captured
+type ContentType =
+  | { type: "url" | "email" | "path" | "color" | "json" | "uuid" | "phone" }
+  | { type: "code"; language: string | null };
+
+const CONTENT_TYPE_LABELS: Record<ContentType["type"], string> = {
+  url: "URL",
+  email: "Email",
+  path: "Path",
+  color: "Color",
+  json: "JSON",
+  uuid: "UUID",
+  phone: "Phone",
+  code: "Code",
+};
+
+function contentTypeLabel(contentType: ContentType): string {
+  if (contentType.type === "code" && contentType.language) {
+    return `Code (${contentType.language})`;
+  }
+  return CONTENT_TYPE_LABELS[contentType.type];
+}
+
+// How `search_clipboard_history` matches the query
+type SearchMode = "substring" | "fuzzy" | "regex";
+
+// Byte range of a search match in an item's text
+interface MatchRange {
+  start: number;
+  end: number;
+}
+
+interface SearchHit extends ClipboardItem {
+  score: number;
+  matches: MatchRange[];
+  ocr_matches: MatchRange[];
+}
+
+interface SearchResults {
+  hits: SearchHit[];
+  total: number;
+}
+
+// A rectangle in pixels on the image as it is at that edit step
+interface ImageRect {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+// One step of an image edit; steps apply in order
+type ImageOperation =
+  | { op: "crop"; rect: ImageRect }
+  | { op: "resize"; width?: number; height?: number }
+  | { op: "rotate"; degrees: number }
+  | { op: "flip"; axis: "horizontal" | "vertical" }
+  | { op: "redact"; rect: ImageRect; style: "pixelate" | "blur"; strength?: number }
+  | { op: "arrow"; from: { x: number; y: number }; to: { x: number; y: number }; color: string; thickness: number }
+  | { op: "box"; rect: ImageRect; color: string; thickness: number };
+
+type ImageFormat = "png" | "jpeg" | "webp";
+
+const IMAGE_OPERATION_LABELS: Record<ImageOperation["op"], string> = {
+  crop: "Crop",
+  resize: "Resize",
+  rotate: "Rotate",
+  flip: "Flip",
+  redact: "Redact",
+  arrow: "Arrow",
+  box: "Box",
+};
+
+function describeImageOperation(operation: ImageOperation): string {
+  const rect = (r: ImageRect) => `${r.width}×${r.height} at ${r.x},${r.y}`;
+  switch (operation.op) {
+    case "crop": return `Crop to ${rect(operation.rect)}`;
+    case "resize": return `Resize to ${operation.width ?? "auto"}×${operation.height ?? "auto"}`;
+    case "rotate": return `Rotate ${operation.degrees}°`;
+    case "flip": return `Flip ${operation.axis}`;
+    case "redact": return `${operation.style === "blur" ? "Blur" : "Pixelate"} ${rect(operation.rect)}`;
+    case "arrow": return `Arrow ${operation.from.x},${operation.from.y} → ${operation.to.x},${operation.to.y}`;
+    case "box": return `Box ${rect(operation.rect)}`;
+  }
+}
+
+// A new step with defaults fitted to an image of the given size
+function newImageOperation(op: ImageOperation["op"], width: number, height: number): ImageOperation {
+  const middle = {
+    x: Math.floor(width / 4),
+    y: Math.floor(height / 4),
+    width: Math.max(1, Math.floor(width / 2)),
+    height: Math.max(1, Math.floor(height / 2)),
+  };
+  switch (op) {
+    case "crop": return { op, rect: { x: 0, y: 0, width, height } };
+    case "resize": return { op, width: Math.max(1, Math.floor(width / 2)) };
+    case "rotate": return { op, degrees: 90 };
+    case "flip": return { op, axis: "horizontal" };
+    case "redact": return { op, rect: middle, style: "pixelate" };
+    case "arrow": return { op, from: { x: 0, y: 0 }, to: { x: middle.x, y: middle.y }, color: "#ff0000", thickness: 4 };
+    case "box": return { op, rect: middle, color: "#ff0000", thickness: 4 };
+  }
+}
+
+// An action offered for an item's content type
+interface ItemActionInfo {
+  action: string;
+  label: string;
+}
+
+// Payload of the `pause-state` event
+interface PauseState {
+  paused: boolean;
+  resume_at: number | null;
+}
+
+// Payload of the `paste-queue` event. The first item is on the clipboard
+// while `loaded`; with `detects_pastes` each paste loads the next one.
+interface QueueStatus {
+  items: ClipboardItem[];
+  loaded: boolean;
+  detects_pastes: boolean;
+}
+
+// A named set of items in an order the user picks. `item_ids` lists the
+// members in that order, leaving out ones in the trash.
+interface Collection {
+  id: string;
+  name: string;
+  created_at: number;
+  item_ids: string[];
+}
+
+interface CollectionStatistics {
+  totalItems: number;
+  favoriteItems: number;
+  trashedItems: number;
+  textItems: number;
+  imageItems: number;
+  fileListItems: number;
+  richTextItems: number;
+  totalBytes: number;
+  topTags: [string, number][];
+  earliestTimestamp: number;
+  latestTimestamp: number;
+}
+
+// Window a clip was copied from (X11 only)
+interface SourceApp {
+  class: string;
+  title: string;
+}
+
+// Matches a source app by window class and/or a substring of its title
+interface AppRule {
+  class?: string | null;
+  title_contains?: string | null;
+}
+
+// An item the retention policy removed, and the limit that removed it
+type Eviction = { id: string } & (
+  | { reason: "max_items" }
+  | { reason: "max_total_bytes" }
+  | { reason: "max_age"; kind: string }
+  | { reason: "tag_ttl"; tag: string }
+);
+
+interface RetentionPolicy {
+  max_items: number | null;
+  max_total_bytes: number | null;
+  max_age_seconds: number | null;
+  max_age_by_kind: Record<string, number>;
+  tag_ttl_seconds: Record<string, number>;
+  exempt_favorites: boolean;
+  trash_retention_seconds: number;
+}
+
+// Background text recognition of images through Tesseract
+interface OcrSettings {
+  enabled: boolean;
+  languages: string;
+  tesseract_path: string | null;
+}
+
+interface OcrStatus {
+  enabled: boolean;
+  version: string | null;
+  error: string | null;
+  pending: number;
+}
+
+// How much damage a rendered QR code survives; higher makes larger codes
+type QrErrorCorrection = "low" | "medium" | "quartile" | "high";
+
+// A QR code or barcode found in an image item
+interface DecodedCode {
+  format: "qr_code" | "ean13" | "ean8" | "upc_a" | "code128";
+  text: string;
+}
+
+const CODE_FORMAT_LABELS: Record<DecodedCode["format"], string> = {
+  qr_code: "QR Code",
+  ean13: "EAN-13",
+  ean8: "EAN-8",
+  upc_a: "UPC-A",
+  code128: "Code 128",
+};
+
+// An item in the trash. `reason` is `deleted`, `cleared`, `undone_import` or
+// the retention limit that evicted it.
+type TrashedItem = ClipboardItem & { deleted_at: number; reason: string };
+
+// Persisted settings. Only the fields listed here are edited; the rest is
+// passed back to `update_settings` unchanged.
+type AppSettings = {
+  retention: RetentionPolicy;
+  remember_pause: boolean;
+  ocr: OcrSettings;
+  exclude: { apps: AppRule[] };
+  transform_presets: Record<string, string[]>;
+} & Record<string, unknown>;
+
+// A curated snippet; `body` may contain `{date}`, `{date:%H:%M}`, `{uuid}`,
+// `{clipboard}` and `{input:Name}` placeholders
+interface Snippet {
+  id?: string;
+  name: string;
+  body: string;
+  updated_at?: number;
+}
+
+interface TransformInfo {
+  name: string;
+  description: string;
+}
+
+const DAY_SECONDS = 24 * 60 * 60;
+const MEGABYTE = 1024 * 1024;
+
+// Payload of the `clipboard-update` event: what one change did to the history
+type HistoryChange =
+  | { type: "added"; items: ClipboardItem[]; evicted: Eviction[] }
+  | { type: "updated"; items: ClipboardItem[] }
+  | { type: "removed"; ids: string[] }
+  | { type: "evicted"; evictions: Eviction[] }
+  | { type: "cleared" }
+  | { type: "collections"; collections: Collection[] }
+  | { type: "reloaded" };
+
+// Text shown in previews and used by the local search filter
+// One-line description of an item in the paste queue
+function queueLabel(item: ClipboardItem): string {
+  return item.payload.kind === "image" ? `Image ${item.payload.width}x${item.payload.height}` : itemText(item);
+}
+
+function itemText(item: ClipboardItem): string {
+  switch (item.payload.kind) {
+    case "text":
+    case "rich_text":
+      return item.payload.text;
+    case "file_list":
+      return item.payload.paths.join("\n");
+    case "image":
+      return "";
+  }
+}
+
+// Number of history items the list loads at a time
+const HISTORY_PAGE_SIZE = 500;
+
+// Number of search results fetched at a time
+const SEARCH_PAGE_SIZE = 100;
+
+// Largest size, in CSS pixels, a thumbnail is shown at in the history list
+const THUMBNAIL_SIZE = 56;
+
+// Convert byte ranges in `text` (as the backend reports them) to string
+// index ranges
+function toStringRanges(text: string, matches: MatchRange[]): [number, number][] {
+  const lastByte = Math.max(0, ...matches.map(m => m.end));
+  const indexAt = new Map<number, number>();
+  let byte = 0;
+  let index = 0;
+  for (const ch of text) {
+    if (byte > lastByte) break;
+    indexAt.set(byte, index);
+    const codePoint = ch.codePointAt(0) ?? 0;
+    byte += codePoint < 0x80 ? 1 : codePoint < 0x800 ? 2 : codePoint < 0x10000 ? 3 : 4;
+    index += ch.length;
+  }
+  indexAt.set(byte, index);
+  return matches
+    .filter(m => indexAt.has(m.start) && indexAt.has(m.end))
+    .map(m => [indexAt.get(m.start)!, indexAt.get(m.end)!]);
+}
+
 function App() {
   // Helper function to truncate text and show first few lines
   const truncateTextPreview = (text: string, maxLines: number = 4, maxCharsPerLine: number = 100) => {
@@ -89,30 +435,110 @@ function App() {
     return result;
   };
 
+  // Preview like `truncateTextPreview`, with search matches highlighted
+  const highlightedPreview = (text: string, matches: MatchRange[] | undefined, maxLines: number = 4, maxCharsPerLine: number = 100) => {
+    if (!text || !matches || matches.length === 0) return truncateTextPreview(text, maxLines, maxCharsPerLine);
+
+    const ranges = toStringRanges(text, matches);
+    const lines = text.split('\n');
+    const parts: React.ReactNode[] = [];
+    let lineStart = 0;
+    lines.slice(0, maxLines).forEach((line, lineIndex) => {
+      if (lineIndex > 0) parts.push('\n');
+      const shownEnd = lineStart + Math.min(line.length, maxCharsPerLine);
+      let position = lineStart;
+      for (const [start, end] of ranges) {
+        const from = Math.max(start, position);
+        const to = Math.min(end, shownEnd);
+        if (from >= to) continue;
+        parts.push(text.slice(position, from));
+        parts.push(<mark key={`${lineIndex}-${from}`}>{text.slice(from, to)}</mark>);
+        position = to;
+      }
+      parts.push(text.slice(position, shownEnd));
+      if (line.length > maxCharsPerLine) parts.push('...');
+      lineStart += line.length + 1;
+    });
+    if (lines.length > maxLines) {
+      parts.push(`\n... (${lines.length - maxLines} more lines)`);
+    }
+    return parts;
+  };
+
   const [clipboardText, setClipboardText] = useState("");
   const [clipboardContent, setClipboardContent] = useState("");
-  const [clipboardHistory, setClipboardHistory] = useState<any[]>([]);
-  const [filteredHistory, setFilteredHistory] = useState<any[]>([]);
+  const [clipboardHistory, setClipboardHistory] = useState<ClipboardItem[]>([]);
+  const [filteredHistory, setFilteredHistory] = useState<ClipboardItem[]>([]);
   const [searchQuery, setSearchQuery] = useState("");
+  const [contentTypeFilter, setContentTypeFilter] = useState("");
+  const [searchMode, setSearchMode] = useState<SearchMode>("substring");
+  const [tagFilter, setTagFilter] = useState("");
+  const [sinceDate, setSinceDate] = useState("");
+  const [untilDate, setUntilDate] = useState("");
+  const [allTags, setAllTags] = useState<string[]>([]);
+  const [searchMatches, setSearchMatches] = useState<Record<string, Pick<SearchHit, "matches" | "ocr_matches">>>({});
+  const [searchTotal, setSearchTotal] = useState(0);
+  const [searchError, setSearchError] = useState<string | null>(null);
+  const [actionMenu, setActionMenu] = useState<{ anchor: HTMLElement; item: ClipboardItem; actions: ItemActionInfo[] } | null>(null);
   const [favorites, setFavorites] = useState<string[]>([]);
   const [showFavoritesOnly, setShowFavoritesOnly] = useState(false);
-  const [autoDeleteEnabled, setAutoDeleteEnabled] = useState(false);
-  const [autoDeleteDays, setAutoDeleteDays] = useState(7);
+  const [settings, setSettings] = useState<AppSettings | null>(null);
+  const [ocrStatus, setOcrStatus] = useState<OcrStatus | null>(null);
+  const [transforms, setTransforms] = useState<TransformInfo[]>([]);
+  const [transformTarget, setTransformTarget] = useState<ClipboardItem | null>(null);
+  const [transformChain, setTransformChain] = useState<string[]>([]);
+  const [imageEditTarget, setImageEditTarget] = useState<ClipboardItem | null>(null);
+  const [imageEdits, setImageEdits] = useState<ImageOperation[]>([]);
+  const [imageEditDraft, setImageEditDraft] = useState<ImageOperation | null>(null);
+  const [imageEditPreview, setImageEditPreview] = useState<string | null>(null);
+  const [imageEditError, setImageEditError] = useState<string | null>(null);
+  const [imageEditFormat, setImageEditFormat] = useState<ImageFormat>("png");
+  const [imageEditQuality, setImageEditQuality] = useState(90);
+  const [qrTarget, setQrTarget] = useState<ClipboardItem | null>(null);
+  const [qrErrorCorrection, setQrErrorCorrection] = useState<QrErrorCorrection>("medium");
+  const [qrPreview, setQrPreview] = useState<string | null>(null);
+  const [qrError, setQrError] = useState<string | null>(null);
+  const [codeScan, setCodeScan] = useState<{ item: ClipboardItem; codes: DecodedCode[] | null; error: string | null } | null>(null);
+  const [presetName, setPresetName] = useState("");
+  const [pauseState, setPauseState] = useState<PauseState>({ paused: false, resume_at: null });
+  const [pasteQueue, setPasteQueue] = useState<QueueStatus>({ items: [], loaded: false, detects_pastes: false });
+  const [collections, setCollections] = useState<Collection[]>([]);
+  const [collectionFilter, setCollectionFilter] = useState("");
+  const [collectionMenu, setCollectionMenu] = useState<{ anchor: HTMLElement; item: ClipboardItem } | null>(null);
+  const [showCollections, setShowCollections] = useState(false);
+  // Item a collection created from the item's menu starts with
+  const [collectionSeed, setCollectionSeed] = useState<ClipboardItem | null>(null);
+  const [collectionName, setCollectionName] = useState("");
+  const [collectionTag, setCollectionTag] = useState("");
+  const [renamingCollection, setRenamingCollection] = useState<{ id: string; name: string } | null>(null);
+  const [collectionStats, setCollectionStats] = useState<{ collection: Collection; stats: CollectionStatistics } | null>(null);
+  const [pauseMenuAnchor, setPauseMenuAnchor] = useState<HTMLElement | null>(null);
+  const [excludeDraft, setExcludeDraft] = useState<AppRule>({ class: "", title_contains: "" });
+  const [snippets, setSnippets] = useState<Snippet[]>([]);
+  const [snippetDraft, setSnippetDraft] = useState<Snippet | null>(null);
+  const [snippetPrompt, setSnippetPrompt] = useState<{ snippet: Snippet; values: Record<string, string> } | null>(null);
   const [imageThumbnails, setImageThumbnails] = useState<Record<string, string>>({});
-  const [selectedImage, setSelectedImage] = useState<{filePath: string, dimensions: string} | null>(null);
+  const [selectedImage, setSelectedImage] = useState<{src: string, dimensions: string} | null>(null);
+  const [encryption, setEncryption] = useState<EncryptionStatus>({ enabled: false, locked: false, keySource: null, error: null });
+  const [passphrase, setPassphrase] = useState("");
   const [statistics, setStatistics] = useState<any>(null);
   const [showStatistics, setShowStatistics] = useState(false);
   const [darkMode, setDarkMode] = useState(false);
   const [snackbarOpen, setSnackbarOpen] = useState(false);
   const [snackbarMessage, setSnackbarMessage] = useState("");
   const [snackbarSeverity, setSnackbarSeverity] = useState<"success" | "error" | "warning" | "info">("success");
+  const [snackbarUndo, setSnackbarUndo] = useState(false);
+  const [trash, setTrash] = useState<TrashedItem[]>([]);
+  const [showTrash, setShowTrash] = useState(false);
   const [loading, setLoading] = useState(false);
 
   const theme = useMemo(() => createAppTheme(darkMode ? 'dark' : 'light'), [darkMode]);
 
-  const showSnackbar = (message: string, severity: "success" | "error" | "warning" | "info" = "success") => {
+  // `undo` adds an Undo button for the bulk operation just performed
+  const showSnackbar = (message: string, severity: "success" | "error" | "warning" | "info" = "success", undo = false) => {
     setSnackbarMessage(message);
     setSnackbarSeverity(severity);
+    setSnackbarUndo(undo);
     setSnackbarOpen(true);
   };
 
@@ -124,9 +550,7 @@ function App() {
     setLoading(true);
     try {
       const text = await invoke<string>("read_clipboard_text");
-      // Extract the text part (first part before the timestamp)
-      const textPart = text.split('|')[0];
-      setClipboardContent(textPart);
+      setClipboardContent(text);
       showSnackbar("Text pasted from clipboard!", "success");
     } catch (error) {
       // Try to read image if text reading fails
@@ -153,8 +577,6 @@ function App() {
       await invoke("write_clipboard_text", { text: clipboardText });
       // Save to history
       await invoke("save_clipboard_history", { text: clipboardText });
-      // Refresh history
-      loadHistory();
       showSnackbar("Text copied to clipboard!", "success");
     } catch (error) {
       showSnackbar("Failed to write clipboard: " + error, "error");
@@ -165,103 +587,148 @@ function App() {
 
   async function loadHistory() {
     try {
-      const history: any[] = await invoke("load_clipboard_history");
+      // A locked history has nothing to show until it is unlocked
+      const status = await invoke<EncryptionStatus>("get_encryption_status");
+      setEncryption(status);
+      if (status.locked) {
+        setClipboardHistory([]);
+        setFilteredHistory([]);
+        setImageThumbnails({});
+        return;
+      }
+
+      const history = await invoke<ClipboardItem[]>("load_clipboard_history", { limit: HISTORY_PAGE_SIZE });
       setClipboardHistory(history);
       setFilteredHistory(history);
-      
-      // Load thumbnails for image entries
-      const newThumbnails: Record<string, string> = {};
-      for (const item of history) {
-        const itemContent = typeof item === 'string' ? item : item.content;
-        if (itemContent.startsWith("[Image]")) {
-          console.log("Processing image item:", itemContent); // Debugging
-          const parts = itemContent.split('|');
-          if (parts.length >= 3) {
-            const filePath = parts[parts.length - 2]; // Second to last part is the file path
-            console.log("File path:", filePath); // Debugging
-            try {
-              const thumbnail = await invoke<string>("get_image_thumbnail", { filePath: filePath });
-              newThumbnails[itemContent] = thumbnail;
-            } catch (error) {
-              console.error("Failed to load thumbnail for", itemContent, "with file path", filePath, error);
-              // Use a placeholder if thumbnail fails to load
-              newThumbnails[itemContent] = "🖼️";
-            }
-          }
-        }
-      }
-      console.log("Image thumbnails:", newThumbnails); // Debugging
-      setImageThumbnails(newThumbnails);
+      setFavorites(history.filter(item => item.is_favorite).map(item => item.id));
+      setImageThumbnails(await loadThumbnails(history));
     } catch (error) {
       console.error("Failed to load clipboard history:", error);
       showSnackbar("Failed to load clipboard history", "error");
     }
   }
 
+  // Load thumbnails for image entries, keyed by item ID. The backend caches
+  // them, so they're requested all at once at the size the screen needs.
+  async function loadThumbnails(items: ClipboardItem[]): Promise<Record<string, string>> {
+    const size = Math.ceil(THUMBNAIL_SIZE * window.devicePixelRatio);
+    const entries = await Promise.all(items.map(async item => {
+      if (item.payload.kind !== "image") return null;
+      const filePath = item.payload.blob_path;
+      try {
+        const thumbnail = await invoke<string>("get_image_thumbnail", { filePath, size });
+        return [item.id, thumbnail] as const;
+      } catch (error) {
+        console.error("Failed to load thumbnail for", item.id, "with file path", filePath, error);
+        // Use a placeholder if thumbnail fails to load
+        return [item.id, "🖼️"] as const;
+      }
+    }));
+    return Object.fromEntries(entries.filter(entry => entry !== null));
+  }
+
+  // Apply one change from the backend to the loaded history instead of
+  // reloading all of it
+  async function applyHistoryChange(change: HistoryChange) {
+    switch (change.type) {
+      case "added": {
+        const gone = new Set(change.evicted.map(eviction => eviction.id));
+        const fresh = change.items.filter(item => !gone.has(item.id));
+        setClipboardHistory(prev => [...fresh, ...prev.filter(item => !gone.has(item.id))].slice(0, HISTORY_PAGE_SIZE));
+        setFavorites(prev => [
+          ...prev.filter(id => !gone.has(id)),
+          ...fresh.filter(item => item.is_favorite).map(item => item.id),
+        ]);
+        const thumbnails = await loadThumbnails(fresh);
+        setImageThumbnails(prev => ({ ...prev, ...thumbnails }));
+        break;
+      }
+      case "updated": {
+        const updated = new Map(change.items.map(item => [item.id, item]));
+        setClipboardHistory(prev => prev.map(item => updated.get(item.id) ?? item));
+        setFavorites(prev => [
+          ...prev.filter(id => !updated.has(id)),
+          ...change.items.filter(item => item.is_favorite).map(item => item.id),
+        ]);
+        break;
+      }
+      case "removed":
+      case "evicted": {
+        const gone = new Set(change.type === "removed" ? change.ids : change.evictions.map(eviction => eviction.id));
+        setClipboardHistory(prev => prev.filter(item => !gone.has(item.id)));
+        setFavorites(prev => prev.filter(id => !gone.has(id)));
+        break;
+      }
+      case "cleared":
+        setClipboardHistory([]);
+        setFavorites([]);
+        setImageThumbnails({});
+        break;
+      case "collections":
+        setCollections(change.collections);
+        // Stop filtering by a collection that was deleted
+        setCollectionFilter(prev => prev && !change.collections.some(collection => collection.id === prev) ? "" : prev);
+        break;
+      case "reloaded":
+        loadHistory();
+        break;
+    }
+  }
+
   async function loadFavorites() {
     try {
-      const favs = await invoke<string[]>("load_favorites");
-      setFavorites(favs);
+      const favs = await invoke<ClipboardItem[]>("load_favorites");
+      setFavorites(favs.map(fav => fav.id));
     } catch (error) {
       console.error("Failed to load favorites:", error);
       showSnackbar("Failed to load favorites", "error");
     }
   }
 
-  async function toggleFavorite(item: any) {
-    const itemContent = typeof item === 'string' ? item : item.content;
-    
+  async function toggleFavorite(item: ClipboardItem) {
     try {
-      const isNowFavorite = await invoke<boolean>("toggle_favorite", { itemContent });
+      const isNowFavorite = await invoke<boolean>("toggle_favorite", { id: item.id });
       
-      // Update the favorites list
+      // The favorites list is updated by the `clipboard-update` event
       if (isNowFavorite) {
-        setFavorites(prev => [...prev, itemContent]);
         showSnackbar("Item added to favorites!", "success");
       } else {
-        setFavorites(prev => prev.filter(fav => fav !== itemContent));
         showSnackbar("Item removed from favorites", "info");
       }
-      
-      // Reload history to reflect changes
-      loadHistory();
     } catch (error) {
       console.error("Failed to toggle favorite:", error);
       showSnackbar("Failed to update favorite status", "error");
     }
   }
 
-  async function viewFullImage(item: any) {
-    const itemContent = typeof item === 'string' ? item : item.content;
-    
-    if (!itemContent.startsWith("[Image]")) return;
+  async function viewFullImage(item: ClipboardItem) {
+    if (item.payload.kind !== "image") return;
     
     try {
-      // Extract file path and dimensions from the item
-      const parts = itemContent.split('|');
-      if (parts.length >= 3) {
-        const filePath = parts[parts.length - 2];
-        const dimensions = parts[0].replace('[Image] ', '');
-        setSelectedImage({ filePath, dimensions });
-      }
+      const { width, height, blob_path } = item.payload;
+      const src = await invoke<string>("get_image_data_url", { filePath: blob_path });
+      setSelectedImage({ src, dimensions: `${width}x${height}` });
     } catch (error) {
       console.error("Failed to view full image:", error);
       showSnackbar("Failed to view full image", "error");
     }
   }
 
+  function downloadFile(blob: Blob, extension: string, name = "clipboard-history") {
+    const url = URL.createObjectURL(blob);
+    const a = document.createElement('a');
+    a.href = url;
+    a.download = `${name}-${new Date().toISOString().slice(0, 10)}.${extension}`;
+    document.body.appendChild(a);
+    a.click();
+    document.body.removeChild(a);
+    URL.revokeObjectURL(url);
+  }
+
   async function exportHistory() {
     try {
       const jsonData = await invoke<string>("export_history");
-      const blob = new Blob([jsonData], { type: 'application/json' });
-      const url = URL.createObjectURL(blob);
-      const a = document.createElement('a');
-      a.href = url;
-      a.download = `clipboard-history-${new Date().toISOString().slice(0, 10)}.json`;
-      document.body.appendChild(a);
-      a.click();
-      document.body.removeChild(a);
-      URL.revokeObjectURL(url);
+      downloadFile(new Blob([jsonData], { type: 'application/json' }), "json");
       showSnackbar("History exported successfully!", "success");
     } catch (error) {
       console.error("Failed to export history:", error);
@@ -269,41 +736,156 @@ function App() {
     }
   }
 
+  // Zip archive with the images included, for moving to another machine
+  async function exportHistoryArchive() {
+    try {
+      const data = await invoke<ArrayBuffer>("export_history_archive");
+      downloadFile(new Blob([data], { type: 'application/zip' }), "zip");
+      showSnackbar("History archive exported successfully!", "success");
+    } catch (error) {
+      console.error("Failed to export history archive:", error);
+      showSnackbar(`Failed to export history archive: ${error}`, "error");
+    }
+  }
+
   async function importHistory(event: React.ChangeEvent<HTMLInputElement>) {
     const file = event.target.files?.[0];
     if (!file) return;
 
     try {
-      const reader = new FileReader();
-      reader.onload = async (e) => {
-        const jsonData = e.target?.result as string;
-        if (jsonData) {
-          const addedCount = await invoke<number>("import_history", { jsonData });
-          showSnackbar(`Imported ${addedCount} items successfully!`, "success");
-          loadHistory();
-        }
-      };
-      reader.readAsText(file);
+      let addedCount: number;
+      if (file.name.toLowerCase().endsWith(".zip")) {
+        // Archives are sent as raw bytes rather than JSON
+        const data = new Uint8Array(await file.arrayBuffer());
+        addedCount = await invoke<number>("import_history_archive", data);
+      } else {
+        const jsonData = await file.text();
+        addedCount = await invoke<number>("import_history", { jsonData });
+      }
+      showSnackbar(`Imported ${addedCount} items successfully!`, "success", addedCount > 0);
     } catch (error) {
       console.error("Failed to import history:", error);
-      showSnackbar("Failed to import history", "error");
+      showSnackbar(`Failed to import history: ${error}`, "error");
     }
     
     // Reset the file input
     event.target.value = '';
   }
 
-  async function removeTagFromItem(item: string, tag: string) {
+  async function importFromClipboardManager(manager: "copyq" | "gpaste") {
+    try {
+      const addedCount = await invoke<number>("import_from_clipboard_manager", { manager });
+      showSnackbar(`Imported ${addedCount} items successfully!`, "success", addedCount > 0);
+    } catch (error) {
+      console.error("Failed to import from clipboard manager:", error);
+      showSnackbar(`${error}`, "error");
+    }
+  }
+
+  async function removeTagFromItem(item: ClipboardItem, tag: string) {
     try {
-      await invoke("remove_tag_from_item", { itemContent: item, tag });
+      await invoke("remove_tag_from_item", { id: item.id, tag });
       showSnackbar(`Tag "${tag}" removed successfully!`, "success");
-      loadHistory();
     } catch (error) {
       console.error("Failed to remove tag:", error);
       showSnackbar("Failed to remove tag", "error");
     }
   }
 
+  async function createCollection() {
+    try {
+      const itemIds = collectionSeed ? [collectionSeed.id] : [];
+      const collection = await invoke<Collection>("create_collection", { name: collectionName, itemIds });
+      setCollectionName("");
+      setCollectionSeed(null);
+      showSnackbar(`Collection "${collection.name}" created`, "success");
+    } catch (error) {
+      showSnackbar(`Failed to create collection: ${error}`, "error");
+    }
+  }
+
+  async function createCollectionFromTag() {
+    try {
+      const collection = await invoke<Collection>("create_collection_from_tag", { tag: collectionTag });
+      setCollectionTag("");
+      showSnackbar(`Collection "${collection.name}" created with ${collection.item_ids.length} items`, "success");
+    } catch (error) {
+      showSnackbar(`Failed to create collection: ${error}`, "error");
+    }
+  }
+
+  async function renameCollection() {
+    if (!renamingCollection) return;
+    try {
+      await invoke("rename_collection", { id: renamingCollection.id, name: renamingCollection.name });
+      setRenamingCollection(null);
+    } catch (error) {
+      showSnackbar(`Failed to rename collection: ${error}`, "error");
+    }
+  }
+
+  async function deleteCollection(collection: Collection) {
+    try {
+      await invoke("delete_collection", { id: collection.id });
+      if (collectionStats?.collection.id === collection.id) setCollectionStats(null);
+      showSnackbar(`Collection "${collection.name}" deleted; its items stay in the history`, "info");
+    } catch (error) {
+      showSnackbar(`Failed to delete collection: ${error}`, "error");
+    }
+  }
+
+  async function addToCollection(collection: Collection, item: ClipboardItem) {
+    setCollectionMenu(null);
+    try {
+      const added = await invoke<number>("add_to_collection", { id: collection.id, itemIds: [item.id] });
+      showSnackbar(added > 0 ? `Added to "${collection.name}"` : `Already in "${collection.name}"`, added > 0 ? "success" : "info");
+    } catch (error) {
+      showSnackbar(`Failed to add to collection: ${error}`, "error");
+    }
+  }
+
+  async function removeFromCollection(collectionId: string, item: ClipboardItem) {
+    try {
+      await invoke("remove_from_collection", { id: collectionId, itemIds: [item.id] });
+    } catch (error) {
+      showSnackbar(`Failed to remove from collection: ${error}`, "error");
+    }
+  }
+
+  // Swap an item of the filtered collection with its neighbour
+  async function moveCollectionItem(index: number, offset: number) {
+    const ids = filteredHistory.map(item => item.id);
+    const target = index + offset;
+    if (!collectionFilter || target < 0 || target >= ids.length) return;
+    [ids[index], ids[target]] = [ids[target], ids[index]];
+    try {
+      await invoke("reorder_collection", { id: collectionFilter, itemIds: ids });
+    } catch (error) {
+      showSnackbar(`Failed to reorder collection: ${error}`, "error");
+    }
+  }
+
+  async function loadCollectionStatistics(collection: Collection) {
+    try {
+      const stats = await invoke<CollectionStatistics>("get_collection_statistics", { id: collection.id });
+      setCollectionStats({ collection, stats });
+    } catch (error) {
+      showSnackbar(`Failed to load collection statistics: ${error}`, "error");
+    }
+  }
+
+  // Zip archive of one collection and its items, read back by Import History
+  async function exportCollection(collection: Collection) {
+    try {
+      const data = await invoke<ArrayBuffer>("export_collection_archive", { id: collection.id });
+      const name = collection.name.replace(/[^\w-]+/g, "-").toLowerCase();
+      downloadFile(new Blob([data], { type: 'application/zip' }), "zip", `collection-${name}`);
+      showSnackbar(`Collection "${collection.name}" exported`, "success");
+    } catch (error) {
+      showSnackbar(`Failed to export collection: ${error}`, "error");
+    }
+  }
+
   async function loadStatistics() {
     try {
       const stats = await invoke<any>("get_clipboard_statistics");
@@ -316,30 +898,98 @@ function App() {
 
   // Helper function to truncate text and show first few lines
 
-  // Handle search input changes and filters
+  // Handle search input changes and filters. Searches run against the
+  // backend index so they cover the whole history, not just the loaded page.
+  const searching = searchQuery.trim() !== "" || contentTypeFilter !== "" || tagFilter !== "" || collectionFilter !== "" || sinceDate !== "" || untilDate !== "";
+  // A collection's items are shown in its order until a query ranks them
+  const orderingCollection = collectionFilter !== "" && searchQuery.trim() === "";
+  const searchFilters = useMemo(() => ({
+    content_type: contentTypeFilter || null,
+    tag: tagFilter || null,
+    collection: collectionFilter || null,
+    favorite: showFavoritesOnly ? true : null,
+    since: sinceDate ? Math.floor(new Date(`${sinceDate}T00:00:00`).getTime() / 1000) : null,
+    until: untilDate ? Math.floor(new Date(`${untilDate}T23:59:59`).getTime() / 1000) : null,
+  }), [contentTypeFilter, tagFilter, collectionFilter, showFavoritesOnly, sinceDate, untilDate]);
+
+  function searchPage(offset: number) {
+    return invoke<SearchResults>("search_clipboard_history", {
+      query: searchQuery,
+      mode: searchMode,
+      filters: searchFilters,
+      offset,
+      limit: SEARCH_PAGE_SIZE,
+    });
+  }
+
+  function matchesById(hits: SearchHit[]): Record<string, Pick<SearchHit, "matches" | "ocr_matches">> {
+    return Object.fromEntries(hits.map(hit => [hit.id, { matches: hit.matches, ocr_matches: hit.ocr_matches }]));
+  }
+
   useEffect(() => {
-    let results = clipboardHistory;
+    let cancelled = false;
     
-    // Apply favorites filter if enabled
-    if (showFavoritesOnly) {
-      results = results.filter(item => {
-        const itemObj = typeof item === 'string' ? { content: item, is_favorite: false } : item;
-        return itemObj.is_favorite;
-      });
+    async function applyFilters() {
+      if (!searching) {
+        // Apply favorites filter if enabled
+        const results = showFavoritesOnly ? clipboardHistory.filter(item => item.is_favorite) : clipboardHistory;
+        setFilteredHistory(results);
+        setSearchMatches({});
+        setSearchTotal(results.length);
+        setSearchError(null);
+        return;
+      }
+
+      try {
+        const results = await searchPage(0);
+        if (!cancelled) {
+          setFilteredHistory(results.hits);
+          setSearchMatches(matchesById(results.hits));
+          setSearchTotal(results.total);
+          setSearchError(null);
+        }
+      } catch (error) {
+        console.error("Failed to search clipboard history:", error);
+        if (!cancelled) {
+          setFilteredHistory([]);
+          setSearchMatches({});
+          setSearchTotal(0);
+          setSearchError(String(error));
+        }
+      }
     }
     
-    // Apply search filter
-    if (searchQuery.trim()) {
-      results = results.filter(item => {
-        const itemObj = typeof item === 'string' ? { content: item } : item;
-        // Extract the text part (before the timestamp)
-        const textPart = itemObj.content.split('|')[0];
-        return textPart.toLowerCase().includes(searchQuery.toLowerCase());
-      });
+    applyFilters();
+    return () => {
+      cancelled = true;
+    };
+  }, [searchQuery, searchMode, searchFilters, clipboardHistory, collections, showFavoritesOnly]);
+
+  async function loadMoreSearchResults() {
+    try {
+      const results = await searchPage(filteredHistory.length);
+      setFilteredHistory(prev => [...prev, ...results.hits]);
+      setSearchMatches(prev => ({ ...prev, ...matchesById(results.hits) }));
+      setSearchTotal(results.total);
+    } catch (error) {
+      console.error("Failed to load more search results:", error);
+      showSnackbar("Failed to load more results", "error");
     }
-    
-    setFilteredHistory(results);
-  }, [searchQuery, clipboardHistory, showFavoritesOnly]);
+  }
+
+  // Tags offered by the tag filter
+  useEffect(() => {
+    invoke<string[]>("get_all_tags")
+      .then(setAllTags)
+      .catch(error => console.error("Failed to load tags:", error));
+  }, [clipboardHistory]);
+
+  // Collections, whose members change as items are deleted or restored
+  useEffect(() => {
+    invoke<Collection[]>("get_collections")
+      .then(setCollections)
+      .catch(error => console.error("Failed to load collections:", error));
+  }, [clipboardHistory]);
 
   // Load favorites when component mounts
   useEffect(() => {
@@ -390,28 +1040,19 @@ function App() {
       await invoke("clear_clipboard_history");
       setClipboardHistory([]);
       setImageThumbnails({});
-      showSnackbar("Clipboard history cleared!", "success");
+      showSnackbar("Clipboard history moved to trash", "success", true);
     } catch (error) {
       console.error("Failed to clear clipboard history:", error);
       showSnackbar("Failed to clear clipboard history", "error");
     }
   }
 
-  async function copyFromHistory(item: any) {
-    const itemContent = typeof item === 'string' ? item : item.content;
-    
-    if (itemContent.startsWith("[Image]")) {
+  async function copyFromHistory(item: ClipboardItem) {
+    if (item.payload.kind === "image") {
       setLoading(true);
       try {
-        // Extract file path from the image entry (it's the second to last part)
-        const parts = itemContent.split('|');
-        if (parts.length >= 3) {
-          const filePath = parts[parts.length - 2]; // Second to last part is the file path
-          await invoke("copy_image_from_file_to_clipboard", { file_path: filePath });
-          showSnackbar("Image copied from history!", "success");
-        } else {
-          showSnackbar("Invalid image entry format", "error");
-        }
+        await invoke("copy_image_from_file_to_clipboard", { file_path: item.payload.blob_path });
+        showSnackbar("Image copied from history!", "success");
       } catch (error) {
         showSnackbar("Failed to copy image from history: " + error, "error");
       } finally {
@@ -422,9 +1063,9 @@ function App() {
 
     setLoading(true);
     try {
-      // Extract the text part (first part before the timestamp)
-      const textPart = itemContent.split('|')[0];
-      await invoke("write_clipboard_text", { text: textPart });
+      // Restores every stored format (HTML, RTF, file list), not just the text
+      const textPart = itemText(item);
+      await invoke("restore_clipboard_item", { id: item.id });
       setClipboardContent(textPart);
       showSnackbar("Text copied from history!", "success");
     } catch (error) {
@@ -434,38 +1075,641 @@ function App() {
     }
   }
 
-  async function cleanupOldItems() {
+  async function unlockHistory() {
     try {
-      const maxAgeSeconds = autoDeleteDays * 24 * 60 * 60;
-      const removedCount = await invoke<number>("cleanup_old_items", { maxAgeSeconds });
-      showSnackbar(`Removed ${removedCount} old items from history`, "success");
-      loadHistory();
+      await invoke("unlock_history", { passphrase: encryption.keySource === "passphrase" ? passphrase : null });
+      setPassphrase("");
+      showSnackbar("History unlocked", "success");
     } catch (error) {
-      console.error("Failed to cleanup old items:", error);
-      showSnackbar("Failed to cleanup old items", "error");
+      showSnackbar("Failed to unlock history: " + error, "error");
     }
   }
 
-  // Load history when component mounts
+  async function lockHistory() {
+    try {
+      await invoke("lock_history");
+      showSnackbar("History locked", "info");
+    } catch (error) {
+      showSnackbar("Failed to lock history: " + error, "error");
+    }
+  }
+
+  // Without a passphrase the key is generated and kept in the OS keyring
+  async function enableEncryption(usePassphrase: boolean) {
+    if (usePassphrase && !passphrase) {
+      showSnackbar("Please enter a passphrase", "warning");
+      return;
+    }
+    try {
+      await invoke("enable_encryption", { passphrase: usePassphrase ? passphrase : null });
+      setPassphrase("");
+      showSnackbar("History is now encrypted", "success");
+    } catch (error) {
+      showSnackbar("Failed to enable encryption: " + error, "error");
+    }
+  }
+
+  async function disableEncryption() {
+    try {
+      await invoke("disable_encryption");
+      showSnackbar("History is no longer encrypted", "info");
+    } catch (error) {
+      showSnackbar("Failed to disable encryption: " + error, "error");
+    }
+  }
+
+  async function loadSettings() {
+    try {
+      setSettings(await invoke<AppSettings>("get_settings"));
+    } catch (error) {
+      console.error("Failed to load settings:", error);
+    }
+  }
+
+  async function loadOcrStatus() {
+    try {
+      setOcrStatus(await invoke<OcrStatus>("get_ocr_status"));
+    } catch (error) {
+      console.error("Failed to load OCR status:", error);
+    }
+  }
+
+  // Save a change to the OCR settings; the background worker picks it up
+  async function updateOcr(change: Partial<OcrSettings>) {
+    if (!settings) return;
+    const updated = { ...settings, ocr: { ...settings.ocr, ...change } };
+    setSettings(updated);
+    try {
+      await invoke("update_settings", { settings: updated });
+      await loadOcrStatus();
+    } catch (error) {
+      showSnackbar("Failed to save OCR settings: " + error, "error");
+    }
+  }
+
+  // Copy the text recognized in an image, running OCR on it first if needed
+  async function copyRecognizedText(item: ClipboardItem) {
+    try {
+      const text = await invoke<string>("copy_recognized_text", { id: item.id });
+      showSnackbar(`Copied: ${truncateTextPreview(text, 1, 60)}`, "success");
+    } catch (error) {
+      showSnackbar(String(error), "error");
+    }
+  }
+
+  function openQrCode(item: ClipboardItem) {
+    setQrTarget(item);
+    setQrPreview(null);
+    setQrError(null);
+  }
+
+  // Re-render the QR code whenever the error correction level changes
   useEffect(() => {
-    loadHistory();
-    
-    // Listen for clipboard updates
-    const unlisten = listen('clipboard-update', () => {
-      loadHistory();
-    });
-    
-    // Cleanup listener on component unmount
+    if (!qrTarget) return;
+    let cancelled = false;
+    invoke<string>("render_qr_code", { id: qrTarget.id, errorCorrection: qrErrorCorrection })
+      .then(src => {
+        if (cancelled) return;
+        setQrPreview(src);
+        setQrError(null);
+      })
+      .catch(error => {
+        if (cancelled) return;
+        setQrPreview(null);
+        setQrError(String(error));
+      });
     return () => {
-      unlisten.then((u) => u());
+      cancelled = true;
     };
-  }, []);
+  }, [qrTarget, qrErrorCorrection]);
 
-  return (
-    <ThemeProvider theme={theme}>
-      <CssBaseline />
-      <Box sx={{ flexGrow: 1, minHeight: "100vh", bgcolor: "background.default", display: 'flex', flexDirection: 'column' }}>
-        <AppBar position="static" color="primary" sx={{ 
+  async function copyQrCode() {
+    if (!qrTarget) return;
+    try {
+      await invoke("copy_qr_code", { id: qrTarget.id, errorCorrection: qrErrorCorrection });
+      showSnackbar("QR code copied!", "success");
+    } catch (error) {
+      showSnackbar("Failed to copy QR code: " + error, "error");
+    }
+  }
+
+  async function saveQrCode() {
+    if (!qrTarget) return;
+    try {
+      const added = await invoke<boolean>("save_qr_code", { id: qrTarget.id, errorCorrection: qrErrorCorrection });
+      showSnackbar(added ? "QR code saved to history" : "This QR code is already in the history", added ? "success" : "info");
+    } catch (error) {
+      showSnackbar("Failed to save QR code: " + error, "error");
+    }
+  }
+
+  // Look for QR codes and barcodes in an image and list what they say
+  async function scanImageCodes(item: ClipboardItem) {
+    setCodeScan({ item, codes: null, error: null });
+    try {
+      const codes = await invoke<DecodedCode[]>("scan_image_codes", { id: item.id });
+      setCodeScan({ item, codes, error: null });
+    } catch (error) {
+      setCodeScan({ item, codes: null, error: String(error) });
+    }
+  }
+
+  async function addDecodedText(code: DecodedCode) {
+    try {
+      const added = await invoke<boolean>("add_decoded_text", { text: code.text });
+      showSnackbar(added ? `Added: ${truncateTextPreview(code.text, 1, 60)}` : "This text wasn't added to the history", added ? "success" : "info");
+    } catch (error) {
+      showSnackbar("Failed to add decoded text: " + error, "error");
+    }
+  }
+
+  // Save a change to the retention policy; the backend applies it right away
+  async function updateRetention(change: Partial<RetentionPolicy>) {
+    if (!settings) return;
+    const updated = { ...settings, retention: { ...settings.retention, ...change } };
+    setSettings(updated);
+    try {
+      await invoke("update_settings", { settings: updated });
+    } catch (error) {
+      console.error("Failed to save retention policy:", error);
+      showSnackbar("Failed to save retention policy", "error");
+    }
+  }
+
+  async function openItemActions(anchor: HTMLElement, item: ClipboardItem) {
+    try {
+      const actions = await invoke<ItemActionInfo[]>("get_item_actions", { id: item.id });
+      setActionMenu({ anchor, item, actions });
+    } catch (error) {
+      showSnackbar("Failed to load actions: " + error, "error");
+    }
+  }
+
+  // Run a content type action. Actions that produce text have already put it
+  // on the clipboard.
+  async function runItemAction(item: ClipboardItem, action: ItemActionInfo) {
+    setActionMenu(null);
+    try {
+      const text = await invoke<string | null>("run_item_action", { id: item.id, action: action.action });
+      if (text !== null) {
+        showSnackbar(`Copied: ${truncateTextPreview(text, 1, 60)}`, "success");
+      } else if (action.action === "validate_json") {
+        showSnackbar("JSON is valid", "success");
+      }
+    } catch (error) {
+      showSnackbar(String(error), "error");
+    }
+  }
+
+  async function openTransforms(item: ClipboardItem) {
+    setTransformTarget(item);
+    setTransformChain([]);
+    setPresetName("");
+    if (transforms.length === 0) {
+      try {
+        setTransforms(await invoke<TransformInfo[]>("list_transforms"));
+      } catch (error) {
+        console.error("Failed to load transforms:", error);
+      }
+    }
+  }
+
+  function transformDescription(name: string) {
+    return transforms.find(t => t.name === name)?.description ?? name;
+  }
+
+  // Copy the item with the transforms (or a saved preset) applied; the
+  // history keeps the original
+  async function copyTransformed(preset?: string) {
+    if (!transformTarget) return;
+    try {
+      const text = preset
+        ? await invoke<string>("apply_transform_preset", { id: transformTarget.id, preset })
+        : await invoke<string>("transform_clipboard_item", { id: transformTarget.id, transforms: transformChain });
+      setClipboardContent(text);
+      setTransformTarget(null);
+      showSnackbar("Transformed text copied!", "success");
+    } catch (error) {
+      showSnackbar("Failed to transform: " + error, "error");
+    }
+  }
+
+  async function saveTransformPreset() {
+    try {
+      await invoke("save_transform_preset", { name: presetName, transforms: transformChain });
+      await loadSettings();
+      setPresetName("");
+      showSnackbar(`Preset "${presetName.trim()}" saved`, "success");
+    } catch (error) {
+      showSnackbar("Failed to save preset: " + error, "error");
+    }
+  }
+
+  async function deleteTransformPreset(name: string) {
+    try {
+      await invoke("delete_transform_preset", { name });
+      await loadSettings();
+    } catch (error) {
+      showSnackbar("Failed to delete preset: " + error, "error");
+    }
+  }
+
+  function openImageEditor(item: ClipboardItem) {
+    setImageEditTarget(item);
+    setImageEdits([]);
+    setImageEditDraft(null);
+    setImageEditPreview(null);
+    setImageEditError(null);
+  }
+
+  // Re-render the preview whenever the steps change
+  useEffect(() => {
+    if (!imageEditTarget) return;
+    let cancelled = false;
+    invoke<string>("preview_image_edit", { id: imageEditTarget.id, operations: imageEdits })
+      .then(src => {
+        if (cancelled) return;
+        setImageEditPreview(src);
+        setImageEditError(null);
+      })
+      .catch(error => {
+        if (!cancelled) setImageEditError(String(error));
+      });
+    return () => {
+      cancelled = true;
+    };
+  }, [imageEditTarget, imageEdits]);
+
+  function addImageEditStep() {
+    if (!imageEditDraft) return;
+    setImageEdits(prev => [...prev, imageEditDraft]);
+    setImageEditDraft(null);
+  }
+
+  // Save the edited image as a new history item, optionally copying it too
+  async function saveImageEdit(copyToClipboard: boolean) {
+    if (!imageEditTarget) return;
+    try {
+      const item = await invoke<ClipboardItem | null>("save_image_edit", {
+        id: imageEditTarget.id,
+        operations: imageEdits,
+        format: imageEditFormat,
+        quality: imageEditQuality,
+        copyToClipboard,
+      });
+      setImageEditTarget(null);
+      if (item) {
+        showSnackbar(copyToClipboard ? "Edited image saved and copied!" : "Edited image saved", "success");
+      } else {
+        showSnackbar("That image is already in the history", "info");
+      }
+    } catch (error) {
+      showSnackbar("Failed to save edited image: " + error, "error");
+    }
+  }
+
+  // Inputs for the step being added
+  function imageEditFields(draft: ImageOperation) {
+    const update = (changes: Partial<ImageOperation>) => setImageEditDraft({ ...draft, ...changes } as ImageOperation);
+    const numberField = (label: string, value: number | undefined, onChange: (value: number | undefined) => void) => (
+      <TextField
+        key={label}
+        label={label}
+        type="number"
+        size="small"
+        value={value ?? ""}
+        onChange={(e) => onChange(e.target.value === "" ? undefined : Math.max(0, Math.round(Number(e.target.value))))}
+        sx={{ width: 90 }}
+      />
+    );
+    const rectFields = (rect: ImageRect) => (
+      [["X", "x"], ["Y", "y"], ["Width", "width"], ["Height", "height"]] as const
+    ).map(([label, key]) => numberField(label, rect[key], value => update({ rect: { ...rect, [key]: value ?? 0 } })));
+    const strokeFields = (color: string, thickness: number) => [
+      <TextField key="color" label="Color" size="small" value={color} onChange={(e) => update({ color: e.target.value })} sx={{ width: 110 }} />,
+      numberField("Thickness", thickness, value => update({ thickness: value ?? 1 })),
+    ];
+
+    switch (draft.op) {
+      case "crop":
+        return rectFields(draft.rect);
+      case "resize":
+        return [
+          numberField("Width", draft.width, width => update({ width })),
+          numberField("Height", draft.height, height => update({ height })),
+        ];
+      case "rotate":
+        return (
+          <TextField select label="Degrees" size="small" value={draft.degrees} onChange={(e) => update({ degrees: Number(e.target.value) })} sx={{ width: 110 }}>
+            <MenuItem value={90}>90°</MenuItem>
+            <MenuItem value={180}>180°</MenuItem>
+            <MenuItem value={270}>270°</MenuItem>
+          </TextField>
+        );
+      case "flip":
+        return (
+          <TextField select label="Axis" size="small" value={draft.axis} onChange={(e) => update({ axis: e.target.value as "horizontal" | "vertical" })} sx={{ width: 130 }}>
+            <MenuItem value="horizontal">Horizontal</MenuItem>
+            <MenuItem value="vertical">Vertical</MenuItem>
+          </TextField>
+        );
+      case "redact":
+        return [
+          ...rectFields(draft.rect),
+          <TextField key="style" select label="Style" size="small" value={draft.style} onChange={(e) => update({ style: e.target.value as "pixelate" | "blur" })} sx={{ width: 120 }}>
+            <MenuItem value="pixelate">Pixelate</MenuItem>
+            <MenuItem value="blur">Blur</MenuItem>
+          </TextField>,
+          numberField("Strength", draft.strength, strength => update({ strength })),
+        ];
+      case "arrow":
+        return [
+          numberField("From X", draft.from.x, x => update({ from: { ...draft.from, x: x ?? 0 } })),
+          numberField("From Y", draft.from.y, y => update({ from: { ...draft.from, y: y ?? 0 } })),
+          numberField("To X", draft.to.x, x => update({ to: { ...draft.to, x: x ?? 0 } })),
+          numberField("To Y", draft.to.y, y => update({ to: { ...draft.to, y: y ?? 0 } })),
+          ...strokeFields(draft.color, draft.thickness),
+        ];
+      case "box":
+        return [...rectFields(draft.rect), ...strokeFields(draft.color, draft.thickness)];
+    }
+  }
+
+  async function loadSnippets() {
+    try {
+      setSnippets(await invoke<Snippet[]>("list_snippets"));
+    } catch (error) {
+      console.error("Failed to load snippets:", error);
+    }
+  }
+
+  async function saveSnippet() {
+    if (!snippetDraft) return;
+    try {
+      await invoke("save_snippet", { snippet: snippetDraft });
+      setSnippetDraft(null);
+      await loadSnippets();
+    } catch (error) {
+      showSnackbar("Failed to save snippet: " + error, "error");
+    }
+  }
+
+  async function deleteSnippet(snippet: Snippet) {
+    try {
+      await invoke("delete_snippet", { id: snippet.id });
+      await loadSnippets();
+    } catch (error) {
+      showSnackbar("Failed to delete snippet: " + error, "error");
+    }
+  }
+
+  // Ask for the snippet's variables first if it has any
+  async function pickSnippet(snippet: Snippet) {
+    try {
+      const names = await invoke<string[]>("get_snippet_variables", { id: snippet.id });
+      if (names.length > 0) {
+        setSnippetPrompt({ snippet, values: Object.fromEntries(names.map(name => [name, ""])) });
+      } else {
+        await expandSnippet(snippet, {});
+      }
+    } catch (error) {
+      showSnackbar("Failed to expand snippet: " + error, "error");
+    }
+  }
+
+  async function expandSnippet(snippet: Snippet, values: Record<string, string>) {
+    try {
+      const text = await invoke<string>("expand_snippet", { id: snippet.id, values });
+      setClipboardContent(text);
+      setSnippetPrompt(null);
+      showSnackbar(`Snippet "${snippet.name}" copied!`, "success");
+    } catch (error) {
+      showSnackbar("Failed to expand snippet: " + error, "error");
+    }
+  }
+
+  async function exportSnippets() {
+    try {
+      const jsonData = await invoke<string>("export_snippets");
+      const blob = new Blob([jsonData], { type: 'application/json' });
+      const url = URL.createObjectURL(blob);
+      const a = document.createElement('a');
+      a.href = url;
+      a.download = `clipboard-snippets-${new Date().toISOString().slice(0, 10)}.json`;
+      document.body.appendChild(a);
+      a.click();
+      document.body.removeChild(a);
+      URL.revokeObjectURL(url);
+      showSnackbar("Snippets exported successfully!", "success");
+    } catch (error) {
+      console.error("Failed to export snippets:", error);
+      showSnackbar("Failed to export snippets", "error");
+    }
+  }
+
+  async function importSnippets(event: React.ChangeEvent<HTMLInputElement>) {
+    const file = event.target.files?.[0];
+    if (!file) return;
+
+    const reader = new FileReader();
+    reader.onload = async (e) => {
+      const jsonData = e.target?.result as string;
+      if (!jsonData) return;
+      try {
+        const addedCount = await invoke<number>("import_snippets", { jsonData });
+        await loadSnippets();
+        showSnackbar(`Imported ${addedCount} snippets successfully!`, "success");
+      } catch (error) {
+        console.error("Failed to import snippets:", error);
+        showSnackbar("Failed to import snippets", "error");
+      }
+    };
+    reader.readAsText(file);
+
+    // Reset the file input
+    event.target.value = '';
+  }
+
+  // Pause capture for `durationSeconds`, or until resumed when omitted
+  async function pauseCapture(durationSeconds?: number) {
+    setPauseMenuAnchor(null);
+    try {
+      await invoke("pause_capture", { durationSeconds: durationSeconds ?? null });
+    } catch (error) {
+      showSnackbar("Failed to pause capture: " + error, "error");
+    }
+  }
+
+  async function resumeCapture() {
+    try {
+      await invoke("resume_capture");
+    } catch (error) {
+      showSnackbar("Failed to resume capture: " + error, "error");
+    }
+  }
+
+  async function enqueuePasteItem(item: ClipboardItem) {
+    try {
+      await invoke("enqueue_paste_items", { ids: [item.id] });
+      showSnackbar(`Queued: ${truncateTextPreview(queueLabel(item), 1, 60)}`, "success");
+    } catch (error) {
+      showSnackbar("Failed to queue item: " + error, "error");
+    }
+  }
+
+  // Send the queue in a new order; ids left out are removed from it
+  async function reorderPasteQueue(ids: string[]) {
+    try {
+      await invoke("reorder_paste_queue", { ids });
+    } catch (error) {
+      showSnackbar("Failed to reorder the paste queue: " + error, "error");
+    }
+  }
+
+  function movePasteQueueItem(index: number, offset: number) {
+    const ids = pasteQueue.items.map(item => item.id);
+    const target = index + offset;
+    if (target < 0 || target >= ids.length) return;
+    [ids[index], ids[target]] = [ids[target], ids[index]];
+    reorderPasteQueue(ids);
+  }
+
+  async function advancePasteQueue() {
+    try {
+      await invoke("advance_paste_queue");
+    } catch (error) {
+      showSnackbar("Failed to load the next queued item: " + error, "error");
+    }
+  }
+
+  async function clearPasteQueue() {
+    try {
+      await invoke("clear_paste_queue");
+    } catch (error) {
+      showSnackbar("Failed to clear the paste queue: " + error, "error");
+    }
+  }
+
+  async function updateRememberPause(remember_pause: boolean) {
+    if (!settings) return;
+    const updated = { ...settings, remember_pause };
+    setSettings(updated);
+    try {
+      await invoke("update_settings", { settings: updated });
+    } catch (error) {
+      showSnackbar("Failed to save setting: " + error, "error");
+    }
+  }
+
+  async function updateExcludedApps(apps: AppRule[]) {
+    if (!settings) return;
+    const updated = { ...settings, exclude: { ...settings.exclude, apps } };
+    setSettings(updated);
+    try {
+      await invoke("update_settings", { settings: updated });
+    } catch (error) {
+      console.error("Failed to save excluded apps:", error);
+      showSnackbar("Failed to save excluded apps", "error");
+    }
+  }
+
+  function addExcludedApp() {
+    if (!settings) return;
+    const rule: AppRule = {
+      class: excludeDraft.class?.trim() || null,
+      title_contains: excludeDraft.title_contains?.trim() || null,
+    };
+    if (!rule.class && !rule.title_contains) return;
+    updateExcludedApps([...settings.exclude.apps, rule]);
+    setExcludeDraft({ class: "", title_contains: "" });
+  }
+
+  async function applyRetentionPolicy() {
+    try {
+      const evictions = await invoke<Eviction[]>("apply_retention_policy");
+      showSnackbar(`Moved ${evictions.length} items to trash`, "success", evictions.length > 0);
+    } catch (error) {
+      console.error("Failed to apply retention policy:", error);
+      showSnackbar("Failed to apply retention policy", "error");
+    }
+  }
+
+  async function loadTrash() {
+    try {
+      setTrash(await invoke<TrashedItem[]>("list_trash"));
+    } catch (error) {
+      console.error("Failed to load trash:", error);
+      showSnackbar("Failed to load trash", "error");
+    }
+  }
+
+  async function restoreFromTrash(ids: string[]) {
+    try {
+      const restored = await invoke<number>("restore_from_trash", { ids });
+      showSnackbar(`Restored ${restored} items`, "success");
+    } catch (error) {
+      showSnackbar("Failed to restore items: " + error, "error");
+    }
+    loadTrash();
+  }
+
+  async function emptyTrash() {
+    try {
+      const removed = await invoke<number>("empty_trash");
+      showSnackbar(`Permanently deleted ${removed} items`, "info");
+    } catch (error) {
+      showSnackbar("Failed to empty trash: " + error, "error");
+    }
+    loadTrash();
+  }
+
+  // Undo the most recent clear, clean-up or import
+  async function undoLastOperation() {
+    setSnackbarOpen(false);
+    try {
+      const operation = await invoke<{ kind: string; items: number }>("undo_last_operation");
+      showSnackbar(
+        operation.kind === "import" ? `Removed ${operation.items} imported items` : `Restored ${operation.items} items`,
+        "info"
+      );
+    } catch (error) {
+      showSnackbar("Failed to undo: " + error, "error");
+    }
+  }
+
+  // Load history when component mounts
+  useEffect(() => {
+    loadHistory();
+    loadSettings();
+    loadOcrStatus();
+    loadSnippets();
+    invoke<PauseState>("get_pause_state").then(setPauseState).catch(() => {});
+    invoke<QueueStatus>("get_paste_queue").then(setPasteQueue).catch(() => {});
+    
+    // Listen for clipboard updates
+    const unlisten = listen<HistoryChange>('clipboard-update', (event) => {
+      applyHistoryChange(event.payload);
+    });
+    const unlistenPause = listen<PauseState>('pause-state', (event) => {
+      setPauseState(event.payload);
+    });
+    const unlistenQueue = listen<QueueStatus>('paste-queue', (event) => {
+      setPasteQueue(event.payload);
+    });
+    
+    // Cleanup listeners on component unmount
+    return () => {
+      unlisten.then((u) => u());
+      unlistenPause.then((u) => u());
+      unlistenQueue.then((u) => u());
+    };
+  }, []);
+
+  return (
+    <ThemeProvider theme={theme}>
+      <CssBaseline />
+      <Box sx={{ flexGrow: 1, minHeight: "100vh", bgcolor: "background.default", display: 'flex', flexDirection: 'column' }}>
+        <AppBar position="static" color="primary" sx={{ 
           background: (theme) => `linear-gradient(45deg, ${theme.palette.primary.main} 30%, ${theme.palette.secondary.main} 90%)`,
           boxShadow: (theme) => theme.shadows[2],
           height: { xs: 40, sm: 48 },
@@ -474,6 +1718,57 @@ function App() {
             <Typography variant="body1" component="div" sx={{ flexGrow: 1, fontWeight: '500', fontSize: { xs: '0.85rem', sm: '0.95rem' }, color: 'white' }}>
               Clipboard Manager
             </Typography>
+            <Tooltip title={pauseState.paused ? "Resume Capture" : "Pause Capture"}>
+              <IconButton
+                onClick={(e) => pauseState.paused ? resumeCapture() : setPauseMenuAnchor(e.currentTarget)}
+                sx={{ color: 'white' }}
+              >
+                {pauseState.paused
+                  ? <PlayArrowIcon sx={{ fontSize: { xs: '1rem', sm: '1.25rem' } }} />
+                  : <PauseIcon sx={{ fontSize: { xs: '1rem', sm: '1.25rem' } }} />}
+              </IconButton>
+            </Tooltip>
+            <Menu
+              anchorEl={pauseMenuAnchor}
+              open={pauseMenuAnchor !== null}
+              onClose={() => setPauseMenuAnchor(null)}
+            >
+              <MenuItem onClick={() => pauseCapture(5 * 60)}>Pause for 5 minutes</MenuItem>
+              <MenuItem onClick={() => pauseCapture(15 * 60)}>Pause for 15 minutes</MenuItem>
+              <MenuItem onClick={() => pauseCapture(60 * 60)}>Pause for 1 hour</MenuItem>
+              <MenuItem onClick={() => pauseCapture()}>Pause until resumed</MenuItem>
+            </Menu>
+            <Menu
+              anchorEl={actionMenu?.anchor}
+              open={actionMenu !== null}
+              onClose={() => setActionMenu(null)}
+            >
+              {actionMenu?.actions.map((action) => (
+                <MenuItem key={action.action} onClick={() => runItemAction(actionMenu.item, action)}>
+                  {action.label}
+                </MenuItem>
+              ))}
+            </Menu>
+            <Menu
+              anchorEl={collectionMenu?.anchor}
+              open={collectionMenu !== null}
+              onClose={() => setCollectionMenu(null)}
+            >
+              {collections.map((collection) => (
+                <MenuItem key={collection.id} onClick={() => collectionMenu && addToCollection(collection, collectionMenu.item)}>
+                  {collection.name}
+                </MenuItem>
+              ))}
+              <MenuItem
+                onClick={() => {
+                  setCollectionSeed(collectionMenu?.item ?? null);
+                  setCollectionMenu(null);
+                  setShowCollections(true);
+                }}
+              >
+                New Collection…
+              </MenuItem>
+            </Menu>
             <Tooltip title={`Switch to ${darkMode ? 'light' : 'dark'} mode`}>
               <IconButton 
                 onClick={() => setDarkMode(!darkMode)}
@@ -512,6 +1807,19 @@ function App() {
                     Clipboard History
                   </Typography>
                   <Box>
+                    {encryption.enabled && !encryption.locked && (
+                      <Tooltip title="Lock History">
+                        <IconButton onClick={lockHistory} sx={{ 
+                          color: 'white',
+                          mr: 1,
+                          '&:hover': {
+                            backgroundColor: darkMode ? 'rgba(255, 255, 255, 0.1)' : 'rgba(0, 0, 0, 0.1)'
+                          }
+                        }}>
+                          <LockIcon />
+                        </IconButton>
+                      </Tooltip>
+                    )}
                     <Tooltip title="Show Statistics">
                       <IconButton 
                         onClick={() => {
@@ -540,6 +1848,40 @@ function App() {
                         <Refresh />
                       </IconButton>
                     </Tooltip>
+                    <Tooltip title="Collections">
+                      <IconButton
+                        onClick={() => {
+                          setCollectionSeed(null);
+                          setShowCollections(true);
+                        }}
+                        sx={{
+                          color: 'white',
+                          mr: 1,
+                          '&:hover': {
+                            backgroundColor: darkMode ? 'rgba(255, 255, 255, 0.1)' : 'rgba(0, 0, 0, 0.1)'
+                          }
+                        }}
+                      >
+                        <CollectionsBookmarkIcon />
+                      </IconButton>
+                    </Tooltip>
+                    <Tooltip title="Trash">
+                      <IconButton
+                        onClick={() => {
+                          loadTrash();
+                          setShowTrash(true);
+                        }}
+                        sx={{
+                          color: 'white',
+                          mr: 1,
+                          '&:hover': {
+                            backgroundColor: darkMode ? 'rgba(255, 255, 255, 0.1)' : 'rgba(0, 0, 0, 0.1)'
+                          }
+                        }}
+                      >
+                        <RestoreFromTrashIcon />
+                      </IconButton>
+                    </Tooltip>
                     <Tooltip title="Clear History">
                       <IconButton onClick={clearHistory} sx={{ 
                         color: 'white',
@@ -553,7 +1895,102 @@ function App() {
                   </Box>
                 </Toolbar>
               </AppBar>
+
+              {/* Paused Banner */}
+              {pauseState.paused && (
+                <Alert
+                  severity="warning"
+                  sx={{ mb: 2 }}
+                  action={<Button color="inherit" size="small" onClick={resumeCapture}>Resume</Button>}
+                >
+                  Capture paused{pauseState.resume_at
+                    ? ` until ${new Date(pauseState.resume_at * 1000).toLocaleTimeString([], { hour: '2-digit', minute: '2-digit' })}`
+                    : ""}. New clips aren't being recorded.
+                </Alert>
+              )}
               
+              {/* Paste Queue */}
+              {pasteQueue.items.length > 0 && (
+                <Card variant="outlined" sx={{ mb: 2, p: 1.5, textAlign: 'left' }}>
+                  <Box sx={{ display: 'flex', alignItems: 'center', gap: 1 }}>
+                    <PlaylistAddIcon color="primary" />
+                    <Typography variant="subtitle2" sx={{ flexGrow: 1 }}>
+                      Paste Queue ({pasteQueue.items.length})
+                    </Typography>
+                    <Button
+                      size="small"
+                      variant={pasteQueue.loaded && !pasteQueue.detects_pastes ? "contained" : "text"}
+                      startIcon={<SkipNextIcon />}
+                      onClick={advancePasteQueue}
+                    >
+                      {pasteQueue.loaded ? "Next" : "Load"}
+                    </Button>
+                    <Button size="small" color="error" onClick={clearPasteQueue}>
+                      Clear
+                    </Button>
+                  </Box>
+                  <Typography variant="caption" color="text.secondary">
+                    {!pasteQueue.loaded
+                      ? "Something else was copied, so the queue is off the clipboard. Load puts the first item back."
+                      : pasteQueue.detects_pastes
+                        ? "The first item is on the clipboard; each paste loads the next one."
+                        : "The first item is on the clipboard. Pastes can only be noticed on X11, so press Next after each paste to load the next one."}
+                  </Typography>
+                  <List dense disablePadding>
+                    {pasteQueue.items.map((item, index) => (
+                      <ListItem key={item.id} disableGutters sx={{ pr: 12 }}>
+                        <ListItemText
+                          primary={truncateTextPreview(queueLabel(item), 1, 80)}
+                          primaryTypographyProps={{ fontWeight: index === 0 && pasteQueue.loaded ? 'bold' : 'normal', noWrap: true }}
+                        />
+                        <ListItemSecondaryAction>
+                          <IconButton size="small" aria-label="move up" disabled={index === 0} onClick={() => movePasteQueueItem(index, -1)}>
+                            <ArrowUpwardIcon sx={{ fontSize: '1rem' }} />
+                          </IconButton>
+                          <IconButton size="small" aria-label="move down" disabled={index === pasteQueue.items.length - 1} onClick={() => movePasteQueueItem(index, 1)}>
+                            <ArrowDownwardIcon sx={{ fontSize: '1rem' }} />
+                          </IconButton>
+                          <IconButton
+                            size="small"
+                            aria-label="remove from queue"
+                            onClick={() => reorderPasteQueue(pasteQueue.items.filter(other => other.id !== item.id).map(other => other.id))}
+                          >
+                            <CloseIcon sx={{ fontSize: '1rem' }} />
+                          </IconButton>
+                        </ListItemSecondaryAction>
+                      </ListItem>
+                    ))}
+                  </List>
+                </Card>
+              )}
+
+              {/* Unlock Prompt */}
+              {encryption.error && (
+                <Alert severity="error" sx={{ mb: 2 }}>
+                  {encryption.error}. The history stays locked and new clips aren't saved until the file is restored.
+                </Alert>
+              )}
+              {encryption.locked && !encryption.error && (
+                <Box sx={{ display: 'flex', gap: 2, mb: 2, px: 0, alignItems: 'center' }}>
+                  {encryption.keySource === "passphrase" && (
+                    <TextField
+                      fullWidth
+                      type="password"
+                      label="Passphrase"
+                      variant="outlined"
+                      value={passphrase}
+                      onChange={(e) => setPassphrase(e.target.value)}
+                      onKeyDown={(e) => {
+                        if (e.key === 'Enter') unlockHistory();
+                      }}
+                    />
+                  )}
+                  <Button variant="contained" startIcon={<LockOpenIcon />} onClick={unlockHistory}>
+                    Unlock
+                  </Button>
+                </Box>
+              )}
+
               {/* Search Field */}
               <Box sx={{ display: 'flex', gap: 2, mb: 2, px: 0 }}>
                 <TextField
@@ -562,6 +1999,8 @@ function App() {
                   variant="outlined"
                   value={searchQuery}
                   onChange={(e) => setSearchQuery(e.target.value)}
+                  error={searchError !== null}
+                  helperText={searchError ?? undefined}
                   InputProps={{
                     startAdornment: (
                       <InputAdornment position="start">
@@ -577,6 +2016,31 @@ function App() {
                   }}
                   title="Search clipboard history (Ctrl+Shift+F)"
                 />
+                <TextField
+                  select
+                  label="Match"
+                  size="small"
+                  value={searchMode}
+                  onChange={(e) => setSearchMode(e.target.value as SearchMode)}
+                  sx={{ minWidth: 110 }}
+                >
+                  <MenuItem value="substring">Text</MenuItem>
+                  <MenuItem value="fuzzy">Fuzzy</MenuItem>
+                  <MenuItem value="regex">Regex</MenuItem>
+                </TextField>
+                <TextField
+                  select
+                  label="Type"
+                  size="small"
+                  value={contentTypeFilter}
+                  onChange={(e) => setContentTypeFilter(e.target.value)}
+                  sx={{ minWidth: 110 }}
+                >
+                  <MenuItem value="">Any</MenuItem>
+                  {Object.entries(CONTENT_TYPE_LABELS).map(([type, label]) => (
+                    <MenuItem key={type} value={type}>{label}</MenuItem>
+                  ))}
+                </TextField>
                 <FormControlLabel
                   control={
                     <Switch
@@ -588,13 +2052,64 @@ function App() {
                   label="Favorites Only"
                 />
               </Box>
+              <Box sx={{ display: 'flex', gap: 2, mb: 2, px: 0, alignItems: 'center' }}>
+                <TextField
+                  select
+                  label="Tag"
+                  size="small"
+                  value={tagFilter}
+                  onChange={(e) => setTagFilter(e.target.value)}
+                  sx={{ minWidth: 110 }}
+                >
+                  <MenuItem value="">Any</MenuItem>
+                  {allTags.map(tag => (
+                    <MenuItem key={tag} value={tag}>{tag}</MenuItem>
+                  ))}
+                </TextField>
+                <TextField
+                  select
+                  label="Collection"
+                  size="small"
+                  value={collectionFilter}
+                  onChange={(e) => setCollectionFilter(e.target.value)}
+                  sx={{ minWidth: 130 }}
+                >
+                  <MenuItem value="">Any</MenuItem>
+                  {collections.map(collection => (
+                    <MenuItem key={collection.id} value={collection.id}>
+                      {collection.name} ({collection.item_ids.length})
+                    </MenuItem>
+                  ))}
+                </TextField>
+                <TextField
+                  type="date"
+                  label="From"
+                  size="small"
+                  value={sinceDate}
+                  onChange={(e) => setSinceDate(e.target.value)}
+                  InputLabelProps={{ shrink: true }}
+                />
+                <TextField
+                  type="date"
+                  label="To"
+                  size="small"
+                  value={untilDate}
+                  onChange={(e) => setUntilDate(e.target.value)}
+                  InputLabelProps={{ shrink: true }}
+                />
+                {searching && !searchError && (
+                  <Typography variant="body2" color="text.secondary">
+                    {searchTotal} {searchTotal === 1 ? "match" : "matches"}
+                  </Typography>
+                )}
+              </Box>
               
               {filteredHistory.length > 0 ? (
                 <List sx={{ maxHeight: 400, overflow: "auto", px: 0, py: 0, mx: 0 }}>
                   <AnimatePresence>
                     {filteredHistory.map((item, index) => (
                       <motion.div
-                        key={index}
+                        key={item.id}
                         initial={{ opacity: 0, x: -20 }}
                         animate={{ opacity: 1, x: 0 }}
                         exit={{ opacity: 0, x: 20 }}
@@ -623,14 +2138,14 @@ function App() {
                               height: { xs: 40, sm: 48, md: 56 },
                               background: 'linear-gradient(45deg, #2196F3 30%, #21CBF3 90%)',
                               boxShadow: '0 1px 3px rgba(0,0,0,0.1)',
-                              cursor: (typeof item === 'string' ? item : item.content).startsWith("[Image]") ? 'pointer' : 'default',
+                              cursor: item.payload.kind === "image" ? 'pointer' : 'default',
                             }}
-                            onClick={() => (typeof item === 'string' ? item : item.content).startsWith("[Image]") && viewFullImage(item)}
+                            onClick={() => item.payload.kind === "image" && viewFullImage(item)}
                           >
-                            {(typeof item === 'string' ? item : item.content).startsWith("[Image]") ? (
-                              imageThumbnails[typeof item === 'string' ? item : item.content] ? (
+                            {item.payload.kind === "image" ? (
+                              imageThumbnails[item.id] ? (
                                 <img 
-                                  src={imageThumbnails[typeof item === 'string' ? item : item.content]} 
+                                  src={imageThumbnails[item.id]} 
                                   alt="Thumbnail" 
                                   style={{ width: '100%', height: '100%', objectFit: 'cover' }} 
                                 />
@@ -644,13 +2159,13 @@ function App() {
                           <ListItemText
                             primary={
                               <Box sx={{ display: "flex", alignItems: "flex-start", gap: 1, width: '100%' }}>
-                                {(typeof item === 'string' ? item : item.content).startsWith("[Image]") ? (
+                                {item.payload.kind === "image" ? (
                                   <>
                                     <Typography component="span" sx={{ fontWeight: 'medium', fontSize: '1.1rem' }}>
                                       🖼️ Image
                                     </Typography>
                                     <Chip 
-                                      label={(typeof item === 'string' ? item : item.content).split('|')[0].replace('[Image] ', '')} 
+                                      label={item.payload.kind === "image" ? `${item.payload.width}x${item.payload.height}` : ""} 
                                       size="small" 
                                       sx={{ 
                                         background: 'linear-gradient(45deg, #4CAF50 30%, #8BC34A 90%)',
@@ -659,6 +2174,22 @@ function App() {
                                         fontSize: '0.7rem'
                                       }} 
                                     />
+                                    {item.ocr_text && (
+                                      <Typography
+                                        component="div"
+                                        sx={{
+                                          overflow: "hidden",
+                                          whiteSpace: "pre-wrap",
+                                          flex: 1,
+                                          color: 'text.secondary',
+                                          fontSize: { xs: '0.7rem', sm: '0.75rem' },
+                                          fontFamily: 'monospace',
+                                          lineHeight: 1.3,
+                                        }}
+                                      >
+                                        {highlightedPreview(item.ocr_text, searchMatches[item.id]?.ocr_matches, 2)}
+                                      </Typography>
+                                    )}
                                   </>
                                 ) : (
                                   <>
@@ -675,7 +2206,7 @@ function App() {
                                         lineHeight: 1.3,
                                       }}
                                     >
-                                      {truncateTextPreview((typeof item === 'string' ? item : item.content).split('|')[0])}
+                                      {highlightedPreview(itemText(item), searchMatches[item.id]?.matches)}
                                     </Typography>
                                   </>
                                 )}
@@ -685,10 +2216,10 @@ function App() {
                               <Box sx={{ display: "flex", flexDirection: "column", gap: 1 }}>
                                 <Box sx={{ display: "flex", alignItems: "center", gap: 1 }}>
                                   <Chip 
-                                    label={(typeof item === 'string' ? item : item.content).startsWith("[Image]") ? "IMAGE" : "TEXT"} 
+                                    label={{ image: "IMAGE", rich_text: "RICH TEXT", file_list: "FILES", text: "TEXT" }[item.payload.kind]} 
                                     size="small" 
                                     sx={{ 
-                                      background: (typeof item === 'string' ? item : item.content).startsWith("[Image]") ? 
+                                      background: item.payload.kind === "image" ? 
                                         'linear-gradient(45deg, #FF9800 30%, #FFC107 90%)' : 
                                         'linear-gradient(45deg, #2196F3 30%, #21CBF3 90%)',
                                       color: 'white',
@@ -704,29 +2235,31 @@ function App() {
                                     •
                                   </Typography>
                                   <Typography component="span" variant="caption" sx={{ color: 'text.disabled', fontSize: '0.7rem' }}>
-                                    {(() => {
-                                      // Parse timestamp from the item
-                                      const itemContent = typeof item === 'string' ? item : item.content;
-                                      const parts = itemContent.split('|');
-                                      const timestamp = parts[parts.length - 1];
-                                      if (timestamp && !isNaN(parseInt(timestamp))) {
-                                        const date = new Date(parseInt(timestamp) * 1000);
-                                        return date.toLocaleTimeString([], {hour: '2-digit', minute:'2-digit'});
-                                      }
-                                      return new Date().toLocaleTimeString([], {hour: '2-digit', minute:'2-digit'});
-                                    })()}
+                                    {new Date(item.timestamp * 1000).toLocaleTimeString([], {hour: '2-digit', minute:'2-digit'})}
                                   </Typography>
+                                  {item.source && (
+                                    <Tooltip title={item.source.title}>
+                                      <Typography component="span" variant="caption" sx={{ color: 'text.disabled', fontSize: '0.7rem' }}>
+                                        • {item.source.class}
+                                      </Typography>
+                                    </Tooltip>
+                                  )}
+                                  {item.content_type && (
+                                    <Typography component="span" variant="caption" sx={{ color: 'text.disabled', fontSize: '0.7rem' }}>
+                                      • {contentTypeLabel(item.content_type)}
+                                    </Typography>
+                                  )}
                                 </Box>
                                 
                                 {/* Tags Display */}
-                                {typeof item !== 'string' && item.tags && item.tags.length > 0 && (
+                                {item.tags.length > 0 && (
                                   <Box sx={{ display: "flex", gap: 0.5, flexWrap: "wrap" }}>
                                     {item.tags.map((tag: string, tagIndex: number) => (
                                       <Chip
                                         key={tagIndex}
                                         label={tag}
                                         size="small"
-                                        onDelete={() => removeTagFromItem(typeof item === 'string' ? item : item.content, tag)}
+                                        onDelete={() => removeTagFromItem(item, tag)}
                                         sx={{
                                           background: 'linear-gradient(45deg, #9C27B0 30%, #E91E63 90%)',
                                           color: 'white',
@@ -749,21 +2282,73 @@ function App() {
                             }
                           />
                           <ListItemSecondaryAction>
-                            <Tooltip title={(typeof item === 'string' ? item : item.content).startsWith("[Image]") ? 
-                              (favorites.includes(typeof item === 'string' ? item : item.content) ? "Remove from Favorites" : "Add to Favorites") :
-                              (favorites.includes(typeof item === 'string' ? item : item.content) ? "Remove from Favorites" : "Add to Favorites")}>
+                            {orderingCollection && (
+                              <>
+                                <Tooltip title="Move Up">
+                                  <span>
+                                    <IconButton
+                                      edge="end"
+                                      aria-label="move up"
+                                      disabled={index === 0}
+                                      onClick={() => moveCollectionItem(index, -1)}
+                                      sx={{
+                                        ml: 0.5,
+                                        width: { xs: 28, sm: 32 },
+                                        height: { xs: 28, sm: 32 },
+                                      }}
+                                    >
+                                      <ArrowUpwardIcon sx={{ fontSize: '1rem' }} />
+                                    </IconButton>
+                                  </span>
+                                </Tooltip>
+                                <Tooltip title="Move Down">
+                                  <span>
+                                    <IconButton
+                                      edge="end"
+                                      aria-label="move down"
+                                      disabled={index === filteredHistory.length - 1}
+                                      onClick={() => moveCollectionItem(index, 1)}
+                                      sx={{
+                                        ml: 0.5,
+                                        width: { xs: 28, sm: 32 },
+                                        height: { xs: 28, sm: 32 },
+                                      }}
+                                    >
+                                      <ArrowDownwardIcon sx={{ fontSize: '1rem' }} />
+                                    </IconButton>
+                                  </span>
+                                </Tooltip>
+                                <Tooltip title="Remove from Collection">
+                                  <IconButton
+                                    edge="end"
+                                    aria-label="remove from collection"
+                                    onClick={() => removeFromCollection(collectionFilter, item)}
+                                    sx={{
+                                      ml: 0.5,
+                                      width: { xs: 28, sm: 32 },
+                                      height: { xs: 28, sm: 32 },
+                                    }}
+                                  >
+                                    <RemoveCircleOutlineIcon sx={{ fontSize: '1rem' }} />
+                                  </IconButton>
+                                </Tooltip>
+                              </>
+                            )}
+                            <Tooltip title={item.payload.kind === "image" ? 
+                              (favorites.includes(item.id) ? "Remove from Favorites" : "Add to Favorites") :
+                              (favorites.includes(item.id) ? "Remove from Favorites" : "Add to Favorites")}>
                               <IconButton
                                 edge="end"
                                 aria-label="favorite"
                                 onClick={() => toggleFavorite(item)}
                                 sx={{ 
                                   ml: 0.5,
-                                  background: favorites.includes(typeof item === 'string' ? item : item.content) ? 
+                                  background: favorites.includes(item.id) ? 
                                     'linear-gradient(45deg, #FFD700 30%, #FFA500 90%)' : 
                                     'linear-gradient(45deg, #e0e0e0 30%, #bdbdbd 90%)',
-                                  color: favorites.includes(typeof item === 'string' ? item : item.content) ? 'white' : 'text.secondary',
+                                  color: favorites.includes(item.id) ? 'white' : 'text.secondary',
                                   '&:hover': {
-                                    background: favorites.includes(typeof item === 'string' ? item : item.content) ? 
+                                    background: favorites.includes(item.id) ? 
                                       (darkMode ? 'linear-gradient(45deg, #FFC107 30%, #FF9800 90%)' : 'linear-gradient(45deg, #FFC107 30%, #FF9800 90%)') : 
                                       (darkMode ? 'linear-gradient(45deg, #757575 30%, #616161 90%)' : 'linear-gradient(45deg, #bdbdbd 30%, #9e9e9e 90%)'),
                                   },
@@ -774,9 +2359,133 @@ function App() {
                                   }
                                 }}
                               >
-                                {favorites.includes(typeof item === 'string' ? item : item.content) ? <StarIcon sx={{ fontSize: '1rem' }} /> : <StarBorderIcon sx={{ fontSize: '1rem' }} />}
+                                {favorites.includes(item.id) ? <StarIcon sx={{ fontSize: '1rem' }} /> : <StarBorderIcon sx={{ fontSize: '1rem' }} />}
                               </IconButton>
                             </Tooltip>
+                            <Tooltip title="Add to Collection">
+                              <IconButton
+                                edge="end"
+                                aria-label="add to collection"
+                                onClick={(e) => setCollectionMenu({ anchor: e.currentTarget, item })}
+                                sx={{
+                                  ml: 0.5,
+                                  width: { xs: 28, sm: 32 },
+                                  height: { xs: 28, sm: 32 },
+                                }}
+                              >
+                                <LibraryAddIcon sx={{ fontSize: '1rem' }} />
+                              </IconButton>
+                            </Tooltip>
+                            {item.content_type && (
+                              <Tooltip title="Actions">
+                                <IconButton
+                                  edge="end"
+                                  aria-label="actions"
+                                  onClick={(e) => openItemActions(e.currentTarget, item)}
+                                  sx={{
+                                    ml: 0.5,
+                                    width: { xs: 28, sm: 32 },
+                                    height: { xs: 28, sm: 32 },
+                                  }}
+                                >
+                                  <BoltIcon sx={{ fontSize: '1rem' }} />
+                                </IconButton>
+                              </Tooltip>
+                            )}
+                            {item.payload.kind !== "image" && (
+                              <Tooltip title="Transform and Copy">
+                                <IconButton
+                                  edge="end"
+                                  aria-label="transform"
+                                  onClick={() => openTransforms(item)}
+                                  sx={{
+                                    ml: 0.5,
+                                    width: { xs: 28, sm: 32 },
+                                    height: { xs: 28, sm: 32 },
+                                  }}
+                                >
+                                  <AutoFixHighIcon sx={{ fontSize: '1rem' }} />
+                                </IconButton>
+                              </Tooltip>
+                            )}
+                            {item.payload.kind === "image" && (
+                              <Tooltip title="Edit Image">
+                                <IconButton
+                                  edge="end"
+                                  aria-label="edit image"
+                                  onClick={() => openImageEditor(item)}
+                                  sx={{
+                                    ml: 0.5,
+                                    width: { xs: 28, sm: 32 },
+                                    height: { xs: 28, sm: 32 },
+                                  }}
+                                >
+                                  <EditIcon sx={{ fontSize: '1rem' }} />
+                                </IconButton>
+                              </Tooltip>
+                            )}
+                            <Tooltip title="Add to Paste Queue">
+                              <IconButton
+                                edge="end"
+                                aria-label="add to paste queue"
+                                onClick={() => enqueuePasteItem(item)}
+                                sx={{
+                                  ml: 0.5,
+                                  width: { xs: 28, sm: 32 },
+                                  height: { xs: 28, sm: 32 },
+                                }}
+                              >
+                                <PlaylistAddIcon sx={{ fontSize: '1rem' }} />
+                              </IconButton>
+                            </Tooltip>
+                            {(item.payload.kind === "text" || item.payload.kind === "rich_text") && (
+                              <Tooltip title="Show as QR Code">
+                                <IconButton
+                                  edge="end"
+                                  aria-label="show as qr code"
+                                  onClick={() => openQrCode(item)}
+                                  sx={{
+                                    ml: 0.5,
+                                    width: { xs: 28, sm: 32 },
+                                    height: { xs: 28, sm: 32 },
+                                  }}
+                                >
+                                  <QrCodeIcon sx={{ fontSize: '1rem' }} />
+                                </IconButton>
+                              </Tooltip>
+                            )}
+                            {item.payload.kind === "image" && (
+                              <Tooltip title="Scan QR Codes and Barcodes">
+                                <IconButton
+                                  edge="end"
+                                  aria-label="scan codes"
+                                  onClick={() => scanImageCodes(item)}
+                                  sx={{
+                                    ml: 0.5,
+                                    width: { xs: 28, sm: 32 },
+                                    height: { xs: 28, sm: 32 },
+                                  }}
+                                >
+                                  <QrCodeScannerIcon sx={{ fontSize: '1rem' }} />
+                                </IconButton>
+                              </Tooltip>
+                            )}
+                            {item.payload.kind === "image" && item.ocr_text !== "" && (
+                              <Tooltip title="Copy Recognized Text">
+                                <IconButton
+                                  edge="end"
+                                  aria-label="copy recognized text"
+                                  onClick={() => copyRecognizedText(item)}
+                                  sx={{
+                                    ml: 0.5,
+                                    width: { xs: 28, sm: 32 },
+                                    height: { xs: 28, sm: 32 },
+                                  }}
+                                >
+                                  <DocumentScannerIcon sx={{ fontSize: '1rem' }} />
+                                </IconButton>
+                              </Tooltip>
+                            )}
                             <Tooltip title="Copy to Clipboard">
                               <IconButton
                                 edge="end"
@@ -813,15 +2522,22 @@ function App() {
                       </motion.div>
                     ))}
                   </AnimatePresence>
+                  {searching && filteredHistory.length < searchTotal && (
+                    <Box sx={{ display: 'flex', justifyContent: 'center', py: 1 }}>
+                      <Button size="small" onClick={loadMoreSearchResults}>
+                        Load more ({searchTotal - filteredHistory.length} left)
+                      </Button>
+                    </Box>
+                  )}
                 </List>
               ) : (
                 <Box sx={{ textAlign: "center", py: 6 }}>
                   <ImageIcon sx={{ fontSize: 64, color: "primary.main", mb: 2 }} />
                   <Typography variant="h6" color="text.secondary" gutterBottom sx={{ fontWeight: 'medium' }}>
-                    {searchQuery ? "No matching items found" : "No clipboard history yet"}
+                    {searching ? "No matching items found" : "No clipboard history yet"}
                   </Typography>
                   <Typography variant="body2" color="text.secondary">
-                    {searchQuery ? "Try a different search term" : "Copy some text or images to see them appear here"}
+                    {searching ? "Try a different search term or filter" : "Copy some text or images to see them appear here"}
                   </Typography>
                 </Box>
               )}
@@ -930,6 +2646,98 @@ function App() {
           borderRadius: 0
         }}
       >
+        <Accordion sx={{ mb: 1 }}>
+          <AccordionSummary
+            expandIcon={<ExpandMoreIcon />}
+            aria-controls="snippets-content"
+            id="snippets-header"
+          >
+            <Box sx={{ display: 'flex', alignItems: 'center', gap: 1 }}>
+              <TextSnippetIcon />
+              <Typography>Snippets</Typography>
+            </Box>
+          </AccordionSummary>
+          <AccordionDetails>
+            <Box sx={{ display: 'flex', flexDirection: 'column', gap: 1, textAlign: 'left' }}>
+              {snippets.length > 0 ? (
+                <List dense>
+                  {snippets.map(snippet => (
+                    <ListItem key={snippet.id}>
+                      <ListItemText
+                        primary={snippet.name}
+                        secondary={snippet.body.length > 80 ? snippet.body.slice(0, 80) + "…" : snippet.body}
+                      />
+                      <ListItemSecondaryAction>
+                        <Tooltip title="Expand and Copy">
+                          <IconButton size="small" onClick={() => pickSnippet(snippet)}>
+                            <ContentCopy sx={{ fontSize: '1rem' }} />
+                          </IconButton>
+                        </Tooltip>
+                        <Tooltip title="Edit">
+                          <IconButton size="small" onClick={() => setSnippetDraft({ ...snippet })}>
+                            <EditIcon sx={{ fontSize: '1rem' }} />
+                          </IconButton>
+                        </Tooltip>
+                        <Tooltip title="Delete">
+                          <IconButton size="small" onClick={() => deleteSnippet(snippet)}>
+                            <Delete sx={{ fontSize: '1rem' }} />
+                          </IconButton>
+                        </Tooltip>
+                      </ListItemSecondaryAction>
+                    </ListItem>
+                  ))}
+                </List>
+              ) : (
+                <Typography variant="body2" color="text.secondary">No snippets yet</Typography>
+              )}
+
+              {snippetDraft ? (
+                <Box sx={{ display: 'flex', flexDirection: 'column', gap: 1 }}>
+                  <TextField
+                    label="Name"
+                    size="small"
+                    value={snippetDraft.name}
+                    onChange={(e) => setSnippetDraft({ ...snippetDraft, name: e.target.value })}
+                  />
+                  <TextField
+                    label="Text"
+                    size="small"
+                    multiline
+                    minRows={3}
+                    value={snippetDraft.body}
+                    onChange={(e) => setSnippetDraft({ ...snippetDraft, body: e.target.value })}
+                    helperText="Placeholders: {date}, {date:%H:%M}, {uuid}, {clipboard}, {input:Name}"
+                  />
+                  <Box sx={{ display: 'flex', gap: 1 }}>
+                    <Button variant="contained" onClick={saveSnippet} disabled={!snippetDraft.name.trim()}>
+                      Save
+                    </Button>
+                    <Button onClick={() => setSnippetDraft(null)}>Cancel</Button>
+                  </Box>
+                </Box>
+              ) : (
+                <Box sx={{ display: 'flex', gap: 2 }}>
+                  <Button variant="outlined" onClick={() => setSnippetDraft({ name: "", body: "" })}>
+                    New Snippet
+                  </Button>
+                  <Button variant="outlined" onClick={exportSnippets}>
+                    Export Snippets
+                  </Button>
+                  <Button variant="outlined" component="label">
+                    Import Snippets
+                    <input
+                      type="file"
+                      hidden
+                      accept=".json"
+                      onChange={importSnippets}
+                    />
+                  </Button>
+                </Box>
+              )}
+            </Box>
+          </AccordionDetails>
+        </Accordion>
+
         <Accordion sx={{ mb: 1 }}>
           <AccordionSummary
             expandIcon={<ExpandMoreIcon />}
@@ -943,21 +2751,61 @@ function App() {
           </AccordionSummary>
           <AccordionDetails>
             <Box sx={{ display: 'flex', flexDirection: 'column', gap: 2 }}>
-              <Box sx={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center' }}>
-                <Typography>Auto-delete old items</Typography>
-                <Switch
-                  checked={autoDeleteEnabled}
-                  onChange={(e) => setAutoDeleteEnabled(e.target.checked)}
-                  color="primary"
-                />
-              </Box>
-              
-              {autoDeleteEnabled && (
+              {/* Retention Section */}
+              {settings && (
                 <Box sx={{ display: 'flex', flexDirection: 'column', gap: 1 }}>
-                  <Typography>Auto-delete after: {autoDeleteDays} days</Typography>
+                  <Typography variant="h6">Retention</Typography>
+                  <Box sx={{ display: 'flex', gap: 2 }}>
+                    <TextField
+                      label="Max items"
+                      type="number"
+                      size="small"
+                      placeholder="No limit"
+                      value={settings.retention.max_items ?? ""}
+                      onChange={(e) => updateRetention({ max_items: e.target.value ? Math.max(1, Number(e.target.value)) : null })}
+                    />
+                    <TextField
+                      label="Max size (MB)"
+                      type="number"
+                      size="small"
+                      placeholder="No limit"
+                      value={settings.retention.max_total_bytes != null ? Math.round(settings.retention.max_total_bytes / MEGABYTE) : ""}
+                      onChange={(e) => updateRetention({ max_total_bytes: e.target.value ? Math.max(1, Number(e.target.value)) * MEGABYTE : null })}
+                    />
+                  </Box>
+                  <Box sx={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center' }}>
+                    <Typography>Auto-delete old items</Typography>
+                    <Switch
+                      checked={settings.retention.max_age_seconds != null}
+                      onChange={(e) => updateRetention({ max_age_seconds: e.target.checked ? 7 * DAY_SECONDS : null })}
+                      color="primary"
+                    />
+                  </Box>
+                  {settings.retention.max_age_seconds != null && (
+                    <>
+                      <Typography>Auto-delete after: {Math.round(settings.retention.max_age_seconds / DAY_SECONDS)} days</Typography>
+                      <Slider
+                        value={Math.round(settings.retention.max_age_seconds / DAY_SECONDS)}
+                        onChange={(_, value) => updateRetention({ max_age_seconds: (value as number) * DAY_SECONDS })}
+                        min={1}
+                        max={30}
+                        step={1}
+                        valueLabelDisplay="auto"
+                      />
+                    </>
+                  )}
+                  <Box sx={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center' }}>
+                    <Typography>Never delete favorites</Typography>
+                    <Switch
+                      checked={settings.retention.exempt_favorites}
+                      onChange={(e) => updateRetention({ exempt_favorites: e.target.checked })}
+                      color="primary"
+                    />
+                  </Box>
+                  <Typography>Keep deleted items for: {Math.round(settings.retention.trash_retention_seconds / DAY_SECONDS)} days</Typography>
                   <Slider
-                    value={autoDeleteDays}
-                    onChange={(_, value) => setAutoDeleteDays(value as number)}
+                    value={Math.round(settings.retention.trash_retention_seconds / DAY_SECONDS)}
+                    onChange={(_, value) => updateRetention({ trash_retention_seconds: (value as number) * DAY_SECONDS })}
                     min={1}
                     max={30}
                     step={1}
@@ -965,7 +2813,7 @@ function App() {
                   />
                   <Button
                     variant="outlined"
-                    onClick={cleanupOldItems}
+                    onClick={applyRetentionPolicy}
                     sx={{ alignSelf: 'flex-start' }}
                   >
                     Clean Up Now
@@ -973,6 +2821,121 @@ function App() {
                 </Box>
               )}
               
+              {settings && (
+                <Box sx={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center' }}>
+                  <Typography>Stay paused after restart</Typography>
+                  <Switch
+                    checked={settings.remember_pause}
+                    onChange={(e) => updateRememberPause(e.target.checked)}
+                    color="primary"
+                  />
+                </Box>
+              )}
+
+              {/* Text Recognition Section */}
+              {settings && (
+                <Box sx={{ display: 'flex', flexDirection: 'column', gap: 1, pt: 2 }}>
+                  <Typography variant="h6">Text Recognition</Typography>
+                  <Box sx={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center' }}>
+                    <Typography>Recognize text in images</Typography>
+                    <Switch
+                      checked={settings.ocr.enabled}
+                      onChange={(e) => updateOcr({ enabled: e.target.checked })}
+                      color="primary"
+                    />
+                  </Box>
+                  <TextField
+                    label="Languages"
+                    size="small"
+                    value={settings.ocr.languages}
+                    onChange={(e) => updateOcr({ languages: e.target.value })}
+                    helperText="Tesseract language codes joined with +, like eng+deu"
+                  />
+                  {ocrStatus && (
+                    <Typography variant="body2" color={ocrStatus.error ? "error" : "text.secondary"}>
+                      {ocrStatus.error
+                        ?? `${ocrStatus.version}${ocrStatus.pending > 0 ? ` • ${ocrStatus.pending} images waiting` : ""}`}
+                    </Typography>
+                  )}
+                </Box>
+              )}
+
+              {/* Excluded Apps Section */}
+              {settings && (
+                <Box sx={{ display: 'flex', flexDirection: 'column', gap: 1, pt: 2 }}>
+                  <Typography variant="h6">Excluded Apps</Typography>
+                  <Typography variant="body2" color="text.secondary">
+                    Clips copied from these windows are never recorded (X11 only).
+                  </Typography>
+                  <Box sx={{ display: 'flex', flexWrap: 'wrap', gap: 1 }}>
+                    {settings.exclude.apps.map((rule, index) => (
+                      <Chip
+                        key={index}
+                        label={[rule.class, rule.title_contains && `title contains "${rule.title_contains}"`].filter(Boolean).join(", ")}
+                        size="small"
+                        onDelete={() => updateExcludedApps(settings.exclude.apps.filter((_, i) => i !== index))}
+                      />
+                    ))}
+                  </Box>
+                  <Box sx={{ display: 'flex', gap: 1 }}>
+                    <TextField
+                      label="Window class"
+                      size="small"
+                      value={excludeDraft.class ?? ""}
+                      onChange={(e) => setExcludeDraft({ ...excludeDraft, class: e.target.value })}
+                    />
+                    <TextField
+                      label="Title contains"
+                      size="small"
+                      value={excludeDraft.title_contains ?? ""}
+                      onChange={(e) => setExcludeDraft({ ...excludeDraft, title_contains: e.target.value })}
+                    />
+                    <Button
+                      variant="outlined"
+                      onClick={addExcludedApp}
+                      disabled={!excludeDraft.class?.trim() && !excludeDraft.title_contains?.trim()}
+                    >
+                      Add
+                    </Button>
+                  </Box>
+                </Box>
+              )}
+
+              {/* Encryption Section */}
+              <Box sx={{ display: 'flex', flexDirection: 'column', gap: 1, pt: 2 }}>
+                <Typography variant="h6">Encryption</Typography>
+                {encryption.enabled ? (
+                  <Box sx={{ display: 'flex', gap: 2, alignItems: 'center' }}>
+                    <Typography variant="body2" color="text.secondary">
+                      History is encrypted ({encryption.keySource === "keyring" ? "key in OS keyring" : "passphrase"})
+                    </Typography>
+                    <Button
+                      variant="outlined"
+                      onClick={disableEncryption}
+                      disabled={encryption.locked}
+                    >
+                      Disable Encryption
+                    </Button>
+                  </Box>
+                ) : (
+                  <Box sx={{ display: 'flex', gap: 2, alignItems: 'center' }}>
+                    <TextField
+                      type="password"
+                      label="Passphrase"
+                      size="small"
+                      value={passphrase}
+                      onChange={(e) => setPassphrase(e.target.value)}
+                    />
+                    <Button variant="outlined" onClick={() => enableEncryption(true)}>
+                      Encrypt with Passphrase
+                    </Button>
+                    <Button variant="outlined" onClick={() => enableEncryption(false)}>
+                      Use OS Keyring
+                    </Button>
+                  </Box>
+                )}
+              </Box>
+
               {/* Export/Import Section */}
               <Box sx={{ display: 'flex', flexDirection: 'column', gap: 1, pt: 2 }}>
                 <Typography variant="h6">Export/Import History</Typography>
@@ -983,6 +2946,12 @@ function App() {
                   >
                     Export History
                   </Button>
+                  <Button
+                    variant="outlined"
+                    onClick={exportHistoryArchive}
+                  >
+                    Export Archive
+                  </Button>
                   <Button
                     variant="outlined"
                     component="label"
@@ -991,11 +2960,22 @@ function App() {
                     <input
                       type="file"
                       hidden
-                      accept=".json"
+                      accept=".json,.zip"
                       onChange={importHistory}
                     />
                   </Button>
                 </Box>
+                <Typography variant="body2" color="text.secondary">
+                  Archives include images, so they can be imported on another machine.
+                </Typography>
+                <Box sx={{ display: 'flex', gap: 2 }}>
+                  <Button variant="outlined" onClick={() => importFromClipboardManager("copyq")}>
+                    Import from CopyQ
+                  </Button>
+                  <Button variant="outlined" onClick={() => importFromClipboardManager("gpaste")}>
+                    Import from GPaste
+                  </Button>
+                </Box>
               </Box>
             </Box>
           </AccordionDetails>
@@ -1055,7 +3035,7 @@ function App() {
                   Image Preview ({selectedImage.dimensions})
                 </Typography>
                 <img 
-                  src={`file://${selectedImage.filePath}`} 
+                  src={selectedImage.src} 
                   alt="Full size preview" 
                   style={{ 
                     maxWidth: '80vw', 
@@ -1064,19 +3044,296 @@ function App() {
                     borderRadius: 4,
                   }} 
                 />
-                <Button 
-                  onClick={() => setSelectedImage(null)} 
-                  variant="contained" 
-                  sx={{ mt: 2 }}
+                <Button 
+                  onClick={() => setSelectedImage(null)} 
+                  variant="contained" 
+                  sx={{ mt: 2 }}
+                >
+                  Close
+                </Button>
+              </Box>
+            )}
+          </Box>
+        </Fade>
+      </Modal>
+      
+      {/* Trash Modal */}
+      <Modal
+        open={showTrash}
+        onClose={() => setShowTrash(false)}
+        closeAfterTransition
+        BackdropComponent={Backdrop}
+        BackdropProps={{
+          timeout: 500,
+        }}
+      >
+        <Fade in={showTrash}>
+          <Box sx={{
+            position: 'absolute',
+            top: '50%',
+            left: '50%',
+            transform: 'translate(-50%, -50%)',
+            width: '80vw',
+            maxHeight: '80vh',
+            outline: 'none',
+          }}>
+            <Card sx={{
+              bgcolor: 'background.paper',
+              borderRadius: 2,
+              p: 3,
+              boxShadow: 24,
+              overflow: 'auto',
+              maxHeight: '80vh',
+            }}>
+              <Box sx={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center', mb: 2 }}>
+                <Typography variant="h5" sx={{ fontWeight: 'bold', color: 'primary.main' }}>
+                  <RestoreFromTrashIcon sx={{ mr: 1, verticalAlign: 'middle' }} />
+                  Trash
+                </Typography>
+                <IconButton onClick={() => setShowTrash(false)}>
+                  <CloseIcon />
+                </IconButton>
+              </Box>
+
+              {trash.length > 0 ? (
+                <List dense>
+                  {trash.map((item) => (
+                    <ListItem key={item.id} divider>
+                      <ListItemText
+                        primary={item.payload.kind === "image"
+                          ? `Image (${item.payload.width}x${item.payload.height})`
+                          : truncateTextPreview(itemText(item), 1)}
+                        secondary={`${item.reason.replace(/_/g, ' ')} · ${new Date(item.deleted_at * 1000).toLocaleString()}`}
+                      />
+                      <ListItemSecondaryAction>
+                        <Tooltip title="Restore">
+                          <IconButton edge="end" onClick={() => restoreFromTrash([item.id])}>
+                            <RestoreFromTrashIcon />
+                          </IconButton>
+                        </Tooltip>
+                      </ListItemSecondaryAction>
+                    </ListItem>
+                  ))}
+                </List>
+              ) : (
+                <Typography>The trash is empty</Typography>
+              )}
+
+              <Box sx={{ display: 'flex', justifyContent: 'flex-end', gap: 1, mt: 3 }}>
+                <Button
+                  onClick={() => restoreFromTrash(trash.map((item) => item.id))}
+                  disabled={trash.length === 0}
+                >
+                  Restore All
+                </Button>
+                <Button
+                  onClick={emptyTrash}
+                  color="error"
+                  disabled={trash.length === 0}
+                >
+                  Empty Trash
+                </Button>
+                <Button
+                  onClick={() => setShowTrash(false)}
+                  variant="contained"
+                  color="primary"
+                >
+                  Close
+                </Button>
+              </Box>
+            </Card>
+          </Box>
+        </Fade>
+      </Modal>
+
+      {/* Collections Modal */}
+      <Modal
+        open={showCollections}
+        onClose={() => setShowCollections(false)}
+        closeAfterTransition
+        BackdropComponent={Backdrop}
+        BackdropProps={{
+          timeout: 500,
+        }}
+      >
+        <Fade in={showCollections}>
+          <Box sx={{
+            position: 'absolute',
+            top: '50%',
+            left: '50%',
+            transform: 'translate(-50%, -50%)',
+            width: '80vw',
+            maxHeight: '80vh',
+            outline: 'none',
+          }}>
+            <Card sx={{
+              bgcolor: 'background.paper',
+              borderRadius: 2,
+              p: 3,
+              boxShadow: 24,
+              overflow: 'auto',
+              maxHeight: '80vh',
+            }}>
+              <Box sx={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center', mb: 2 }}>
+                <Typography variant="h5" sx={{ fontWeight: 'bold', color: 'primary.main' }}>
+                  <CollectionsBookmarkIcon sx={{ mr: 1, verticalAlign: 'middle' }} />
+                  Collections
+                </Typography>
+                <IconButton onClick={() => setShowCollections(false)}>
+                  <CloseIcon />
+                </IconButton>
+              </Box>
+
+              <Box sx={{ display: 'flex', gap: 1, mb: 1, alignItems: 'center' }}>
+                <TextField
+                  label="New collection"
+                  size="small"
+                  value={collectionName}
+                  onChange={(e) => setCollectionName(e.target.value)}
+                  onKeyDown={(e) => e.key === 'Enter' && collectionName.trim() && createCollection()}
+                  sx={{ flexGrow: 1 }}
+                />
+                <Button variant="contained" onClick={createCollection} disabled={!collectionName.trim()}>
+                  Create
+                </Button>
+              </Box>
+              {collectionSeed && (
+                <Typography variant="body2" color="text.secondary" sx={{ mb: 1 }}>
+                  Starts with: {collectionSeed.payload.kind === "image" ? "an image" : truncateTextPreview(itemText(collectionSeed), 1, 60)}
+                </Typography>
+              )}
+              <Box sx={{ display: 'flex', gap: 1, mb: 2, alignItems: 'center' }}>
+                <TextField
+                  select
+                  label="From tag"
+                  size="small"
+                  value={collectionTag}
+                  onChange={(e) => setCollectionTag(e.target.value)}
+                  sx={{ flexGrow: 1 }}
+                >
+                  {allTags.map(tag => (
+                    <MenuItem key={tag} value={tag}>{tag}</MenuItem>
+                  ))}
+                </TextField>
+                <Button variant="outlined" onClick={createCollectionFromTag} disabled={!collectionTag}>
+                  Create from Tag
+                </Button>
+              </Box>
+              <Typography variant="body2" color="text.secondary" sx={{ mb: 1 }}>
+                Items in a collection are never removed by the retention limits. Exported collections are imported with Import History.
+              </Typography>
+
+              {collections.length > 0 ? (
+                <List dense>
+                  {collections.map((collection) => (
+                    <ListItem key={collection.id} divider sx={{ pr: 22 }}>
+                      {renamingCollection?.id === collection.id ? (
+                        <TextField
+                          size="small"
+                          autoFocus
+                          value={renamingCollection.name}
+                          onChange={(e) => setRenamingCollection({ id: collection.id, name: e.target.value })}
+                          onKeyDown={(e) => {
+                            if (e.key === 'Enter') renameCollection();
+                            if (e.key === 'Escape') setRenamingCollection(null);
+                          }}
+                          onBlur={renameCollection}
+                          fullWidth
+                        />
+                      ) : (
+                        <ListItemText
+                          primary={collection.name}
+                          secondary={`${collection.item_ids.length} ${collection.item_ids.length === 1 ? "item" : "items"} · created ${new Date(collection.created_at * 1000).toLocaleDateString()}`}
+                        />
+                      )}
+                      <ListItemSecondaryAction>
+                        <Tooltip title="Show Items">
+                          <IconButton
+                            onClick={() => {
+                              setCollectionFilter(collection.id);
+                              setShowCollections(false);
+                            }}
+                          >
+                            <SearchIcon />
+                          </IconButton>
+                        </Tooltip>
+                        <Tooltip title="Statistics">
+                          <IconButton onClick={() => loadCollectionStatistics(collection)}>
+                            <BarChartIcon />
+                          </IconButton>
+                        </Tooltip>
+                        <Tooltip title="Rename">
+                          <IconButton onClick={() => setRenamingCollection({ id: collection.id, name: collection.name })}>
+                            <EditIcon />
+                          </IconButton>
+                        </Tooltip>
+                        <Tooltip title="Export">
+                          <IconButton onClick={() => exportCollection(collection)}>
+                            <FileDownloadIcon />
+                          </IconButton>
+                        </Tooltip>
+                        <Tooltip title="Delete Collection">
+                          <IconButton edge="end" onClick={() => deleteCollection(collection)}>
+                            <Delete />
+                          </IconButton>
+                        </Tooltip>
+                      </ListItemSecondaryAction>
+                    </ListItem>
+                  ))}
+                </List>
+              ) : (
+                <Typography>No collections yet</Typography>
+              )}
+
+              {collectionStats && (
+                <Card variant="outlined" sx={{ p: 2, mt: 2 }}>
+                  <Typography variant="h6" sx={{ mb: 1, fontWeight: 'bold' }}>
+                    {collectionStats.collection.name}
+                  </Typography>
+                  <Typography>
+                    <strong>Items:</strong> {collectionStats.stats.totalItems}
+                    {collectionStats.stats.trashedItems > 0 && ` (+${collectionStats.stats.trashedItems} in the trash)`}
+                  </Typography>
+                  <Typography>
+                    <strong>Text:</strong> {collectionStats.stats.textItems + collectionStats.stats.richTextItems} ·{' '}
+                    <strong>Images:</strong> {collectionStats.stats.imageItems} ·{' '}
+                    <strong>Files:</strong> {collectionStats.stats.fileListItems} ·{' '}
+                    <strong>Favorites:</strong> {collectionStats.stats.favoriteItems}
+                  </Typography>
+                  <Typography>
+                    <strong>Size:</strong> {(collectionStats.stats.totalBytes / MEGABYTE).toFixed(2)} MB
+                  </Typography>
+                  {collectionStats.stats.totalItems > 0 && (
+                    <Typography>
+                      <strong>Copied:</strong> {new Date(collectionStats.stats.earliestTimestamp * 1000).toLocaleDateString()}
+                      {' – '}
+                      {new Date(collectionStats.stats.latestTimestamp * 1000).toLocaleDateString()}
+                    </Typography>
+                  )}
+                  {collectionStats.stats.topTags.length > 0 && (
+                    <Box sx={{ display: 'flex', flexWrap: 'wrap', gap: 0.5, mt: 1 }}>
+                      {collectionStats.stats.topTags.map(([tag, count]) => (
+                        <Chip key={tag} label={`${tag} (${count})`} size="small" />
+                      ))}
+                    </Box>
+                  )}
+                </Card>
+              )}
+
+              <Box sx={{ display: 'flex', justifyContent: 'flex-end', gap: 1, mt: 3 }}>
+                <Button
+                  onClick={() => setShowCollections(false)}
+                  variant="contained"
+                  color="primary"
                 >
                   Close
                 </Button>
               </Box>
-            )}
+            </Card>
           </Box>
         </Fade>
       </Modal>
-      
+
       {/* Statistics Modal */}
       <Modal
         open={showStatistics}
@@ -1153,6 +3410,66 @@ function App() {
                       <Typography>No tags found</Typography>
                     )}
                   </Card>
+
+                  <Card sx={{ p: 2, bgcolor: 'primary.light' }}>
+                    <Typography variant="h6" sx={{ mb: 2, fontWeight: 'bold' }}>
+                      Top Collections
+                    </Typography>
+                    {statistics.topCollections && statistics.topCollections.length > 0 ? (
+                      <Box sx={{ display: 'flex', flexDirection: 'column', gap: 1 }}>
+                        {statistics.topCollections.map(([name, count]: [string, number], index: number) => (
+                          <Box key={index} sx={{ display: 'flex', justifyContent: 'space-between' }}>
+                            <Chip label={name} size="small" sx={{ bgcolor: 'primary.main', color: 'white' }} />
+                            <Typography>{count} items</Typography>
+                          </Box>
+                        ))}
+                      </Box>
+                    ) : (
+                      <Typography>No collections yet</Typography>
+                    )}
+                  </Card>
+
+                  <Card sx={{ p: 2, bgcolor: 'info.light', gridColumn: '1 / -1' }}>
+                    <Typography variant="h6" sx={{ mb: 2, fontWeight: 'bold' }}>
+                      Top Source Apps
+                    </Typography>
+                    {statistics.topSources && statistics.topSources.length > 0 ? (
+                      <Box sx={{ display: 'flex', flexDirection: 'column', gap: 1 }}>
+                        {statistics.topSources.map(([app, count]: [string, number], index: number) => (
+                          <Box key={index} sx={{ display: 'flex', justifyContent: 'space-between' }}>
+                            <Chip label={app} size="small" sx={{ bgcolor: 'info.main', color: 'white' }} />
+                            <Typography>{count} items</Typography>
+                          </Box>
+                        ))}
+                      </Box>
+                    ) : (
+                      <Typography>No source apps recorded</Typography>
+                    )}
+                  </Card>
+
+                  <Card sx={{ p: 2, bgcolor: 'warning.light', gridColumn: '1 / -1' }}>
+                    <Typography variant="h6" sx={{ mb: 2, fontWeight: 'bold' }}>
+                      Content Types
+                    </Typography>
+                    {statistics.contentTypes && Object.keys(statistics.contentTypes).length > 0 ? (
+                      <Box sx={{ display: 'flex', flexDirection: 'column', gap: 1 }}>
+                        {Object.entries(statistics.contentTypes as Record<ContentType["type"], number>).map(([type, count]) => (
+                          <Box key={type} sx={{ display: 'flex', justifyContent: 'space-between' }}>
+                            <Chip label={CONTENT_TYPE_LABELS[type as ContentType["type"]] ?? type} size="small" sx={{ bgcolor: 'warning.main', color: 'white' }} />
+                            <Typography>{count} items</Typography>
+                          </Box>
+                        ))}
+                        {statistics.codeLanguages && statistics.codeLanguages.length > 0 && (
+                          <Typography variant="body2" sx={{ mt: 1 }}>
+                            <strong>Code languages:</strong>{' '}
+                            {statistics.codeLanguages.map(([language, count]: [string, number]) => `${language} (${count})`).join(', ')}
+                          </Typography>
+                        )}
+                      </Box>
+                    ) : (
+                      <Typography>No content types detected</Typography>
+                    )}
+                  </Card>
                   
                   <Card sx={{ p: 2, bgcolor: 'success.light', gridColumn: '1 / -1' }}>
                     <Typography variant="h6" sx={{ mb: 2, fontWeight: 'bold' }}>
@@ -1184,6 +3501,423 @@ function App() {
         </Fade>
       </Modal>
       
+      {/* Transform Modal */}
+      <Modal
+        open={transformTarget !== null}
+        onClose={() => setTransformTarget(null)}
+        closeAfterTransition
+        BackdropComponent={Backdrop}
+        BackdropProps={{
+          timeout: 500,
+        }}
+      >
+        <Fade in={transformTarget !== null}>
+          <Box sx={{
+            position: 'absolute',
+            top: '50%',
+            left: '50%',
+            transform: 'translate(-50%, -50%)',
+            width: { xs: '90vw', sm: 520 },
+            maxHeight: '80vh',
+            outline: 'none',
+          }}>
+            <Card sx={{ bgcolor: 'background.paper', borderRadius: 2, p: 3, boxShadow: 24, overflow: 'auto' }}>
+              <Box sx={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center', mb: 2 }}>
+                <Typography variant="h6" sx={{ fontWeight: 'bold', color: 'primary.main' }}>
+                  <AutoFixHighIcon sx={{ mr: 1, verticalAlign: 'middle' }} />
+                  Transform and Copy
+                </Typography>
+                <IconButton onClick={() => setTransformTarget(null)}>
+                  <CloseIcon />
+                </IconButton>
+              </Box>
+
+              {settings && Object.keys(settings.transform_presets).length > 0 && (
+                <Box sx={{ mb: 2 }}>
+                  <Typography variant="subtitle2" sx={{ mb: 1 }}>Presets</Typography>
+                  <Box sx={{ display: 'flex', flexWrap: 'wrap', gap: 1 }}>
+                    {Object.entries(settings.transform_presets).map(([name, chain]) => (
+                      <Tooltip key={name} title={chain.map(transformDescription).join(" → ")}>
+                        <Chip
+                          label={name}
+                          color="primary"
+                          onClick={() => copyTransformed(name)}
+                          onDelete={() => deleteTransformPreset(name)}
+                        />
+                      </Tooltip>
+                    ))}
+                  </Box>
+                </Box>
+              )}
+
+              <Typography variant="subtitle2" sx={{ mb: 1 }}>Add steps</Typography>
+              <Box sx={{ display: 'flex', flexWrap: 'wrap', gap: 1, mb: 2 }}>
+                {transforms.map(t => (
+                  <Chip
+                    key={t.name}
+                    label={t.description}
+                    size="small"
+                    variant="outlined"
+                    onClick={() => setTransformChain(prev => [...prev, t.name])}
+                  />
+                ))}
+              </Box>
+
+              <Typography variant="subtitle2" sx={{ mb: 1 }}>Chain</Typography>
+              <Box sx={{ display: 'flex', flexWrap: 'wrap', gap: 1, mb: 2, minHeight: 32 }}>
+                {transformChain.length > 0 ? transformChain.map((name, index) => (
+                  <Chip
+                    key={index}
+                    label={`${index + 1}. ${transformDescription(name)}`}
+                    size="small"
+                    color="secondary"
+                    onDelete={() => setTransformChain(prev => prev.filter((_, i) => i !== index))}
+                  />
+                )) : (
+                  <Typography variant="body2" color="text.secondary">Pick steps above; they run in order.</Typography>
+                )}
+              </Box>
+
+              <Box sx={{ display: 'flex', gap: 1, alignItems: 'center' }}>
+                <TextField
+                  size="small"
+                  placeholder="Preset name"
+                  value={presetName}
+                  onChange={(e) => setPresetName(e.target.value)}
+                  sx={{ flexGrow: 1 }}
+                />
+                <Button
+                  variant="outlined"
+                  onClick={saveTransformPreset}
+                  disabled={!presetName.trim() || transformChain.length === 0}
+                >
+                  Save Preset
+                </Button>
+                <Button
+                  variant="contained"
+                  onClick={() => copyTransformed()}
+                  disabled={transformChain.length === 0}
+                >
+                  Copy
+                </Button>
+              </Box>
+            </Card>
+          </Box>
+        </Fade>
+      </Modal>
+
+      {/* Image Edit Modal */}
+      <Modal
+        open={imageEditTarget !== null}
+        onClose={() => setImageEditTarget(null)}
+        closeAfterTransition
+        BackdropComponent={Backdrop}
+        BackdropProps={{
+          timeout: 500,
+        }}
+      >
+        <Fade in={imageEditTarget !== null}>
+          <Box sx={{
+            position: 'absolute',
+            top: '50%',
+            left: '50%',
+            transform: 'translate(-50%, -50%)',
+            width: { xs: '90vw', sm: 640 },
+            maxHeight: '90vh',
+            outline: 'none',
+          }}>
+            <Card sx={{ bgcolor: 'background.paper', borderRadius: 2, p: 3, boxShadow: 24, overflow: 'auto', maxHeight: '90vh' }}>
+              <Box sx={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center', mb: 2 }}>
+                <Typography variant="h6" sx={{ fontWeight: 'bold', color: 'primary.main' }}>
+                  <EditIcon sx={{ mr: 1, verticalAlign: 'middle' }} />
+                  Edit Image
+                </Typography>
+                <IconButton onClick={() => setImageEditTarget(null)}>
+                  <CloseIcon />
+                </IconButton>
+              </Box>
+
+              <Box sx={{ display: 'flex', justifyContent: 'center', alignItems: 'center', mb: 2, minHeight: 120 }}>
+                {imageEditPreview ? (
+                  <img
+                    src={imageEditPreview}
+                    alt="Edited image preview"
+                    style={{ maxWidth: '100%', maxHeight: '40vh', objectFit: 'contain', borderRadius: 4 }}
+                  />
+                ) : (
+                  <CircularProgress size={24} />
+                )}
+              </Box>
+              {imageEditError && (
+                <Alert severity="error" sx={{ mb: 2 }}>{imageEditError}</Alert>
+              )}
+
+              <Typography variant="subtitle2" sx={{ mb: 1 }}>Steps</Typography>
+              <Box sx={{ display: 'flex', flexWrap: 'wrap', gap: 1, mb: 2, minHeight: 32 }}>
+                {imageEdits.length > 0 ? imageEdits.map((operation, index) => (
+                  <Chip
+                    key={index}
+                    label={`${index + 1}. ${describeImageOperation(operation)}`}
+                    size="small"
+                    color="secondary"
+                    onDelete={() => setImageEdits(prev => prev.filter((_, i) => i !== index))}
+                  />
+                )) : (
+                  <Typography variant="body2" color="text.secondary">
+                    Add steps below; they run in order, and positions refer to the image after the steps before.
+                  </Typography>
+                )}
+              </Box>
+
+              <Box sx={{ display: 'flex', flexWrap: 'wrap', gap: 1, mb: 1 }}>
+                {(Object.keys(IMAGE_OPERATION_LABELS) as ImageOperation["op"][]).map(op => (
+                  <Chip
+                    key={op}
+                    label={IMAGE_OPERATION_LABELS[op]}
+                    size="small"
+                    variant={imageEditDraft?.op === op ? "filled" : "outlined"}
+                    color={imageEditDraft?.op === op ? "primary" : "default"}
+                    onClick={() => {
+                      if (imageEditTarget?.payload.kind !== "image") return;
+                      setImageEditDraft(newImageOperation(op, imageEditTarget.payload.width, imageEditTarget.payload.height));
+                    }}
+                  />
+                ))}
+              </Box>
+              {imageEditDraft && (
+                <Box sx={{ display: 'flex', flexWrap: 'wrap', gap: 1, alignItems: 'center', mb: 2 }}>
+                  {imageEditFields(imageEditDraft)}
+                  <Button variant="outlined" size="small" onClick={addImageEditStep}>
+                    Add Step
+                  </Button>
+                </Box>
+              )}
+
+              <Box sx={{ display: 'flex', flexWrap: 'wrap', gap: 1, alignItems: 'center', mt: 2 }}>
+                <TextField
+                  select
+                  label="Format"
+                  size="small"
+                  value={imageEditFormat}
+                  onChange={(e) => setImageEditFormat(e.target.value as ImageFormat)}
+                  sx={{ minWidth: 110 }}
+                >
+                  <MenuItem value="png">PNG</MenuItem>
+                  <MenuItem value="jpeg">JPEG</MenuItem>
+                  <MenuItem value="webp">WebP</MenuItem>
+                </TextField>
+                {imageEditFormat === "jpeg" && (
+                  <Box sx={{ width: 140, px: 1 }}>
+                    <Typography variant="caption" color="text.secondary">Quality {imageEditQuality}</Typography>
+                    <Slider
+                      size="small"
+                      min={10}
+                      max={100}
+                      value={imageEditQuality}
+                      onChange={(_, value) => setImageEditQuality(value as number)}
+                    />
+                  </Box>
+                )}
+                <Box sx={{ flexGrow: 1 }} />
+                <Button variant="outlined" onClick={() => saveImageEdit(false)} disabled={imageEditError !== null}>
+                  Save
+                </Button>
+                <Button variant="contained" onClick={() => saveImageEdit(true)} disabled={imageEditError !== null}>
+                  Save & Copy
+                </Button>
+              </Box>
+            </Card>
+          </Box>
+        </Fade>
+      </Modal>
+
+      {/* QR Code Modal */}
+      <Modal
+        open={qrTarget !== null}
+        onClose={() => setQrTarget(null)}
+        closeAfterTransition
+        BackdropComponent={Backdrop}
+        BackdropProps={{
+          timeout: 500,
+        }}
+      >
+        <Fade in={qrTarget !== null}>
+          <Box sx={{
+            position: 'absolute',
+            top: '50%',
+            left: '50%',
+            transform: 'translate(-50%, -50%)',
+            width: { xs: '90vw', sm: 420 },
+            maxHeight: '90vh',
+            outline: 'none',
+          }}>
+            <Card sx={{ bgcolor: 'background.paper', borderRadius: 2, p: 3, boxShadow: 24, overflow: 'auto', maxHeight: '90vh' }}>
+              <Box sx={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center', mb: 2 }}>
+                <Typography variant="h6" sx={{ fontWeight: 'bold', color: 'primary.main' }}>
+                  <QrCodeIcon sx={{ mr: 1, verticalAlign: 'middle' }} />
+                  QR Code
+                </Typography>
+                <IconButton onClick={() => setQrTarget(null)}>
+                  <CloseIcon />
+                </IconButton>
+              </Box>
+
+              <Box sx={{ display: 'flex', justifyContent: 'center', alignItems: 'center', mb: 2, minHeight: 120 }}>
+                {qrPreview ? (
+                  <img
+                    src={qrPreview}
+                    alt="QR code"
+                    style={{ maxWidth: '100%', maxHeight: '50vh', objectFit: 'contain', imageRendering: 'pixelated' }}
+                  />
+                ) : qrError ? (
+                  <Alert severity="error">{qrError}</Alert>
+                ) : (
+                  <CircularProgress size={24} />
+                )}
+              </Box>
+
+              <Box sx={{ display: 'flex', flexWrap: 'wrap', gap: 1, alignItems: 'center' }}>
+                <TextField
+                  select
+                  label="Error Correction"
+                  size="small"
+                  value={qrErrorCorrection}
+                  onChange={(e) => setQrErrorCorrection(e.target.value as QrErrorCorrection)}
+                  sx={{ minWidth: 150 }}
+                >
+                  <MenuItem value="low">Low (7%)</MenuItem>
+                  <MenuItem value="medium">Medium (15%)</MenuItem>
+                  <MenuItem value="quartile">Quartile (25%)</MenuItem>
+                  <MenuItem value="high">High (30%)</MenuItem>
+                </TextField>
+                <Box sx={{ flexGrow: 1 }} />
+                <Button variant="outlined" onClick={saveQrCode} disabled={qrPreview === null}>
+                  Save to History
+                </Button>
+                <Button variant="contained" onClick={copyQrCode} disabled={qrPreview === null}>
+                  Copy
+                </Button>
+              </Box>
+            </Card>
+          </Box>
+        </Fade>
+      </Modal>
+
+      {/* Scanned Codes Modal */}
+      <Modal
+        open={codeScan !== null}
+        onClose={() => setCodeScan(null)}
+        closeAfterTransition
+        BackdropComponent={Backdrop}
+        BackdropProps={{
+          timeout: 500,
+        }}
+      >
+        <Fade in={codeScan !== null}>
+          <Box sx={{
+            position: 'absolute',
+            top: '50%',
+            left: '50%',
+            transform: 'translate(-50%, -50%)',
+            width: { xs: '90vw', sm: 520 },
+            maxHeight: '90vh',
+            outline: 'none',
+          }}>
+            <Card sx={{ bgcolor: 'background.paper', borderRadius: 2, p: 3, boxShadow: 24, overflow: 'auto', maxHeight: '90vh' }}>
+              <Box sx={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center', mb: 2 }}>
+                <Typography variant="h6" sx={{ fontWeight: 'bold', color: 'primary.main' }}>
+                  <QrCodeScannerIcon sx={{ mr: 1, verticalAlign: 'middle' }} />
+                  Scanned Codes
+                </Typography>
+                <IconButton onClick={() => setCodeScan(null)}>
+                  <CloseIcon />
+                </IconButton>
+              </Box>
+
+              {!codeScan ? null : codeScan.error ? (
+                <Alert severity="error">{codeScan.error}</Alert>
+              ) : codeScan.codes === null ? (
+                <Box sx={{ display: 'flex', justifyContent: 'center', py: 2 }}>
+                  <CircularProgress size={24} />
+                </Box>
+              ) : codeScan.codes.length === 0 ? (
+                <Typography variant="body2" color="text.secondary">
+                  No QR codes or barcodes were found in this image.
+                </Typography>
+              ) : (
+                <Box sx={{ display: 'flex', flexDirection: 'column', gap: 1.5 }}>
+                  {codeScan.codes.map((code, index) => (
+                    <Box key={index} sx={{ display: 'flex', alignItems: 'center', gap: 1 }}>
+                      <Chip label={CODE_FORMAT_LABELS[code.format]} size="small" color="secondary" />
+                      <Typography variant="body2" sx={{ flexGrow: 1, wordBreak: 'break-all', fontFamily: 'monospace' }}>
+                        {truncateTextPreview(code.text, 3, 200)}
+                      </Typography>
+                      <Button size="small" variant="outlined" onClick={() => addDecodedText(code)}>
+                        Add to History
+                      </Button>
+                    </Box>
+                  ))}
+                </Box>
+              )}
+            </Card>
+          </Box>
+        </Fade>
+      </Modal>
+
+      {/* Snippet Variables Modal */}
+      <Modal
+        open={snippetPrompt !== null}
+        onClose={() => setSnippetPrompt(null)}
+        closeAfterTransition
+        BackdropComponent={Backdrop}
+        BackdropProps={{
+          timeout: 500,
+        }}
+      >
+        <Fade in={snippetPrompt !== null}>
+          <Box sx={{
+            position: 'absolute',
+            top: '50%',
+            left: '50%',
+            transform: 'translate(-50%, -50%)',
+            width: { xs: '90vw', sm: 420 },
+            outline: 'none',
+          }}>
+            {snippetPrompt && (
+              <Card sx={{ bgcolor: 'background.paper', borderRadius: 2, p: 3, boxShadow: 24 }}>
+                <Typography variant="h6" sx={{ fontWeight: 'bold', color: 'primary.main', mb: 2 }}>
+                  {snippetPrompt.snippet.name}
+                </Typography>
+                <Box sx={{ display: 'flex', flexDirection: 'column', gap: 2 }}>
+                  {Object.keys(snippetPrompt.values).map((name, index) => (
+                    <TextField
+                      key={name}
+                      label={name}
+                      size="small"
+                      autoFocus={index === 0}
+                      value={snippetPrompt.values[name]}
+                      onChange={(e) => setSnippetPrompt({
+                        ...snippetPrompt,
+                        values: { ...snippetPrompt.values, [name]: e.target.value },
+                      })}
+                    />
+                  ))}
+                  <Box sx={{ display: 'flex', justifyContent: 'flex-end', gap: 1 }}>
+                    <Button onClick={() => setSnippetPrompt(null)}>Cancel</Button>
+                    <Button
+                      variant="contained"
+                      onClick={() => expandSnippet(snippetPrompt.snippet, snippetPrompt.values)}
+                    >
+                      Copy
+                    </Button>
+                  </Box>
+                </Box>
+              </Card>
+            )}
+          </Box>
+        </Fade>
+      </Modal>
+
       {/* Snackbar for notifications */}
       <Snackbar
         open={snackbarOpen}
@@ -1194,6 +3928,11 @@ function App() {
         <Alert
           onClose={handleSnackbarClose}
           severity={snackbarSeverity}
+          action={snackbarUndo ? (
+            <Button color="inherit" size="small" onClick={undoLastOperation}>
+              Undo
+            </Button>
+          ) : undefined}
           sx={{ width: "100%" }}
         >
           {snackbarMessage}